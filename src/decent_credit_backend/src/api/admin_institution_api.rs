@@ -1,21 +1,25 @@
 use candid::{CandidType, Deserialize, Principal};
 use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
 use log::{info, debug, warn, error};  // 替换原来的 log_info
 use serde::Serialize;
 
 use crate::services::admin_institution_service::*;
-use crate::models::record::{DCCTransactionRequest, BalanceResponse};
+use crate::models::record::{DCCTransactionRequest, BalanceResponse, InstitutionTransaction};
 use crate::models::dashboard::{AdminDashboardData};
 use crate::models::institution::*;
 use crate::services::token_service::*;
+use crate::services::institution_score_service;
+use crate::models::institution_score::ScoreChangeReason;
 use ic_cdk::api::time;
+use crate::utils::auth::ensure_institution_caller;
 
 
 
 
 
 /// 注册新机构
-#[update]
+#[update(guard = "general_guard")]
 pub async fn register_institution(request: RegisterRequest) -> Result<Principal, String> {
     let caller = ic_cdk::caller();
     info!("Institution registration attempt by {}", caller.to_text());
@@ -38,7 +42,7 @@ pub async fn register_institution(request: RegisterRequest) -> Result<Principal,
 
 
 // 然后添加更新接口
-#[update]
+#[update(guard = "general_guard")]
 pub async fn update_service_settings(request: UpdateServiceSettingsRequest) -> Result<(), String> {
     let caller = ic_cdk::caller();
     info!("Service settings update initiated by {}", caller.to_text());
@@ -69,7 +73,7 @@ pub async fn update_service_settings(request: UpdateServiceSettingsRequest) -> R
     })
 }
 /// 修改机构状态
-#[update]
+#[update(guard = "general_guard")]
 pub async fn update_institution_status(id: Principal, is_active: bool) -> Result<(), String> {
     let caller = ic_cdk::caller();
     info!("Institution status update by {} for ID: {}", caller.to_text(), id.to_text());
@@ -123,29 +127,26 @@ pub fn get_all_institutions() -> Vec<Institution> {
 }
 
 /// 更新信用分数
-#[update]
+#[update(guard = "general_guard")]
 pub async fn update_credit_score(id: Principal, score: u64) -> Result<(), String> {
     let caller = ic_cdk::caller();
     info!("Credit score update initiated by {} for ID: {}", caller.to_text(), id.to_text());
     debug!("New credit score: {}", score);
 
-    ADMIN_SERVICE.with(|service| {
-        let mut service = service.borrow_mut();
-        match service.update_credit_score(id, score) {
-            Ok(_) => {
-                info!("Successfully updated credit score");
-                Ok(())
-            },
-            Err(e) => {
-                error!("Failed to update credit score: {}", e);
-                Err(e)
-            }
+    match institution_score_service::change_score(id, score, ScoreChangeReason::ManualAdjustment, None) {
+        Ok(_) => {
+            info!("Successfully updated credit score");
+            Ok(())
+        },
+        Err(e) => {
+            error!("Failed to update credit score: {}", e);
+            Err(e)
         }
-    })
+    }
 }
 
 
-#[update]
+#[update(guard = "general_guard")]
 pub async fn get_balance(id: Principal) -> Result<BalanceResponse, String> {
     debug!("Fetching balance for institution: {}", id.to_text());
 
@@ -176,7 +177,7 @@ pub async fn get_balance(id: Principal) -> Result<BalanceResponse, String> {
 }
 
 /// 更新USDT汇率
-#[update]
+#[update(guard = "general_guard")]
 pub async fn update_usdt_rate(rate: f64) -> Result<(), String> {
     let caller = ic_cdk::caller();
     info!("USDT rate update initiated by {}", caller.to_text());
@@ -198,7 +199,7 @@ pub async fn update_usdt_rate(rate: f64) -> Result<(), String> {
 }
 
 
-#[update]
+#[update(guard = "general_guard")]
 pub async fn record_token_trading(id: Principal, is_buy: bool, amount: u64) -> Result<(), String> {
     info!("Recording token trading for institution: {}", id.to_text());
     debug!("Trading details - Type: {}, Amount: {}", 
@@ -245,10 +246,28 @@ pub async fn record_token_trading(id: Principal, is_buy: bool, amount: u64) -> R
         }
     }
 }
+/// 获取机构的链上交易明细（含本地备注），用于财务对账
+#[update(guard = "general_guard")]
+pub async fn get_institution_transactions(id: Principal) -> Result<Vec<InstitutionTransaction>, String> {
+    ensure_institution_caller(id)?;
+    info!("Fetching ledger transactions for institution: {}", id.to_text());
+
+    match AdminService::get_institution_transactions(id).await {
+        Ok(transactions) => {
+            debug!("Retrieved {} transactions", transactions.len());
+            Ok(transactions)
+        }
+        Err(e) => {
+            error!("Failed to get institution transactions: {}", e);
+            Err(e)
+        }
+    }
+}
+
 // === 会话相关接口 ===
 
 /// 登录接口
-#[update]
+#[update(guard = "general_guard")]
 pub async fn institution_login(request: LoginRequest) -> LoginResponse {
     info!("Login attempt for user: {}", request.name);
     debug!("Login attempt received");
@@ -266,7 +285,7 @@ pub async fn institution_login(request: LoginRequest) -> LoginResponse {
 }
 
 /// 修改密码
-#[update]
+#[update(guard = "general_guard")]
 pub async fn change_password(old_password: String, new_password: String) -> Result<(), String> {
     let caller = ic_cdk::caller();
     info!("Password change attempt for user: {}", caller.to_text());
@@ -288,7 +307,7 @@ pub async fn change_password(old_password: String, new_password: String) -> Resu
 }
 
 /// 重置密码
-#[update]
+#[update(guard = "general_guard")]
 pub async fn reset_password(id: Principal) -> Result<String, String> {
     info!("Password reset attempt for user: {}", id.to_text());
     debug!("Password reset request received");
@@ -0,0 +1,56 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::{info, debug};
+
+use crate::models::billing::*;
+use crate::services::billing_service::{self, BILLING_SERVICE};
+use crate::utils::auth::{ensure_controller, ensure_institution_caller};
+
+// 指定机构时只能查看本机构的数据，不指定时仅限控制者查看全部
+fn ensure_billing_access(institution_id: Option<Principal>) -> Result<(), String> {
+    match institution_id {
+        Some(id) => ensure_institution_caller(id),
+        None => ensure_controller(),
+    }
+}
+
+/// 查询计费流水（预留、结算、退款每一步）
+#[query]
+pub fn get_billing_ledger(institution_id: Option<Principal>) -> Result<Vec<BillingLedgerEntry>, String> {
+    ensure_billing_access(institution_id)?;
+    debug!("Fetching billing ledger for {:?}", institution_id.map(|id| id.to_text()));
+
+    Ok(BILLING_SERVICE.with(|service| {
+        service.borrow().get_ledger(institution_id)
+    }))
+}
+
+/// 查询托管记录，可按机构和状态过滤
+#[query]
+pub fn get_query_escrows(institution_id: Option<Principal>, status: Option<EscrowStatus>) -> Result<Vec<QueryEscrow>, String> {
+    ensure_billing_access(institution_id)?;
+    debug!("Fetching query escrows, status filter: {:?}", status);
+
+    Ok(BILLING_SERVICE.with(|service| {
+        service.borrow().get_escrows(institution_id, status)
+    }))
+}
+
+/// 立即重试待结算/待退款的托管（定时任务也会自动重试）
+#[update(guard = "general_guard")]
+pub async fn retry_pending_settlements() -> Result<Vec<QueryEscrow>, String> {
+    ensure_controller()?;
+    info!("Manual retry of pending settlements by {}", ic_cdk::caller().to_text());
+
+    billing_service::retry_pending_settlements().await;
+
+    Ok(BILLING_SERVICE.with(|service| {
+        let service = service.borrow();
+        let mut pending = service.get_escrows(None, Some(EscrowStatus::SettlementPending));
+        pending.extend(service.get_escrows(None, Some(EscrowStatus::RefundPending)));
+        pending
+    }))
+}
+
+candid::export_service!();
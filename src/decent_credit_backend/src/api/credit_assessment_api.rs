@@ -1,27 +1,49 @@
 use candid::{CandidType, Deserialize, Principal};
 use ic_cdk_macros::*;
+use crate::services::rate_limit_service::{assessment_guard, check_institution, general_guard};
+use crate::models::rate_limit::RateLimitCategory;
 use log::{info, debug, warn, error};
 use serde::Serialize;
 use std::collections::HashMap;
 use crate::models::credit::*;
-use crate::services::credit_service::CREDIT_SERVICE;
+use crate::services::credit_service::{record_assessment, CREDIT_SERVICE};
+use crate::services::quota_service::QUOTA_SERVICE;
+use crate::services::score_history_service::SCORE_HISTORY_SERVICE;
+use crate::models::dashboard::ScoreTrend;
+use crate::services::record_service::RECORD_SERVICE;
+use crate::utils::auth::{caller_institution_ids, ensure_controller, ensure_institution_caller};
 
 
 
-#[update]
+#[update(guard = "assessment_guard")]
 pub fn get_risk_assessment(institution_id: Principal, user_did: String) -> Result<RiskAssessment, String> {
     let caller = ic_cdk::caller();
     debug!("Get risk assessment by {} for user {}", caller.to_text(), user_did);
 
+    // 先确认调用者可以代表该机构，再占用其配额
+    ensure_institution_caller(institution_id)?;
+    check_institution(RateLimitCategory::Assessment, institution_id)?;
+    get_risk_assessment_for(institution_id, user_did)
+}
+
+/// 机构身份已校验且已限流（调用者或 API Key）后的风险评估
+pub(crate) fn get_risk_assessment_for(institution_id: Principal, user_did: String) -> Result<RiskAssessment, String> {
+    QUOTA_SERVICE.with(|service| {
+        service.borrow_mut().consume_query(institution_id)
+    }).map_err(|e| e.to_string())?;
+
     CREDIT_SERVICE.with(|service| {
         let mut service = service.borrow_mut(); // 改为可变引用
         match service.assess_user_risk(institution_id, &user_did) {
             Ok(assessment) => {
                 debug!("Successfully retrieved risk assessment");
+                // 每次评估都生成报告并记录评分历史
+                record_assessment(institution_id, &user_did, &assessment);
                 Ok(assessment)
             },
             Err(e) => {
                 warn!("Failed to get risk assessment: {}", e);
+                QUOTA_SERVICE.with(|service| service.borrow_mut().release_query(institution_id));
                 Err(e)
             }
         }
@@ -31,6 +53,13 @@ pub fn get_risk_assessment(institution_id: Principal, user_did: String) -> Resul
 pub async fn query_assessment_reports(institution_id: Principal, days: Option<u64>) -> AssessmentListResponse {
     let caller = ic_cdk::caller();
     info!("Starting query_assessment_reports for institution: {}", institution_id);
+    if let Err(e) = ensure_institution_caller(institution_id) {
+        return AssessmentListResponse {
+            status: "UNAUTHORIZED".to_string(),
+            message: Some(e),
+            data: Vec::new(),
+        };
+    }
     
     let response = CREDIT_SERVICE.with(|service| {
         let service = service.borrow();
@@ -70,5 +99,132 @@ pub async fn query_assessment_reports(institution_id: Principal, days: Option<u6
     response
 }
 
+/// 评分模拟：在现有记录上叠加假设记录，返回模拟分数与当前分数，不保存任何数据
+/// 结果包含用户当前评分，与风险评估一样需要机构身份和用户访问权限并占用配额
+#[update(guard = "assessment_guard")]
+pub fn simulate_credit_score(institution_id: Principal, request: ScoreSimulationRequest) -> Result<ScoreSimulationResult, String> {
+    ensure_institution_caller(institution_id)?;
+    ensure_user_score_access(&request.user_did)?;
+    check_institution(RateLimitCategory::Assessment, institution_id)?;
+    debug!(
+        "Simulating score for {} with {} hypothetical records",
+        request.user_did,
+        request.hypothetical_records.len()
+    );
+
+    QUOTA_SERVICE.with(|service| {
+        service.borrow_mut().consume_query(institution_id)
+    }).map_err(|e| e.to_string())?;
+
+    let result = CREDIT_SERVICE.with(|service| {
+        service.borrow().simulate_score(&request)
+    });
+    if result.is_err() {
+        QUOTA_SERVICE.with(|service| service.borrow_mut().release_query(institution_id));
+    }
+    result
+}
+
+/// 分页查询评估报告，支持按用户过滤和排序
+#[query]
+pub fn query_assessment_reports_page(query: ReportQuery) -> Result<AssessmentPageResponse, String> {
+    ensure_institution_caller(query.institution_id)?;
+    debug!("Paged report query for institution {}", query.institution_id.to_text());
+
+    Ok(CREDIT_SERVICE.with(|service| {
+        service.borrow().query_assessment_reports_page(&query)
+    }))
+}
+
+/// 按报告ID查询评估报告
+#[query]
+pub fn get_assessment_report(institution_id: Principal, report_id: String) -> AssessmentResponse {
+    if let Err(e) = ensure_institution_caller(institution_id) {
+        return AssessmentResponse {
+            status: "UNAUTHORIZED".to_string(),
+            message: Some(e),
+            data: None,
+        };
+    }
+    CREDIT_SERVICE.with(|service| {
+        service.borrow().get_assessment_report(institution_id, &report_id)
+    })
+}
+
+// 用户评分数据仅限控制者，或提交过/评估过该用户记录的机构查看
+fn ensure_user_score_access(user_did: &str) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if ic_cdk::api::is_controller(&caller) {
+        return Ok(());
+    }
+
+    let related = caller_institution_ids(caller).into_iter().any(|institution_id| {
+        RECORD_SERVICE.with(|service| service.borrow().has_user_records(institution_id, user_did))
+            || SCORE_HISTORY_SERVICE.with(|service| service.borrow().institution_follows(institution_id, user_did))
+    });
+    if related {
+        Ok(())
+    } else {
+        warn!("Caller {} has no records for {}", caller.to_text(), user_did);
+        Err("无权查看该用户的评分数据".to_string())
+    }
+}
+
+/// 查询用户的历史评分，可按时间范围和条数过滤
+#[query]
+pub fn get_score_history(user_did: String, range: Option<ScoreHistoryRange>) -> Result<Vec<ScoreHistoryEntry>, String> {
+    ensure_user_score_access(&user_did)?;
+    debug!("Fetching score history for {}", user_did);
+
+    Ok(SCORE_HISTORY_SERVICE.with(|service| {
+        service.borrow().get_history(&user_did, &range.unwrap_or_default())
+    }))
+}
+
+/// 按天汇总的评分趋势
+#[query]
+pub fn get_score_trend(user_did: String, days: u64) -> Result<Vec<ScoreTrend>, String> {
+    ensure_user_score_access(&user_did)?;
+
+    Ok(SCORE_HISTORY_SERVICE.with(|service| {
+        service.borrow().get_trend(&user_did, days)
+    }))
+}
+
+/// 评分下降提醒，可按用户或关注的机构过滤；两者都不指定时仅限控制者
+#[query]
+pub fn get_score_alerts(user_did: Option<String>, institution_id: Option<Principal>, unacknowledged_only: bool) -> Result<Vec<ScoreAlert>, String> {
+    match (&user_did, institution_id) {
+        (_, Some(id)) => ensure_institution_caller(id)?,
+        (Some(did), None) => ensure_user_score_access(did)?,
+        (None, None) => ensure_controller()?,
+    }
+
+    Ok(SCORE_HISTORY_SERVICE.with(|service| {
+        service.borrow().get_alerts(user_did, institution_id, unacknowledged_only)
+    }))
+}
+
+/// 确认提醒，仅限触发提醒的机构或控制者
+#[update(guard = "general_guard")]
+pub fn acknowledge_score_alert(alert_id: u64) -> Result<(), String> {
+    let alert = SCORE_HISTORY_SERVICE.with(|service| service.borrow().get_alert(alert_id))
+        .ok_or_else(|| format!("提醒不存在: {}", alert_id))?;
+    ensure_institution_caller(alert.institution_id)?;
+
+    SCORE_HISTORY_SERVICE.with(|service| {
+        service.borrow_mut().acknowledge_alert(alert_id)
+    })
+}
+
+/// 设置评分下降提醒阈值（分）
+#[update(guard = "general_guard")]
+pub fn set_score_drop_threshold(points: u32) -> Result<(), String> {
+    ensure_controller()?;
+
+    SCORE_HISTORY_SERVICE.with(|service| {
+        service.borrow_mut().set_drop_threshold(points)
+    })
+}
 
 candid::export_service!();
@@ -0,0 +1,62 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::info;
+
+use crate::models::data_quality::*;
+use crate::services::data_quality_service::{self, DATA_QUALITY_SERVICE};
+use crate::utils::auth::{ensure_controller, ensure_institution_caller};
+
+/// 按当前数据计算机构的数据质量指标
+#[query]
+pub fn get_data_quality_metrics(institution_id: Principal) -> Result<DataQualityMetrics, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(data_quality_service::measure_institution(institution_id))
+}
+
+/// 机构信用分的自动调整记录（按时间倒序）
+#[query]
+pub fn get_quality_adjustments(institution_id: Principal, limit: u32) -> Result<Vec<QualityAdjustment>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(DATA_QUALITY_SERVICE.with(|service| {
+        service.borrow().get_adjustments(Some(institution_id), limit as usize)
+    }))
+}
+
+#[query]
+pub fn list_quality_adjustments(limit: u32) -> Result<Vec<QualityAdjustment>, String> {
+    ensure_controller()?;
+    Ok(DATA_QUALITY_SERVICE.with(|service| {
+        service.borrow().get_adjustments(None, limit as usize)
+    }))
+}
+
+/// 立即评估数据质量并调整信用分，未指定机构时评估全部机构
+#[update(guard = "general_guard")]
+pub fn evaluate_data_quality(institution_id: Option<Principal>) -> Result<Vec<QualityAdjustment>, String> {
+    ensure_controller()?;
+    let operator = ic_cdk::caller();
+    info!("Manual data quality evaluation by {}", operator.to_text());
+
+    match institution_id {
+        Some(id) => data_quality_service::evaluate_institution(id, operator).map(|a| vec![a]),
+        None => Ok(data_quality_service::evaluate_all_institutions(operator)),
+    }
+}
+
+#[query]
+pub fn get_data_quality_config() -> DataQualityConfig {
+    DATA_QUALITY_SERVICE.with(|service| {
+        service.borrow().get_config()
+    })
+}
+
+#[update(guard = "general_guard")]
+pub fn update_data_quality_config(config: DataQualityConfig) -> Result<(), String> {
+    ensure_controller()?;
+    DATA_QUALITY_SERVICE.with(|service| {
+        service.borrow_mut().update_config(config)
+    })
+}
+
+candid::export_service!();
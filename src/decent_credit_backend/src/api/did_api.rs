@@ -0,0 +1,97 @@
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::{info, debug};
+
+use crate::models::did::*;
+use crate::services::did_service::{self, is_did_controller, DID_SERVICE};
+use crate::services::reports_storage::REPORTS_STORAGE;
+use crate::utils::auth::{ensure_controller, ensure_institution_caller};
+
+/// 本平台凭证签发方的 DID
+#[query]
+pub fn get_issuer_did() -> String {
+    did_service::issuer_did()
+}
+
+/// 解析 DID 文档；借款人本人可看到关联的其他 DID
+#[query]
+pub fn resolve_did(did: String) -> Result<DidDocument, String> {
+    debug!("Resolving {}", did);
+    let include_links = is_did_controller(&did, ic_cdk::caller());
+    DID_SERVICE.with(|service| {
+        service.borrow().resolve(&did, include_links)
+    })
+}
+
+/// 解析 DID 文档并返回 W3C JSON 格式
+#[query]
+pub fn resolve_did_json(did: String) -> Result<String, String> {
+    let include_links = is_did_controller(&did, ic_cdk::caller());
+    DID_SERVICE.with(|service| {
+        service.borrow().resolve(&did, include_links)
+    }).map(|document| did_service::did_document_json(&document))
+}
+
+/// 借款人为自己的 DID 登记 Ed25519 公钥
+#[update(guard = "general_guard")]
+pub fn add_did_verification_key(user_did: String, public_key: Vec<u8>) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if !is_did_controller(&user_did, caller) {
+        return Err("只有借款人本人可以管理 DID 公钥".to_string());
+    }
+
+    DID_SERVICE.with(|service| {
+        service.borrow_mut().add_user_key(&user_did, public_key, caller)
+    })
+}
+
+#[update(guard = "general_guard")]
+pub fn remove_did_verification_key(user_did: String, key_id: String) -> Result<(), String> {
+    if !is_did_controller(&user_did, ic_cdk::caller()) {
+        return Err("只有借款人本人可以管理 DID 公钥".to_string());
+    }
+
+    DID_SERVICE.with(|service| {
+        service.borrow_mut().remove_user_key(&user_did, &key_id)
+    })
+}
+
+/// 将评估报告签发为可验证凭证，借款人本人或报告所属机构可调用
+#[update(guard = "general_guard")]
+pub async fn issue_report_credential(report_id: String) -> Result<VerifiableCredential, String> {
+    let report = REPORTS_STORAGE.with(|storage| storage.borrow().get_report(&report_id))
+        .ok_or_else(|| format!("报告不存在: {}", report_id))?;
+    if !is_did_controller(&report.user_did, ic_cdk::caller()) {
+        ensure_institution_caller(report.institution_id)?;
+    }
+
+    info!("Issuing credential for report {}", report_id);
+    did_service::issue_report_credential(&report).await
+}
+
+/// 校验本平台签发的凭证
+#[query]
+pub fn verify_report_credential(jwt: String) -> CredentialVerification {
+    DID_SERVICE.with(|service| {
+        service.borrow().verify_credential(&jwt)
+    })
+}
+
+#[update(guard = "general_guard")]
+pub fn set_credential_validity_days(days: u64) -> Result<(), String> {
+    ensure_controller()?;
+    DID_SERVICE.with(|service| {
+        service.borrow_mut().set_credential_validity_days(days)
+    })
+}
+
+/// 设置凭证签名使用的阈值 ECDSA 密钥名称（主网 key_1，本地 dfx_test_key），签发首个凭证后不可更改
+#[update(guard = "general_guard")]
+pub fn set_credential_signing_key(name: String) -> Result<(), String> {
+    ensure_controller()?;
+    DID_SERVICE.with(|service| {
+        service.borrow_mut().set_signing_key_name(name)
+    })
+}
+
+candid::export_service!();
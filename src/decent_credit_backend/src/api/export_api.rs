@@ -0,0 +1,60 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::{check_institution, general_guard};
+use crate::models::rate_limit::RateLimitCategory;
+use log::info;
+
+use crate::models::export::*;
+use crate::services::export_service::EXPORT_SERVICE;
+use crate::utils::auth::ensure_institution_caller;
+
+fn ensure_job_access(job_id: &str) -> Result<ExportJob, String> {
+    let job = EXPORT_SERVICE.with(|service| service.borrow().get_job(job_id))
+        .ok_or_else(|| format!("导出任务不存在或已过期: {}", job_id))?;
+    ensure_institution_caller(job.institution_id)?;
+    Ok(job)
+}
+
+/// 导出本机构的信用记录、扣分记录和计费流水
+#[update(guard = "general_guard")]
+pub fn create_export_job(request: CreateExportJobRequest) -> Result<ExportJob, String> {
+    ensure_institution_caller(request.institution_id)?;
+    check_institution(RateLimitCategory::General, request.institution_id)?;
+    info!("Export requested by {} for {:?}", request.institution_id.to_text(), request.datasets);
+
+    EXPORT_SERVICE.with(|service| {
+        service.borrow_mut().create_job(request)
+    })
+}
+
+#[query]
+pub fn get_export_job(job_id: String) -> Result<ExportJob, String> {
+    ensure_job_access(&job_id)
+}
+
+/// 按序号依次获取导出分片，分片在获取时生成
+#[update(guard = "general_guard")]
+pub fn get_export_chunk(job_id: String, index: u32) -> Result<ExportChunk, String> {
+    ensure_job_access(&job_id)?;
+    EXPORT_SERVICE.with(|service| {
+        service.borrow_mut().get_chunk(&job_id, index)
+    })
+}
+
+#[query]
+pub fn list_export_jobs(institution_id: Principal) -> Result<Vec<ExportJob>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(EXPORT_SERVICE.with(|service| {
+        service.borrow().list_jobs(institution_id)
+    }))
+}
+
+#[update(guard = "general_guard")]
+pub fn delete_export_job(job_id: String) -> Result<(), String> {
+    ensure_job_access(&job_id)?;
+    EXPORT_SERVICE.with(|service| {
+        service.borrow_mut().delete_job(&job_id)
+    })
+}
+
+candid::export_service!();
@@ -0,0 +1,172 @@
+use candid::Principal;
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::{check_authenticated_institution, general_guard, http_guard};
+use crate::models::rate_limit::RateLimitCategory;
+use log::{info, debug, warn};
+
+use crate::models::http::*;
+use crate::models::record::RecordSubmissionRequest;
+use crate::api::admin_institution_api::get_balance;
+use crate::api::credit_assessment_api::get_risk_assessment_for;
+use crate::api::record_api::{query_records_by_user_did_for, submit_record_for};
+use crate::services::api_key_service::API_KEY_SERVICE;
+use crate::services::did_service::{did_document_json, DID_SERVICE};
+use crate::services::http_gateway::{self, api_key, error_response, json_response, path_segments};
+use crate::utils::auth::ensure_institution_caller;
+use crate::utils::error::Error;
+
+// REST 网关：公开且确定的响应在查询调用中直接返回（带认证的走 IC-Certificate），
+// 需要 API Key 的请求一律升级为更新调用，以便扣减配额、计费并让响应经过共识
+
+#[query]
+pub fn http_request(request: HttpRequest) -> HttpResponse {
+    let segments = path_segments(&request.url);
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["openapi.json"]) => http_gateway::certified_response(http_gateway::OPENAPI_PATH)
+            .unwrap_or_else(|| error_response(404, "Not found")),
+        ("GET", ["v1", "dids", did]) => resolve_did_document(did),
+        _ => http_gateway::upgrade_response(),
+    }
+}
+
+// 网关调用者均为匿名主体，先计入共享的匿名桶；认证后按 API Key 所属机构限流
+#[update(guard = "http_guard")]
+pub async fn http_request_update(request: HttpRequest) -> HttpResponse {
+    let segments = path_segments(&request.url);
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    debug!("HTTP {} {}", request.method, request.url);
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["openapi.json"]) => json_response(200, &http_gateway::openapi_document()),
+        ("GET", ["v1", "dids", did]) => resolve_did_document(did),
+        ("POST", ["v1", "records"]) => match authenticate(&request, RateLimitCategory::Submission) {
+            Ok(institution_id) => handle_submit_record(institution_id, &request.body).await,
+            Err(response) => response,
+        },
+        ("GET", ["v1", "users", did, "records"]) => match authenticate(&request, RateLimitCategory::Query) {
+            Ok(institution_id) => match query_records_by_user_did_for(institution_id, did.to_string()).await {
+                Ok(records) => json_response(200, &records),
+                Err(e) => service_error(&e),
+            },
+            Err(response) => response,
+        },
+        ("POST", ["v1", "users", did, "assessment"]) => match authenticate(&request, RateLimitCategory::Assessment) {
+            Ok(institution_id) => match get_risk_assessment_for(institution_id, did.to_string()) {
+                Ok(assessment) => json_response(200, &assessment),
+                Err(e) => service_error(&e),
+            },
+            Err(response) => response,
+        },
+        ("GET", ["v1", "balance"]) => match authenticate(&request, RateLimitCategory::General) {
+            Ok(institution_id) => handle_balance(institution_id).await,
+            Err(response) => response,
+        },
+        (_, ["openapi.json"] | ["v1", ..]) => error_response(405, "Method not allowed"),
+        _ => error_response(404, "Not found"),
+    }
+}
+
+// 校验 API Key，成功时记录使用时间并按所属机构限流（所有路由计入 General，再计入路由自身的类别）
+fn authenticate(request: &HttpRequest, category: RateLimitCategory) -> Result<Principal, HttpResponse> {
+    let key = api_key(request)
+        .ok_or_else(|| error_response(401, "缺少 API Key"))?;
+
+    let institution_id = API_KEY_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        let (key_id, institution_id) = service.authenticate(key)
+            .map_err(|e| error_response(401, &e))?;
+        service.touch(&key_id);
+        Ok(institution_id)
+    })?;
+
+    check_authenticated_institution(RateLimitCategory::General, institution_id)
+        .map_err(|e| error_response(429, &e))?;
+    if category != RateLimitCategory::General {
+        check_authenticated_institution(category, institution_id)
+            .map_err(|e| error_response(429, &e))?;
+    }
+    Ok(institution_id)
+}
+
+// 业务错误中限流与配额不足返回 429，其余返回 400
+fn service_error(message: &str) -> HttpResponse {
+    if message.contains(&Error::RateLimitExceeded.to_string()) {
+        error_response(429, message)
+    } else {
+        error_response(400, message)
+    }
+}
+
+fn resolve_did_document(did: &str) -> HttpResponse {
+    // 网关调用者为匿名主体，不展示关联 DID
+    match DID_SERVICE.with(|service| service.borrow().resolve(did, false)) {
+        Ok(document) => HttpResponse {
+            status_code: 200,
+            headers: vec![("Content-Type".to_string(), "application/did+json".to_string())],
+            body: did_document_json(&document).into_bytes(),
+            upgrade: None,
+        },
+        Err(e) => error_response(404, &e),
+    }
+}
+
+async fn handle_submit_record(institution_id: Principal, body: &[u8]) -> HttpResponse {
+    let submission: HttpRecordSubmission = match serde_json::from_slice(body) {
+        Ok(submission) => submission,
+        Err(e) => return error_response(400, &format!("请求体无效: {}", e)),
+    };
+
+    let request = RecordSubmissionRequest {
+        institution_id,
+        record_type: submission.record_type,
+        user_did: submission.user_did,
+        event_date: submission.event_date,
+        content: submission.content,
+    };
+    match submit_record_for(request) {
+        Ok(response) => json_response(201, &response),
+        Err(e) => service_error(&e),
+    }
+}
+
+async fn handle_balance(institution_id: Principal) -> HttpResponse {
+    match get_balance(institution_id).await {
+        Ok(balance) => json_response(200, &balance),
+        Err(e) => service_error(&e),
+    }
+}
+
+/// 为机构创建 REST 接口的 API Key，明文只返回这一次
+#[update(guard = "general_guard")]
+pub async fn create_api_key(institution_id: Principal) -> Result<ApiKeyCreated, String> {
+    ensure_institution_caller(institution_id)?;
+
+    let (random,) = raw_rand().await
+        .map_err(|(code, msg)| format!("生成 API Key 失败: {:?} {}", code, msg))?;
+    info!("Creating API key for {}", institution_id.to_text());
+    Ok(API_KEY_SERVICE.with(|service| {
+        service.borrow_mut().create(institution_id, &random)
+    }))
+}
+
+#[query]
+pub fn list_api_keys(institution_id: Principal) -> Result<Vec<ApiKeyInfo>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(API_KEY_SERVICE.with(|service| {
+        service.borrow().list(institution_id)
+    }))
+}
+
+#[update(guard = "general_guard")]
+pub fn revoke_api_key(institution_id: Principal, key_id: String) -> Result<(), String> {
+    ensure_institution_caller(institution_id)?;
+    warn!("Revoking API key {} of {}", key_id, institution_id.to_text());
+    API_KEY_SERVICE.with(|service| {
+        service.borrow_mut().revoke(institution_id, &key_id)
+    })
+}
+
+candid::export_service!();
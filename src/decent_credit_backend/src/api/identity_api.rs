@@ -0,0 +1,122 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::{info, debug};
+
+use crate::models::identity::*;
+use crate::services::identity_service::{ensure_identity_salt, IDENTITY_SERVICE};
+use crate::utils::auth::{caller_institution_ids, ensure_controller, ensure_institution_caller};
+
+/// 由身份哈希获取平台统一 DID，新提交的记录应使用该 DID
+#[update(guard = "general_guard")]
+pub async fn issue_canonical_did(institution_id: Principal, identity_hash: String) -> Result<String, String> {
+    ensure_institution_caller(institution_id)?;
+    ensure_identity_salt().await?;
+
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow().canonical_did(&identity_hash)
+    })
+}
+
+/// 借款人本人认领平台身份，之后机构发起的关联请求都需经其同意
+#[update(guard = "general_guard")]
+pub async fn claim_platform_identity(identity_hash: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("匿名调用者不能认领身份".to_string());
+    }
+    // 机构主体不能替借款人认领
+    if !caller_institution_ids(caller).is_empty() {
+        return Err("机构主体不能认领借款人身份".to_string());
+    }
+    ensure_identity_salt().await?;
+
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow_mut().claim_identity(&identity_hash, caller)
+    })
+}
+
+/// 管理员解除有争议的身份认领，同时撤销该身份下的全部关联
+#[update(guard = "general_guard")]
+pub fn release_identity_claim(canonical_did: String) -> Result<Vec<String>, String> {
+    ensure_controller()?;
+    info!("Identity claim on {} released by {}", canonical_did, ic_cdk::caller().to_text());
+
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow_mut().release_claim(&canonical_did)
+    })
+}
+
+/// 机构发起迁移：把已有机构 DID 关联到平台 DID，需借款人确认后生效
+#[update(guard = "general_guard")]
+pub async fn request_did_link(request: DidLinkRequestInput) -> Result<DidLinkRequest, String> {
+    ensure_institution_caller(request.institution_id)?;
+    ensure_identity_salt().await?;
+    info!("DID link requested for {} by {}", request.institution_did, request.institution_id.to_text());
+
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow_mut().request_link(request)
+    })
+}
+
+/// 借款人同意关联
+#[update(guard = "general_guard")]
+pub fn approve_did_link(request_id: String) -> Result<DidLinkRequest, String> {
+    let caller = ic_cdk::caller();
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow_mut().approve_link(&request_id, caller)
+    })
+}
+
+/// 借款人拒绝关联
+#[update(guard = "general_guard")]
+pub fn reject_did_link(request_id: String) -> Result<DidLinkRequest, String> {
+    let caller = ic_cdk::caller();
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow_mut().reject_link(&request_id, caller)
+    })
+}
+
+/// 借款人撤回某个机构 DID 的关联
+#[update(guard = "general_guard")]
+pub fn revoke_did_link(institution_did: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow_mut().revoke_link(&institution_did, caller)
+    })
+}
+
+/// 当前调用者（借款人）收到的关联请求
+#[query]
+pub fn get_my_did_link_requests() -> Vec<DidLinkRequest> {
+    let caller = ic_cdk::caller();
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow().requests_for_borrower(caller)
+    })
+}
+
+/// 机构发起的关联请求
+#[query]
+pub fn get_institution_did_link_requests(institution_id: Principal) -> Result<Vec<DidLinkRequest>, String> {
+    ensure_institution_caller(institution_id)?;
+    IDENTITY_SERVICE.with(|service| {
+        Ok(service.borrow().requests_for_institution(institution_id))
+    })
+}
+
+/// 查询 DID 所属的平台身份及已关联的机构 DID，仅限认领该身份的借款人或管理员
+#[query]
+pub fn get_linked_identity(user_did: String) -> Result<Option<LinkedIdentity>, String> {
+    debug!("Fetching linked identity for {}", user_did);
+    let identity = IDENTITY_SERVICE.with(|service| {
+        service.borrow().get_linked_identity(&user_did)
+    });
+
+    let caller = ic_cdk::caller();
+    if identity.as_ref().and_then(|i| i.controller) != Some(caller) {
+        ensure_controller()?;
+    }
+    Ok(identity)
+}
+
+candid::export_service!();
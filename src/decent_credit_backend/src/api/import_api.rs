@@ -0,0 +1,92 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::{check_institution, general_guard, submission_guard};
+use crate::models::rate_limit::RateLimitCategory;
+use log::info;
+
+use crate::models::import::*;
+use crate::services::import_service::{self, IMPORT_SERVICE};
+use crate::utils::auth::ensure_institution_caller;
+
+// 每页最多返回的行错误数
+const MAX_ERROR_PAGE: usize = 1000;
+
+fn ensure_job_access(job_id: &str) -> Result<ImportJob, String> {
+    let job = IMPORT_SERVICE.with(|service| service.borrow().get_job(job_id))
+        .ok_or_else(|| format!("导入任务不存在: {}", job_id))?;
+    ensure_institution_caller(job.institution_id)?;
+    Ok(job)
+}
+
+/// 创建批量导入任务
+#[update(guard = "submission_guard")]
+pub fn create_import_job(request: CreateImportJobRequest) -> Result<ImportJob, String> {
+    ensure_institution_caller(request.institution_id)?;
+    check_institution(RateLimitCategory::Submission, request.institution_id)?;
+
+    IMPORT_SERVICE.with(|service| {
+        service.borrow_mut().create_job(request)
+    })
+}
+
+/// 上传一个 CSV/NDJSON 分片，分片可在任意字节处切分
+#[update(guard = "submission_guard")]
+pub fn upload_import_chunk(job_id: String, chunk_index: u32, data: Vec<u8>) -> Result<ImportJob, String> {
+    let job = ensure_job_access(&job_id)?;
+    check_institution(RateLimitCategory::Submission, job.institution_id)?;
+
+    IMPORT_SERVICE.with(|service| {
+        service.borrow_mut().upload_chunk(&job_id, chunk_index, data)
+    })
+}
+
+/// 结束上传，之后由定时任务按批次校验导入
+#[update(guard = "submission_guard")]
+pub fn finalize_import_job(job_id: String) -> Result<ImportJob, String> {
+    ensure_job_access(&job_id)?;
+    info!("Finalizing import job {}", job_id);
+
+    IMPORT_SERVICE.with(|service| {
+        service.borrow_mut().finalize(&job_id)
+    })
+}
+
+/// 立即处理下一批数据，无需等待定时任务
+#[update(guard = "submission_guard")]
+pub fn process_import_job(job_id: String) -> Result<ImportJob, String> {
+    ensure_job_access(&job_id)?;
+    import_service::process_import_batch(&job_id)
+}
+
+#[update(guard = "general_guard")]
+pub fn cancel_import_job(job_id: String) -> Result<ImportJob, String> {
+    ensure_job_access(&job_id)?;
+    IMPORT_SERVICE.with(|service| {
+        service.borrow_mut().cancel(&job_id)
+    })
+}
+
+/// 查询导入任务进度
+#[query]
+pub fn get_import_job(job_id: String) -> Result<ImportJob, String> {
+    ensure_job_access(&job_id)
+}
+
+#[query]
+pub fn list_import_jobs(institution_id: Principal) -> Result<Vec<ImportJob>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(IMPORT_SERVICE.with(|service| {
+        service.borrow().list_jobs(institution_id)
+    }))
+}
+
+/// 分页查询导入任务的行错误
+#[query]
+pub fn get_import_job_errors(job_id: String, offset: u64, limit: u64) -> Result<ImportErrorPage, String> {
+    ensure_job_access(&job_id)?;
+    IMPORT_SERVICE.with(|service| {
+        service.borrow().get_errors(&job_id, offset as usize, (limit as usize).min(MAX_ERROR_PAGE))
+    })
+}
+
+candid::export_service!();
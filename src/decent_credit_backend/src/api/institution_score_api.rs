@@ -0,0 +1,89 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::info;
+
+use crate::models::dashboard::ScoreTrend;
+use crate::models::institution_score::*;
+use crate::services::institution_score_service::{self, INSTITUTION_SCORE_SERVICE};
+use crate::services::record_service::RECORD_SERVICE;
+use crate::utils::auth::{ensure_controller, ensure_institution_caller};
+
+/// 机构对扣分记录提出申诉
+#[update(guard = "general_guard")]
+pub fn appeal_deduction(deduction_id: String, reason: String) -> Result<DeductionAppeal, String> {
+    let deduction = RECORD_SERVICE.with(|service| service.borrow().get_deduction_record(&deduction_id))
+        .ok_or_else(|| format!("扣分记录不存在: {}", deduction_id))?;
+    ensure_institution_caller(deduction.institution_id)?;
+
+    INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow_mut().submit_appeal(&deduction, reason)
+    })
+}
+
+#[query]
+pub fn get_institution_appeals(institution_id: Principal) -> Result<Vec<DeductionAppeal>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow().list_appeals(Some(institution_id), None)
+    }))
+}
+
+/// 管理员查看申诉，可按状态过滤
+#[query]
+pub fn list_deduction_appeals(status: Option<AppealStatus>) -> Result<Vec<DeductionAppeal>, String> {
+    ensure_controller()?;
+    Ok(INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow().list_appeals(None, status)
+    }))
+}
+
+/// 审核申诉，通过时撤销扣分并恢复分数
+#[update(guard = "general_guard")]
+pub fn review_deduction_appeal(request: ReviewAppealRequest) -> Result<DeductionAppeal, String> {
+    ensure_controller()?;
+    institution_score_service::review_appeal(request, ic_cdk::caller())
+}
+
+/// 机构信用分变更历史
+#[query]
+pub fn get_institution_score_history(institution_id: Principal, since: Option<u64>) -> Result<Vec<InstitutionScoreChange>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow().get_history(institution_id, since)
+    }))
+}
+
+#[query]
+pub fn get_institution_score_trend(institution_id: Principal, days: u64) -> Result<Vec<ScoreTrend>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow().get_trend(institution_id, days)
+    }))
+}
+
+#[query]
+pub fn get_recovery_policy() -> RecoveryPolicy {
+    INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow().get_policy()
+    })
+}
+
+#[update(guard = "general_guard")]
+pub fn update_recovery_policy(policy: RecoveryPolicy) -> Result<(), String> {
+    ensure_controller()?;
+    INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow_mut().update_policy(policy)
+    })
+}
+
+/// 立即按恢复策略检查所有机构
+#[update(guard = "general_guard")]
+pub fn run_score_recovery() -> Result<Vec<InstitutionScoreChange>, String> {
+    ensure_controller()?;
+    let changes = institution_score_service::apply_time_based_recovery();
+    info!("Manual score recovery restored {} institutions", changes.len());
+    Ok(changes)
+}
+
+candid::export_service!();
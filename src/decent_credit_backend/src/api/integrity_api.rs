@@ -0,0 +1,67 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::info;
+
+use crate::models::integrity::*;
+use crate::models::record::RejectionReason;
+use crate::services::integrity_service::INTEGRITY_SERVICE;
+use crate::services::record_service::RECORD_SERVICE;
+use crate::utils::auth::{ensure_controller, ensure_institution_caller};
+
+/// 管理员查看重复/冲突记录审核队列，可按状态过滤
+#[query]
+pub fn list_review_cases(status: Option<ReviewStatus>) -> Result<Vec<ReviewCase>, String> {
+    ensure_controller()?;
+    Ok(INTEGRITY_SERVICE.with(|service| {
+        service.borrow().list_cases(status)
+    }))
+}
+
+/// 机构查看自己被标记的记录
+#[query]
+pub fn list_institution_review_cases(institution_id: Principal) -> Result<Vec<ReviewCase>, String> {
+    ensure_institution_caller(institution_id)?;
+    Ok(INTEGRITY_SERVICE.with(|service| {
+        service.borrow().cases_for_institution(institution_id)
+    }))
+}
+
+#[query]
+pub fn get_review_case(case_id: String) -> Result<ReviewCase, String> {
+    let case = INTEGRITY_SERVICE.with(|service| service.borrow().get_case(&case_id))
+        .ok_or_else(|| format!("审核案件不存在: {}", case_id))?;
+    ensure_institution_caller(case.institution_id)?;
+    Ok(case)
+}
+
+/// 处理审核案件：问题成立时驳回记录并计入机构违规次数；
+/// 问题不成立且该记录没有其他未处理案件时，记录纳入评分
+#[update(guard = "general_guard")]
+pub fn resolve_review_case(request: ResolveReviewRequest) -> Result<ReviewCase, String> {
+    ensure_controller()?;
+    let operator = ic_cdk::caller();
+
+    let case = INTEGRITY_SERVICE.with(|service| {
+        service.borrow_mut().resolve(request, operator)
+    })?;
+    if case.status == ReviewStatus::Upheld {
+        let reason = RejectionReason {
+            code: format!("{:?}", case.issue),
+            field: None,
+            message: case.resolution_note.clone().unwrap_or_else(|| case.details.clone()),
+            rejected_at: ic_cdk::api::time(),
+        };
+        RECORD_SERVICE.with(|service| {
+            service.borrow_mut().reject_record(&case.record_id, reason)
+        }).map_err(|e| format!("驳回记录失败: {:?}", e))?;
+    } else if !INTEGRITY_SERVICE.with(|service| service.borrow().has_open_cases(&case.record_id)) {
+        RECORD_SERVICE.with(|service| {
+            service.borrow_mut().admit_record(&case.record_id)
+        }).map_err(|e| format!("记录纳入评分失败: {:?}", e))?;
+    }
+    info!("Review case {} resolved: {:?}", case.id, case.status);
+    Ok(case)
+}
+
+candid::export_service!();
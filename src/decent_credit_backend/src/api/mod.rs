@@ -2,3 +2,32 @@ pub mod admin_institution_api;
 pub mod record_api;
 pub mod credit_assessment_api;
 pub mod dashboard_api;
+pub mod billing_api;
+
+pub mod reconciliation_api;
+
+pub mod pricing_api;
+
+pub mod quota_api;
+
+pub mod rate_limit_api;
+
+pub mod scoring_api;
+
+pub mod identity_api;
+
+pub mod did_api;
+
+pub mod http_api;
+
+pub mod import_api;
+
+pub mod export_api;
+
+pub mod validation_api;
+
+pub mod integrity_api;
+
+pub mod data_quality_api;
+
+pub mod institution_score_api;
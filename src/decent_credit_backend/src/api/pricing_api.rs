@@ -0,0 +1,84 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::{info, debug};
+
+use crate::models::pricing::*;
+use crate::services::admin_institution_service::ADMIN_SERVICE;
+use crate::services::pricing_service::PRICING_SERVICE;
+use crate::utils::auth::ensure_controller;
+
+#[query]
+pub fn get_revenue_split_config() -> RevenueSplitConfig {
+    PRICING_SERVICE.with(|service| {
+        service.borrow().get_split_config()
+    })
+}
+
+/// 更新平台金库、机构分成上下限和借款人分成比例
+#[update(guard = "general_guard")]
+pub fn update_revenue_split_config(config: RevenueSplitConfig) -> Result<(), String> {
+    ensure_controller()?;
+    info!("Revenue split config update by {}", ic_cdk::caller().to_text());
+
+    PRICING_SERVICE.with(|service| {
+        service.borrow_mut().update_split_config(config)
+    })
+}
+
+/// 绑定或解绑借款人的收款账户，绑定后借款人可获得查询费分成
+#[update(guard = "general_guard")]
+pub fn set_borrower_payout_account(user_did: String, account: Option<Principal>) -> Result<(), String> {
+    ensure_controller()?;
+
+    PRICING_SERVICE.with(|service| {
+        service.borrow_mut().set_borrower_account(user_did, account)
+    });
+    Ok(())
+}
+
+/// 预览某机构数据被查询时一笔查询费的分成明细
+#[query]
+pub fn preview_revenue_split(institution_id: Principal, user_did: String, amount: u64) -> Result<Vec<RevenueSplitLine>, String> {
+    debug!("Previewing revenue split of {} for {}", amount, institution_id.to_text());
+
+    let owner_ratio = ADMIN_SERVICE.with(|service| {
+        service.borrow().get_institution(institution_id).map(|inst| inst.reward_share_ratio)
+    }).ok_or("机构不存在")?;
+
+    Ok(PRICING_SERVICE.with(|service| {
+        service.borrow().split_fee(amount, institution_id, owner_ratio, &user_did)
+    }))
+}
+
+/// 查询机构当前生效的定价表（未设置时按 query_price 统一计价）
+#[query]
+pub fn get_pricing_schedule(institution_id: Principal) -> Result<PricingSchedule, String> {
+    let query_price = ADMIN_SERVICE.with(|service| {
+        service.borrow().get_institution(institution_id).map(|inst| inst.query_price)
+    }).ok_or("机构不存在")?;
+
+    Ok(PRICING_SERVICE.with(|service| {
+        service.borrow().get_schedule(institution_id, query_price)
+    }))
+}
+
+/// 设置机构所属集团，同一集团内的机构互查免费
+#[update(guard = "general_guard")]
+pub fn set_institution_group(institution_id: Principal, group: Option<String>) -> Result<(), String> {
+    ensure_controller()?;
+
+    let exists = ADMIN_SERVICE.with(|service| {
+        service.borrow().get_institution(institution_id).is_some()
+    });
+    if !exists {
+        return Err("机构不存在".to_string());
+    }
+
+    PRICING_SERVICE.with(|service| {
+        service.borrow_mut().set_institution_group(institution_id, group)
+    });
+    Ok(())
+}
+
+candid::export_service!();
@@ -0,0 +1,47 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::{info, debug};
+
+use crate::models::quota::*;
+use crate::services::quota_service::{self, QUOTA_SERVICE};
+use crate::utils::auth::{ensure_controller, ensure_institution_caller};
+
+/// 获取所有订阅套餐
+#[query]
+pub fn get_subscription_plans() -> Vec<SubscriptionPlan> {
+    QUOTA_SERVICE.with(|service| {
+        service.borrow().get_plans()
+    })
+}
+
+/// 获取机构当前套餐及本周期配额使用情况
+#[query]
+pub fn get_quota_usage(institution_id: Principal) -> QuotaUsage {
+    debug!("Fetching quota usage for {}", institution_id.to_text());
+
+    QUOTA_SERVICE.with(|service| {
+        service.borrow().get_usage(institution_id)
+    })
+}
+
+/// 新增或修改套餐
+#[update(guard = "general_guard")]
+pub fn upsert_subscription_plan(plan: SubscriptionPlan) -> Result<(), String> {
+    ensure_controller()?;
+
+    QUOTA_SERVICE.with(|service| {
+        service.borrow_mut().upsert_plan(plan)
+    })
+}
+
+/// 机构变更套餐，按套餐价格支付 DCC
+#[update(guard = "general_guard")]
+pub async fn change_subscription_plan(request: ChangePlanRequest) -> Result<QuotaUsage, String> {
+    ensure_institution_caller(request.institution_id)?;
+    info!("Institution {} changing plan to {}", request.institution_id.to_text(), request.plan_id);
+
+    quota_service::change_plan(request.institution_id, request.plan_id).await
+}
+
+candid::export_service!();
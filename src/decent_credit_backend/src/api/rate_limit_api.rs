@@ -0,0 +1,27 @@
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::info;
+
+use crate::models::rate_limit::*;
+use crate::services::rate_limit_service::RATE_LIMIT_SERVICE;
+use crate::utils::auth::ensure_controller;
+
+#[query]
+pub fn get_rate_limit_config() -> RateLimitConfig {
+    RATE_LIMIT_SERVICE.with(|service| {
+        service.borrow().get_config()
+    })
+}
+
+/// 运行时调整各类接口的调用者/机构限流参数
+#[update(guard = "general_guard")]
+pub fn update_rate_limit_config(config: RateLimitConfig) -> Result<(), String> {
+    ensure_controller()?;
+    info!("Rate limit config update by {}", ic_cdk::caller().to_text());
+
+    RATE_LIMIT_SERVICE.with(|service| {
+        service.borrow_mut().update_config(config)
+    })
+}
+
+candid::export_service!();
@@ -0,0 +1,70 @@
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::{info, debug};
+
+use crate::models::reconciliation::*;
+use crate::services::reconciliation_service::{self, RECONCILIATION_SERVICE};
+use crate::utils::auth::ensure_controller;
+
+/// 最近一次对账报告，包含各机构本地余额与链上余额的差异
+#[query]
+pub fn get_balance_discrepancies() -> Result<Option<ReconciliationReport>, String> {
+    ensure_controller()?;
+
+    Ok(RECONCILIATION_SERVICE.with(|service| {
+        service.borrow().latest_report()
+    }))
+}
+
+/// 历史对账报告（按时间倒序）
+#[query]
+pub fn get_reconciliation_reports(limit: u32) -> Result<Vec<ReconciliationReport>, String> {
+    ensure_controller()?;
+    debug!("Fetching {} reconciliation reports", limit);
+
+    Ok(RECONCILIATION_SERVICE.with(|service| {
+        service.borrow().get_reports(limit as usize)
+    }))
+}
+
+/// 立即执行一次对账
+#[update(guard = "general_guard")]
+pub async fn run_balance_reconciliation() -> Result<ReconciliationReport, String> {
+    ensure_controller()?;
+    info!("Manual reconciliation triggered by {}", ic_cdk::caller().to_text());
+
+    reconciliation_service::run_reconciliation().await
+}
+
+#[query]
+pub fn get_reconciliation_config() -> Result<ReconciliationConfig, String> {
+    ensure_controller()?;
+
+    Ok(RECONCILIATION_SERVICE.with(|service| {
+        service.borrow().get_config()
+    }))
+}
+
+/// 本地余额不足以记录消费、等待对账核实的机构
+#[query]
+pub fn get_flagged_balances() -> Result<Vec<FlaggedBalance>, String> {
+    ensure_controller()?;
+
+    Ok(RECONCILIATION_SERVICE.with(|service| {
+        service.borrow().get_flagged()
+    }))
+}
+
+/// 更新对账配置（是否启用、自动修正、间隔），并按新配置重启定时器
+#[update(guard = "general_guard")]
+pub fn update_reconciliation_config(config: ReconciliationConfig) -> Result<(), String> {
+    ensure_controller()?;
+
+    RECONCILIATION_SERVICE.with(|service| {
+        service.borrow_mut().update_config(config)
+    })?;
+    reconciliation_service::init_reconciliation_timer();
+    Ok(())
+}
+
+candid::export_service!();
@@ -1,5 +1,7 @@
 use candid::{CandidType, Deserialize, Principal};
 use ic_cdk_macros::*;
+use crate::services::rate_limit_service::{check_institution, query_guard, submission_guard};
+use crate::models::rate_limit::RateLimitCategory;
 use crate::models::*;
 use log::{info, debug, warn, error};  // 替换原来的 log_info
 use crate::services::record_service::*;
@@ -8,10 +10,15 @@ use crate::services::credit_service::CREDIT_SERVICE;
 use crate::services::reports_storage::REPORTS_STORAGE;  // 移到顶部
 use crate::services::token_service::TOKEN_SERVICE;  // 移到顶部
 use crate::services::admin_institution_service::ADMIN_SERVICE;  // 移到顶部
+use crate::services::billing_service;
+use crate::models::billing::QueryCharge;
+use crate::services::quota_service::QUOTA_SERVICE;
+use crate::utils::auth::ensure_institution_caller;
+use std::collections::HashMap;
 
 
 
-#[update]
+#[update(guard = "submission_guard")]
 pub async fn submit_record(request: RecordSubmissionRequest) -> Result<RecordSubmissionResponse, String> {
     let caller = ic_cdk::caller();
     info!("Submit record by caller: {}", caller.to_text());
@@ -20,7 +27,12 @@ pub async fn submit_record(request: RecordSubmissionRequest) -> Result<RecordSub
         request.event_date,
         request.record_type
     );
+    check_institution(RateLimitCategory::Submission, request.institution_id)?;
+    submit_record_for(request)
+}
 
+/// 已限流后的记录提交（HTTP 网关在 API Key 认证并限流后调用）
+pub(crate) fn submit_record_for(request: RecordSubmissionRequest) -> Result<RecordSubmissionResponse, String> {
     RECORD_SERVICE.with(|service| {
         let mut service = service.borrow_mut();
         match service.submit_record(request) {
@@ -41,7 +53,7 @@ pub async fn submit_record(request: RecordSubmissionRequest) -> Result<RecordSub
     })
 }
 /// 批量提交记录
-#[update]
+#[update(guard = "submission_guard")]
 pub async fn submit_records_batch(request: BatchSubmissionRequest) -> Result<BatchSubmissionResponse, String> {
     let caller = ic_cdk::caller();
 
@@ -56,6 +68,18 @@ pub async fn submit_records_batch(request: BatchSubmissionRequest) -> Result<Bat
         return Err("单次提交不能超过1000条记录".to_string());
     }
 
+    // 按提交机构的套餐校验批量大小
+    let mut batch_sizes: HashMap<Principal, usize> = HashMap::new();
+    for record in &request.records {
+        *batch_sizes.entry(record.institution_id).or_insert(0) += 1;
+    }
+    for (institution_id, size) in batch_sizes {
+        check_institution(RateLimitCategory::Submission, institution_id)?;
+        QUOTA_SERVICE.with(|service| {
+            service.borrow().check_batch_size(institution_id, size)
+        }).map_err(|e| e.to_string())?;
+    }
+
     let mut submitted = 0;
     let mut failed = 0;
     let mut record_ids = Vec::new();
@@ -85,34 +109,151 @@ pub async fn submit_records_batch(request: BatchSubmissionRequest) -> Result<Bat
     })
 }
 
-#[update]
-pub fn query_record_by_id(record_id: String, institution_id: Principal) -> Result<CreditRecord, String> {
-    debug!("Querying record by id: {}", record_id);
+// 取失败记录所属机构并校验调用者
+fn failed_record_institution(record_id: &str) -> Result<Principal, String> {
+    let institution_id = RECORD_SERVICE.with(|service| {
+        service.borrow().get_record_institution(record_id)
+    }).ok_or_else(|| format!("记录不存在: {}", record_id))?;
+    ensure_institution_caller(institution_id)?;
+    Ok(institution_id)
+}
+
+/// 修正失败记录后重新提交
+#[update(guard = "submission_guard")]
+pub async fn resubmit_failed_record(record_id: String, corrected_content: RecordContent) -> Result<RecordSubmissionResponse, String> {
+    let institution_id = failed_record_institution(&record_id)?;
+    check_institution(RateLimitCategory::Submission, institution_id)?;
 
     RECORD_SERVICE.with(|service| {
-        let service = service.borrow();
+        let mut service = service.borrow_mut();
+        match service.resubmit_failed_record(&record_id, corrected_content) {
+            Ok(new_record_id) => Ok(RecordSubmissionResponse {
+                record_id: new_record_id,
+                status: RecordStatus::Pending,
+                timestamp: ic_cdk::api::time(),
+                reward_amount: None
+            }),
+            Err(e) => {
+                warn!("Failed to resubmit record {}: {:?}", record_id, e);
+                Err(format!("重新提交失败: {:?}", e))
+            }
+        }
+    })
+}
 
-        // 调用逻辑层的 get_record_by_id 方法
-        match service.get_record_by_id(&record_id, institution_id) {
-            Some(record) => {
-                debug!("Record successfully retrieved: {:?}", record);
-                Ok(record)
-            },
-            None => {
-                error!("Failed to retrieve record: {}", record_id);
-                Err(format!("Record with ID {} not found or access denied.", record_id))
+/// 批量修正并重试失败记录，逐条返回结果
+#[update(guard = "submission_guard")]
+pub async fn resubmit_failed_records_batch(corrections: Vec<FailedRecordCorrection>) -> Result<BatchResubmissionResponse, String> {
+    if corrections.is_empty() {
+        return Err("没有需要重新提交的记录".to_string());
+    }
+    if corrections.len() > 1000 {
+        return Err("单次提交不能超过1000条记录".to_string());
+    }
+
+    let mut batch_sizes: HashMap<Principal, usize> = HashMap::new();
+    for correction in &corrections {
+        let institution_id = failed_record_institution(&correction.record_id)?;
+        *batch_sizes.entry(institution_id).or_insert(0) += 1;
+    }
+    for (institution_id, size) in batch_sizes {
+        check_institution(RateLimitCategory::Submission, institution_id)?;
+        QUOTA_SERVICE.with(|service| {
+            service.borrow().check_batch_size(institution_id, size)
+        }).map_err(|e| e.to_string())?;
+    }
+
+    let mut resubmitted = 0;
+    let mut failed = 0;
+    let mut results = Vec::new();
+
+    for correction in corrections {
+        let result = RECORD_SERVICE.with(|service| {
+            service.borrow_mut().resubmit_failed_record(&correction.record_id, correction.corrected_content)
+        });
+        match result {
+            Ok(new_record_id) => {
+                resubmitted += 1;
+                results.push(ResubmissionResult {
+                    original_record_id: correction.record_id,
+                    new_record_id: Some(new_record_id),
+                    error: None
+                });
+            }
+            Err(e) => {
+                failed += 1;
+                results.push(ResubmissionResult {
+                    original_record_id: correction.record_id,
+                    new_record_id: None,
+                    error: Some(e.to_string())
+                });
             }
         }
+    }
+    info!("Batch resubmission finished: {} resubmitted, {} failed", resubmitted, failed);
+
+    Ok(BatchResubmissionResponse {
+        resubmitted,
+        failed,
+        results,
+        timestamp: ic_cdk::api::time(),
     })
 }
-#[update]
-pub fn query_records_by_user_did(institution_id: Principal, user_did: String) -> Result<Vec<CreditRecord>, String> {
+
+#[update(guard = "query_guard")]
+pub async fn query_record_by_id(record_id: String, institution_id: Principal) -> Result<CreditRecord, String> {
+    ensure_institution_caller(institution_id)?;
+    check_institution(RateLimitCategory::Query, institution_id)?;
+    query_record_by_id_for(record_id, institution_id).await
+}
+
+/// 机构身份已校验且已限流（调用者或 API Key）后的按ID计费查询
+pub(crate) async fn query_record_by_id_for(record_id: String, institution_id: Principal) -> Result<CreditRecord, String> {
+    debug!("Querying record by id: {}", record_id);
+
+    // 先预留查询费，再解密返回记录，最后结算或退款
+    match billing_service::billed_record_by_id(record_id.clone(), institution_id).await {
+        Ok(record) => {
+            debug!("Record successfully retrieved: {:?}", record);
+            Ok(record)
+        },
+        Err(e) => {
+            error!("Failed to retrieve record {}: {}", record_id, e);
+            Err(e)
+        }
+    }
+}
+/// 查询前报价：按数据所有方汇总每条记录的价格明细，不产生扣费
+#[query]
+pub fn quote_records_by_user_did(institution_id: Principal, user_did: String) -> Result<Vec<QueryCharge>, String> {
+    debug!("Quoting records of {} for {}", user_did, institution_id.to_text());
+
     RECORD_SERVICE.with(|service| {
-        let mut service = service.borrow_mut();
-        service.get_record_userId(institution_id, user_did)
+        service.borrow().quote_user_records(institution_id, &user_did)
     })
 }
 
+#[update(guard = "query_guard")]
+pub async fn query_records_by_user_did(institution_id: Principal, user_did: String) -> Result<Vec<CreditRecord>, String> {
+    ensure_institution_caller(institution_id)?;
+    check_institution(RateLimitCategory::Query, institution_id)?;
+    query_records_by_user_did_for(institution_id, user_did).await
+}
+
+/// 机构身份已校验且已限流（调用者或 API Key）后的按用户计费查询
+pub(crate) async fn query_records_by_user_did_for(institution_id: Principal, user_did: String) -> Result<Vec<CreditRecord>, String> {
+    // 先占用一次查询配额，查询失败则归还
+    QUOTA_SERVICE.with(|service| {
+        service.borrow_mut().consume_query(institution_id)
+    }).map_err(|e| e.to_string())?;
+
+    let result = billing_service::billed_records_query(institution_id, user_did).await;
+    if result.is_err() {
+        QUOTA_SERVICE.with(|service| service.borrow_mut().release_query(institution_id));
+    }
+    result
+}
+
 /// 按参数查询记录
 #[query]
 pub fn query_records(params: RecordQueryParams) -> Vec<CreditRecord> {
@@ -147,7 +288,7 @@ pub fn get_record_statistics(institution_id: Option<Principal>) -> Result<Record
 
 
 /// 创建信用扣分记录
-#[update]
+#[update(guard = "submission_guard")]
 pub async fn create_credit_record(request: CreateCreditRecordRequest) -> Result<CreditDeductionRecord, String> {
     let caller = ic_cdk::caller();
     info!("Create credit deduction record by {}", caller.to_text());
@@ -206,9 +347,10 @@ pub fn get_credit_records(institution_id: Option<Principal>) -> Vec<CreditDeduct
 }
 
 /// 查询机构某个用户did的详细信用记录
-#[update]
+#[update(guard = "query_guard")]
 pub   fn query_institution_records_list(institution_id: Principal, user_did: String) -> Result<InstitutionRecordResponse, String> {
     debug!("Query institution records by {}", institution_id);
+    check_institution(RateLimitCategory::Query, institution_id)?;
 
     RECORD_SERVICE.with(|service| {
         let mut service = service.borrow_mut();  // 获取可变引用
@@ -225,10 +367,12 @@ pub   fn query_institution_records_list(institution_id: Principal, user_did: Str
     })
 }
 
-/// 查询机构某个用户did的详细信用记录
-#[query]
+/// 查询机构校验失败的记录
+#[update(guard = "query_guard")]
 pub   fn query_institution_records_failed_list(institution_id: Principal) -> Result<InstitutionRecordResponse, String> {
     debug!("Query institution records by {}", institution_id);
+    ensure_institution_caller(institution_id)?;
+    check_institution(RateLimitCategory::Query, institution_id)?;
 
     RECORD_SERVICE.with(|service| {
         let mut service = service.borrow_mut();  // 获取可变引用
@@ -0,0 +1,65 @@
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::info;
+
+use crate::models::scoring::*;
+use crate::models::backtest::{BacktestReport, BacktestRequest};
+use crate::services::credit_service::CREDIT_SERVICE;
+use crate::utils::auth::ensure_controller;
+
+/// 列出所有评分模型及其历史版本
+#[query]
+pub fn get_scoring_models() -> Vec<ScoringModel> {
+    CREDIT_SERVICE.with(|service| {
+        service.borrow().scoring_registry().list()
+    })
+}
+
+/// 获取指定模型版本，未指定版本时返回最新版本
+#[query]
+pub fn get_scoring_model(model_id: String, version: Option<u32>) -> Option<ScoringModel> {
+    CREDIT_SERVICE.with(|service| {
+        service.borrow().scoring_registry().get(&model_id, version)
+    })
+}
+
+#[query]
+pub fn get_active_scoring_model() -> ScoringModel {
+    CREDIT_SERVICE.with(|service| {
+        service.borrow().scoring_registry().active_model()
+    })
+}
+
+/// 上传评分模型参数，生成该模型的新版本
+#[update(guard = "general_guard")]
+pub fn register_scoring_model(upload: ScoringModelUpload) -> Result<ModelVersion, String> {
+    ensure_controller()?;
+    let caller = ic_cdk::caller();
+    info!("Scoring model {} uploaded by {}", upload.model_id, caller.to_text());
+
+    CREDIT_SERVICE.with(|service| {
+        service.borrow_mut().scoring_registry_mut().register(upload, caller)
+    })
+}
+
+/// 切换评估使用的模型版本
+#[update(guard = "general_guard")]
+pub fn activate_scoring_model(model_id: String, version: u32) -> Result<(), String> {
+    ensure_controller()?;
+
+    CREDIT_SERVICE.with(|service| {
+        service.borrow_mut().scoring_registry_mut().activate(&model_id, version)
+    })
+}
+
+/// 回测：按历史时间点重放记录，对比两个模型版本的评分
+#[query]
+pub fn run_scoring_backtest(request: BacktestRequest) -> Result<BacktestReport, String> {
+    ensure_controller()?;
+
+    CREDIT_SERVICE.with(|service| {
+        service.borrow().run_backtest(&request)
+    })
+}
+
+candid::export_service!();
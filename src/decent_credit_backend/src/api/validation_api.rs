@@ -0,0 +1,81 @@
+use candid::Principal;
+use ic_cdk_macros::*;
+use crate::services::rate_limit_service::general_guard;
+use log::info;
+
+use crate::models::validation::*;
+use crate::services::validation_service::{DEFAULT_JURISDICTION, VALIDATION_SERVICE};
+use crate::utils::auth::ensure_controller;
+
+/// 获取辖区当前生效的校验规则，未指定辖区时返回基础规则
+#[query]
+pub fn get_validation_rules(jurisdiction: Option<String>) -> Result<ValidationRuleSet, String> {
+    let jurisdiction = jurisdiction.unwrap_or_else(|| DEFAULT_JURISDICTION.to_string());
+    VALIDATION_SERVICE.with(|service| {
+        service.borrow().get_rule_set(&jurisdiction, None)
+    }).ok_or_else(|| format!("辖区不存在: {}", jurisdiction))
+}
+
+/// 获取辖区规则集的全部历史版本
+#[query]
+pub fn list_validation_rule_versions(jurisdiction: String) -> Vec<ValidationRuleSet> {
+    VALIDATION_SERVICE.with(|service| {
+        service.borrow().list_versions(&jurisdiction)
+    })
+}
+
+#[query]
+pub fn list_validation_jurisdictions() -> Vec<String> {
+    VALIDATION_SERVICE.with(|service| {
+        service.borrow().list_jurisdictions()
+    })
+}
+
+/// 修改辖区的校验规则，保存为新版本
+#[update(guard = "general_guard")]
+pub fn update_validation_rules(request: UpdateRuleSetRequest) -> Result<ValidationRuleSet, String> {
+    ensure_controller()?;
+    let operator = ic_cdk::caller();
+
+    VALIDATION_SERVICE.with(|service| {
+        service.borrow_mut().update_rule_set(request, operator)
+    })
+}
+
+/// 回滚到某个历史版本：以该版本的规则生成新版本
+#[update(guard = "general_guard")]
+pub fn rollback_validation_rules(jurisdiction: String, version: u32) -> Result<ValidationRuleSet, String> {
+    ensure_controller()?;
+    let operator = ic_cdk::caller();
+    info!("Rolling back validation rules of {} to v{}", jurisdiction, version);
+
+    VALIDATION_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        let previous = service.get_rule_set(&jurisdiction, Some(version))
+            .ok_or_else(|| format!("规则版本不存在: {} v{}", jurisdiction, version))?;
+        service.update_rule_set(UpdateRuleSetRequest {
+            jurisdiction,
+            rules: previous.rules,
+            note: Some(format!("回滚至 v{}", version)),
+        }, operator)
+    })
+}
+
+/// 设置机构所属辖区，决定其提交记录时适用的规则
+#[update(guard = "general_guard")]
+pub fn set_institution_jurisdiction(institution_id: Principal, jurisdiction: String) -> Result<(), String> {
+    ensure_controller()?;
+
+    VALIDATION_SERVICE.with(|service| {
+        service.borrow_mut().set_institution_jurisdiction(institution_id, jurisdiction)
+    })
+}
+
+#[query]
+pub fn get_institution_jurisdiction(institution_id: Principal) -> String {
+    VALIDATION_SERVICE.with(|service| {
+        service.borrow().institution_jurisdiction(institution_id)
+    })
+}
+
+candid::export_service!();
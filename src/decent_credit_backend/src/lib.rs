@@ -24,9 +24,36 @@ fn init() {
     services::record_service::init_record_service();
     info!("Record service initialized");
 
+    // 启动计费结算重试定时器
+    services::billing_service::init_billing_timer();
+
+    // 启动套餐续费定时器
+    services::quota_service::init_quota_timer();
+
+    // 启动余额对账定时器
+    services::reconciliation_service::init_reconciliation_timer();
+
+    // 生成 HTTP 网关的认证响应
+    services::http_gateway::init_certified_assets();
+
+    // 启动批量导入处理定时器
+    services::import_service::init_import_timer();
+
+    // 启动数据质量评估与信用分恢复定时器
+    services::data_quality_service::init_data_quality_timer();
+    services::institution_score_service::init_score_recovery_timer();
+
     info!("All services initialized successfully");
 }
 
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = services::stable_state::StableState::collect();
+    if let Err(e) = ic_cdk::storage::stable_save((state,)) {
+        ic_cdk::trap(&format!("Failed to save state before upgrade: {:?}", e));
+    }
+}
+
 #[post_upgrade]
 fn post_upgrade() {
     info!("Starting post upgrade initialization");
@@ -38,6 +65,19 @@ fn post_upgrade() {
     }
     services::record_service::init_record_service();
 
+    // 恢复计费托管、对账、分成、配额、限流与评分模型等状态，并重新启动定时器
+    match ic_cdk::storage::stable_restore::<(services::stable_state::StableState,)>() {
+        Ok((state,)) => state.restore(),
+        Err(e) => warn!("No state restored after upgrade: {}", e),
+    }
+    services::billing_service::init_billing_timer();
+    services::quota_service::init_quota_timer();
+    services::reconciliation_service::init_reconciliation_timer();
+    services::http_gateway::init_certified_assets();
+    services::import_service::init_import_timer();
+    services::data_quality_service::init_data_quality_timer();
+    services::institution_score_service::init_score_recovery_timer();
+
     info!("Post upgrade initialization completed");
 }
 
@@ -46,6 +86,21 @@ pub use api::credit_assessment_api::*;
 pub use api::dashboard_api::*;
 pub use api::record_api::*;
 pub use api::admin_institution_api::*;
+pub use api::billing_api::*;
+pub use api::reconciliation_api::*;
+pub use api::pricing_api::*;
+pub use api::quota_api::*;
+pub use api::rate_limit_api::*;
+pub use api::scoring_api::*;
+pub use api::identity_api::*;
+pub use api::did_api::*;
+pub use api::http_api::*;
+pub use api::import_api::*;
+pub use api::export_api::*;
+pub use api::validation_api::*;
+pub use api::integrity_api::*;
+pub use api::data_quality_api::*;
+pub use api::institution_score_api::*;
 
 // 公共类型定义
 pub type Result<T> = std::result::Result<T, String>;
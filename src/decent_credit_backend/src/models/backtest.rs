@@ -0,0 +1,46 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use crate::models::dashboard::LevelDistribution;
+use crate::models::scoring::ModelVersion;
+
+// === 评分模型回测相关结构 ===
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BacktestRequest {
+    pub as_of: u64,                     // 回放时间点（纳秒），仅使用此前提交的记录
+    pub outcome_until: Option<u64>,     // 观察后续逾期的截止时间，为空时观察到最新记录
+    pub baseline: ModelVersion,
+    pub candidate: ModelVersion,
+    pub user_dids: Option<Vec<String>>, // 为空时回测全部用户
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct BacktestScore {
+    pub user_did: String,
+    pub baseline_score: u32,
+    pub candidate_score: u32,
+    pub delta: i64,                     // 候选模型分数 - 基准模型分数
+    pub later_overdue: bool,            // 回放时间点之后是否出现逾期
+}
+
+#[derive(CandidType, Serialize)]
+pub struct ModelBacktestSummary {
+    pub model: ModelVersion,
+    pub average_score: f64,
+    pub level_distribution: LevelDistribution,
+    // 排序能力：随机抽取一个未逾期用户与一个后续逾期用户，前者分数更高的概率（AUC）
+    // 任一组为空时无法计算
+    pub rank_ordering: Option<f64>,
+}
+
+#[derive(CandidType, Serialize)]
+pub struct BacktestReport {
+    pub as_of: u64,
+    pub users_scored: u64,
+    pub users_with_later_overdue: u64,
+    pub average_delta: f64,
+    pub max_abs_delta: u64,
+    pub baseline: ModelBacktestSummary,
+    pub candidate: ModelBacktestSummary,
+    pub scores: Vec<BacktestScore>,
+}
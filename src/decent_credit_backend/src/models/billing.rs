@@ -0,0 +1,65 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use crate::models::pricing::{QuoteItem, RevenueSplitLine, SplitRole};
+
+// === 查询计费托管相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum EscrowStatus {
+    Created,            // 已创建，尚未预留
+    Reserved,           // 查询费已从查询方转入托管账户
+    Settled,            // 已结算给数据所有方
+    Refunded,           // 已退还查询方
+    SettlementPending,  // 结算失败，等待定时重试
+    RefundPending,      // 退款失败，等待定时重试
+    Failed,             // 预留失败，未产生资金变动
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryEscrow {
+    pub id: String,
+    pub payer: Principal,          // 查询方机构
+    pub payee: Principal,          // 数据所有方机构
+    pub amount: u64,
+    pub user_did: String,
+    pub record_ids: Vec<String>,
+    pub splits: Vec<RevenueSplitLine>,  // 结算时按分成逐项支付
+    pub status: EscrowStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum BillingStep {
+    Reserve,
+    Settle,
+    Refund,
+}
+
+// 计费流水，每一步（成功或失败）都会追加一条
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BillingLedgerEntry {
+    pub id: u64,
+    pub escrow_id: String,
+    pub step: BillingStep,
+    pub from: Principal,
+    pub to: Principal,
+    pub amount: u64,
+    pub split_role: Option<SplitRole>,  // 结算分成单项对应的角色
+    pub success: bool,
+    pub block_height: Option<u64>,
+    pub tx_hash: Option<String>,
+    pub message: Option<String>,
+    pub timestamp: u64,
+}
+
+// 查询报价：按数据所有方汇总的应付查询费
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryCharge {
+    pub payee: Principal,
+    pub amount: u64,
+    pub record_ids: Vec<String>,
+    pub items: Vec<QuoteItem>,
+}
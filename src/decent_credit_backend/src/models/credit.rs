@@ -1,14 +1,38 @@
 use candid::{CandidType, Deserialize, Principal};
 use serde::Serialize;
+use crate::models::record::{RecordContent, RecordType};
+use crate::models::scoring::ModelVersion;
 
 
 // === 风险评估相关结构 ===
-#[derive(CandidType, Deserialize, Clone)] // 
+#[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct RiskAssessment {
     pub credit_score: u32,
     pub risk_level: String,
     pub assessment_details: Vec<String>,
     pub suggestions: Vec<String>,
+    pub model_id: String,          // 生成该评估的评分模型
+    pub model_version: u32,
+    pub contributions: Vec<FeatureContribution>,   // 每个特征对分数的贡献
+    pub top_adverse_factors: Vec<String>,          // 失分最多的原因代码，按失分从高到低
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum ContributionDirection {
+    Positive,
+    Negative,
+    Neutral,
+}
+
+// 单个特征的评分贡献，原因代码供前端本地化和审计使用
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct FeatureContribution {
+    pub code: String,              // 原因代码，如 "OVERDUE_DAYS"
+    pub feature: String,           // 对应的特征字段
+    pub value: f64,                // 特征取值
+    pub contribution: f64,         // 对最终分数的贡献（未计入基础分）
+    pub max_contribution: f64,     // 该特征可获得的最高贡献
+    pub direction: ContributionDirection,
 }
 
 
@@ -34,3 +58,90 @@ pub struct AssessmentListResponse {
     pub message: Option<String>,
     pub data: Vec<RiskAssessmentReport>,
 }
+
+// === 评分历史相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScoreHistoryEntry {
+    pub timestamp: u64,
+    pub credit_score: u32,
+    pub risk_level: String,
+    pub model_id: String,
+    pub model_version: u32,
+    pub institution_id: Principal,     // 触发本次评估的机构
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct ScoreHistoryRange {
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    pub limit: Option<u32>,            // 只返回最近的若干条
+}
+
+// 评分下降超过阈值时产生的提醒
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScoreAlert {
+    pub id: u64,
+    pub user_did: String,
+    pub previous_score: u32,
+    pub current_score: u32,
+    pub drop_points: u32,
+    pub institution_id: Principal,
+    pub model_version: u32,
+    pub created_at: u64,
+    pub acknowledged: bool,
+}
+
+// === 评估报告查询相关结构 ===
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum ReportSort {
+    #[default]
+    CreatedAtDesc,
+    CreatedAtAsc,
+    ScoreDesc,
+    ScoreAsc,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ReportQuery {
+    pub institution_id: Principal,
+    pub user_did: Option<String>,
+    pub days: Option<u64>,             // 只返回最近若干天内的报告
+    pub sort: Option<ReportSort>,
+    pub offset: u64,
+    pub limit: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct AssessmentPageResponse {
+    pub total: u64,
+    pub offset: u64,
+    pub limit: u64,
+    pub data: Vec<RiskAssessmentReport>,
+}
+
+// === 评分模拟相关结构 ===
+
+// 假设新增的记录，如"全额还清某笔贷款"或"新增一笔30天逾期"
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HypotheticalRecord {
+    pub record_type: RecordType,
+    pub event_date: String,
+    pub content: RecordContent,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ScoreSimulationRequest {
+    pub user_did: String,
+    pub hypothetical_records: Vec<HypotheticalRecord>,
+    pub model: Option<ModelVersion>,   // 为空时使用当前启用的模型
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ScoreSimulationResult {
+    pub user_did: String,
+    pub current: Option<RiskAssessment>,   // 用户暂无记录时为空
+    pub simulated: RiskAssessment,
+    pub score_delta: i64,                  // 模拟分数 - 当前分数
+}
@@ -90,6 +90,20 @@ pub struct LevelDistribution {
     pub other_count: u64,
 }
 
+impl LevelDistribution {
+    // 按分数段计入对应等级
+    pub fn add_score(&mut self, score: u64) {
+        match score {
+            90..=100 => self.aaa_count += 1,
+            80..=89 => self.aa_count += 1,
+            70..=79 => self.a_count += 1,
+            60..=69 => self.bbb_count += 1,
+            50..=59 => self.bb_count += 1,
+            _ => self.other_count += 1,
+        }
+    }
+}
+
 #[derive(CandidType, Serialize)]
 pub struct ScoreTrend {
     pub date: String,
@@ -155,6 +169,7 @@ pub struct CreditInfo {
     pub credit_score: u64,
     pub credit_level: String,
     pub data_quality_score: u64,
+    pub score_trend: Vec<ScoreTrend>,   // 最近90天信用分变化
 }
 
 
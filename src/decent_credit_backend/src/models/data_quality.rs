@@ -0,0 +1,83 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 机构数据质量评分相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct DataQualityConfig {
+    pub enabled: bool,                  // 是否定时评估并自动调整机构信用分
+    pub window_days: u64,               // 滚动统计窗口（天）
+    pub late_threshold_days: u64,       // 事件发生后超过该天数才上报视为迟报
+    pub min_submissions: u64,           // 窗口内提交数不足时只评分不调整
+    pub rejection_weight: f64,
+    pub dispute_weight: f64,
+    pub late_weight: f64,
+    pub duplicate_weight: f64,
+    pub deduction_threshold: u64,       // 质量分低于该值时扣分
+    pub recovery_threshold: u64,        // 质量分不低于该值时恢复
+    pub max_deduction: u32,             // 单次评估最多扣分
+    pub max_recovery: u32,              // 单次评估最多恢复
+}
+
+impl Default for DataQualityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_days: 90,
+            late_threshold_days: 30,
+            min_submissions: 20,
+            rejection_weight: 0.35,
+            dispute_weight: 0.25,
+            late_weight: 0.2,
+            duplicate_weight: 0.2,
+            deduction_threshold: 80,
+            recovery_threshold: 90,
+            max_deduction: 5,
+            max_recovery: 2,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct DataQualityMetrics {
+    pub institution_id: Principal,
+    pub window_start: u64,
+    pub window_end: u64,
+    pub submissions: u64,
+    pub rejected: u64,
+    pub rejection_rate: f64,
+    pub disputes_resolved: u64,         // 窗口内已处理的审核案件
+    pub disputes_lost: u64,             // 其中问题成立的案件
+    pub dispute_loss_rate: f64,
+    pub late_reports: u64,
+    pub late_reporting_rate: f64,
+    pub average_reporting_delay_days: f64,
+    pub duplicates: u64,
+    pub duplicate_rate: f64,
+    pub repeat_offense_penalty: u64,    // 窗口内问题成立的审核案件扣分
+    pub quality_score: u64,             // 0-100
+    pub computed_at: u64,
+    pub last_incident_at: Option<u64>,  // 窗口内最近一次计入评分的数据问题发生时间
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum QualityAdjustmentKind {
+    Deduction,
+    Recovery,
+    NoChange,
+}
+
+// 每次评估的审计记录，包括未调整的情况
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QualityAdjustment {
+    pub id: u64,
+    pub institution_id: Principal,
+    pub kind: QualityAdjustmentKind,
+    pub previous_credit_score: u64,
+    pub new_credit_score: u64,
+    pub metrics: DataQualityMetrics,
+    pub reason: String,
+    pub deduction_record_id: Option<String>,   // 扣分时对应的扣分记录
+    pub triggered_by: Principal,
+    pub created_at: u64,
+}
@@ -0,0 +1,61 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === DID 文档与可验证凭证相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PublicKeyJwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String,              // base64url 编码的公钥（EC 密钥为 x 坐标）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,      // EC 密钥的 y 坐标
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct VerificationMethod {
+    pub id: String,
+    pub method_type: String,    // JsonWebKey2020
+    pub controller: String,
+    pub public_key_jwk: PublicKeyJwk,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct DidDocument {
+    pub id: String,
+    pub controller: Option<String>,
+    pub also_known_as: Vec<String>,
+    pub verification_method: Vec<VerificationMethod>,
+    pub authentication: Vec<String>,
+    pub assertion_method: Vec<String>,
+}
+
+// 借款人为自己的 DID 登记的 Ed25519 公钥
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct UserVerificationKey {
+    pub key_id: String,         // DID URL 片段，如 key-1
+    pub public_key: Vec<u8>,
+    pub added_by: Principal,
+    pub added_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct VerifiableCredential {
+    pub id: String,             // urn:decent_credit:report:<report_id>
+    pub issuer: String,
+    pub subject: String,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    pub credential_json: String,    // W3C VC 数据模型（JSON-LD），证明见 jwt
+    pub jwt: String,                // 由子网阈值 ECDSA 签名的 vc-jwt（ES256K）
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CredentialVerification {
+    pub valid: bool,
+    pub issuer: Option<String>,
+    pub subject: Option<String>,
+    pub credential_id: Option<String>,
+    pub expires_at: Option<u64>,
+    pub error: Option<String>,
+}
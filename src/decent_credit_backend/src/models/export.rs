@@ -0,0 +1,69 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use crate::models::record::{RecordStatus, RecordType};
+
+// === 机构数据导出相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ExportDataset {
+    Records,            // 本机构提交的信用记录
+    DeductionRecords,   // 本机构的扣分记录
+    BillingHistory,     // 本机构作为付费方或收款方的计费流水
+}
+
+// 时间范围按纳秒时间戳过滤（含边界）；类型和状态只作用于信用记录
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ExportFilter {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub record_type: Option<RecordType>,
+    pub status: Option<RecordStatus>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CreateExportJobRequest {
+    pub institution_id: Principal,
+    pub datasets: Vec<ExportDataset>,
+    pub format: ExportFormat,
+    pub filter: ExportFilter,
+    pub page_size: Option<u32>,     // 每个分片的最大行数
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ExportDatasetSummary {
+    pub dataset: ExportDataset,
+    pub rows: u64,
+    pub first_chunk: Option<u32>,   // 分片按序生成，尚未生成到该数据集时为空
+    pub chunk_count: u32,           // 已生成的分片数
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ExportJob {
+    pub id: String,
+    pub institution_id: Principal,
+    pub format: ExportFormat,
+    pub filter: ExportFilter,
+    pub datasets: Vec<ExportDatasetSummary>,
+    pub total_rows: u64,
+    pub total_chunks: Option<u32>,  // 生成最后一个分片后确定
+    // 各分片 SHA-256 摘要（32 字节原始值）依次拼接后再做 SHA-256，十六进制；生成最后一个分片后确定
+    pub checksum: Option<String>,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ExportChunk {
+    pub job_id: String,
+    pub index: u32,
+    pub dataset: ExportDataset,
+    pub data: Vec<u8>,          // CSV 分片首行为表头
+    pub sha256: String,
+    pub is_last: bool,
+}
@@ -0,0 +1,46 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use crate::models::record::{RecordContent, RecordType};
+
+// === HTTP 网关相关结构 ===
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub upgrade: Option<bool>,      // 为 true 时网关改用 http_request_update 重新发起
+}
+
+// 机构 API Key，密钥明文只在创建时返回一次
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ApiKeyInfo {
+    pub key_id: String,
+    pub institution_id: Principal,
+    pub created_at: u64,
+    pub last_used_at: Option<u64>,
+    pub revoked: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ApiKeyCreated {
+    pub key_id: String,
+    pub api_key: String,
+}
+
+// REST 接口提交记录的请求体，机构由 API Key 确定
+#[derive(Deserialize, Clone, Debug)]
+pub struct HttpRecordSubmission {
+    pub record_type: RecordType,
+    pub user_did: String,
+    pub event_date: String,
+    pub content: RecordContent,
+}
@@ -0,0 +1,39 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 跨机构身份关联相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum DidLinkStatus {
+    PendingConsent,     // 等待借款人同意
+    Linked,             // 借款人已同意，机构 DID 已关联到平台 DID
+    Rejected,           // 借款人拒绝
+    Revoked,            // 借款人撤回同意
+}
+
+// 机构发起的迁移请求：把机构 DID 关联到平台统一 DID
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DidLinkRequestInput {
+    pub institution_id: Principal,
+    pub institution_did: String,
+    pub identity_hash: String,      // 借款人须已用该身份哈希认领平台身份
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct DidLinkRequest {
+    pub id: String,
+    pub institution_id: Principal,
+    pub institution_did: String,
+    pub canonical_did: String,
+    pub borrower: Principal,            // 认领该平台身份的借款人主体
+    pub status: DidLinkStatus,
+    pub created_at: u64,
+    pub decided_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct LinkedIdentity {
+    pub canonical_did: String,
+    pub controller: Option<Principal>,  // 认领该平台身份的借款人主体
+    pub linked_dids: Vec<String>,       // 已关联的机构 DID
+}
@@ -0,0 +1,63 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 历史记录批量导入相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum ImportFormat {
+    Csv,        // 首行为表头
+    Ndjson,     // 每行一个 JSON 对象
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum ImportJobStatus {
+    Receiving,      // 接收分片中
+    Processing,     // 已结束上传，按批次校验并导入
+    Completed,
+    Cancelled,
+    Failed,         // 上传超时或数据无法继续处理，原因见 failure_reason
+}
+
+// 字段名 -> 文件中的列名；未映射的字段按同名列读取
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ColumnMapping {
+    pub field: String,
+    pub column: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CreateImportJobRequest {
+    pub institution_id: Principal,
+    pub format: ImportFormat,
+    pub column_mapping: Vec<ColumnMapping>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ImportJob {
+    pub id: String,
+    pub institution_id: Principal,
+    pub format: ImportFormat,
+    pub status: ImportJobStatus,
+    pub chunks_received: u32,
+    pub bytes_received: u64,
+    pub rows_processed: u64,
+    pub rows_imported: u64,
+    pub rows_failed: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub completed_at: Option<u64>,
+    pub failure_reason: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ImportRowError {
+    pub line: u64,          // 文件中的行号（从 1 开始，含表头）
+    pub message: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ImportErrorPage {
+    pub errors: Vec<ImportRowError>,
+    pub total: u64,
+    pub truncated: bool,    // 超出保存上限的错误只计数不保存
+}
@@ -1,5 +1,6 @@
 use candid::{CandidType, Deserialize, Principal};
 use serde::Serialize;
+use crate::models::pricing::PricingSchedule;
 
 #[derive(CandidType, Deserialize, Clone)]
 pub struct Institution {
@@ -74,5 +75,6 @@ pub struct UpdateServiceSettingsRequest {
     pub data_service_enabled: bool,
     pub query_price: u64,
     pub reward_share_ratio: u8,
+    pub pricing_schedule: Option<PricingSchedule>,  // 按记录类型和查询量的定价表，缺省按 query_price 统一计价
 }
 
@@ -0,0 +1,71 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 机构信用分变更、申诉与恢复相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum ScoreChangeReason {
+    ManualAdjustment,       // 管理员直接修改
+    Deduction,              // 扣分记录（含数据质量自动扣分）
+    QualityRecovery,        // 数据质量达标恢复
+    AppealReversal,         // 申诉成功撤销扣分
+    TimeBasedRecovery,      // 一段时间内无违规自动恢复
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct InstitutionScoreChange {
+    pub institution_id: Principal,
+    pub previous_score: u64,
+    pub new_score: u64,
+    pub reason: ScoreChangeReason,
+    pub reference: Option<String>,      // 关联的扣分记录或申诉ID
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum AppealStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct DeductionAppeal {
+    pub id: String,
+    pub deduction_id: String,
+    pub institution_id: Principal,
+    pub deduction_points: u32,
+    pub reason: String,
+    pub status: AppealStatus,
+    pub submitted_at: u64,
+    pub reviewed_at: Option<u64>,
+    pub reviewed_by: Option<Principal>,
+    pub review_note: Option<String>,
+    pub restored_points: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ReviewAppealRequest {
+    pub appeal_id: String,
+    pub approve: bool,
+    pub note: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RecoveryPolicy {
+    pub enabled: bool,
+    pub incident_free_months: u32,      // 连续无扣分的月数
+    pub recovery_points: u64,           // 每满一个周期恢复的分数
+    pub max_score: u64,                 // 自动恢复的上限
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            incident_free_months: 3,
+            recovery_points: 5,
+            max_score: 100,
+        }
+    }
+}
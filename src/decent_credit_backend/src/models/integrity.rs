@@ -0,0 +1,52 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 重复与冲突记录检测相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum IntegrityIssueType {
+    Duplicate,                      // 其他机构已提交相同指纹的记录
+    RepaymentExceedsOutstanding,    // 还款金额超过贷款未还余额
+    OverdueOnSettledLoan,           // 用户贷款均已结清仍上报逾期
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum ReviewStatus {
+    Open,
+    Upheld,         // 问题成立，记录被驳回
+    Dismissed,      // 问题不成立，记录纳入评分
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ReviewCase {
+    pub id: String,
+    pub issue: IntegrityIssueType,
+    pub record_id: String,
+    pub institution_id: Principal,
+    pub user_did: String,
+    pub fingerprint: String,
+    pub related_record_ids: Vec<String>,
+    pub related_institutions: Vec<Principal>,
+    pub details: String,
+    pub status: ReviewStatus,
+    pub created_at: u64,
+    pub resolved_at: Option<u64>,
+    pub resolved_by: Option<Principal>,
+    pub resolution_note: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ResolveReviewRequest {
+    pub case_id: String,
+    pub uphold: bool,
+    pub note: Option<String>,
+}
+
+// 检测到的问题，提交成功后进入审核队列
+#[derive(Clone, Debug)]
+pub struct DetectedIssue {
+    pub issue: IntegrityIssueType,
+    pub related_record_ids: Vec<String>,
+    pub related_institutions: Vec<Principal>,
+    pub details: String,
+}
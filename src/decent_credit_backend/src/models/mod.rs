@@ -2,4 +2,34 @@
 pub mod institution;
 pub mod dashboard;
 pub mod credit;
-pub mod record;
\ No newline at end of file
+pub mod record;
+pub mod billing;
+pub mod reconciliation;
+
+pub mod pricing;
+
+pub mod quota;
+
+pub mod rate_limit;
+
+pub mod scoring;
+
+pub mod backtest;
+
+pub mod identity;
+
+pub mod did;
+
+pub mod http;
+
+pub mod import;
+
+pub mod export;
+
+pub mod validation;
+
+pub mod integrity;
+
+pub mod data_quality;
+
+pub mod institution_score;
@@ -0,0 +1,86 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use crate::models::record::RecordType;
+
+// === 查询费分成相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum SplitRole {
+    DataOwner,          // 数据所有方机构
+    PlatformTreasury,   // 平台金库
+    Borrower,           // 数据主体（借款人）
+}
+
+// 管理员配置的分成规则，百分比均以查询费为基数
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RevenueSplitConfig {
+    pub treasury: Option<Principal>,     // 平台金库账户，未配置时平台份额留存在本 canister 账户
+    pub min_owner_share: u8,             // 机构可设置的 reward_share_ratio 下限
+    pub max_owner_share: u8,             // 机构可设置的 reward_share_ratio 上限
+    pub default_owner_share: u8,         // 新注册机构的默认分成比例
+    pub borrower_share: u8,              // 借款人分成比例，仅在借款人绑定了收款账户时支付
+}
+
+impl Default for RevenueSplitConfig {
+    fn default() -> Self {
+        Self {
+            treasury: None,
+            min_owner_share: 50,
+            max_owner_share: 95,
+            default_owner_share: 90,
+            borrower_share: 0,
+        }
+    }
+}
+
+// 一笔查询费拆分后的单项
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RevenueSplitLine {
+    pub role: SplitRole,
+    pub recipient: Principal,
+    pub share: u8,
+    pub amount: u64,
+    pub settled: bool,
+    pub block_height: Option<u64>,
+}
+
+// === 查询定价相关结构 ===
+
+// 按查询方当月对本机构的查询次数给予折扣
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct VolumeTier {
+    pub min_monthly_queries: u64,   // 达到该查询次数后适用
+    pub discount_percent: u8,       // 折扣比例(0-100)
+}
+
+// 机构的定价表：按记录类型定价，并支持阶梯折扣
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PricingSchedule {
+    pub loan_price: u64,
+    pub repayment_price: u64,
+    pub overdue_price: u64,
+    pub volume_tiers: Vec<VolumeTier>,
+}
+
+impl PricingSchedule {
+    // 未设置定价表的机构，所有记录类型都按 query_price 计价
+    pub fn flat(price: u64) -> Self {
+        Self {
+            loan_price: price,
+            repayment_price: price,
+            overdue_price: price,
+            volume_tiers: Vec::new(),
+        }
+    }
+}
+
+// 单条记录的报价明细
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QuoteItem {
+    pub record_id: String,
+    pub record_type: RecordType,
+    pub base_price: u64,
+    pub discount_percent: u8,
+    pub price: u64,
+    pub free_reason: Option<String>,   // 免费原因，如同组机构查询
+}
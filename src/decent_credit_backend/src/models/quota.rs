@@ -0,0 +1,38 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 订阅套餐与配额相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SubscriptionPlan {
+    pub id: String,
+    pub name: String,
+    pub monthly_query_allowance: u64,   // 每月可用查询次数（记录查询与风险评估）
+    pub batch_size_limit: u64,          // 单次批量提交的记录上限
+    pub price_dcc: u64,                 // 每个计费周期需支付的 DCC
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct InstitutionSubscription {
+    pub institution_id: Principal,
+    pub plan_id: String,
+    pub period: u32,                    // 当前计费周期（自然月，如 202410）
+    pub queries_used: u64,
+    pub subscribed_at: u64,
+    pub paid_period: Option<u32>,       // 已付费的周期；付费套餐未续费时按基础套餐计算额度
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QuotaUsage {
+    pub institution_id: Principal,
+    pub plan: SubscriptionPlan,
+    pub period: u32,
+    pub queries_used: u64,
+    pub queries_remaining: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ChangePlanRequest {
+    pub institution_id: Principal,
+    pub plan_id: String,
+}
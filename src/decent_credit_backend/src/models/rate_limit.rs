@@ -0,0 +1,74 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+// === 令牌桶限流相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RateLimitCategory {
+    Submission,     // 记录提交
+    Query,          // 记录查询
+    Assessment,     // 风险评估
+    General,        // 其他更新接口
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BucketLimit {
+    pub capacity: u64,              // 桶容量，即允许的突发请求数
+    pub refill_per_minute: u64,     // 每分钟补充的令牌数
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CategoryLimits {
+    pub per_principal: BucketLimit,
+    pub per_institution: BucketLimit,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub exempt_controllers: bool,   // 控制者调用不受限
+    pub submission: CategoryLimits,
+    pub query: CategoryLimits,
+    pub assessment: CategoryLimits,
+    pub general: CategoryLimits,
+    pub anonymous: Option<BucketLimit>,     // 所有匿名调用（HTTP 网关）共享的桶，为空时使用默认值
+}
+
+// 网关代所有 API Key 机构调用，容量需覆盖多家机构的并发请求
+const DEFAULT_ANONYMOUS_LIMIT: BucketLimit = BucketLimit { capacity: 1_200, refill_per_minute: 1_200 };
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            exempt_controllers: true,
+            submission: limits(60, 120),
+            query: limits(120, 240),
+            assessment: limits(60, 120),
+            general: limits(120, 240),
+            anonymous: Some(DEFAULT_ANONYMOUS_LIMIT),
+        }
+    }
+}
+
+fn limits(per_principal: u64, per_institution: u64) -> CategoryLimits {
+    CategoryLimits {
+        per_principal: BucketLimit { capacity: per_principal, refill_per_minute: per_principal },
+        per_institution: BucketLimit { capacity: per_institution, refill_per_minute: per_institution },
+    }
+}
+
+impl RateLimitConfig {
+    pub fn limits_for(&self, category: RateLimitCategory) -> &CategoryLimits {
+        match category {
+            RateLimitCategory::Submission => &self.submission,
+            RateLimitCategory::Query => &self.query,
+            RateLimitCategory::Assessment => &self.assessment,
+            RateLimitCategory::General => &self.general,
+        }
+    }
+
+    pub fn anonymous_limit(&self) -> BucketLimit {
+        self.anonymous.clone().unwrap_or(DEFAULT_ANONYMOUS_LIMIT)
+    }
+}
@@ -0,0 +1,57 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 本地余额与链上账本对账相关结构 ===
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ReconciliationConfig {
+    pub enabled: bool,           // 是否启用定时对账
+    pub auto_correct: bool,      // 发现差异时是否以链上余额修正本地余额
+    pub interval_secs: u64,      // 对账间隔（秒）
+}
+
+impl Default for ReconciliationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            auto_correct: false,
+            interval_secs: 3600,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BalanceDiscrepancy {
+    pub institution_id: Principal,
+    pub institution_name: String,
+    pub local_balance: u64,
+    pub chain_balance: u64,
+    pub difference: i64,         // 链上余额 - 本地余额
+    pub corrected: bool,
+    pub detected_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ReconciliationReport {
+    pub run_id: u64,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub institutions_checked: u64,
+    pub discrepancies: Vec<BalanceDiscrepancy>,
+    pub errors: Vec<ReconciliationError>,   // 查询链上余额失败的机构
+    pub auto_corrected: bool,
+}
+
+// 本地扣减超出本地余额时记录的差额，等待下一次对账核实
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct FlaggedBalance {
+    pub institution_id: Principal,
+    pub shortfall: u64,          // 未能从本地余额扣除的累计金额
+    pub flagged_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ReconciliationError {
+    pub institution_id: Principal,
+    pub message: String,
+}
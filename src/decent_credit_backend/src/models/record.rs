@@ -19,7 +19,20 @@ pub struct CreditRecord {
     pub timestamp: u64,                // 记录时间戳
     pub status: RecordStatus,          // 记录状态
     pub reward_amount: Option<u64>,     // 奖励代币数量
-    pub query_price: u64
+    pub query_price: u64,
+    pub rejection_reason: Option<RejectionReason>,  // 被拒绝的原因
+    pub resubmitted_from: Option<String>,  // 修正前的失败记录ID
+    pub resubmitted_as: Option<String>,    // 修正后重新提交的记录ID
+    pub imported: Option<bool>             // 是否为批量导入的历史数据
+}
+
+// 结构化的拒绝原因，便于机构定位并修正数据
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct RejectionReason {
+    pub code: String,                  // 错误代码（校验失败时为规则ID）
+    pub field: Option<String>,         // 出错的字段
+    pub message: String,               // 错误描述
+    pub rejected_at: u64               // 拒绝时间
 }
 #[derive(Clone)]
 pub struct TokenOperation {
@@ -69,7 +82,7 @@ pub struct ReviewResult {
 }
 
 // === DCC交易相关结构 ===
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Deserialize, Clone)]
 pub struct DCCTransactionRequest {
     pub dcc_amount: u64,
     pub usdt_amount: f64,
@@ -84,6 +97,20 @@ pub struct BalanceResponse {
     pub usdt_value: f64,
 }
 
+// 链上区块与本地交易备注合并后的交易明细
+#[derive(CandidType, Deserialize, Clone)]
+pub struct InstitutionTransaction {
+    pub block_height: u64,
+    pub tx_hash: String,
+    pub operation: String,         // Mint / Burn / Transfer
+    pub direction: String,         // IN / OUT
+    pub counterparty: Option<Principal>,
+    pub amount: u64,
+    pub timestamp: u64,
+    pub usdt_amount: Option<f64>,
+    pub remarks: Option<String>,   // 来自 DCCTransactionRequest 的备注
+}
+
 
 // === 原始数据结构 ===
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]  // Added Serialize
@@ -117,6 +144,28 @@ pub struct RecordSubmissionResponse {
     pub reward_amount: Option<u64>
 }
 
+// === 失败记录修正重提 ===
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FailedRecordCorrection {
+    pub record_id: String,
+    pub corrected_content: RecordContent
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ResubmissionResult {
+    pub original_record_id: String,
+    pub new_record_id: Option<String>,
+    pub error: Option<String>
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BatchResubmissionResponse {
+    pub resubmitted: usize,
+    pub failed: usize,
+    pub results: Vec<ResubmissionResult>,
+    pub timestamp: u64
+}
+
 // === 批量提交相关结构 ===
 #[derive(CandidType, Deserialize)]  // Added Serialize
 pub struct BatchSubmissionRequest {
@@ -189,6 +238,7 @@ pub struct CreditDeductionRecord {
     pub created_at: u64,
     pub operator_id: Principal,
     pub operator_name: String,
+    pub reversed_at: Option<u64>,      // 申诉通过后撤销的时间
 }
 
 #[derive(CandidType, Deserialize)]
@@ -0,0 +1,134 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+// === 信用评分模型相关结构 ===
+
+// 评分公式中的权重、上限与风险等级阈值
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScoringParams {
+    pub base_score: f64,
+
+    // 行为分
+    pub new_user_loan_activity: f64,        // 无借款记录时的活跃度基础分
+    pub loan_frequency_weight: f64,
+    pub loan_activity_cap: f64,
+    pub new_user_repayment: f64,            // 新用户还款基础分
+    pub repayment_ratio_weight: f64,
+    pub overdue_ratio_penalty: f64,
+
+    // 金额分
+    pub stability_weight: f64,
+    pub repayment_strength_weight: f64,
+
+    // 时序分
+    pub recent_activity_weight: f64,
+    pub consistency_weight: f64,
+    pub non_increasing_trend_bonus: f64,
+
+    // 风险惩罚
+    pub overdue_days_unit: f64,             // 逾期天数按该天数折算
+    pub overdue_days_weight: f64,
+    pub overdue_days_cap: f64,
+    pub overdue_frequency_weight: f64,
+    pub overdue_frequency_cap: f64,
+    pub overdue_amount_unit: f64,           // 逾期金额按该金额折算一分
+    pub overdue_amount_cap: f64,
+
+    pub min_score: f64,
+    pub max_score: f64,
+
+    // 风险等级阈值：分数 <= high_risk_max 为高风险，<= medium_risk_max 为中风险
+    pub high_risk_max: u32,
+    pub medium_risk_max: u32,
+
+    // 基于事件日期的时序特征配置，旧版本模型为空时使用默认配置
+    pub time_features: Option<TimeFeatureParams>,
+}
+
+impl ScoringParams {
+    pub fn time_features(&self) -> TimeFeatureParams {
+        self.time_features.clone().unwrap_or_default()
+    }
+}
+
+// 事件日期缺失或无法解析时的处理方式
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum InvalidDateHandling {
+    FallbackToSubmission,   // 使用提交时间代替
+    Exclude,                // 不参与时序特征计算
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TimeFeatureParams {
+    pub lookback_windows_months: Vec<u32>,  // 统计近 N 个月的活动
+    pub recency_half_life_months: f64,      // 近期权重衰减的半衰期
+    pub invalid_date_handling: InvalidDateHandling,
+}
+
+impl Default for TimeFeatureParams {
+    fn default() -> Self {
+        Self {
+            lookback_windows_months: vec![3, 6, 12, 24],
+            recency_half_life_months: 6.0,
+            invalid_date_handling: InvalidDateHandling::FallbackToSubmission,
+        }
+    }
+}
+
+impl Default for ScoringParams {
+    // 与最初硬编码的评分公式一致
+    fn default() -> Self {
+        Self {
+            base_score: 60.0,
+            new_user_loan_activity: 5.0,
+            loan_frequency_weight: 5.0,
+            loan_activity_cap: 10.0,
+            new_user_repayment: 5.0,
+            repayment_ratio_weight: 10.0,
+            overdue_ratio_penalty: 10.0,
+            stability_weight: 10.0,
+            repayment_strength_weight: 10.0,
+            recent_activity_weight: 10.0,
+            consistency_weight: 5.0,
+            non_increasing_trend_bonus: 5.0,
+            overdue_days_unit: 30.0,
+            overdue_days_weight: 20.0,
+            overdue_days_cap: 20.0,
+            overdue_frequency_weight: 10.0,
+            overdue_frequency_cap: 20.0,
+            overdue_amount_unit: 10000.0,
+            overdue_amount_cap: 10.0,
+            min_score: 0.0,
+            max_score: 150.0,
+            high_risk_max: 50,
+            medium_risk_max: 70,
+            time_features: Some(TimeFeatureParams::default()),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScoringModel {
+    pub model_id: String,
+    pub version: u32,
+    pub name: String,
+    pub description: String,
+    pub params: ScoringParams,
+    pub created_by: Principal,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScoringModelUpload {
+    pub model_id: String,
+    pub name: String,
+    pub description: String,
+    pub params: ScoringParams,
+    pub activate: bool,         // 上传后是否立即启用
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ModelVersion {
+    pub model_id: String,
+    pub version: u32,
+}
@@ -0,0 +1,95 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use crate::models::record::RecordType;
+
+// === 记录校验规则相关结构 ===
+
+// 规则可校验的字段，取值来自记录内容或提交请求
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum RecordField {
+    Amount,
+    LoanId,
+    TermMonths,
+    InterestRate,       // 年化利率(%)
+    RepaymentDate,
+    OverdueDays,
+    PeriodAmount,
+    EventDate,
+    UserDid,
+}
+
+impl RecordField {
+    // 与记录内容中的字段名一致，用于拒绝原因中定位出错字段
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordField::Amount => "amount",
+            RecordField::LoanId => "loan_id",
+            RecordField::TermMonths => "term_months",
+            RecordField::InterestRate => "interest_rate",
+            RecordField::RepaymentDate => "repayment_date",
+            RecordField::OverdueDays => "overdue_days",
+            RecordField::PeriodAmount => "period_amount",
+            RecordField::EventDate => "event_date",
+            RecordField::UserDid => "user_did",
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum CharacterSet {
+    Digits,
+    Alphanumeric,
+    AlphanumericDash,   // 字母、数字、- 和 _
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum RuleCondition {
+    Required,               // 数值非零，文本非空
+    GreaterThan(f64),
+    AtLeast(f64),
+    AtMost(f64),
+    Format {                // 文本格式，如贷款编号
+        prefix: Option<String>,
+        min_length: Option<u32>,
+        max_length: Option<u32>,
+        charset: Option<CharacterSet>,
+    },
+    ValidDate,              // YYYY-MM-DD
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ValidationRule {
+    pub id: String,                 // 规则ID，出现在校验错误中，如 loan.interest_rate.max
+    pub record_type: RecordType,
+    pub field: RecordField,
+    pub condition: RuleCondition,
+    pub message: String,
+    pub enabled: bool,
+}
+
+// 某个司法辖区的一个规则集版本；每次修改生成新版本，旧版本保留
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ValidationRuleSet {
+    pub jurisdiction: String,
+    pub version: u32,
+    pub rules: Vec<ValidationRule>,
+    pub note: Option<String>,
+    pub updated_by: Principal,
+    pub updated_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UpdateRuleSetRequest {
+    pub jurisdiction: String,
+    pub rules: Vec<ValidationRule>,
+    pub note: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RuleViolation {
+    pub rule_id: String,
+    pub jurisdiction: String,
+    pub version: u32,
+    pub field: RecordField,
+    pub message: String,
+}
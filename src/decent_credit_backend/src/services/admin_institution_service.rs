@@ -7,11 +7,11 @@ use crate::models::institution::*;
 use crate::models::credit::*;
 use crate::models::record::*;
 use crate::models::dashboard::*;
-use log::{info, debug, warn, error};
+use log::{info, debug, warn};
 use crate::utils::error::Error;
 use crate::services::token_service::*;
 use crate::services::record_service::*;
-use crate::services::record_service::RecordService;
+use crate::services::pricing_service::PRICING_SERVICE;
 const DEFAULT_PASSWORD: &str = "123"; // 默认密码
 
 
@@ -20,7 +20,7 @@ pub struct AdminService {
     caller_institutions: HashMap<Principal, Vec<Principal>>,
     name_to_id: HashMap<String, Principal>,
     next_id: u64,
-    dcc_transactions: Vec<DCCTransactionRequest>,
+    dcc_transactions: HashMap<String, DCCTransactionRequest>,  // 链上 tx_hash -> 交易请求
     usdt_rate: f64,
 }
 
@@ -35,7 +35,7 @@ impl AdminService {
             caller_institutions: HashMap::new(),
             name_to_id: HashMap::new(),
             next_id: 0,
-            dcc_transactions: Vec::new(),
+            dcc_transactions: HashMap::new(),
             usdt_rate: 1.0,
         }
     }
@@ -103,7 +103,7 @@ impl AdminService {
             },
             data_service_enabled:true,
             query_price:0,
-            reward_share_ratio:0,
+            reward_share_ratio: PRICING_SERVICE.with(|service| service.borrow().default_owner_share()),
             inbound_queries:0,
             outbound_queries:0,
             consumption:0,
@@ -142,11 +142,21 @@ impl AdminService {
         // 获取机构信息
         let institution = self.institutions.get_mut(&institution_id)
             .ok_or_else(|| "机构不存在".to_string())?;
+
+        // 分成比例需在管理员设定的范围内，定价表一并校验保存
+        PRICING_SERVICE.with(|service| {
+            let mut service = service.borrow_mut();
+            service.validate_owner_share(request.reward_share_ratio)?;
+            match request.pricing_schedule.clone() {
+                Some(schedule) => service.set_schedule(institution_id, schedule),
+                None => Ok(()),
+            }
+        })?;
         
         // 更新设置
         institution.data_service_enabled = request.data_service_enabled;
         institution.query_price = request.query_price;
-        institution.reward_share_ratio = 10;
+        institution.reward_share_ratio = request.reward_share_ratio;
 
         // 记录更新
         info!(
@@ -208,33 +218,8 @@ impl AdminService {
                 target_institution.last_active = time();
             }
 
-             // 获取查询价格
-             let target_institution = self.get_institution(record.institution_id);
-
-
-            // 如果机构存在，处理代币操作
-            if let Some(target_institution) = target_institution {
-                let token_op =TokenOperation {
-                    from_id: id,
-                    to_id: record.institution_id,
-                    user_did: record.user_did.clone(),
-                    query_price: target_institution.query_price,
-                    record: record.clone(),
-                };
-
-                // 在后台异步处理代币操作
-                ic_cdk::spawn(async move {
-                    if let Err(e) = RecordService::process_token_operation(token_op).await {
-                        error!("代币操作处理失败: {}", e);
-                    }
-                });
-            } else {
-                warn!("未找到机构信息，跳过代币处理: {}", record.institution_id);
-            }
+            // 查询费由计费服务通过托管预留和结算，这里只更新统计
         }
-
-                               
-                            
     }
 
     pub fn institution_record_data_upload(&mut self, id: Principal, count: u64) {
@@ -320,14 +305,17 @@ pub async fn recharge_dcc(id: Principal, request: DCCTransactionRequest) -> Resu
     }
 
     // 如果成功，更新本地记录
-    if result.is_ok() {
+    if let Ok(transfer_result) = &result {
         ADMIN_SERVICE.with(|service| {
             let mut admin_service = service.borrow_mut();
-            admin_service.record_token_trading(id, true, dcc_amount);
+            if let Err(e) = admin_service.record_token_trading(id, true, dcc_amount) {
+                warn!("Local trading counters not updated for {}: {}", id.to_text(), e);
+            }
+            admin_service.record_dcc_transaction(request, transfer_result);
         });
     }
 
-    result
+    result.map(|_| ())
 }
 
 pub async fn deduct_dcc(id: Principal, request: DCCTransactionRequest) -> Result<(), String> {
@@ -349,14 +337,58 @@ pub async fn deduct_dcc(id: Principal, request: DCCTransactionRequest) -> Result
     }).await;
 
     // 如果成功，更新本地记录
-    if result.is_ok() {
+    if let Ok(transfer_result) = &result {
         ADMIN_SERVICE.with(|service| {
             let mut admin_service = service.borrow_mut();
-            admin_service.record_token_trading(id, false, dcc_amount);
+            if let Err(e) = admin_service.record_token_trading(id, false, dcc_amount) {
+                warn!("Local trading counters not updated for {}: {}", id.to_text(), e);
+            }
+            admin_service.record_dcc_transaction(request, transfer_result);
         });
     }
 
-    result
+    result.map(|_| ())
+}
+
+/// 获取机构的链上交易明细，并合并本地交易请求中的备注
+pub async fn get_institution_transactions(id: Principal) -> Result<Vec<InstitutionTransaction>, String> {
+    let exists = ADMIN_SERVICE.with(|service| {
+        service.borrow().institutions.contains_key(&id)
+    });
+    if !exists {
+        return Err("机构不存在".to_string());
+    }
+
+    let token_canister_id = TOKEN_SERVICE.with(|service| {
+        service.borrow().token_canister_id
+    });
+    let ledger_transactions = TokenService::get_account_transactions_static(token_canister_id, id).await?;
+
+    let transactions = ADMIN_SERVICE.with(|service| {
+        let service = service.borrow();
+        ledger_transactions.into_iter()
+            .map(|tx| {
+                let local = service.dcc_transactions.get(&tx.tx_hash);
+                let incoming = tx.to == Some(id);
+                InstitutionTransaction {
+                    block_height: tx.block_height,
+                    operation: format!("{:?}", tx.operation),
+                    direction: if incoming { "IN" } else { "OUT" }.to_string(),
+                    counterparty: if incoming { tx.from } else { tx.to },
+                    amount: tx.amount,
+                    timestamp: tx.timestamp,
+                    usdt_amount: local.map(|req| req.usdt_amount),
+                    remarks: local
+                        .map(|req| req.remarks.clone())
+                        .or_else(|| String::from_utf8(tx.memo.clone()).ok()),
+                    tx_hash: tx.tx_hash,
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+
+    info!("Joined {} ledger transactions for institution {}", transactions.len(), id.to_text());
+    Ok(transactions)
 }
 
     pub fn update_usdt_rate(&mut self, rate: f64) -> Result<(), String> {
@@ -369,35 +401,70 @@ pub async fn deduct_dcc(id: Principal, request: DCCTransactionRequest) -> Result
 
 
 
-    pub fn record_token_trading(&mut self, id: Principal, is_buy: bool, amount: u64) {
-        if let Some(institution) = self.institutions.get_mut(&id) {
-            if is_buy {
-                institution.token_trading.bought += amount;
-                institution.balance+=amount;
-            } else {
-                institution.token_trading.sold += amount;
-                institution.balance-=amount;
-            }
-            institution.last_active = time();
+    pub fn record_token_trading(&mut self, id: Principal, is_buy: bool, amount: u64) -> Result<(), String> {
+        let institution = self.institutions.get_mut(&id).ok_or("机构不存在")?;
+        if is_buy {
+            let bought = institution.token_trading.bought.checked_add(amount).ok_or("购买累计溢出")?;
+            let balance = institution.balance.checked_add(amount).ok_or("本地余额溢出")?;
+            institution.token_trading.bought = bought;
+            institution.balance = balance;
+        } else {
+            let sold = institution.token_trading.sold.checked_add(amount).ok_or("出售累计溢出")?;
+            let balance = institution.balance.checked_sub(amount).ok_or("本地余额不足，等待对账修正")?;
+            institution.token_trading.sold = sold;
+            institution.balance = balance;
         }
+        institution.last_active = time();
+        Ok(())
     }
-    pub fn record_token_reward(&mut self, id: Principal, amount: u64) {
-        if let Some(institution) = self.institutions.get_mut(&id) {
-            institution.rewards += amount;
-            institution.balance +=amount;
-            institution.last_active = time();
-            info!("Updated institution rewards: +{} for {}", amount, id);
-        }
+    /// 以链上 tx_hash 为键保存交易请求，供对账时关联备注
+    pub fn record_dcc_transaction(&mut self, mut request: DCCTransactionRequest, transfer_result: &TransferResult) {
+        request.tx_hash = transfer_result.tx_hash.clone();
+        debug!("Recorded DCC transaction at block {}: {}", transfer_result.block_height, request.tx_hash);
+        self.dcc_transactions.insert(request.tx_hash.clone(), request);
     }
 
-    pub fn record_token_consumption(&mut self, id: Principal, amount: u64) {
-        if let Some(institution) = self.institutions.get_mut(&id) {
-            institution.consumption += amount;
-            institution.balance -=amount;
+    pub fn record_token_reward(&mut self, id: Principal, amount: u64) -> Result<(), String> {
+        let institution = self.institutions.get_mut(&id).ok_or("机构不存在")?;
+        let rewards = institution.rewards.checked_add(amount).ok_or("奖励累计溢出")?;
+        let balance = institution.balance.checked_add(amount).ok_or("本地余额溢出")?;
+        institution.rewards = rewards;
+        institution.balance = balance;
+        institution.last_active = time();
+        info!("Updated institution rewards: +{} for {}", amount, id);
+        Ok(())
+    }
 
-            institution.last_active = time();
-            info!("Updated institution consumption: +{} for {}", amount, id);
+    /// 链上已发生的消费总是计入；本地余额不足时扣到 0，返回未能扣除的差额供对账核实
+    pub fn record_token_consumption(&mut self, id: Principal, amount: u64) -> Result<u64, String> {
+        let institution = self.institutions.get_mut(&id).ok_or("机构不存在")?;
+        let shortfall = amount.saturating_sub(institution.balance);
+        institution.consumption = institution.consumption.saturating_add(amount);
+        institution.balance = institution.balance.saturating_sub(amount);
+        institution.last_active = time();
+        info!("Updated institution consumption: +{} for {}", amount, id);
+        if shortfall > 0 {
+            warn!("Local balance of {} short by {} when recording consumption", id, shortfall);
         }
+        Ok(shortfall)
+    }
+
+    // === 对账相关方法 ===
+
+    /// 本地余额快照：(机构ID, 名称, 本地余额)
+    pub fn local_balances(&self) -> Vec<(Principal, String, u64)> {
+        self.institutions.values()
+            .map(|inst| (inst.id, inst.name.clone(), inst.balance))
+            .collect()
+    }
+
+    /// 以链上余额覆盖本地余额，返回修正前的值
+    pub fn correct_local_balance(&mut self, id: Principal, chain_balance: u64) -> Result<u64, String> {
+        let institution = self.institutions.get_mut(&id).ok_or("机构不存在")?;
+        let previous = institution.balance;
+        institution.balance = chain_balance;
+        info!("Corrected local balance for {}: {} -> {}", id, previous, chain_balance);
+        Ok(previous)
     }
 
 
@@ -0,0 +1,158 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use sha2::{Sha256, Digest};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use log::{info, warn};
+use crate::models::http::*;
+
+const API_KEY_PREFIX: &str = "dck";
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ApiKeyRecord {
+    pub info: ApiKeyInfo,
+    pub secret_hash: Vec<u8>,       // 只保存密钥哈希
+}
+
+// 需要跨升级保存的 API Key 状态
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct ApiKeyState {
+    pub keys: BTreeMap<String, ApiKeyRecord>,
+    pub next_id: u64,
+}
+
+pub struct ApiKeyService {
+    state: ApiKeyState,
+}
+
+thread_local! {
+    pub static API_KEY_SERVICE: RefCell<ApiKeyService> = RefCell::new(ApiKeyService::new());
+}
+
+fn hash_secret(secret: &str) -> Vec<u8> {
+    Sha256::digest(secret.as_bytes()).to_vec()
+}
+
+impl Default for ApiKeyService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ApiKeyService {
+    pub fn new() -> Self {
+        Self {
+            state: ApiKeyState::default(),
+        }
+    }
+
+    /// 为机构创建 API Key，格式为 dck_<key_id>_<secret>
+    pub fn create(&mut self, institution_id: Principal, random: &[u8]) -> ApiKeyCreated {
+        self.create_at(institution_id, random, time())
+    }
+
+    fn create_at(&mut self, institution_id: Principal, random: &[u8], now: u64) -> ApiKeyCreated {
+        self.state.next_id += 1;
+        let key_id = format!("k{}", self.state.next_id);
+        let secret = hex::encode(random);
+
+        self.state.keys.insert(key_id.clone(), ApiKeyRecord {
+            info: ApiKeyInfo {
+                key_id: key_id.clone(),
+                institution_id,
+                created_at: now,
+                last_used_at: None,
+                revoked: false,
+            },
+            secret_hash: hash_secret(&secret),
+        });
+        info!("API key {} created for {}", key_id, institution_id.to_text());
+
+        ApiKeyCreated {
+            api_key: format!("{}_{}_{}", API_KEY_PREFIX, key_id, secret),
+            key_id,
+        }
+    }
+
+    /// 校验 API Key，返回 key_id 与所属机构
+    pub fn authenticate(&self, api_key: &str) -> Result<(String, Principal), String> {
+        let mut parts = api_key.splitn(3, '_');
+        let (Some(API_KEY_PREFIX), Some(key_id), Some(secret)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err("API Key 格式无效".to_string());
+        };
+
+        let record = self.state.keys.get(key_id)
+            .filter(|r| !r.info.revoked && r.secret_hash == hash_secret(secret))
+            .ok_or_else(|| {
+                warn!("Rejected API key {}", key_id);
+                "API Key 无效或已吊销".to_string()
+            })?;
+        Ok((key_id.to_string(), record.info.institution_id))
+    }
+
+    /// 记录最近使用时间，仅在更新调用中生效
+    pub fn touch(&mut self, key_id: &str) {
+        if let Some(record) = self.state.keys.get_mut(key_id) {
+            record.info.last_used_at = Some(time());
+        }
+    }
+
+    pub fn list(&self, institution_id: Principal) -> Vec<ApiKeyInfo> {
+        self.state.keys.values()
+            .filter(|r| r.info.institution_id == institution_id)
+            .map(|r| r.info.clone())
+            .collect()
+    }
+
+    pub fn revoke(&mut self, institution_id: Principal, key_id: &str) -> Result<(), String> {
+        let record = self.state.keys.get_mut(key_id)
+            .filter(|r| r.info.institution_id == institution_id)
+            .ok_or_else(|| format!("API Key 不存在: {}", key_id))?;
+        record.info.revoked = true;
+        info!("API key {} revoked", key_id);
+        Ok(())
+    }
+
+    pub fn export_state(&self) -> ApiKeyState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: ApiKeyState) {
+        info!("Restored {} API keys", state.keys.len());
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn created_keys_authenticate_until_revoked() {
+        let bank = Principal::from_slice(&[1]);
+        let mut service = ApiKeyService::new();
+        let created = service.create_at(bank, &[0xab; 4], 0);
+        assert_eq!(created.key_id, "k1");
+        assert_eq!(created.api_key, "dck_k1_abababab");
+
+        assert_eq!(service.authenticate(&created.api_key), Ok(("k1".to_string(), bank)));
+        assert!(service.authenticate("dck_k1_abababac").is_err());
+        assert!(service.authenticate("xyz_k1_abababab").is_err());
+        assert!(service.authenticate("dck_k1").is_err());
+
+        // 只能吊销本机构的 Key
+        assert!(service.revoke(Principal::from_slice(&[2]), "k1").is_err());
+        service.revoke(bank, "k1").unwrap();
+        assert!(service.authenticate(&created.api_key).is_err());
+        assert!(service.list(bank)[0].revoked);
+    }
+
+    #[test]
+    fn only_secret_hashes_are_stored() {
+        let mut service = ApiKeyService::new();
+        let created = service.create_at(Principal::from_slice(&[1]), &[1, 2, 3], 0);
+        let record = &service.export_state().keys[&created.key_id];
+        assert_eq!(record.secret_hash, hash_secret("010203"));
+        assert_ne!(record.secret_hash, b"010203".to_vec());
+    }
+}
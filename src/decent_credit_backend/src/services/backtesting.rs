@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, HashSet};
+use log::info;
+use crate::models::backtest::*;
+use crate::models::dashboard::LevelDistribution;
+use crate::models::record::*;
+use crate::models::scoring::{InvalidDateHandling, ModelVersion, ScoringModel};
+use crate::services::credit_service::{resolve_event_time, CreditService};
+
+// 评分模型回测：按历史时间点重放记录，用两个模型版本分别评分并对比。
+// 不依赖 canister 运行时，可在原生 cargo test 中直接调用。
+
+/// 对给定记录集回测两个模型版本
+///
+/// 与评分一致按事件发生时间（`event_date`，无效时回退到提交时间）切分：
+/// 只有 `as_of` 之前（含）发生的记录参与评分；`as_of` 之后、`outcome_until`
+/// 之前发生逾期的用户视为"后续逾期"，用于衡量分数的排序能力。
+pub fn run_backtest(
+    service: &CreditService,
+    records: &[&CreditRecord],
+    baseline: &ScoringModel,
+    candidate: &ScoringModel,
+    as_of: u64,
+    outcome_until: Option<u64>,
+    user_dids: Option<&[String]>,
+) -> BacktestReport {
+    let selected: Option<HashSet<&str>> = user_dids
+        .map(|dids| dids.iter().map(|d| d.as_str()).collect());
+
+    let mut history: BTreeMap<&str, Vec<&CreditRecord>> = BTreeMap::new();
+    let mut later_overdue: HashSet<&str> = HashSet::new();
+
+    for &record in records {
+        let did = record.user_did.as_str();
+        if selected.as_ref().is_some_and(|s| !s.contains(did)) {
+            continue;
+        }
+        let event_time = resolve_event_time(record, u64::MAX, &InvalidDateHandling::FallbackToSubmission)
+            .unwrap_or(record.timestamp);
+        if event_time <= as_of {
+            history.entry(did).or_default().push(record);
+        } else if matches!(record.record_type, RecordType::OverdueRecord)
+            && outcome_until.is_none_or(|until| event_time <= until)
+        {
+            later_overdue.insert(did);
+        }
+    }
+
+    let scores: Vec<BacktestScore> = history.iter()
+        .map(|(did, user_records)| {
+            let baseline_score = service.assess_records_at(user_records, baseline, as_of).credit_score;
+            let candidate_score = service.assess_records_at(user_records, candidate, as_of).credit_score;
+            BacktestScore {
+                user_did: did.to_string(),
+                baseline_score,
+                candidate_score,
+                delta: candidate_score as i64 - baseline_score as i64,
+                later_overdue: later_overdue.contains(did),
+            }
+        })
+        .collect();
+
+    let users_scored = scores.len() as u64;
+    let (average_delta, max_abs_delta) = if scores.is_empty() {
+        (0.0, 0)
+    } else {
+        (
+            scores.iter().map(|s| s.delta as f64).sum::<f64>() / scores.len() as f64,
+            scores.iter().map(|s| s.delta.unsigned_abs()).max().unwrap_or(0),
+        )
+    };
+
+    info!(
+        "Backtest as of {}: {} users, {} with later overdue",
+        as_of, users_scored, scores.iter().filter(|s| s.later_overdue).count()
+    );
+
+    BacktestReport {
+        as_of,
+        users_scored,
+        users_with_later_overdue: scores.iter().filter(|s| s.later_overdue).count() as u64,
+        average_delta,
+        max_abs_delta,
+        baseline: summarize(version_of(baseline), &scores, |s| s.baseline_score),
+        candidate: summarize(version_of(candidate), &scores, |s| s.candidate_score),
+        scores,
+    }
+}
+
+fn version_of(model: &ScoringModel) -> ModelVersion {
+    ModelVersion {
+        model_id: model.model_id.clone(),
+        version: model.version,
+    }
+}
+
+fn summarize(
+    model: ModelVersion,
+    scores: &[BacktestScore],
+    score_of: impl Fn(&BacktestScore) -> u32,
+) -> ModelBacktestSummary {
+    let mut level_distribution = LevelDistribution::default();
+    for s in scores {
+        level_distribution.add_score(score_of(s) as u64);
+    }
+
+    let average_score = if scores.is_empty() {
+        0.0
+    } else {
+        scores.iter().map(|s| score_of(s) as f64).sum::<f64>() / scores.len() as f64
+    };
+
+    ModelBacktestSummary {
+        model,
+        average_score,
+        level_distribution,
+        rank_ordering: rank_ordering(scores, &score_of),
+    }
+}
+
+// 两两比较未逾期用户与后续逾期用户的分数，分数相同记半分
+fn rank_ordering(scores: &[BacktestScore], score_of: &impl Fn(&BacktestScore) -> u32) -> Option<f64> {
+    let good: Vec<u32> = scores.iter().filter(|s| !s.later_overdue).map(score_of).collect();
+    let bad: Vec<u32> = scores.iter().filter(|s| s.later_overdue).map(score_of).collect();
+    if good.is_empty() || bad.is_empty() {
+        return None;
+    }
+
+    let mut concordant = 0.0;
+    for g in &good {
+        for b in &bad {
+            if g > b {
+                concordant += 1.0;
+            } else if g == b {
+                concordant += 0.5;
+            }
+        }
+    }
+    Some(concordant / (good.len() * bad.len()) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+    use crate::models::scoring::ScoringParams;
+
+    const AS_OF: u64 = 1_000;
+    const OUTCOME_UNTIL: u64 = 2_000;
+
+    // 除基础分和逾期次数外全部归零，便于手算分数
+    fn model(version: u32, base_score: f64, overdue_frequency_weight: f64) -> ScoringModel {
+        ScoringModel {
+            model_id: "backtest".to_string(),
+            version,
+            name: format!("v{}", version),
+            description: String::new(),
+            params: ScoringParams {
+                base_score,
+                new_user_loan_activity: 0.0,
+                loan_frequency_weight: 0.0,
+                loan_activity_cap: 0.0,
+                new_user_repayment: 0.0,
+                repayment_ratio_weight: 0.0,
+                overdue_ratio_penalty: 0.0,
+                stability_weight: 0.0,
+                repayment_strength_weight: 0.0,
+                recent_activity_weight: 0.0,
+                consistency_weight: 0.0,
+                non_increasing_trend_bonus: 0.0,
+                overdue_days_unit: 1.0,
+                overdue_days_weight: 0.0,
+                overdue_days_cap: 0.0,
+                overdue_frequency_weight,
+                overdue_frequency_cap: 40.0,
+                overdue_amount_unit: 1.0,
+                overdue_amount_cap: 0.0,
+                max_score: 100.0,
+                ..ScoringParams::default()
+            },
+            created_by: Principal::anonymous(),
+            created_at: 0,
+        }
+    }
+
+    fn record(user_did: &str, record_type: RecordType, timestamp: u64) -> CreditRecord {
+        let content = match record_type {
+            RecordType::LoanRecord => RecordContent::Loan(LoanContent {
+                amount: 1_000,
+                loan_id: "L1".to_string(),
+                term_months: 12,
+                interest_rate: 5.0,
+            }),
+            RecordType::RepaymentRecord => RecordContent::Repayment(RepaymentContent {
+                amount: 1_000,
+                loan_id: "L1".to_string(),
+                repayment_date: String::new(),
+            }),
+            RecordType::OverdueRecord => RecordContent::Overdue(OverdueContent {
+                amount: 1_000,
+                overdueDays: 30,
+                period_amount: 1_000,
+            }),
+        };
+        CreditRecord {
+            id: format!("{}-{}", user_did, timestamp),
+            institution_id: Principal::anonymous(),
+            institution_name: String::new(),
+            institution_full_name: String::new(),
+            record_type,
+            user_did: user_did.to_string(),
+            event_date: String::new(),
+            content,
+            encrypted_content: Vec::new(),
+            proof: Vec::new(),
+            canister_id: String::new(),
+            timestamp,
+            status: RecordStatus::Confirmed,
+            reward_amount: None,
+            query_price: 0,
+            rejection_reason: None,
+            resubmitted_from: None,
+            resubmitted_as: None,
+            imported: None,
+        }
+    }
+
+    fn sample_records() -> Vec<CreditRecord> {
+        vec![
+            // alice：无逾期，截止时间之后的逾期不计入
+            record("did:alice", RecordType::LoanRecord, 100),
+            record("did:alice", RecordType::OverdueRecord, 3_000),
+            // bob：历史一次逾期，观察期内再次逾期
+            record("did:bob", RecordType::LoanRecord, 100),
+            record("did:bob", RecordType::OverdueRecord, 200),
+            record("did:bob", RecordType::OverdueRecord, 1_500),
+            // carol：历史一次逾期，之后未逾期
+            record("did:carol", RecordType::LoanRecord, 100),
+            record("did:carol", RecordType::OverdueRecord, 300),
+            // dave：历史两次逾期，观察期内再次逾期
+            record("did:dave", RecordType::LoanRecord, 100),
+            record("did:dave", RecordType::OverdueRecord, 200),
+            record("did:dave", RecordType::OverdueRecord, 300),
+            record("did:dave", RecordType::OverdueRecord, 1_800),
+            // erin：只有回放时间点之后的记录，不参与评分
+            record("did:erin", RecordType::LoanRecord, 1_200),
+        ]
+    }
+
+    #[test]
+    fn compares_models_on_hand_computed_scores() {
+        let records = sample_records();
+        let refs: Vec<&CreditRecord> = records.iter().collect();
+        let baseline = model(1, 85.0, 0.0);
+        let candidate = model(2, 95.0, 20.0);
+
+        let report = run_backtest(&CreditService::new(), &refs, &baseline, &candidate, AS_OF, Some(OUTCOME_UNTIL), None);
+
+        // 基准模型所有人 85 分；候选模型 95 - 20 × 历史逾期次数
+        let scores: Vec<(&str, u32, u32, i64, bool)> = report.scores.iter()
+            .map(|s| (s.user_did.as_str(), s.baseline_score, s.candidate_score, s.delta, s.later_overdue))
+            .collect();
+        assert_eq!(scores, vec![
+            ("did:alice", 85, 95, 10, false),
+            ("did:bob", 85, 75, -10, true),
+            ("did:carol", 85, 75, -10, false),
+            ("did:dave", 85, 55, -30, true),
+        ]);
+        assert_eq!(report.users_scored, 4);
+        assert_eq!(report.users_with_later_overdue, 2);
+        assert_eq!(report.average_delta, -10.0);
+        assert_eq!(report.max_abs_delta, 30);
+
+        let base = &report.baseline.level_distribution;
+        assert_eq!((base.aaa_count, base.aa_count, base.a_count, base.bb_count), (0, 4, 0, 0));
+        let cand = &report.candidate.level_distribution;
+        assert_eq!((cand.aaa_count, cand.aa_count, cand.a_count, cand.bb_count), (1, 0, 2, 1));
+        assert_eq!(report.baseline.average_score, 85.0);
+        assert_eq!(report.candidate.average_score, 75.0);
+
+        // 基准模型全部同分，每对记半分；候选模型：95>75, 95>55, 75=75, 75>55 → 3.5 / 4
+        assert_eq!(report.baseline.rank_ordering, Some(0.5));
+        assert_eq!(report.candidate.rank_ordering, Some(0.875));
+    }
+
+    #[test]
+    fn user_filter_and_missing_outcomes() {
+        let records = sample_records();
+        let refs: Vec<&CreditRecord> = records.iter().collect();
+        let baseline = model(1, 85.0, 0.0);
+        let candidate = model(2, 95.0, 20.0);
+        let selected = vec!["did:alice".to_string(), "did:carol".to_string()];
+
+        let report = run_backtest(&CreditService::new(), &refs, &baseline, &candidate, AS_OF, None, Some(&selected));
+
+        assert_eq!(report.users_scored, 2);
+        // 不限截止时间时 alice 之后的逾期也计入
+        assert!(report.scores.iter().find(|s| s.user_did == "did:alice").unwrap().later_overdue);
+        assert_eq!(report.candidate.rank_ordering, Some(0.0));
+
+        let report = run_backtest(&CreditService::new(), &refs, &baseline, &candidate, AS_OF, Some(OUTCOME_UNTIL), Some(&selected));
+        assert_eq!(report.users_with_later_overdue, 0);
+        assert_eq!(report.candidate.rank_ordering, None);
+    }
+
+    #[test]
+    fn splits_history_by_event_date() {
+        // 2024-10-01 与 2024-11-01（UTC）
+        let as_of = 1_727_740_800 * 1_000_000_000;
+        let later = 1_730_419_200 * 1_000_000_000;
+        // 回放时间点之后才补录、但发生在之前的逾期计入历史
+        let mut backfilled = record("did:frank", RecordType::OverdueRecord, later);
+        backfilled.event_date = "2024-09-15".to_string();
+        // 回放时间点之前提交、但发生在之后的逾期视为后续逾期
+        let mut future = record("did:gina", RecordType::OverdueRecord, 100);
+        future.event_date = "2024-10-20".to_string();
+        let records = [
+            record("did:frank", RecordType::LoanRecord, 100),
+            backfilled,
+            record("did:gina", RecordType::LoanRecord, 100),
+            future,
+        ];
+        let refs: Vec<&CreditRecord> = records.iter().collect();
+
+        let report = run_backtest(&CreditService::new(), &refs, &model(1, 85.0, 0.0), &model(2, 95.0, 20.0), as_of, Some(later), None);
+
+        let scores: Vec<(&str, u32, bool)> = report.scores.iter()
+            .map(|s| (s.user_did.as_str(), s.candidate_score, s.later_overdue))
+            .collect();
+        assert_eq!(scores, vec![("did:frank", 75, false), ("did:gina", 95, true)]);
+    }
+}
@@ -0,0 +1,605 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use log::{info, debug, warn, error};
+use crate::models::billing::*;
+use crate::models::pricing::*;
+use crate::models::record::*;
+use crate::services::admin_institution_service::ADMIN_SERVICE;
+use crate::services::pricing_service::PRICING_SERVICE;
+use crate::services::record_service::RECORD_SERVICE;
+use crate::services::token_service::*;
+
+// 待结算/待退款托管的重试间隔
+const SETTLEMENT_RETRY_INTERVAL_SECS: u64 = 60;
+
+// 需要跨升级保存的计费状态
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct BillingState {
+    pub escrows: HashMap<String, QueryEscrow>,
+    pub ledger: Vec<BillingLedgerEntry>,
+    pub next_escrow_id: u64,
+}
+
+pub struct BillingService {
+    state: BillingState,
+    in_flight: HashSet<String>,  // 正在进行链上操作的托管，避免定时任务重复处理
+}
+
+impl Default for BillingService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static BILLING_SERVICE: RefCell<BillingService> = RefCell::new(BillingService::new());
+}
+
+impl BillingService {
+    pub fn new() -> Self {
+        Self {
+            state: BillingState::default(),
+            in_flight: HashSet::new(),
+        }
+    }
+
+    pub fn export_state(&self) -> BillingState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: BillingState) {
+        info!(
+            "Restored billing state: {} escrows, {} ledger entries",
+            state.escrows.len(),
+            state.ledger.len()
+        );
+        self.state = state;
+        self.in_flight.clear();
+    }
+
+    pub fn open_escrow(&mut self, payer: Principal, charge: &QueryCharge, user_did: &str, splits: Vec<RevenueSplitLine>) -> String {
+        self.state.next_escrow_id += 1;
+        let id = format!("ESC-{}-{}", time() / 1_000_000_000, self.state.next_escrow_id);
+        let now = time();
+
+        self.state.escrows.insert(id.clone(), QueryEscrow {
+            id: id.clone(),
+            payer,
+            payee: charge.payee,
+            amount: charge.amount,
+            user_did: user_did.to_string(),
+            record_ids: charge.record_ids.clone(),
+            splits,
+            status: EscrowStatus::Created,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        });
+        debug!("Opened escrow {} for {} DCC", id, charge.amount);
+        id
+    }
+
+    pub fn get_escrow(&self, escrow_id: &str) -> Option<QueryEscrow> {
+        self.state.escrows.get(escrow_id).cloned()
+    }
+
+    pub fn get_escrows(&self, institution_id: Option<Principal>, status: Option<EscrowStatus>) -> Vec<QueryEscrow> {
+        let mut escrows: Vec<QueryEscrow> = self.state.escrows.values()
+            .filter(|e| institution_id.is_none_or(|id| e.payer == id || e.payee == id))
+            .filter(|e| status.as_ref().is_none_or(|s| e.status == *s))
+            .cloned()
+            .collect();
+        escrows.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+        escrows
+    }
+
+    pub fn get_ledger(&self, institution_id: Option<Principal>) -> Vec<BillingLedgerEntry> {
+        self.state.ledger.iter()
+            .filter(|entry| institution_id.is_none_or(|id| entry.from == id || entry.to == id))
+            .cloned()
+            .collect()
+    }
+
+    fn pending_escrow_ids(&self) -> Vec<String> {
+        self.state.escrows.values()
+            .filter(|e| matches!(e.status, EscrowStatus::SettlementPending | EscrowStatus::RefundPending))
+            .filter(|e| !self.in_flight.contains(&e.id))
+            .map(|e| e.id.clone())
+            .collect()
+    }
+
+    // 标记开始链上操作；已在处理中则返回 false
+    fn begin(&mut self, escrow_id: &str) -> bool {
+        self.in_flight.insert(escrow_id.to_string())
+    }
+
+    fn finish(&mut self, escrow_id: &str) {
+        self.in_flight.remove(escrow_id);
+    }
+
+    // route 为 (转出方, 转入方)，statuses 为 (成功后状态, 失败后状态)
+    fn record_step(
+        &mut self,
+        escrow_id: &str,
+        step: BillingStep,
+        (from, to): (Principal, Principal),
+        result: &Result<TransferResult, String>,
+        (next_status, failed_status): (EscrowStatus, EscrowStatus),
+        now: u64,
+    ) {
+        let entry_id = self.state.ledger.len() as u64 + 1;
+        let escrow = match self.state.escrows.get_mut(escrow_id) {
+            Some(escrow) => escrow,
+            None => {
+                error!("Escrow {} disappeared while recording {:?}", escrow_id, step);
+                return;
+            }
+        };
+
+        escrow.attempts += 1;
+        escrow.updated_at = now;
+        match result {
+            Ok(_) => {
+                escrow.status = next_status;
+                escrow.last_error = None;
+            }
+            Err(e) => {
+                escrow.status = failed_status;
+                escrow.last_error = Some(e.clone());
+            }
+        }
+
+        self.state.ledger.push(BillingLedgerEntry {
+            id: entry_id,
+            escrow_id: escrow_id.to_string(),
+            step,
+            from,
+            to,
+            amount: escrow.amount,
+            split_role: None,
+            success: result.is_ok(),
+            block_height: result.as_ref().ok().map(|r| r.block_height),
+            tx_hash: result.as_ref().ok().map(|r| r.tx_hash.clone()),
+            message: result.as_ref().err().cloned(),
+            timestamp: now,
+        });
+    }
+
+    // 记录一项分成的结算结果；全部单项结算后托管才算完成
+    fn record_split_step(
+        &mut self,
+        escrow_id: &str,
+        line_index: usize,
+        from: Principal,
+        result: &Result<Option<TransferResult>, String>,
+        now: u64,
+    ) {
+        let entry_id = self.state.ledger.len() as u64 + 1;
+        let escrow = match self.state.escrows.get_mut(escrow_id) {
+            Some(escrow) => escrow,
+            None => {
+                error!("Escrow {} disappeared while settling split {}", escrow_id, line_index);
+                return;
+            }
+        };
+        let line = &mut escrow.splits[line_index];
+
+        if let Ok(transfer) = result {
+            line.settled = true;
+            line.block_height = transfer.as_ref().map(|r| r.block_height);
+        }
+
+        self.state.ledger.push(BillingLedgerEntry {
+            id: entry_id,
+            escrow_id: escrow_id.to_string(),
+            step: BillingStep::Settle,
+            from,
+            to: line.recipient,
+            amount: line.amount,
+            split_role: Some(line.role.clone()),
+            success: result.is_ok(),
+            block_height: result.as_ref().ok().and_then(|r| r.as_ref().map(|t| t.block_height)),
+            tx_hash: result.as_ref().ok().and_then(|r| r.as_ref().map(|t| t.tx_hash.clone())),
+            message: result.as_ref().err().cloned(),
+            timestamp: now,
+        });
+    }
+
+    fn finish_settlement(&mut self, escrow_id: &str, error: Option<String>, now: u64) -> bool {
+        let Some(escrow) = self.state.escrows.get_mut(escrow_id) else {
+            return false;
+        };
+        escrow.attempts += 1;
+        escrow.updated_at = now;
+        let complete = escrow.splits.iter().all(|line| line.settled);
+        if complete {
+            escrow.status = EscrowStatus::Settled;
+            escrow.last_error = None;
+        } else {
+            escrow.status = EscrowStatus::SettlementPending;
+            escrow.last_error = error;
+        }
+        complete
+    }
+}
+
+fn token_canister_id() -> Principal {
+    TOKEN_SERVICE.with(|service| service.borrow().token_canister_id)
+}
+
+// 托管账户即本 canister 在代币账本上的账户
+fn escrow_account() -> Principal {
+    ic_cdk::id()
+}
+
+async fn move_escrow_funds(
+    escrow_id: &str,
+    step: BillingStep,
+    next_status: EscrowStatus,
+    failed_status: EscrowStatus,
+) -> Result<(), String> {
+    let escrow = BILLING_SERVICE.with(|service| service.borrow().get_escrow(escrow_id))
+        .ok_or_else(|| format!("托管记录不存在: {}", escrow_id))?;
+
+    let (from, to, memo) = match step {
+        BillingStep::Reserve => (escrow.payer, escrow_account(), format!("Reserve query fee {} for user {}", escrow.id, escrow.user_did)),
+        BillingStep::Refund => (escrow_account(), escrow.payer, format!("Refund query fee {} for user {}", escrow.id, escrow.user_did)),
+        BillingStep::Settle => return Err("结算请使用 settle".to_string()),
+    };
+
+    if !BILLING_SERVICE.with(|service| service.borrow_mut().begin(escrow_id)) {
+        return Err(format!("托管 {} 正在处理中", escrow_id));
+    }
+
+    let result = TokenService::operator_transfer_static(
+        token_canister_id(),
+        from,
+        to,
+        escrow.amount,
+        memo.clone(),
+    ).await;
+
+    BILLING_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        service.record_step(escrow_id, step, (from, to), &result, (next_status, failed_status), time());
+        service.finish(escrow_id);
+    });
+
+    let transfer_result = result?;
+    record_institution_transaction(escrow.amount, memo, &transfer_result);
+    Ok(())
+}
+
+fn record_institution_transaction(amount: u64, remarks: String, transfer_result: &TransferResult) {
+    ADMIN_SERVICE.with(|service| {
+        service.borrow_mut().record_dcc_transaction(
+            DCCTransactionRequest {
+                dcc_amount: amount,
+                usdt_amount: 0.0,
+                tx_hash: String::new(),
+                remarks,
+                created_at: time(),
+            },
+            transfer_result,
+        );
+    });
+}
+
+pub async fn reserve(escrow_id: &str) -> Result<(), String> {
+    move_escrow_funds(escrow_id, BillingStep::Reserve, EscrowStatus::Reserved, EscrowStatus::Failed).await
+}
+
+/// 按分成单项逐笔从托管账户支付；已结算的单项不会重复支付
+pub async fn settle(escrow_id: &str) -> Result<(), String> {
+    let escrow = BILLING_SERVICE.with(|service| service.borrow().get_escrow(escrow_id))
+        .ok_or_else(|| format!("托管记录不存在: {}", escrow_id))?;
+
+    if !BILLING_SERVICE.with(|service| service.borrow_mut().begin(escrow_id)) {
+        return Err(format!("托管 {} 正在处理中", escrow_id));
+    }
+
+    let mut last_error = None;
+    for (index, line) in escrow.splits.iter().enumerate().filter(|(_, line)| !line.settled) {
+        let memo = format!("Settle {:?} share of query fee {} for user {}", line.role, escrow.id, escrow.user_did);
+
+        // 平台份额未配置独立金库时留存在托管账户，无需转账
+        let result = if line.recipient == escrow_account() {
+            Ok(None)
+        } else {
+            TokenService::operator_transfer_static(
+                token_canister_id(),
+                escrow_account(),
+                line.recipient,
+                line.amount,
+                memo.clone(),
+            ).await.map(Some)
+        };
+
+        BILLING_SERVICE.with(|service| {
+            service.borrow_mut().record_split_step(escrow_id, index, escrow_account(), &result, time())
+        });
+
+        match result {
+            Ok(Some(transfer_result)) => record_institution_transaction(line.amount, memo, &transfer_result),
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Split {:?} of escrow {} failed: {}", line.role, escrow_id, e);
+                last_error = Some(e);
+                continue;
+            }
+        }
+
+        // 只有数据所有方是机构，计入其收益
+        if line.role == SplitRole::DataOwner {
+            TOKEN_SERVICE.with(|service| {
+                service.borrow_mut().update_institution_stats(line.recipient, line.amount, 0)
+            });
+        }
+    }
+
+    let complete = BILLING_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        let complete = service.finish_settlement(escrow_id, last_error.clone(), time());
+        service.finish(escrow_id);
+        complete
+    });
+
+    if !complete {
+        return Err(last_error.unwrap_or_else(|| "结算未完成".to_string()));
+    }
+
+    // 全部分成结算完成后计入查询方消费
+    TOKEN_SERVICE.with(|service| {
+        service.borrow_mut().update_institution_stats(escrow.payer, 0, escrow.amount)
+    });
+    Ok(())
+}
+
+pub async fn refund(escrow_id: &str) -> Result<(), String> {
+    move_escrow_funds(escrow_id, BillingStep::Refund, EscrowStatus::Refunded, EscrowStatus::RefundPending).await
+}
+
+/// 按数据所有方的分成比例和平台配置拆分查询费
+fn split_charge(charge: &QueryCharge, user_did: &str) -> Vec<RevenueSplitLine> {
+    let owner_ratio = ADMIN_SERVICE.with(|service| {
+        service.borrow().get_institution(charge.payee).map(|inst| inst.reward_share_ratio)
+    }).unwrap_or_else(|| PRICING_SERVICE.with(|service| service.borrow().default_owner_share()));
+
+    PRICING_SERVICE.with(|service| {
+        service.borrow().split_fee(charge.amount, charge.payee, owner_ratio, user_did)
+    })
+}
+
+// 为每个数据所有方开启托管并预留费用；任一失败则退还已预留的部分
+async fn reserve_charges(payer: Principal, user_did: &str, charges: Vec<QueryCharge>) -> Result<Vec<String>, String> {
+    let mut escrow_ids = Vec::new();
+
+    for charge in charges.into_iter().filter(|c| c.amount > 0) {
+        let splits = split_charge(&charge, user_did);
+        let escrow_id = BILLING_SERVICE.with(|service| {
+            service.borrow_mut().open_escrow(payer, &charge, user_did, splits)
+        });
+
+        if let Err(e) = reserve(&escrow_id).await {
+            warn!("Failed to reserve escrow {}: {}", escrow_id, e);
+            release_escrows(&escrow_ids, false).await;
+            return Err(format!("预留查询费失败: {}", e));
+        }
+        escrow_ids.push(escrow_id);
+    }
+
+    Ok(escrow_ids)
+}
+
+// 数据返回成功则结算，否则退款；链上失败的留给定时任务重试
+async fn release_escrows(escrow_ids: &[String], delivered: bool) {
+    for escrow_id in escrow_ids {
+        let result = if delivered {
+            settle(escrow_id).await
+        } else {
+            refund(escrow_id).await
+        };
+        if let Err(e) = result {
+            warn!("Escrow {} left pending for retry: {}", escrow_id, e);
+        }
+    }
+}
+
+// 成功返回数据后累计当月查询量，用于阶梯定价
+fn record_query_volume(payer: Principal, payees: &[Principal]) {
+    PRICING_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        for payee in payees {
+            service.record_query_volume(payer, *payee);
+        }
+    });
+}
+
+/// 先预留查询费，再解密返回数据，最后结算或退款
+pub async fn billed_records_query(institution_id: Principal, user_did: String) -> Result<Vec<CreditRecord>, String> {
+    let charges = RECORD_SERVICE.with(|service| {
+        service.borrow().quote_user_records(institution_id, &user_did)
+    })?;
+
+    let payees: Vec<Principal> = charges.iter().map(|c| c.payee).collect();
+    let escrow_ids = reserve_charges(institution_id, &user_did, charges).await?;
+
+    let result = RECORD_SERVICE.with(|service| {
+        service.borrow_mut().get_record_userId(institution_id, user_did.clone())
+    });
+
+    if result.is_ok() {
+        record_query_volume(institution_id, &payees);
+    }
+    release_escrows(&escrow_ids, result.is_ok()).await;
+    result
+}
+
+pub async fn billed_record_by_id(record_id: String, institution_id: Principal) -> Result<CreditRecord, String> {
+    let (user_did, charge) = RECORD_SERVICE.with(|service| {
+        service.borrow().quote_record(&record_id, institution_id)
+    })?;
+
+    let payees: Vec<Principal> = charge.iter().map(|c| c.payee).collect();
+    let escrow_ids = reserve_charges(institution_id, &user_did, charge.into_iter().collect()).await?;
+
+    let result = RECORD_SERVICE.with(|service| {
+        service.borrow().get_record_by_id(&record_id, institution_id)
+    }).ok_or_else(|| format!("Record with ID {} not found or access denied.", record_id));
+
+    if result.is_ok() {
+        record_query_volume(institution_id, &payees);
+    }
+    release_escrows(&escrow_ids, result.is_ok()).await;
+    result
+}
+
+/// 重试所有待结算和待退款的托管
+pub async fn retry_pending_settlements() {
+    let pending = BILLING_SERVICE.with(|service| service.borrow().pending_escrow_ids());
+    if pending.is_empty() {
+        return;
+    }
+    info!("Retrying {} pending escrows", pending.len());
+
+    for escrow_id in pending {
+        let status = BILLING_SERVICE.with(|service| {
+            service.borrow().get_escrow(&escrow_id).map(|e| e.status)
+        });
+        let result = match status {
+            Some(EscrowStatus::SettlementPending) => settle(&escrow_id).await,
+            Some(EscrowStatus::RefundPending) => refund(&escrow_id).await,
+            _ => continue,
+        };
+        if let Err(e) = result {
+            warn!("Retry of escrow {} failed: {}", escrow_id, e);
+        }
+    }
+}
+
+pub fn init_billing_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(SETTLEMENT_RETRY_INTERVAL_SECS), || {
+        ic_cdk::spawn(retry_pending_settlements());
+    });
+    info!("Billing settlement retry timer started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    fn split(role: SplitRole, recipient: Principal, amount: u64) -> RevenueSplitLine {
+        RevenueSplitLine { role, recipient, share: 0, amount, settled: false, block_height: None }
+    }
+
+    fn service_with_escrow(status: EscrowStatus) -> BillingService {
+        let mut service = BillingService::new();
+        service.state.escrows.insert("ESC-1".to_string(), QueryEscrow {
+            id: "ESC-1".to_string(),
+            payer: principal(1),
+            payee: principal(2),
+            amount: 100,
+            user_did: "did:example:alice".to_string(),
+            record_ids: vec!["R-1".to_string()],
+            splits: vec![
+                split(SplitRole::DataOwner, principal(2), 70),
+                split(SplitRole::PlatformTreasury, principal(3), 30),
+            ],
+            status,
+            attempts: 0,
+            last_error: None,
+            created_at: 1,
+            updated_at: 1,
+        });
+        service
+    }
+
+    fn transfer(block_height: u64) -> TransferResult {
+        TransferResult { block_height, tx_hash: format!("tx-{}", block_height) }
+    }
+
+    #[test]
+    fn reserve_moves_escrow_to_reserved() {
+        let mut service = service_with_escrow(EscrowStatus::Created);
+        service.record_step(
+            "ESC-1",
+            BillingStep::Reserve,
+            (principal(1), principal(9)),
+            &Ok(transfer(7)),
+            (EscrowStatus::Reserved, EscrowStatus::Failed),
+            10,
+        );
+
+        let escrow = service.get_escrow("ESC-1").unwrap();
+        assert_eq!(escrow.status, EscrowStatus::Reserved);
+        assert_eq!(escrow.attempts, 1);
+        let ledger = service.get_ledger(Some(principal(1)));
+        assert_eq!(ledger.len(), 1);
+        assert!(ledger[0].success);
+        assert_eq!(ledger[0].block_height, Some(7));
+    }
+
+    #[test]
+    fn failed_refund_stays_pending_for_retry() {
+        let mut service = service_with_escrow(EscrowStatus::Reserved);
+        service.record_step(
+            "ESC-1",
+            BillingStep::Refund,
+            (principal(9), principal(1)),
+            &Err("ledger unavailable".to_string()),
+            (EscrowStatus::Refunded, EscrowStatus::RefundPending),
+            10,
+        );
+
+        let escrow = service.get_escrow("ESC-1").unwrap();
+        assert_eq!(escrow.status, EscrowStatus::RefundPending);
+        assert_eq!(escrow.last_error.as_deref(), Some("ledger unavailable"));
+        assert_eq!(service.pending_escrow_ids(), vec!["ESC-1".to_string()]);
+
+        // 正在处理中的托管不会被定时任务重复取出
+        assert!(service.begin("ESC-1"));
+        assert!(!service.begin("ESC-1"));
+        assert!(service.pending_escrow_ids().is_empty());
+        service.finish("ESC-1");
+        assert_eq!(service.pending_escrow_ids().len(), 1);
+    }
+
+    #[test]
+    fn settlement_completes_only_when_every_split_is_paid() {
+        let mut service = service_with_escrow(EscrowStatus::Reserved);
+        service.record_split_step("ESC-1", 0, principal(9), &Ok(Some(transfer(8))), 10);
+        service.record_split_step("ESC-1", 1, principal(9), &Err("timeout".to_string()), 10);
+
+        assert!(!service.finish_settlement("ESC-1", Some("timeout".to_string()), 11));
+        let escrow = service.get_escrow("ESC-1").unwrap();
+        assert_eq!(escrow.status, EscrowStatus::SettlementPending);
+        assert!(escrow.splits[0].settled);
+        assert_eq!(escrow.splits[0].block_height, Some(8));
+        assert!(!escrow.splits[1].settled);
+
+        // 重试时只补付失败的单项
+        service.record_split_step("ESC-1", 1, principal(9), &Ok(Some(transfer(9))), 20);
+        assert!(service.finish_settlement("ESC-1", None, 21));
+        let escrow = service.get_escrow("ESC-1").unwrap();
+        assert_eq!(escrow.status, EscrowStatus::Settled);
+        assert_eq!(escrow.last_error, None);
+        assert_eq!(service.get_ledger(None).len(), 3);
+        assert_eq!(service.get_ledger(Some(principal(3))).len(), 2);
+    }
+
+    #[test]
+    fn escrows_filter_by_party_and_status() {
+        let service = service_with_escrow(EscrowStatus::SettlementPending);
+        assert_eq!(service.get_escrows(Some(principal(2)), None).len(), 1);
+        assert!(service.get_escrows(Some(principal(5)), None).is_empty());
+        assert!(service.get_escrows(None, Some(EscrowStatus::Settled)).is_empty());
+    }
+}
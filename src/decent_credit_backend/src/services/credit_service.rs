@@ -11,8 +11,18 @@ use crate::utils::error::Error;
 
 use crate::models::credit::*;
 use crate::models::record::*;
+use crate::models::scoring::*;
+use crate::models::backtest::{BacktestReport, BacktestRequest};
+use crate::services::backtesting;
+use crate::utils::time::parse_event_date;
+use crate::services::scoring_registry::ScoringRegistry;
+use crate::services::score_history_service::SCORE_HISTORY_SERVICE;
+use crate::services::identity_service::IDENTITY_SERVICE;
 
 
+// 评估结果中返回的主要不利因素数量
+const TOP_ADVERSE_FACTORS: usize = 4;
+
 // === 特征提取结构 ===
 #[derive(Debug)]
 pub struct CreditFeatures {
@@ -34,14 +44,30 @@ pub struct CreditFeatures {
     // 风险特征
     max_overdue_days: u64,
     total_overdue_amount: u64,
-    overdue_frequency: f64
+    overdue_frequency: f64,
+
+    // 近 N 个月活动统计及事件日期缺失/无效的记录数
+    activity_windows: Vec<ActivityWindow>,
+    invalid_date_count: u64,
+}
+
+#[derive(Debug)]
+struct ActivityWindow {
+    months: u32,
+    loans: u64,
+    repayments: u64,
+    overdues: u64,
 }
 
+// 按 30 天折算一个月
+const MONTH_NANOS: f64 = 30.0 * 24.0 * 60.0 * 60.0 * 1_000_000_000.0;
+
 pub struct CreditService {
     pub records: HashMap<String, CreditRecord>,      // 添加这个字段
     deduction_records: Vec<CreditDeductionRecord>,
     institution_records: HashMap<Principal, Vec<CreditRecord>>,
     admin_principals: Vec<Principal>,
+    scoring: ScoringRegistry,                          // 评分模型注册表
    }
 
 thread_local! {
@@ -55,13 +81,12 @@ impl CreditService {
             deduction_records: Vec::new(),
             institution_records: HashMap::new(),
             admin_principals: Vec::new(),
+            scoring: ScoringRegistry::default(),
         }
     }
     pub fn assess_user_risk(&self, institution_id: Principal, user_did: &str) -> Result<RiskAssessment, String> {
-        // 直接从 records HashMap 中获取用户记录
-        let user_records: Vec<&CreditRecord> = self.records.values()
-        .filter(|r| r.user_did == user_did)
-        .collect();
+        // 汇总该用户所有已关联 DID 下的记录
+        let user_records = self.linked_user_records(user_did);
 
     
         info!("Analyzing {} credit records for user {}", user_records.len(), user_did);
@@ -71,29 +96,184 @@ impl CreditService {
             return Err(format!("No credit records found for user {}", user_did));
         }
         
+        // 使用当前启用的评分模型
+        let model = self.scoring.active_model();
+        let assessment = self.assess_records(&user_records, &model);
+    
+        info!(
+            "Successfully created risk assessment for user {} with model {} v{}",
+            user_did, model.model_id, model.version
+        );
+        Ok(assessment)
+    }
+
+    /// 用户在各机构已关联 DID（含平台 DID）下的全部记录
+    fn linked_user_records(&self, user_did: &str) -> Vec<&CreditRecord> {
+        let dids = IDENTITY_SERVICE.with(|service| service.borrow().linked_dids(user_did));
+        if dids.len() > 1 {
+            debug!("Aggregating records across {} linked DIDs for {}", dids.len(), user_did);
+        }
+        self.records.values()
+            .filter(|r| dids.contains(&r.user_did))
+            .collect()
+    }
+
+    /// 用指定模型版本对一组记录评分，相同记录与模型版本总能得到相同结果
+    pub fn assess_records(&self, records: &[&CreditRecord], model: &ScoringModel) -> RiskAssessment {
+        self.assess_records_at(records, model, time())
+    }
+
+    /// 以指定时间点（纳秒）为"当前时间"评分，供回测按历史日期重放记录
+    pub fn assess_records_at(&self, records: &[&CreditRecord], model: &ScoringModel, as_of: u64) -> RiskAssessment {
         // 提取信用特征
-        let features = self.extract_credit_features(&user_records);
+        let features = self.extract_credit_features(records, as_of, &model.params.time_features());
         
-        // 计算信用分数
-        let credit_score = self.calculate_credit_score(&features);
+        // 计算各特征贡献和信用分数
+        let contributions = self.calculate_contributions(&features, &model.params);
+        let credit_score = self.calculate_credit_score(&contributions, &model.params);
         
         // 生成风险评估
-        let (risk_level, details, suggestions) = self.generate_risk_assessment(credit_score, &features);
+        let (risk_level, details, suggestions) = self.generate_risk_assessment(credit_score, &features, &model.params);
         
-        let assessment = RiskAssessment {
+        RiskAssessment {
             credit_score,
-            risk_level: risk_level.clone(),
+            risk_level,
             assessment_details: details,
             suggestions,
+            model_id: model.model_id.clone(),
+            model_version: model.version,
+            top_adverse_factors: top_adverse_factors(&contributions),
+            contributions,
+        }
+    }
+
+    /// 在现有记录基础上加入假设记录重新评分，不保存任何数据
+    pub fn simulate_score(&self, request: &ScoreSimulationRequest) -> Result<ScoreSimulationResult, String> {
+        self.simulate_score_at(request, time())
+    }
+
+    fn simulate_score_at(&self, request: &ScoreSimulationRequest, now: u64) -> Result<ScoreSimulationResult, String> {
+        if request.hypothetical_records.is_empty() {
+            return Err("至少需要一条假设记录".to_string());
+        }
+
+        let model = match &request.model {
+            Some(version) => self.scoring.get(&version.model_id, Some(version.version))
+                .ok_or_else(|| format!("评分模型不存在: {} v{}", version.model_id, version.version))?,
+            None => self.scoring.active_model(),
         };
-    
-        info!("Successfully created risk assessment for user {}", user_did);
-        Ok(assessment)
+
+        let existing = self.linked_user_records(&request.user_did);
+
+        let hypothetical = request.hypothetical_records.iter()
+            .enumerate()
+            .map(|(index, h)| {
+                let content_matches = matches!(
+                    (&h.record_type, &h.content),
+                    (RecordType::LoanRecord, RecordContent::Loan(_))
+                        | (RecordType::RepaymentRecord, RecordContent::Repayment(_))
+                        | (RecordType::OverdueRecord, RecordContent::Overdue(_))
+                );
+                if !content_matches {
+                    return Err(format!("第{}条假设记录的类型与内容不匹配", index + 1));
+                }
+                Ok(CreditRecord {
+                    id: format!("SIM-{}", index + 1),
+                    institution_id: Principal::anonymous(),
+                    institution_name: String::new(),
+                    institution_full_name: String::new(),
+                    record_type: h.record_type.clone(),
+                    user_did: request.user_did.clone(),
+                    event_date: h.event_date.clone(),
+                    content: h.content.clone(),
+                    encrypted_content: Vec::new(),
+                    proof: Vec::new(),
+                    canister_id: String::new(),
+                    timestamp: now,
+                    status: RecordStatus::Confirmed,
+                    reward_amount: None,
+                    query_price: 0,
+                    rejection_reason: None,
+                    resubmitted_from: None,
+                    resubmitted_as: None,
+                    imported: None,
+                })
+            })
+            .collect::<Result<Vec<CreditRecord>, String>>()?;
+
+        let current = if existing.is_empty() {
+            None
+        } else {
+            Some(self.assess_records_at(&existing, &model, now))
+        };
+
+        let mut combined = existing;
+        combined.extend(hypothetical.iter());
+        let simulated = self.assess_records_at(&combined, &model, now);
+
+        let score_delta = simulated.credit_score as i64
+            - current.as_ref().map_or(0, |c| c.credit_score as i64);
+        debug!("Simulated score for {}: delta {}", request.user_did, score_delta);
+
+        Ok(ScoreSimulationResult {
+            user_did: request.user_did.clone(),
+            current,
+            simulated,
+            score_delta,
+        })
     }
 
-fn extract_credit_features(&self, records: &[&CreditRecord]) -> CreditFeatures {
-    let now = time();
-    
+    /// 用当前保存的全部记录回测两个模型版本
+    pub fn run_backtest(&self, request: &BacktestRequest) -> Result<BacktestReport, String> {
+        let baseline = self.scoring.get(&request.baseline.model_id, Some(request.baseline.version))
+            .ok_or_else(|| format!("评分模型不存在: {} v{}", request.baseline.model_id, request.baseline.version))?;
+        let candidate = self.scoring.get(&request.candidate.model_id, Some(request.candidate.version))
+            .ok_or_else(|| format!("评分模型不存在: {} v{}", request.candidate.model_id, request.candidate.version))?;
+        if request.outcome_until.is_some_and(|until| until <= request.as_of) {
+            return Err("观察截止时间必须晚于回放时间点".to_string());
+        }
+
+        let records: Vec<&CreditRecord> = self.records.values().collect();
+        Ok(backtesting::run_backtest(
+            self,
+            &records,
+            &baseline,
+            &candidate,
+            request.as_of,
+            request.outcome_until,
+            request.user_dids.as_deref(),
+        ))
+    }
+
+    // === 评分模型注册表 ===
+
+    pub fn scoring_registry(&self) -> &ScoringRegistry {
+        &self.scoring
+    }
+
+    pub fn scoring_registry_mut(&mut self) -> &mut ScoringRegistry {
+        &mut self.scoring
+    }
+
+    pub fn export_scoring_registry(&self) -> ScoringRegistry {
+        self.scoring.clone()
+    }
+
+    pub fn restore_scoring_registry(&mut self, registry: ScoringRegistry) {
+        info!("Restored scoring registry, active model {:?}", registry.active_version());
+        self.scoring = registry;
+    }
+
+fn extract_credit_features(&self, records: &[&CreditRecord], now: u64, time_params: &TimeFeatureParams) -> CreditFeatures {
+    // 事件发生时间：按 event_date 解析，缺失、无效或晚于评估时间的按配置处理
+    let invalid_date_count = records.iter()
+        .filter(|r| parse_event_date(&r.event_date).is_none_or(|t| t > now))
+        .count() as u64;
+    if invalid_date_count > 0 {
+        debug!("{} records with missing or invalid event_date", invalid_date_count);
+    }
+    let event_time = |r: &CreditRecord| resolve_event_time(r, now, &time_params.invalid_date_handling);
+
     // 按类型分类记录
     let loan_records: Vec<_> = records.iter()
         .filter(|r| matches!(r.record_type, RecordType::LoanRecord))
@@ -108,7 +288,7 @@ fn extract_credit_features(&self, records: &[&CreditRecord]) -> CreditFeatures {
         .collect();
 
     // 计算基础特征
-    let loan_frequency = self.calculate_frequency(&loan_records);
+    let loan_frequency = self.calculate_frequency(&loan_records, now, &event_time);
     let repayment_ratio = if loan_records.is_empty() {
         0.0
     } else {
@@ -126,8 +306,12 @@ fn extract_credit_features(&self, records: &[&CreditRecord]) -> CreditFeatures {
     let amount_variance = self.calculate_amount_variance(&records);
 
     // 计算时序特征
-    let recent_activity_score = self.calculate_recent_activity(&records);
-    let overdue_trend = self.calculate_overdue_trend(&overdue_records);
+    let recent_activity_score = self.calculate_recent_activity(
+        records, now, time_params.recency_half_life_months, &event_time);
+    let overdue_trend = self.calculate_overdue_trend(&overdue_records, &event_time);
+    let activity_windows = time_params.lookback_windows_months.iter()
+        .map(|&months| self.calculate_activity_window(records, now, months, &event_time))
+        .collect();
     let repayment_consistency = self.calculate_repayment_consistency(&repayment_records);
 
     // 计算风险特征
@@ -146,26 +330,29 @@ fn extract_credit_features(&self, records: &[&CreditRecord]) -> CreditFeatures {
         repayment_consistency,
         max_overdue_days,
         total_overdue_amount,
-        overdue_frequency
+        overdue_frequency,
+        activity_windows,
+        invalid_date_count,
     }
 }
 // 修改计算频率的方法，避免 unwrap
-fn calculate_frequency(&self, records: &[&&CreditRecord]) -> f64 {
-    let now = time();
-    
-    match records.len() {
+fn calculate_frequency(
+    &self,
+    records: &[&&CreditRecord],
+    now: u64,
+    event_time: &impl Fn(&CreditRecord) -> Option<u64>,
+) -> f64 {
+    let timestamps: Vec<u64> = records.iter()
+        .filter_map(|r| event_time(r))
+        .collect();
+
+    match timestamps.len() {
         0 => 0.0,
         1 => {
-            let record_time = records[0].timestamp;
-            let time_span = (now - record_time) as f64;
-            let months = time_span / (30.0 * 24.0 * 60.0 * 60.0 * 1_000_000_000.0);
+            let months = now.saturating_sub(timestamps[0]) as f64 / MONTH_NANOS;
             1.0 / months.max(1.0)
         }
         _ => {
-            let timestamps: Vec<u64> = records.iter()
-                .map(|r| r.timestamp)
-                .collect();
-            
             let max_time = timestamps.iter()
                 .max()
                 .copied()
@@ -175,10 +362,9 @@ fn calculate_frequency(&self, records: &[&&CreditRecord]) -> f64 {
                 .copied()
                 .unwrap_or(now);
                 
-            let time_span = (max_time - min_time) as f64;
-            let months = time_span / (30.0 * 24.0 * 60.0 * 60.0 * 1_000_000_000.0);
+            let months = (max_time - min_time) as f64 / MONTH_NANOS;
             
-            records.len() as f64 / months.max(1.0)
+            timestamps.len() as f64 / months.max(1.0)
         }
     }
 }
@@ -220,33 +406,65 @@ fn calculate_amount_variance(&self, records: &[&CreditRecord]) -> f64 {
     variance
 }
 
-fn calculate_recent_activity(&self, records: &[&CreditRecord]) -> f64 {
-    let now = time();
-    let thirty_days_ago = now - 30 * 24 * 60 * 60 * 1_000_000_000;
-    
-    let recent_records: Vec<_> = records.iter()
-        .filter(|r| r.timestamp > thirty_days_ago)
+// 近期活跃度：每条记录按距今月数指数衰减后取平均，半衰期内的记录权重不低于 0.5
+fn calculate_recent_activity(
+    &self,
+    records: &[&CreditRecord],
+    now: u64,
+    half_life_months: f64,
+    event_time: &impl Fn(&CreditRecord) -> Option<u64>,
+) -> f64 {
+    let weights: Vec<f64> = records.iter()
+        .filter_map(|r| event_time(r))
+        .map(|t| {
+            let age_months = now.saturating_sub(t) as f64 / MONTH_NANOS;
+            0.5f64.powf(age_months / half_life_months.max(f64::EPSILON))
+        })
         .collect();
-        
-    if records.is_empty() {
+
+    if weights.is_empty() {
         0.0
     } else {
-        recent_records.len() as f64 / records.len() as f64
+        weights.iter().sum::<f64>() / weights.len() as f64
     }
 }
 
-fn calculate_overdue_trend(&self, overdue_records: &[&&CreditRecord]) -> f64 {
-    if overdue_records.len() < 2 {
-        return 0.0;
+fn calculate_activity_window(
+    &self,
+    records: &[&CreditRecord],
+    now: u64,
+    months: u32,
+    event_time: &impl Fn(&CreditRecord) -> Option<u64>,
+) -> ActivityWindow {
+    let since = now.saturating_sub((months as f64 * MONTH_NANOS) as u64);
+    let mut window = ActivityWindow { months, loans: 0, repayments: 0, overdues: 0 };
+
+    for record in records.iter().filter(|r| event_time(r).is_some_and(|t| t >= since)) {
+        match record.record_type {
+            RecordType::LoanRecord => window.loans += 1,
+            RecordType::RepaymentRecord => window.repayments += 1,
+            RecordType::OverdueRecord => window.overdues += 1,
+        }
     }
+    window
+}
 
+fn calculate_overdue_trend(
+    &self,
+    overdue_records: &[&&CreditRecord],
+    event_time: &impl Fn(&CreditRecord) -> Option<u64>,
+) -> f64 {
     let overdue_amounts: Vec<(u64, f64)> = overdue_records.iter()
-        .map(|r| match &r.content {
-            RecordContent::Overdue(content) => (r.timestamp, content.amount as f64),
-            _ => unreachable!(),
+        .filter_map(|r| match &r.content {
+            RecordContent::Overdue(content) => event_time(r).map(|t| (t, content.amount as f64)),
+            _ => None,
         })
         .collect();
 
+    if overdue_amounts.len() < 2 {
+        return 0.0;
+    }
+
     let n = overdue_amounts.len() as f64;
     let mean_x: f64 = overdue_amounts.iter().map(|(t, _)| *t as f64).sum::<f64>() / n;
     let mean_y: f64 = overdue_amounts.iter().map(|(_, a)| *a).sum::<f64>() / n;
@@ -340,77 +558,89 @@ fn extract_overdue_features(&self, overdue_records: &[&&CreditRecord]) -> (u64,
     (max_overdue_days, total_overdue_amount, overdue_frequency)
 }
 
-fn calculate_credit_score(&self, features: &CreditFeatures) -> u32 {
-    let base_score = 60.0;
-    
-    // 基础行为分数 (0-30分)
-    let behavior_score = {
-        // 对于新用户，应该有初始信用
-        let loan_activity = if features.loan_frequency == 0.0 {
-            5.0  // 给予新用户基础分
-        } else {
-            (features.loan_frequency * 5.0).min(10.0)
-        };
-        
-        let repayment_score = if features.repayment_ratio == 0.0 && features.loan_frequency == 0.0 {
-            5.0  // 新用户基础分
-        } else {
-            features.repayment_ratio * 10.0
-        };
-        
-        let overdue_penalty = features.overdue_ratio * -10.0;
-        
-        loan_activity + repayment_score + overdue_penalty
-    };
-    
-    // 金额特征分数 (0-20分)
-    let amount_score = {
-        let stability = (10.0 / (1.0 + features.amount_variance.sqrt())).min(10.0);
-        let repayment_strength = if features.avg_loan_amount > 0.0 {
-            (features.avg_repayment_amount / features.avg_loan_amount * 10.0).min(10.0)
-        } else {
-            0.0
-        };
+fn calculate_credit_score(&self, contributions: &[FeatureContribution], params: &ScoringParams) -> u32 {
+    let total: f64 = contributions.iter().map(|c| c.contribution).sum();
+
+    let final_score = (params.base_score + total)
+        .round()
+        .max(params.min_score)
+        .min(params.max_score);
         
-        stability + repayment_strength
+    final_score as u32
+}
+
+// 按模型参数计算每个特征的得分贡献，各项之和加基础分即为最终分数（截断前）
+fn calculate_contributions(&self, features: &CreditFeatures, params: &ScoringParams) -> Vec<FeatureContribution> {
+    // 基础行为分数：对于新用户，应该有初始信用
+    let loan_activity = if features.loan_frequency == 0.0 {
+        params.new_user_loan_activity
+    } else {
+        (features.loan_frequency * params.loan_frequency_weight).min(params.loan_activity_cap)
     };
-    
-    // 时序特征分数 (0-20分)
-    let time_score = {
-        let recent_score = features.recent_activity_score * 10.0;
-        let consistency_score = features.repayment_consistency * 5.0;
-        let trend_score = if features.overdue_trend <= 0.0 { 5.0 } else { 0.0 };
-        
-        recent_score + consistency_score + trend_score
+    let repayment_score = if features.repayment_ratio == 0.0 && features.loan_frequency == 0.0 {
+        params.new_user_repayment
+    } else {
+        features.repayment_ratio * params.repayment_ratio_weight
     };
-    
-    // 风险惩罚 (-50到0分)
-    let risk_penalty = {
-        let overdue_penalty = -(features.max_overdue_days as f64 / 30.0 * 20.0).min(20.0);
-        let frequency_penalty = -(features.overdue_frequency * 10.0).min(20.0);
-        let amount_penalty = -(features.total_overdue_amount as f64 / 10000.0).min(10.0);
-        
-        overdue_penalty + frequency_penalty + amount_penalty
+    let overdue_ratio_penalty = features.overdue_ratio * -params.overdue_ratio_penalty;
+
+    // 金额特征分数
+    let stability = (params.stability_weight / (1.0 + features.amount_variance.sqrt())).min(params.stability_weight);
+    let repayment_strength = if features.avg_loan_amount > 0.0 {
+        (features.avg_repayment_amount / features.avg_loan_amount * params.repayment_strength_weight)
+            .min(params.repayment_strength_weight)
+    } else {
+        0.0
     };
-    
-    let final_score = (base_score + behavior_score + amount_score + time_score + risk_penalty)
-        .round()
-        .max(0.0)
-        .min(150.0);
-        
-    final_score as u32
+
+    // 时序特征分数
+    let recent_score = features.recent_activity_score * params.recent_activity_weight;
+    let consistency_score = features.repayment_consistency * params.consistency_weight;
+    let trend_score = if features.overdue_trend <= 0.0 { params.non_increasing_trend_bonus } else { 0.0 };
+
+    // 风险惩罚
+    let overdue_days_penalty = -(features.max_overdue_days as f64 / params.overdue_days_unit * params.overdue_days_weight)
+        .min(params.overdue_days_cap);
+    let frequency_penalty = -(features.overdue_frequency * params.overdue_frequency_weight)
+        .min(params.overdue_frequency_cap);
+    let amount_penalty = -(features.total_overdue_amount as f64 / params.overdue_amount_unit)
+        .min(params.overdue_amount_cap);
+
+    vec![
+        contribution("LOAN_FREQUENCY", "loan_frequency", features.loan_frequency, loan_activity,
+            params.loan_activity_cap.max(params.new_user_loan_activity)),
+        contribution("REPAYMENT_RATIO", "repayment_ratio", features.repayment_ratio, repayment_score,
+            params.repayment_ratio_weight.max(params.new_user_repayment)),
+        contribution("OVERDUE_RATIO", "overdue_ratio", features.overdue_ratio, overdue_ratio_penalty, 0.0),
+        // 平均借款金额仅作为还款能力的分母，本身不单独计分
+        contribution("AVG_LOAN_AMOUNT", "avg_loan_amount", features.avg_loan_amount, 0.0, 0.0),
+        contribution("REPAYMENT_STRENGTH", "avg_repayment_amount", features.avg_repayment_amount, repayment_strength,
+            params.repayment_strength_weight),
+        contribution("AMOUNT_STABILITY", "amount_variance", features.amount_variance, stability, params.stability_weight),
+        contribution("RECENT_ACTIVITY", "recent_activity_score", features.recent_activity_score, recent_score,
+            params.recent_activity_weight),
+        contribution("OVERDUE_TREND", "overdue_trend", features.overdue_trend, trend_score, params.non_increasing_trend_bonus),
+        contribution("REPAYMENT_CONSISTENCY", "repayment_consistency", features.repayment_consistency, consistency_score,
+            params.consistency_weight),
+        contribution("OVERDUE_DAYS", "max_overdue_days", features.max_overdue_days as f64, overdue_days_penalty, 0.0),
+        contribution("OVERDUE_AMOUNT", "total_overdue_amount", features.total_overdue_amount as f64, amount_penalty, 0.0),
+        contribution("OVERDUE_FREQUENCY", "overdue_frequency", features.overdue_frequency, frequency_penalty, 0.0),
+    ]
 }
 
 fn generate_risk_assessment(
     &self,
     credit_score: u32,
-    features: &CreditFeatures
+    features: &CreditFeatures,
+    params: &ScoringParams
 ) -> (String, Vec<String>, Vec<String>) {
-    // 根据信用分数确定风险等级
-    let risk_level = match credit_score {
-        0..=50 => "High Risk",    // 原来是 0..=60
-        51..=70 => "Medium Risk", // 原来是 61..=80
-        _ => "Low Risk",
+    // 根据模型的风险等级阈值确定风险等级
+    let risk_level = if credit_score <= params.high_risk_max {
+        "High Risk"
+    } else if credit_score <= params.medium_risk_max {
+        "Medium Risk"
+    } else {
+        "Low Risk"
     }.to_string();
     
     // 生成详细评估信息
@@ -436,6 +666,15 @@ fn generate_risk_assessment(
             if features.overdue_trend > 0.0 { "Increasing" } else { "Decreasing" }
         ));
     }
+    for window in &features.activity_windows {
+        details.push(format!(
+            "Last {} Months: {} loans, {} repayments, {} overdues",
+            window.months, window.loans, window.repayments, window.overdues
+        ));
+    }
+    if features.invalid_date_count > 0 {
+        details.push(format!("Records With Missing Or Invalid Event Date: {}", features.invalid_date_count));
+    }
 
     // 生成建议
     let suggestions = match risk_level.as_str() {
@@ -506,7 +745,7 @@ pub fn query_assessment_reports(
 ) -> AssessmentListResponse {
    // 使用 with_reports_storage 辅助函数来确保正确的借用
 let reports = with_reports_storage(|storage| {
-    storage.query_reports(institution_id)
+    storage.query_reports(institution_id, days)
 });
     AssessmentListResponse {
         status: "SUCCESS".to_string(),
@@ -514,4 +753,308 @@ let reports = with_reports_storage(|storage| {
         data: reports,
     }
 }
+
+pub fn query_assessment_reports_page(&self, query: &ReportQuery) -> AssessmentPageResponse {
+    with_reports_storage(|storage| storage.query_reports_page(query))
+}
+
+/// 按报告ID查询，只能查看本机构生成的报告
+pub fn get_assessment_report(&self, institution_id: Principal, report_id: &str) -> AssessmentResponse {
+    match with_reports_storage(|storage| storage.get_report(report_id)) {
+        Some(report) if report.institution_id == institution_id => AssessmentResponse {
+            status: "SUCCESS".to_string(),
+            message: None,
+            data: Some(report),
+        },
+        _ => AssessmentResponse {
+            status: "NOT_FOUND".to_string(),
+            message: Some(format!("Report {} not found", report_id)),
+            data: None,
+        },
+    }
+}
+}
+
+/// 保存评估报告和评分历史，分数骤降时生成提醒
+pub fn record_assessment(institution_id: Principal, user_did: &str, assessment: &RiskAssessment) -> RiskAssessmentReport {
+    SCORE_HISTORY_SERVICE.with(|history| {
+        history.borrow_mut().record(user_did, institution_id, assessment)
+    });
+    let report = with_reports_storage(|storage| {
+        storage.create_report(institution_id, user_did, assessment.clone())
+    });
+    info!("Stored assessment report {} for user {}", report.report_id, user_did);
+    report
+}
+
+// 记录的事件发生时间；日期缺失、无法解析或晚于评估时间时按配置回退到提交时间或忽略
+pub(crate) fn resolve_event_time(record: &CreditRecord, now: u64, handling: &InvalidDateHandling) -> Option<u64> {
+    match parse_event_date(&record.event_date) {
+        Some(t) if t <= now => Some(t),
+        _ => match handling {
+            InvalidDateHandling::FallbackToSubmission => Some(record.timestamp.min(now)),
+            InvalidDateHandling::Exclude => None,
+        },
+    }
+}
+
+fn contribution(code: &str, feature: &str, value: f64, contribution: f64, max_contribution: f64) -> FeatureContribution {
+    let direction = if contribution > 0.0 {
+        ContributionDirection::Positive
+    } else if contribution < 0.0 {
+        ContributionDirection::Negative
+    } else {
+        ContributionDirection::Neutral
+    };
+    FeatureContribution {
+        code: code.to_string(),
+        feature: feature.to_string(),
+        value,
+        contribution,
+        max_contribution,
+        direction,
+    }
+}
+
+// 类似 FICO 原因代码：按相对最高贡献的失分排序，取失分最多的前几项
+fn top_adverse_factors(contributions: &[FeatureContribution]) -> Vec<String> {
+    let mut adverse: Vec<(&FeatureContribution, f64)> = contributions.iter()
+        .map(|c| (c, c.max_contribution - c.contribution))
+        .filter(|(_, lost)| *lost > 0.0)
+        .collect();
+    adverse.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    adverse.into_iter()
+        .take(TOP_ADVERSE_FACTORS)
+        .map(|(c, _)| c.code.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-10-15T00:00:00Z
+    const AS_OF: u64 = 1_728_950_400 * 1_000_000_000;
+
+    fn model() -> ScoringModel {
+        ScoringModel {
+            model_id: "default".to_string(),
+            version: 1,
+            name: String::new(),
+            description: String::new(),
+            params: ScoringParams::default(),
+            created_by: Principal::anonymous(),
+            created_at: 0,
+        }
+    }
+
+    fn record(id: &str, event_date: &str, content: RecordContent) -> CreditRecord {
+        let record_type = match content {
+            RecordContent::Loan(_) => RecordType::LoanRecord,
+            RecordContent::Repayment(_) => RecordType::RepaymentRecord,
+            RecordContent::Overdue(_) => RecordType::OverdueRecord,
+        };
+        CreditRecord {
+            id: id.to_string(),
+            institution_id: Principal::anonymous(),
+            institution_name: String::new(),
+            institution_full_name: String::new(),
+            record_type,
+            user_did: "did:alice".to_string(),
+            event_date: event_date.to_string(),
+            content,
+            encrypted_content: Vec::new(),
+            proof: Vec::new(),
+            canister_id: String::new(),
+            timestamp: AS_OF,
+            status: RecordStatus::Confirmed,
+            reward_amount: None,
+            query_price: 0,
+            rejection_reason: None,
+            resubmitted_from: None,
+            resubmitted_as: None,
+            imported: None,
+        }
+    }
+
+    fn loan(amount: u64) -> RecordContent {
+        RecordContent::Loan(LoanContent { amount, loan_id: "L1".to_string(), term_months: 12, interest_rate: 5.0 })
+    }
+
+    fn repayment(amount: u64, repayment_date: &str) -> RecordContent {
+        RecordContent::Repayment(RepaymentContent { amount, loan_id: "L1".to_string(), repayment_date: repayment_date.to_string() })
+    }
+
+    fn overdue(amount: u64, days: u64) -> RecordContent {
+        RecordContent::Overdue(OverdueContent { amount, overdueDays: days, period_amount: amount })
+    }
+
+    fn contribution_of(assessment: &RiskAssessment, code: &str) -> f64 {
+        assessment.contributions.iter().find(|c| c.code == code).unwrap().contribution
+    }
+
+    fn hypothetical(content: RecordContent) -> HypotheticalRecord {
+        let record = record("H", "2024-10-15", content);
+        HypotheticalRecord {
+            record_type: record.record_type,
+            event_date: record.event_date,
+            content: record.content,
+        }
+    }
+
+    fn simulation(user_did: &str, hypothetical_records: Vec<HypotheticalRecord>) -> ScoreSimulationRequest {
+        ScoreSimulationRequest {
+            user_did: user_did.to_string(),
+            hypothetical_records,
+            model: None,
+        }
+    }
+
+    #[test]
+    fn simulation_scores_hypothetical_records_without_saving() {
+        let mut service = CreditService::new();
+        for record in [
+            record("R1", "2024-10-15", loan(1_000)),
+            record("R2", "2024-10-15", repayment(1_000, "2024-10-15")),
+        ] {
+            service.records.insert(record.id.clone(), record);
+        }
+
+        let request = simulation("did:alice", vec![hypothetical(overdue(5_000, 45))]);
+        let result = service.simulate_score_at(&request, AS_OF).unwrap();
+        assert_eq!(result.current.as_ref().map(|c| c.credit_score), Some(115));
+        assert_eq!(result.simulated.credit_score, 65);
+        assert_eq!(result.score_delta, -50);
+        assert_eq!(service.records.len(), 2);
+
+        // 无记录的新用户：当前评估为空，差值按 0 分计算
+        let request = simulation("did:bob", vec![hypothetical(loan(1_000))]);
+        let result = service.simulate_score_at(&request, AS_OF).unwrap();
+        assert!(result.current.is_none());
+        assert_eq!(result.score_delta, result.simulated.credit_score as i64);
+    }
+
+    #[test]
+    fn simulation_rejects_invalid_requests() {
+        let service = CreditService::new();
+        assert!(service.simulate_score_at(&simulation("did:alice", Vec::new()), AS_OF).is_err());
+
+        let mismatched = HypotheticalRecord { record_type: RecordType::OverdueRecord, ..hypothetical(loan(1_000)) };
+        assert!(service.simulate_score_at(&simulation("did:alice", vec![mismatched]), AS_OF).is_err());
+
+        let unknown_model = ScoreSimulationRequest {
+            model: Some(ModelVersion { model_id: "missing".to_string(), version: 1 }),
+            ..simulation("did:alice", vec![hypothetical(loan(1_000))])
+        };
+        assert!(service.simulate_score_at(&unknown_model, AS_OF).is_err());
+    }
+
+    fn time_params(invalid_date_handling: InvalidDateHandling) -> TimeFeatureParams {
+        TimeFeatureParams {
+            lookback_windows_months: vec![1, 6],
+            recency_half_life_months: 6.0,
+            invalid_date_handling,
+        }
+    }
+
+    #[test]
+    fn time_features_use_event_dates() {
+        let records = [
+            // 距评估时间 180 天（6 个折算月），恰为一个半衰期
+            record("R1", "2024-04-18", loan(1_000)),
+            record("R2", "2024-10-05", repayment(1_000, "2024-10-05")),
+            record("R3", "2024-10-15T09:30:00Z", overdue(500, 10)),
+        ];
+        let refs: Vec<&CreditRecord> = records.iter().collect();
+
+        let features = CreditService::new()
+            .extract_credit_features(&refs, AS_OF, &time_params(InvalidDateHandling::Exclude));
+
+        let windows: Vec<(u32, u64, u64, u64)> = features.activity_windows.iter()
+            .map(|w| (w.months, w.loans, w.repayments, w.overdues))
+            .collect();
+        assert_eq!(windows, vec![(1, 0, 1, 1), (6, 1, 1, 1)]);
+        assert_eq!(features.invalid_date_count, 0);
+        // 权重：0.5、0.5^(1/18)、1
+        let expected = (0.5 + 0.5f64.powf(10.0 / 180.0) + 1.0) / 3.0;
+        assert!((features.recent_activity_score - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_event_dates_follow_configured_handling() {
+        let mut missing = record("R1", "", loan(1_000));
+        missing.timestamp = AS_OF - 90 * 24 * 60 * 60 * 1_000_000_000;
+        let records = [
+            missing,
+            record("R2", "2024-13-01", loan(1_000)),
+            // 晚于评估时间的日期视为无效
+            record("R3", "2024-12-01", loan(1_000)),
+        ];
+        let refs: Vec<&CreditRecord> = records.iter().collect();
+        let service = CreditService::new();
+
+        let excluded = service.extract_credit_features(&refs, AS_OF, &time_params(InvalidDateHandling::Exclude));
+        assert_eq!(excluded.invalid_date_count, 3);
+        assert_eq!(excluded.recent_activity_score, 0.0);
+        assert_eq!(excluded.loan_frequency, 0.0);
+
+        // 回退到提交时间：R1 为 90 天前，R2、R3 为评估时间
+        let fallback = service.extract_credit_features(&refs, AS_OF, &time_params(InvalidDateHandling::FallbackToSubmission));
+        assert_eq!(fallback.invalid_date_count, 3);
+        assert_eq!(fallback.activity_windows[0].loans, 2);
+        assert_eq!(fallback.activity_windows[1].loans, 3);
+        assert_eq!(fallback.loan_frequency, 3.0 / 3.0);
+    }
+
+    #[test]
+    fn contributions_add_up_to_score() {
+        let records = [
+            record("R1", "2024-10-15", loan(1_000)),
+            record("R2", "2024-10-15", repayment(1_000, "2024-10-15")),
+        ];
+        let refs: Vec<&CreditRecord> = records.iter().collect();
+
+        let assessment = CreditService::new().assess_records_at(&refs, &model(), AS_OF);
+
+        // 5 + 10 + 0 + 10 + 10 + 10 + 5 + 5 = 55，基础分 60
+        assert_eq!(assessment.credit_score, 115);
+        assert_eq!(assessment.risk_level, "Low Risk");
+        assert_eq!(assessment.contributions.len(), 12);
+        let total: f64 = assessment.contributions.iter().map(|c| c.contribution).sum();
+        assert_eq!((60.0 + total).round() as u32, assessment.credit_score);
+        assert_eq!(contribution_of(&assessment, "LOAN_FREQUENCY"), 5.0);
+        assert_eq!(contribution_of(&assessment, "REPAYMENT_STRENGTH"), 10.0);
+        // 只有借款活跃度未拿满
+        assert_eq!(assessment.top_adverse_factors, vec!["LOAN_FREQUENCY".to_string()]);
+    }
+
+    #[test]
+    fn adverse_factors_ranked_by_points_lost() {
+        let records = [
+            record("R1", "2024-10-15", loan(1_000)),
+            record("R2", "2024-10-15", repayment(1_000, "2024-10-15")),
+            record("R3", "2024-10-15", overdue(5_000, 45)),
+        ];
+        let refs: Vec<&CreditRecord> = records.iter().collect();
+
+        let assessment = CreditService::new().assess_records_at(&refs, &model(), AS_OF);
+
+        assert_eq!(contribution_of(&assessment, "OVERDUE_DAYS"), -20.0);
+        assert_eq!(contribution_of(&assessment, "OVERDUE_FREQUENCY"), -10.0);
+        assert_eq!(contribution_of(&assessment, "OVERDUE_AMOUNT"), -0.5);
+        assert_eq!(assessment.credit_score, 65);
+        assert_eq!(assessment.risk_level, "Medium Risk");
+        // 失分：逾期天数 20，逾期占比与逾期次数各 10（同分保持原顺序），金额稳定性约 9.99
+        assert_eq!(assessment.top_adverse_factors, vec![
+            "OVERDUE_DAYS".to_string(),
+            "OVERDUE_RATIO".to_string(),
+            "OVERDUE_FREQUENCY".to_string(),
+            "AMOUNT_STABILITY".to_string(),
+        ]);
+        let negative: Vec<&str> = assessment.contributions.iter()
+            .filter(|c| c.direction == ContributionDirection::Negative)
+            .map(|c| c.code.as_str())
+            .collect();
+        assert_eq!(negative, vec!["OVERDUE_RATIO", "OVERDUE_DAYS", "OVERDUE_AMOUNT", "OVERDUE_FREQUENCY"]);
+    }
 }
@@ -9,6 +9,11 @@ use crate::models::{
 };
 use crate::services::admin_institution_service::ADMIN_SERVICE;  // 移到顶部
 use crate::services::record_service::RECORD_SERVICE;
+use crate::services::quota_service::QUOTA_SERVICE;
+use crate::services::data_quality_service;
+use crate::services::institution_score_service::INSTITUTION_SCORE_SERVICE;
+
+const SCORE_TREND_DAYS: u64 = 90;
 // 每日统计数据
 #[derive(Default)]
 struct DailyStats {
@@ -30,14 +35,12 @@ thread_local! {
 
 pub struct DashboardService {
     daily_stats: DailyStats,
-    api_quota_limit: u64,
 }
 
 impl DashboardService {
     pub fn new() -> Self {
         Self {
             daily_stats: DailyStats::default(),
-            api_quota_limit: 20000,
         }
     }
 
@@ -172,10 +175,13 @@ impl DashboardService {
                 today_query_others: today_outbound,           // 使用机构今日统计 
                 today_queried_by_others: today_inbound,       // 使用机构今日统计
                 total_queries: institution.api_calls,
-                api_quota: ApiQuota {
-                    used: institution.api_calls,
-                    total: self.api_quota_limit,
-                },
+                api_quota: QUOTA_SERVICE.with(|service| {
+                    let usage = service.borrow().get_usage(institution_id);
+                    ApiQuota {
+                        used: usage.queries_used,
+                        total: usage.plan.monthly_query_allowance,
+                    }
+                }),
             },
             token_info: TokenInfo {
                 balance: institution.balance,
@@ -188,6 +194,9 @@ impl DashboardService {
                 credit_score: self.calculate_institution_credit_score(&institution),
                 credit_level: self.get_credit_level(&institution),
                 data_quality_score: self.calculate_data_quality_score(&institution),
+                score_trend: INSTITUTION_SCORE_SERVICE.with(|service| {
+                    service.borrow().get_trend(institution.id, SCORE_TREND_DAYS)
+                }),
             },
             system_status: SystemStatus {
                 api_health: true,
@@ -261,14 +270,7 @@ impl DashboardService {
         
         for inst in &institutions {
             total_score += inst.credit_score.score as f64;
-            match inst.credit_score.score {
-                90..=100 => level_distribution.aaa_count += 1,
-                80..=89 => level_distribution.aa_count += 1, 
-                70..=79 => level_distribution.a_count += 1,
-                60..=69 => level_distribution.bbb_count += 1,
-                50..=59 => level_distribution.bb_count += 1,
-                _ => level_distribution.other_count += 1,
-            }
+            level_distribution.add_score(inst.credit_score.score);
         }
 
         let average_score = if !institutions.is_empty() {
@@ -302,31 +304,9 @@ impl DashboardService {
 
 
 
+    // 数据质量分由数据质量服务按滚动窗口评估
     fn calculate_data_quality_score(&self, institution: &Institution) -> u64 {
-        // 统计机构的记录情况
-        let records = RECORD_SERVICE.with(|service| {
-            let service = service.borrow();
-            let params = RecordQueryParams {
-                institution_id: Some(institution.id),
-                status: None,
-                record_type: None,
-                user_did: None,
-                start_date: "".to_string(),
-            };
-            service.query_records(params)
-        });
-
-        let total_records = records.len();
-        if total_records == 0 {
-            return 100; // 暂无数据时返回满分
-        }
-
-        // 计算验证通过的记录比例
-        let confirmed_records = records.iter()
-            .filter(|r| r.status == RecordStatus::Confirmed)
-            .count();
-
-        ((confirmed_records as f64 / total_records as f64) * 100.0) as u64
+        data_quality_service::current_quality_score(institution.id)
     }
 
     fn get_institution_data_distribution(&self, institution: &Institution) -> DataDistribution {
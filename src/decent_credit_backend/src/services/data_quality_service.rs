@@ -0,0 +1,529 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use log::{info, debug, warn};
+use crate::models::data_quality::*;
+use crate::models::integrity::{IntegrityIssueType, ReviewCase, ReviewStatus};
+use crate::models::institution_score::ScoreChangeReason;
+use crate::models::record::*;
+use crate::services::admin_institution_service::ADMIN_SERVICE;
+use crate::services::integrity_service::INTEGRITY_SERVICE;
+use crate::services::institution_score_service;
+use crate::services::record_service::{DUPLICATE_RECORD_CODE, RECORD_SERVICE};
+use crate::utils::time::parse_event_date;
+
+const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const EVALUATION_INTERVAL_SECS: u64 = 24 * 60 * 60;
+// 保留的审计记录数量
+const MAX_ADJUSTMENTS: usize = 10_000;
+
+// 需要跨升级保存的评分配置、最新评分和调整审计记录
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct DataQualityState {
+    pub config: DataQualityConfig,
+    pub latest: HashMap<Principal, DataQualityMetrics>,
+    pub adjustments: Vec<QualityAdjustment>,
+    pub next_adjustment_id: u64,
+    pub last_deductions: Option<HashMap<Principal, u64>>,     // 机构最近一次自动扣分的时间
+}
+
+pub struct DataQualityService {
+    state: DataQualityState,
+}
+
+impl Default for DataQualityService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static DATA_QUALITY_SERVICE: RefCell<DataQualityService> = RefCell::new(DataQualityService::new());
+}
+
+fn rate(count: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { count as f64 / total as f64 }
+}
+
+fn window_start(config: &DataQualityConfig, now: u64) -> u64 {
+    now.saturating_sub(config.window_days * DAY_NANOS)
+}
+
+/// 上次扣分之后是否出现了新的数据问题；同一批问题只扣一次分
+pub fn has_new_incidents(metrics: &DataQualityMetrics, last_deduction: Option<u64>) -> bool {
+    match (metrics.last_incident_at, last_deduction) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(incident), Some(deducted)) => incident > deducted,
+    }
+}
+
+/// 按滚动窗口统计机构的拒绝率、争议败诉率、迟报率和重复率，并计算质量分
+pub fn compute_metrics(
+    institution_id: Principal,
+    records: &[CreditRecord],
+    cases: &[ReviewCase],
+    repeat_offense_penalty: u64,
+    config: &DataQualityConfig,
+    now: u64
+) -> DataQualityMetrics {
+    let window_start = window_start(config, now);
+    let in_window: Vec<&CreditRecord> = records.iter()
+        .filter(|r| r.timestamp >= window_start && r.timestamp <= now)
+        .collect();
+
+    // 审核确认后被驳回的记录计入争议，不重复计入拒绝
+    let reviewed: HashSet<&str> = cases.iter().map(|c| c.record_id.as_str()).collect();
+    let is_duplicate = |r: &CreditRecord| r.rejection_reason.as_ref().is_some_and(|reason| reason.code == DUPLICATE_RECORD_CODE);
+
+    let rejected_records: Vec<&&CreditRecord> = in_window.iter()
+        .filter(|r| r.status == RecordStatus::Rejected && !is_duplicate(r) && !reviewed.contains(r.id.as_str()))
+        .collect();
+    let rejected = rejected_records.len() as u64;
+
+    let resolved: Vec<&ReviewCase> = cases.iter()
+        .filter(|c| c.resolved_at.is_some_and(|at| at >= window_start && at <= now))
+        .collect();
+    let upheld: Vec<&&ReviewCase> = resolved.iter().filter(|c| c.status == ReviewStatus::Upheld).collect();
+    let disputes_lost = upheld.len() as u64;
+    let duplicate_records: Vec<&&CreditRecord> = in_window.iter().filter(|r| is_duplicate(r)).collect();
+    let duplicates = duplicate_records.len() as u64
+        + upheld.iter().filter(|c| c.issue == IntegrityIssueType::Duplicate).count() as u64;
+
+    // 迟报：上报时间晚于事件日期超过阈值；批量导入的历史数据本就晚于事件发生，不计入
+    let delays: Vec<(u64, u64)> = in_window.iter()
+        .filter(|r| r.status != RecordStatus::Rejected && r.imported != Some(true))
+        .filter_map(|r| parse_event_date(&r.event_date).map(|event| (r.timestamp, r.timestamp.saturating_sub(event))))
+        .collect();
+    let late: Vec<u64> = delays.iter()
+        .filter(|(_, delay)| *delay > config.late_threshold_days * DAY_NANOS)
+        .map(|(submitted_at, _)| *submitted_at)
+        .collect();
+    let late_reports = late.len() as u64;
+    let average_reporting_delay_days = if delays.is_empty() {
+        0.0
+    } else {
+        delays.iter().map(|(_, delay)| delay).sum::<u64>() as f64 / delays.len() as f64 / DAY_NANOS as f64
+    };
+
+    // 最近一次数据问题的时间，用于判断上次扣分后是否有新问题
+    let last_incident_at = rejected_records.iter().chain(duplicate_records.iter()).map(|r| r.timestamp)
+        .chain(upheld.iter().filter_map(|c| c.resolved_at))
+        .chain(late.iter().copied())
+        .max();
+
+    let submissions = in_window.len() as u64;
+    let rejection_rate = rate(rejected, submissions);
+    let dispute_loss_rate = rate(disputes_lost, resolved.len() as u64);
+    let late_reporting_rate = rate(late_reports, delays.len() as u64);
+    let duplicate_rate = rate(duplicates, submissions);
+
+    let total_weight = config.rejection_weight + config.dispute_weight + config.late_weight + config.duplicate_weight;
+    let weighted = if total_weight > 0.0 {
+        (config.rejection_weight * rejection_rate
+            + config.dispute_weight * dispute_loss_rate
+            + config.late_weight * late_reporting_rate
+            + config.duplicate_weight * duplicate_rate.min(1.0)) / total_weight
+    } else {
+        0.0
+    };
+    let quality_score = ((1.0 - weighted) * 100.0).round().clamp(0.0, 100.0) as u64;
+
+    DataQualityMetrics {
+        institution_id,
+        window_start,
+        window_end: now,
+        submissions,
+        rejected,
+        rejection_rate,
+        disputes_resolved: resolved.len() as u64,
+        disputes_lost,
+        dispute_loss_rate,
+        late_reports,
+        late_reporting_rate,
+        average_reporting_delay_days,
+        duplicates,
+        duplicate_rate,
+        repeat_offense_penalty,
+        quality_score: quality_score.saturating_sub(repeat_offense_penalty),
+        computed_at: now,
+        last_incident_at,
+    }
+}
+
+impl DataQualityService {
+    pub fn new() -> Self {
+        Self {
+            state: DataQualityState::default(),
+        }
+    }
+
+    pub fn export_state(&self) -> DataQualityState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, mut state: DataQualityState) {
+        // 旧版本没有单独保存扣分时间，从审计记录中恢复
+        if state.last_deductions.is_none() {
+            let last_deductions = state.adjustments.iter()
+                .filter(|a| a.kind == QualityAdjustmentKind::Deduction)
+                .map(|a| (a.institution_id, a.created_at))
+                .collect();
+            state.last_deductions = Some(last_deductions);
+        }
+        info!(
+            "Restored data quality state: {} scored institutions, {} adjustments",
+            state.latest.len(),
+            state.adjustments.len()
+        );
+        self.state = state;
+    }
+
+    pub fn get_config(&self) -> DataQualityConfig {
+        self.state.config.clone()
+    }
+
+    pub fn update_config(&mut self, config: DataQualityConfig) -> Result<(), String> {
+        if config.window_days == 0 {
+            return Err("统计窗口必须大于0天".to_string());
+        }
+        let weights = [config.rejection_weight, config.dispute_weight, config.late_weight, config.duplicate_weight];
+        if weights.iter().any(|w| *w < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+            return Err("权重不能为负且不能全为0".to_string());
+        }
+        if config.deduction_threshold > config.recovery_threshold || config.recovery_threshold > 100 {
+            return Err("扣分阈值不能高于恢复阈值，恢复阈值不能超过100".to_string());
+        }
+        info!("Data quality config updated: {:?}", config);
+        self.state.config = config;
+        Ok(())
+    }
+
+    pub fn latest_metrics(&self, institution_id: Principal) -> Option<DataQualityMetrics> {
+        self.state.latest.get(&institution_id).cloned()
+    }
+
+    pub fn get_adjustments(&self, institution_id: Option<Principal>, limit: usize) -> Vec<QualityAdjustment> {
+        self.state.adjustments.iter()
+            .rev()
+            .filter(|a| institution_id.is_none_or(|id| a.institution_id == id))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn last_deduction_at(&self, institution_id: Principal) -> Option<u64> {
+        self.state.last_deductions.as_ref()?.get(&institution_id).copied()
+    }
+
+    fn push_adjustment(&mut self, mut adjustment: QualityAdjustment) -> QualityAdjustment {
+        self.state.next_adjustment_id += 1;
+        adjustment.id = self.state.next_adjustment_id;
+        if adjustment.kind == QualityAdjustmentKind::Deduction {
+            self.state.last_deductions.get_or_insert_with(HashMap::new)
+                .insert(adjustment.institution_id, adjustment.created_at);
+        }
+        self.state.latest.insert(adjustment.institution_id, adjustment.metrics.clone());
+        self.state.adjustments.push(adjustment.clone());
+        if self.state.adjustments.len() > MAX_ADJUSTMENTS {
+            let overflow = self.state.adjustments.len() - MAX_ADJUSTMENTS;
+            self.state.adjustments.drain(..overflow);
+        }
+        adjustment
+    }
+}
+
+/// 以当前数据计算机构的质量指标（不保存）
+pub fn measure_institution(institution_id: Principal) -> DataQualityMetrics {
+    let records = RECORD_SERVICE.with(|service| {
+        service.borrow().query_records(RecordQueryParams {
+            institution_id: Some(institution_id),
+            user_did: None,
+            record_type: None,
+            start_date: String::new(),
+            status: None,
+        })
+    });
+    let config = DATA_QUALITY_SERVICE.with(|service| service.borrow().get_config());
+    let now = time();
+    let (cases, penalty) = INTEGRITY_SERVICE.with(|service| {
+        let service = service.borrow();
+        (service.cases_for_institution(institution_id), service.quality_penalty(institution_id, window_start(&config, now), now))
+    });
+    compute_metrics(institution_id, &records, &cases, penalty, &config, now)
+}
+
+/// 仪表盘展示的数据质量分：优先取最近一次评估结果
+pub fn current_quality_score(institution_id: Principal) -> u64 {
+    DATA_QUALITY_SERVICE.with(|service| service.borrow().latest_metrics(institution_id))
+        .unwrap_or_else(|| measure_institution(institution_id))
+        .quality_score
+}
+
+/// 评估机构数据质量，并按阈值自动扣减或恢复机构信用分
+pub fn evaluate_institution(institution_id: Principal, triggered_by: Principal) -> Result<QualityAdjustment, String> {
+    let institution = ADMIN_SERVICE.with(|service| service.borrow().get_institution(institution_id))
+        .ok_or_else(|| "机构不存在".to_string())?;
+    let config = DATA_QUALITY_SERVICE.with(|service| service.borrow().get_config());
+    let metrics = measure_institution(institution_id);
+    let previous = institution.credit_score.score;
+    let quality = metrics.quality_score;
+    let last_deduction = DATA_QUALITY_SERVICE.with(|service| service.borrow().last_deduction_at(institution_id));
+
+    let mut kind = QualityAdjustmentKind::NoChange;
+    let mut new_score = previous;
+    let mut deduction_record_id = None;
+    let summary = format!(
+        "拒绝率 {:.1}%，争议败诉率 {:.1}%，迟报率 {:.1}%，重复率 {:.1}%，违规扣分 {}",
+        metrics.rejection_rate * 100.0,
+        metrics.dispute_loss_rate * 100.0,
+        metrics.late_reporting_rate * 100.0,
+        metrics.duplicate_rate * 100.0,
+        metrics.repeat_offense_penalty
+    );
+
+    let reason = if metrics.submissions < config.min_submissions {
+        format!("窗口内提交 {} 条，不足 {} 条，不调整", metrics.submissions, config.min_submissions)
+    } else if quality < config.deduction_threshold && previous > 0 && !has_new_incidents(&metrics, last_deduction) {
+        format!("质量分 {} 低于扣分阈值 {}，上次扣分后没有新的数据问题，不重复扣分", quality, config.deduction_threshold)
+    } else if quality < config.deduction_threshold && previous > 0 {
+        let points = ((config.deduction_threshold - quality).div_ceil(5) as u32)
+            .clamp(1, config.max_deduction.max(1))
+            .min(previous as u32);
+        let deduction = RECORD_SERVICE.with(|service| {
+            service.borrow_mut().create_deduction_record(ic_cdk::id(), CreateCreditRecordRequest {
+                institution_id,
+                deduction_points: points,
+                reason: format!("数据质量分 {} 低于 {}，自动扣分", quality, config.deduction_threshold),
+                data_quality_issue: summary.clone(),
+            })
+        })?;
+        kind = QualityAdjustmentKind::Deduction;
+        new_score = previous - points as u64;
+        deduction_record_id = Some(deduction.record_id);
+        format!("质量分 {} 低于扣分阈值 {}，扣除 {} 分", quality, config.deduction_threshold, points)
+    } else if quality >= config.recovery_threshold && previous < 100 {
+        let points = (config.max_recovery as u64).min(100 - previous);
+        if points > 0 {
+            institution_score_service::change_score(
+                institution_id, previous + points, ScoreChangeReason::QualityRecovery, None
+            )?;
+            kind = QualityAdjustmentKind::Recovery;
+            new_score = previous + points;
+        }
+        format!("质量分 {} 达到恢复阈值 {}，恢复 {} 分", quality, config.recovery_threshold, points)
+    } else {
+        format!("质量分 {}，信用分保持不变", quality)
+    };
+
+    if kind != QualityAdjustmentKind::NoChange {
+        info!(
+            "Data quality {:?} for {}: credit score {} -> {} ({})",
+            kind, institution_id.to_text(), previous, new_score, summary
+        );
+    } else {
+        debug!("Data quality for {}: {}", institution_id.to_text(), reason);
+    }
+
+    Ok(DATA_QUALITY_SERVICE.with(|service| {
+        service.borrow_mut().push_adjustment(QualityAdjustment {
+            id: 0,
+            institution_id,
+            kind,
+            previous_credit_score: previous,
+            new_credit_score: new_score,
+            metrics,
+            reason: format!("{}；{}", reason, summary),
+            deduction_record_id,
+            triggered_by,
+            created_at: time(),
+        })
+    }))
+}
+
+/// 评估全部机构
+pub fn evaluate_all_institutions(triggered_by: Principal) -> Vec<QualityAdjustment> {
+    let institution_ids: Vec<Principal> = ADMIN_SERVICE.with(|service| {
+        service.borrow().get_all_institutions().into_iter().map(|inst| inst.id).collect()
+    });
+    institution_ids.into_iter()
+        .filter_map(|id| match evaluate_institution(id, triggered_by) {
+            Ok(adjustment) => Some(adjustment),
+            Err(e) => {
+                warn!("Data quality evaluation failed for {}: {}", id.to_text(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 每天评估一次，配置关闭时跳过
+pub fn init_data_quality_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(EVALUATION_INTERVAL_SECS), || {
+        if DATA_QUALITY_SERVICE.with(|service| service.borrow().state.config.enabled) {
+            let adjustments = evaluate_all_institutions(ic_cdk::id());
+            info!("Scheduled data quality evaluation finished: {} institutions", adjustments.len());
+        }
+    });
+    info!("Data quality timer started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-11-01 00:00:00 UTC
+    const NOW: u64 = 1_730_419_200 * 1_000_000_000;
+
+    fn record(id: &str, event_date: &str, timestamp: u64, rejection_code: Option<&str>) -> CreditRecord {
+        CreditRecord {
+            id: id.to_string(),
+            institution_id: Principal::from_slice(&[1]),
+            institution_name: "bank".to_string(),
+            institution_full_name: "Test Bank".to_string(),
+            record_type: RecordType::LoanRecord,
+            user_did: "did:alice".to_string(),
+            event_date: event_date.to_string(),
+            content: RecordContent::Loan(LoanContent {
+                amount: 1000,
+                loan_id: format!("L-{}", id),
+                term_months: 12,
+                interest_rate: 5.0,
+            }),
+            encrypted_content: Vec::new(),
+            proof: Vec::new(),
+            canister_id: String::new(),
+            timestamp,
+            status: if rejection_code.is_some() { RecordStatus::Rejected } else { RecordStatus::Pending },
+            reward_amount: None,
+            query_price: 0,
+            rejection_reason: rejection_code.map(|code| RejectionReason {
+                code: code.to_string(),
+                field: None,
+                message: String::new(),
+                rejected_at: timestamp,
+            }),
+            resubmitted_from: None,
+            resubmitted_as: None,
+            imported: None,
+        }
+    }
+
+    fn case(record_id: &str, issue: IntegrityIssueType, status: ReviewStatus, resolved_at: Option<u64>) -> ReviewCase {
+        ReviewCase {
+            id: format!("CASE-{}", record_id),
+            issue,
+            record_id: record_id.to_string(),
+            institution_id: Principal::from_slice(&[1]),
+            user_did: "did:alice".to_string(),
+            fingerprint: String::new(),
+            related_record_ids: Vec::new(),
+            related_institutions: Vec::new(),
+            details: String::new(),
+            status,
+            created_at: 0,
+            resolved_at,
+            resolved_by: None,
+            resolution_note: None,
+        }
+    }
+
+    #[test]
+    fn metrics_count_each_issue_once_within_window() {
+        let records = vec![
+            record("r1", "2024-10-30", NOW, None),
+            record("r2", "2024-09-01", NOW, None),
+            record("r3", "2024-10-30", NOW, Some("loan.amount.min")),
+            record("r4", "2024-10-30", NOW, Some(DUPLICATE_RECORD_CODE)),
+            // 审核确认后驳回的记录只计入争议和重复
+            record("r5", "2024-10-30", NOW, Some("Duplicate")),
+            // 窗口外的记录不计入
+            record("r6", "2024-01-01", NOW - 100 * DAY_NANOS, Some("loan.amount.min")),
+        ];
+        let cases = vec![
+            case("r5", IntegrityIssueType::Duplicate, ReviewStatus::Upheld, Some(NOW - DAY_NANOS)),
+            case("r1", IntegrityIssueType::RepaymentExceedsOutstanding, ReviewStatus::Dismissed, Some(NOW - DAY_NANOS)),
+            case("r2", IntegrityIssueType::Duplicate, ReviewStatus::Dismissed, Some(NOW - 2 * DAY_NANOS)),
+            case("r6", IntegrityIssueType::Duplicate, ReviewStatus::Upheld, Some(NOW - 120 * DAY_NANOS)),
+            case("r1", IntegrityIssueType::OverdueOnSettledLoan, ReviewStatus::Open, None),
+        ];
+
+        let metrics = compute_metrics(Principal::from_slice(&[1]), &records, &cases, 3, &DataQualityConfig::default(), NOW);
+        assert_eq!(metrics.window_start, NOW - 90 * DAY_NANOS);
+        assert_eq!(metrics.submissions, 5);
+        assert_eq!(metrics.rejected, 1);
+        assert_eq!((metrics.disputes_resolved, metrics.disputes_lost), (3, 1));
+        assert_eq!(metrics.late_reports, 1);
+        assert_eq!(metrics.duplicates, 2);
+        assert!((metrics.average_reporting_delay_days - 31.5).abs() < 1e-9);
+
+        // 1 - (0.35×0.2 + 0.25×1/3 + 0.2×0.5 + 0.2×0.4) = 0.6667 -> 67，再减违规扣分 3
+        assert_eq!(metrics.quality_score, 64);
+        assert_eq!(metrics.repeat_offense_penalty, 3);
+    }
+
+    #[test]
+    fn institution_without_submissions_scores_full_marks() {
+        let metrics = compute_metrics(Principal::from_slice(&[1]), &[], &[], 0, &DataQualityConfig::default(), NOW);
+        assert_eq!(metrics.submissions, 0);
+        assert_eq!(metrics.rejection_rate, 0.0);
+        assert_eq!(metrics.average_reporting_delay_days, 0.0);
+        assert_eq!(metrics.quality_score, 100);
+
+        let penalized = compute_metrics(Principal::from_slice(&[1]), &[], &[], 150, &DataQualityConfig::default(), NOW);
+        assert_eq!(penalized.quality_score, 0);
+    }
+
+    #[test]
+    fn imported_records_are_not_late() {
+        let mut imported = record("r2", "2023-01-01", NOW, None);
+        imported.imported = Some(true);
+        let records = vec![record("r1", "2023-01-01", NOW, None), imported];
+
+        let metrics = compute_metrics(Principal::from_slice(&[1]), &records, &[], 0, &DataQualityConfig::default(), NOW);
+        assert_eq!(metrics.submissions, 2);
+        assert_eq!(metrics.late_reports, 1);
+        assert_eq!(metrics.late_reporting_rate, 1.0);
+    }
+
+    #[test]
+    fn repeated_evaluations_deduct_only_for_new_incidents() {
+        let config = DataQualityConfig::default();
+        let records = vec![
+            record("r1", "2024-10-30", NOW - 5 * DAY_NANOS, None),
+            record("r2", "2024-10-30", NOW - 3 * DAY_NANOS, Some("loan.amount.min")),
+        ];
+        let cases = vec![case("r1", IntegrityIssueType::Duplicate, ReviewStatus::Upheld, Some(NOW - 4 * DAY_NANOS))];
+        let metrics = compute_metrics(Principal::from_slice(&[1]), &records, &cases, 5, &config, NOW);
+        assert_eq!(metrics.last_incident_at, Some(NOW - 3 * DAY_NANOS));
+
+        assert!(has_new_incidents(&metrics, None));
+        assert!(has_new_incidents(&metrics, Some(NOW - 4 * DAY_NANOS)));
+        // 上次扣分之后没有新问题，不再重复扣分
+        assert!(!has_new_incidents(&metrics, Some(NOW - 2 * DAY_NANOS)));
+
+        let clean = compute_metrics(Principal::from_slice(&[1]), &records[..1], &[], 0, &config, NOW);
+        assert_eq!(clean.last_incident_at, None);
+        assert!(!has_new_incidents(&clean, None));
+    }
+
+    #[test]
+    fn config_updates_are_validated() {
+        let mut service = DataQualityService::new();
+        let valid = DataQualityConfig { window_days: 30, ..DataQualityConfig::default() };
+        assert!(service.update_config(valid).is_ok());
+        assert_eq!(service.get_config().window_days, 30);
+
+        assert!(service.update_config(DataQualityConfig { window_days: 0, ..DataQualityConfig::default() }).is_err());
+        assert!(service.update_config(DataQualityConfig { late_weight: -0.1, ..DataQualityConfig::default() }).is_err());
+        assert!(service.update_config(DataQualityConfig {
+            rejection_weight: 0.0, dispute_weight: 0.0, late_weight: 0.0, duplicate_weight: 0.0,
+            ..DataQualityConfig::default()
+        }).is_err());
+        assert!(service.update_config(DataQualityConfig { deduction_threshold: 95, ..DataQualityConfig::default() }).is_err());
+        assert!(service.update_config(DataQualityConfig { recovery_threshold: 101, ..DataQualityConfig::default() }).is_err());
+        assert_eq!(service.get_config().window_days, 30);
+    }
+}
@@ -0,0 +1,659 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, sign_with_ecdsa, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument, SignWithEcdsaArgument,
+};
+use ic_cdk::api::time;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ed25519_dalek::VerifyingKey;
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use log::{info, debug};
+use crate::models::credit::RiskAssessmentReport;
+use crate::models::did::*;
+use crate::services::identity_service::IDENTITY_SERVICE;
+use crate::utils::time::iso8601;
+
+const DID_PREFIX: &str = "did:decent_credit:";
+const ISSUER_KEY_FRAGMENT: &str = "key-1";
+const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+const JWS_CONTEXT: &str = "https://w3id.org/security/suites/jws-2020/v1";
+const VC_CONTEXT: &str = "https://www.w3.org/2018/credentials/v1";
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const DEFAULT_CREDENTIAL_VALIDITY_DAYS: u64 = 90;
+const MAX_CREDENTIAL_VALIDITY_DAYS: u64 = 3_650;
+const DEFAULT_SIGNING_KEY_NAME: &str = "key_1";
+// secp256k1 的域素数 p
+const SECP256K1_P: &[u8] = b"FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F";
+
+// 需要跨升级保存的 DID 状态。凭证由子网阈值 ECDSA 签名，canister 只缓存公钥；
+// 签名密钥确定后不可更换，否则已签发的凭证无法验证
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DidState {
+    pub issuer_public_key: Option<Vec<u8>>,     // 阈值 ECDSA 公钥（SEC1 压缩格式）
+    pub signing_key_name: Option<String>,       // 为空时使用 key_1，本地开发可设为 dfx_test_key
+    pub issued_credentials: Option<HashMap<String, u64>>,   // 已签发 vc-jwt 的 SHA-256（hex）-> 过期时间
+    pub user_keys: HashMap<String, Vec<UserVerificationKey>>,   // 平台 DID（或未关联的 DID）-> 公钥
+    pub credential_validity_days: u64,
+}
+
+impl Default for DidState {
+    fn default() -> Self {
+        Self {
+            issuer_public_key: None,
+            signing_key_name: None,
+            issued_credentials: None,
+            user_keys: HashMap::new(),
+            credential_validity_days: DEFAULT_CREDENTIAL_VALIDITY_DAYS,
+        }
+    }
+}
+
+pub struct DidService {
+    state: DidState,
+}
+
+thread_local! {
+    pub static DID_SERVICE: RefCell<DidService> = RefCell::new(DidService::new());
+}
+
+/// 首次签发凭证前通过管理 canister 获取阈值 ECDSA 公钥
+pub async fn ensure_signing_key() -> Result<(), String> {
+    if DID_SERVICE.with(|service| service.borrow().state.issuer_public_key.is_some()) {
+        return Ok(());
+    }
+
+    let key_id = DID_SERVICE.with(|service| service.borrow().ecdsa_key_id());
+    let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path: Vec::new(),
+        key_id,
+    }).await
+        .map_err(|(code, msg)| format!("获取签名公钥失败: {:?} {}", code, msg))?;
+    DID_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        if service.state.issuer_public_key.is_none() {
+            service.state.issuer_public_key = Some(response.public_key);
+            info!("Credential signing key initialized");
+        }
+    });
+    Ok(())
+}
+
+/// 将评估报告签发为 W3C 可验证凭证（vc-jwt），由阈值 ECDSA 签名
+pub async fn issue_report_credential(report: &RiskAssessmentReport) -> Result<VerifiableCredential, String> {
+    ensure_signing_key().await?;
+
+    let (mut credential, signing_input) = DID_SERVICE.with(|service| {
+        service.borrow().prepare_report_credential_at(report, issuer_did(), time())
+    })?;
+    let key_id = DID_SERVICE.with(|service| service.borrow().ecdsa_key_id());
+    let (response,) = sign_with_ecdsa(SignWithEcdsaArgument {
+        message_hash: Sha256::digest(signing_input.as_bytes()).to_vec(),
+        derivation_path: Vec::new(),
+        key_id,
+    }).await
+        .map_err(|(code, msg)| format!("凭证签名失败: {:?} {}", code, msg))?;
+
+    DID_SERVICE.with(|service| {
+        service.borrow_mut().attach_signature(&mut credential, &signing_input, &response.signature, time())
+    })?;
+    Ok(credential)
+}
+
+/// 本 canister 作为凭证签发方的 DID
+pub fn issuer_did() -> String {
+    format!("{}issuer:{}", DID_PREFIX, ic_cdk::id().to_text())
+}
+
+fn issuer_key_id() -> String {
+    key_id_of(&issuer_did())
+}
+
+fn key_id_of(issuer: &str) -> String {
+    format!("{}#{}", issuer, ISSUER_KEY_FRAGMENT)
+}
+
+fn ed25519_jwk(public_key: &[u8]) -> PublicKeyJwk {
+    PublicKeyJwk {
+        kty: "OKP".to_string(),
+        crv: "Ed25519".to_string(),
+        x: URL_SAFE_NO_PAD.encode(public_key),
+        y: None,
+    }
+}
+
+fn secp256k1_jwk(public_key: &[u8]) -> Result<PublicKeyJwk, String> {
+    let (x, y) = secp256k1_coordinates(public_key)?;
+    Ok(PublicKeyJwk {
+        kty: "EC".to_string(),
+        crv: "secp256k1".to_string(),
+        x: URL_SAFE_NO_PAD.encode(x),
+        y: Some(URL_SAFE_NO_PAD.encode(y)),
+    })
+}
+
+// SEC1 公钥的坐标；压缩格式按 y² = x³ + 7 (mod p) 求 y，p ≡ 3 (mod 4) 时 y = (x³ + 7)^((p + 1) / 4)
+fn secp256k1_coordinates(public_key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    match public_key {
+        [0x04, point @ ..] if point.len() == 64 => Ok((point[..32].to_vec(), point[32..].to_vec())),
+        [prefix @ (0x02 | 0x03), x @ ..] if x.len() == 32 => {
+            let p = BigUint::parse_bytes(SECP256K1_P, 16).expect("valid field prime");
+            let x_value = BigUint::from_bytes_be(x);
+            if x_value >= p {
+                return Err("无效的 secp256k1 公钥".to_string());
+            }
+            let rhs = (x_value.modpow(&BigUint::from(3u32), &p) + 7u32) % &p;
+            let mut y = rhs.modpow(&((&p + 1u32) >> 2), &p);
+            if (&y * &y) % &p != rhs {
+                return Err("无效的 secp256k1 公钥".to_string());
+            }
+            if y.bit(0) != (*prefix == 0x03) {
+                y = &p - y;
+            }
+            let y = y.to_bytes_be();
+            let mut padded = vec![0u8; 32 - y.len()];
+            padded.extend_from_slice(&y);
+            Ok((x.to_vec(), padded))
+        }
+        _ => Err("无效的 secp256k1 公钥".to_string()),
+    }
+}
+
+// 用户 DID 对应的公钥归属：已关联时归属平台 DID
+fn key_owner_did(user_did: &str) -> String {
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow()
+            .get_linked_identity(user_did)
+            .map(|identity| identity.canonical_did)
+            .unwrap_or_else(|| user_did.to_string())
+    })
+}
+
+/// 调用者是否为该 DID 的借款人本人（通过身份关联确认的主体）
+pub fn is_did_controller(user_did: &str, caller: Principal) -> bool {
+    IDENTITY_SERVICE.with(|service| {
+        service.borrow()
+            .get_linked_identity(user_did)
+            .and_then(|identity| identity.controller)
+            == Some(caller)
+    })
+}
+
+impl Default for DidService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DidService {
+    pub fn new() -> Self {
+        Self {
+            state: DidState::default(),
+        }
+    }
+
+    fn ecdsa_key_id(&self) -> EcdsaKeyId {
+        EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: self.state.signing_key_name.clone()
+                .unwrap_or_else(|| DEFAULT_SIGNING_KEY_NAME.to_string()),
+        }
+    }
+
+    fn issuer_public_key(&self) -> Result<&[u8], String> {
+        self.state.issuer_public_key.as_deref()
+            .ok_or_else(|| "签名密钥尚未初始化".to_string())
+    }
+
+    /// 设置阈值 ECDSA 密钥名称，只能在签发首个凭证前修改
+    pub fn set_signing_key_name(&mut self, name: String) -> Result<(), String> {
+        if self.state.issuer_public_key.is_some() {
+            return Err("签名密钥已初始化，不可更换".to_string());
+        }
+        if name.trim().is_empty() {
+            return Err("密钥名称不能为空".to_string());
+        }
+        info!("Credential signing key name set to {}", name);
+        self.state.signing_key_name = Some(name);
+        Ok(())
+    }
+
+    /// 解析 DID 文档：签发方 DID 或平台内的用户 DID
+    ///
+    /// 任何本方法下的 DID 都返回文档，不暴露平台是否持有该用户的记录；
+    /// 关联的其他 DID（alsoKnownAs）只向借款人本人展示。
+    pub fn resolve(&self, did: &str, include_links: bool) -> Result<DidDocument, String> {
+        if !did.starts_with(DID_PREFIX) {
+            return Err(format!("不支持的 DID 方法: {}", did));
+        }
+        if did == issuer_did() {
+            return self.issuer_document();
+        }
+
+        let also_known_as = if include_links {
+            IDENTITY_SERVICE.with(|service| service.borrow().linked_dids(did))
+                .into_iter()
+                .filter(|d| d != did)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let owner = key_owner_did(did);
+        let verification_method: Vec<VerificationMethod> = self.state.user_keys.get(&owner)
+            .map(|keys| keys.iter()
+                .map(|key| VerificationMethod {
+                    id: format!("{}#{}", did, key.key_id),
+                    method_type: "JsonWebKey2020".to_string(),
+                    controller: did.to_string(),
+                    public_key_jwk: ed25519_jwk(&key.public_key),
+                })
+                .collect())
+            .unwrap_or_default();
+        let authentication: Vec<String> = verification_method.iter().map(|m| m.id.clone()).collect();
+
+        Ok(DidDocument {
+            id: did.to_string(),
+            controller: Some(issuer_did()),
+            also_known_as,
+            verification_method,
+            assertion_method: authentication.clone(),
+            authentication,
+        })
+    }
+
+    fn issuer_document(&self) -> Result<DidDocument, String> {
+        let did = issuer_did();
+        let key_id = issuer_key_id();
+        let public_key_jwk = secp256k1_jwk(self.issuer_public_key()?)?;
+
+        Ok(DidDocument {
+            id: did.clone(),
+            controller: None,
+            also_known_as: Vec::new(),
+            verification_method: vec![VerificationMethod {
+                id: key_id.clone(),
+                method_type: "JsonWebKey2020".to_string(),
+                controller: did,
+                public_key_jwk,
+            }],
+            authentication: vec![key_id.clone()],
+            assertion_method: vec![key_id],
+        })
+    }
+
+    /// 借款人为自己的 DID 登记公钥，返回新公钥的 DID URL
+    pub fn add_user_key(&mut self, user_did: &str, public_key: Vec<u8>, caller: Principal) -> Result<String, String> {
+        let bytes: [u8; 32] = public_key.as_slice().try_into()
+            .map_err(|_| "Ed25519 公钥长度必须为 32 字节".to_string())?;
+        VerifyingKey::from_bytes(&bytes)
+            .map_err(|e| format!("无效的 Ed25519 公钥: {}", e))?;
+
+        let owner = key_owner_did(user_did);
+        let keys = self.state.user_keys.entry(owner).or_default();
+        if keys.iter().any(|k| k.public_key == public_key) {
+            return Err("该公钥已登记".to_string());
+        }
+        let key_id = format!("key-{}", keys.len() + 1);
+        keys.push(UserVerificationKey {
+            key_id: key_id.clone(),
+            public_key,
+            added_by: caller,
+            added_at: time(),
+        });
+        info!("Verification key {} added for {}", key_id, user_did);
+        Ok(format!("{}#{}", user_did, key_id))
+    }
+
+    pub fn remove_user_key(&mut self, user_did: &str, key_id: &str) -> Result<(), String> {
+        let keys = self.state.user_keys.get_mut(&key_owner_did(user_did))
+            .ok_or_else(|| "该 DID 没有登记公钥".to_string())?;
+        let before = keys.len();
+        keys.retain(|k| k.key_id != key_id);
+        if keys.len() == before {
+            return Err(format!("公钥不存在: {}", key_id));
+        }
+        Ok(())
+    }
+
+    /// 生成待签名的凭证（jwt 为空）及 JWS 签名输入
+    fn prepare_report_credential_at(
+        &self,
+        report: &RiskAssessmentReport,
+        issuer: String,
+        issued_at: u64
+    ) -> Result<(VerifiableCredential, String), String> {
+        let expires_at = self.state.credential_validity_days
+            .checked_mul(24 * 60 * 60 * NANOS_PER_SEC)
+            .and_then(|validity| issued_at.checked_add(validity))
+            .ok_or_else(|| "凭证有效期超出范围".to_string())?;
+        let credential_id = format!("urn:decent_credit:report:{}", report.report_id);
+        let assessment = &report.assessment;
+
+        let credential = json!({
+            "@context": [VC_CONTEXT],
+            "id": credential_id,
+            "type": ["VerifiableCredential", "CreditAssessmentCredential"],
+            "issuer": issuer,
+            "issuanceDate": iso8601(issued_at),
+            "expirationDate": iso8601(expires_at),
+            "credentialSubject": {
+                "id": report.user_did,
+                "creditScore": assessment.credit_score,
+                "riskLevel": assessment.risk_level,
+                "assessedAt": iso8601(report.created_at),
+                "assessedBy": report.institution_id.to_text(),
+                "reportId": report.report_id,
+                "modelId": assessment.model_id,
+                "modelVersion": assessment.model_version,
+                "topAdverseFactors": assessment.top_adverse_factors,
+            },
+        });
+
+        let header = json!({ "alg": "ES256K", "typ": "JWT", "kid": key_id_of(&issuer) });
+        let payload = json!({
+            "iss": issuer,
+            "sub": report.user_did,
+            "jti": credential_id,
+            "iat": issued_at / NANOS_PER_SEC,
+            "nbf": issued_at / NANOS_PER_SEC,
+            "exp": expires_at / NANOS_PER_SEC,
+            "vc": credential,
+        });
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(payload.to_string())
+        );
+
+        let credential = VerifiableCredential {
+            id: credential_id,
+            issuer,
+            subject: report.user_did.clone(),
+            issued_at,
+            expires_at,
+            credential_json: credential.to_string(),
+            jwt: String::new(),
+        };
+        Ok((credential, signing_input))
+    }
+
+    /// 附上阈值 ECDSA 签名（r || s）生成 vc-jwt，并登记以便校验；同时清理已过期的登记
+    fn attach_signature(
+        &mut self,
+        credential: &mut VerifiableCredential,
+        signing_input: &str,
+        signature: &[u8],
+        now: u64
+    ) -> Result<(), String> {
+        if signature.len() != 64 {
+            return Err("签名格式无效".to_string());
+        }
+        credential.jwt = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature));
+
+        let issued = self.state.issued_credentials.get_or_insert_with(HashMap::new);
+        issued.retain(|_, expires_at| *expires_at >= now);
+        issued.insert(hex::encode(Sha256::digest(credential.jwt.as_bytes())), credential.expires_at);
+        debug!("Issued credential {} for {}", credential.id, credential.subject);
+        Ok(())
+    }
+
+    /// 校验本 canister 签发的 vc-jwt 的签名与有效期
+    pub fn verify_credential(&self, jwt: &str) -> CredentialVerification {
+        self.verify_credential_at(jwt, &issuer_key_id(), time())
+    }
+
+    fn verify_credential_at(&self, jwt: &str, key_id: &str, now: u64) -> CredentialVerification {
+        match self.check_jwt(jwt, key_id, now) {
+            Ok(payload) => {
+                let claim = |name: &str| payload.get(name).and_then(Value::as_str).map(str::to_string);
+                CredentialVerification {
+                    valid: true,
+                    issuer: claim("iss"),
+                    subject: claim("sub"),
+                    credential_id: claim("jti"),
+                    expires_at: payload.get("exp").and_then(Value::as_u64).map(|s| s * NANOS_PER_SEC),
+                    error: None,
+                }
+            }
+            Err(e) => CredentialVerification {
+                valid: false,
+                issuer: None,
+                subject: None,
+                credential_id: None,
+                expires_at: None,
+                error: Some(e),
+            },
+        }
+    }
+
+    fn check_jwt(&self, jwt: &str, key_id: &str, now: u64) -> Result<Value, String> {
+        let parts: Vec<&str> = jwt.split('.').collect();
+        let [header, payload, signature] = parts.as_slice() else {
+            return Err("JWT 格式无效".to_string());
+        };
+
+        let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| "JWT 编码无效".to_string());
+        let header: Value = serde_json::from_slice(&decode(header)?)
+            .map_err(|_| "JWT 头部无效".to_string())?;
+        if header.get("kid").and_then(Value::as_str) != Some(key_id) {
+            return Err("凭证不是由本平台签发".to_string());
+        }
+
+        if decode(signature)?.len() != 64 {
+            return Err("签名格式无效".to_string());
+        }
+        // 签名由子网阈值 ECDSA 生成，本平台按登记的 JWT 摘要校验；外部验证方使用签发方 DID 文档中的公钥
+        let digest = hex::encode(Sha256::digest(jwt.as_bytes()));
+        let issued = self.state.issued_credentials.as_ref()
+            .is_some_and(|issued| issued.contains_key(&digest));
+        if !issued {
+            return Err("签名校验失败".to_string());
+        }
+
+        let payload: Value = serde_json::from_slice(&decode(payload)?)
+            .map_err(|_| "JWT 载荷无效".to_string())?;
+        let expires_at = payload.get("exp").and_then(Value::as_u64).unwrap_or(0);
+        if expires_at * NANOS_PER_SEC < now {
+            return Err("凭证已过期".to_string());
+        }
+        Ok(payload)
+    }
+
+    pub fn set_credential_validity_days(&mut self, days: u64) -> Result<(), String> {
+        if days == 0 || days > MAX_CREDENTIAL_VALIDITY_DAYS {
+            return Err(format!("凭证有效期必须在 1 到 {} 天之间", MAX_CREDENTIAL_VALIDITY_DAYS));
+        }
+        self.state.credential_validity_days = days;
+        Ok(())
+    }
+
+    pub fn export_state(&self) -> DidState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: DidState) {
+        info!("Restored DID state with {} DIDs holding keys", state.user_keys.len());
+        self.state = state;
+    }
+}
+
+/// W3C DID 文档的 JSON 表示
+pub fn did_document_json(document: &DidDocument) -> String {
+    let methods: Vec<Value> = document.verification_method.iter()
+        .map(|m| json!({
+            "id": m.id,
+            "type": m.method_type,
+            "controller": m.controller,
+            "publicKeyJwk": m.public_key_jwk,
+        }))
+        .collect();
+
+    let mut doc = json!({
+        "@context": [DID_CONTEXT, JWS_CONTEXT],
+        "id": document.id,
+        "verificationMethod": methods,
+        "authentication": document.authentication,
+        "assertionMethod": document.assertion_method,
+    });
+    if let Some(controller) = &document.controller {
+        doc["controller"] = json!(controller);
+    }
+    if !document.also_known_as.is_empty() {
+        doc["alsoKnownAs"] = json!(document.also_known_as);
+    }
+    doc.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::credit::RiskAssessment;
+
+    const ISSUER: &str = "did:decent_credit:issuer:test";
+    const NOW: u64 = 1_728_950_400 * NANOS_PER_SEC;
+    const DAY: u64 = 24 * 60 * 60 * NANOS_PER_SEC;
+    // secp256k1 生成元 G 的压缩公钥及其 y 坐标
+    const GENERATOR_COMPRESSED: [u8; 33] = [
+        0x02, 0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87, 0x0b, 0x07,
+        0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b, 0x16, 0xf8, 0x17, 0x98,
+    ];
+    const GENERATOR_Y: &str = "483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+    fn service() -> DidService {
+        let mut service = DidService::new();
+        service.state.issuer_public_key = Some(GENERATOR_COMPRESSED.to_vec());
+        service
+    }
+
+    fn report() -> RiskAssessmentReport {
+        RiskAssessmentReport {
+            report_id: "RPT-1".to_string(),
+            user_did: "did:decent_credit:alice".to_string(),
+            institution_id: Principal::anonymous(),
+            assessment: RiskAssessment {
+                credit_score: 88,
+                risk_level: "Low Risk".to_string(),
+                assessment_details: Vec::new(),
+                suggestions: Vec::new(),
+                model_id: "default".to_string(),
+                model_version: 1,
+                contributions: Vec::new(),
+                top_adverse_factors: vec!["LOAN_FREQUENCY".to_string()],
+            },
+            created_at: NOW,
+        }
+    }
+
+    // 测试中以固定字节代替阈值 ECDSA 签名
+    fn issue(service: &mut DidService, issued_at: u64) -> VerifiableCredential {
+        let (mut credential, signing_input) = service.prepare_report_credential_at(&report(), ISSUER.to_string(), issued_at).unwrap();
+        service.attach_signature(&mut credential, &signing_input, &[9; 64], issued_at).unwrap();
+        credential
+    }
+
+    #[test]
+    fn issued_credentials_verify_until_expiry() {
+        let mut service = service();
+        let credential = issue(&mut service, NOW);
+        assert_eq!(credential.id, "urn:decent_credit:report:RPT-1");
+        assert_eq!(credential.expires_at, NOW + DEFAULT_CREDENTIAL_VALIDITY_DAYS * DAY);
+
+        let body: Value = serde_json::from_str(&credential.credential_json).unwrap();
+        assert_eq!(body["credentialSubject"]["creditScore"], 88);
+        assert_eq!(body["issuanceDate"], "2024-10-15T00:00:00Z");
+        let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(credential.jwt.split('.').next().unwrap()).unwrap()).unwrap();
+        assert_eq!(header["alg"], "ES256K");
+
+        let key_id = key_id_of(ISSUER);
+        let verification = service.verify_credential_at(&credential.jwt, &key_id, NOW + DAY);
+        assert!(verification.valid, "{:?}", verification.error);
+        assert_eq!(verification.subject.as_deref(), Some("did:decent_credit:alice"));
+        assert_eq!(verification.expires_at, Some(credential.expires_at));
+
+        let expired = service.verify_credential_at(&credential.jwt, &key_id, credential.expires_at + NANOS_PER_SEC);
+        assert!(!expired.valid);
+        assert!(!service.verify_credential_at(&credential.jwt, &key_id_of("did:decent_credit:issuer:other"), NOW).valid);
+
+        // 之后签发时清理已过期的登记
+        issue(&mut service, credential.expires_at + NANOS_PER_SEC);
+        assert_eq!(service.state.issued_credentials.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn tampered_or_unregistered_credentials_are_rejected() {
+        let mut service = service();
+        let credential = issue(&mut service, NOW);
+        let key_id = key_id_of(ISSUER);
+
+        let parts: Vec<&str> = credential.jwt.split('.').collect();
+        let mut payload: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        payload["vc"]["credentialSubject"]["creditScore"] = json!(100);
+        let forged = format!("{}.{}.{}", parts[0], URL_SAFE_NO_PAD.encode(payload.to_string()), parts[2]);
+        assert_eq!(service.verify_credential_at(&forged, &key_id, NOW).error.as_deref(), Some("签名校验失败"));
+
+        // 未在本平台登记的凭证
+        let mut other = DidService::new();
+        let foreign = issue(&mut other, NOW);
+        assert!(!DidService::new().verify_credential_at(&foreign.jwt, &key_id, NOW).valid);
+        assert!(!service.verify_credential_at("not-a-jwt", &key_id, NOW).valid);
+    }
+
+    #[test]
+    fn validity_days_and_signature_are_checked() {
+        let mut service = service();
+        assert!(service.set_credential_validity_days(0).is_err());
+        assert!(service.set_credential_validity_days(u64::MAX).is_err());
+        assert!(service.set_credential_validity_days(MAX_CREDENTIAL_VALIDITY_DAYS).is_ok());
+        assert!(service.prepare_report_credential_at(&report(), ISSUER.to_string(), u64::MAX).is_err());
+
+        let (mut credential, signing_input) = service.prepare_report_credential_at(&report(), ISSUER.to_string(), NOW).unwrap();
+        assert!(service.attach_signature(&mut credential, &signing_input, &[9; 32], NOW).is_err());
+        // 公钥确定后不可更换签名密钥
+        assert!(service.set_signing_key_name("dfx_test_key".to_string()).is_err());
+        assert!(DidService::new().set_signing_key_name("dfx_test_key".to_string()).is_ok());
+    }
+
+    #[test]
+    fn issuer_key_is_published_as_secp256k1_jwk() {
+        let jwk = secp256k1_jwk(&GENERATOR_COMPRESSED).unwrap();
+        assert_eq!((jwk.kty.as_str(), jwk.crv.as_str()), ("EC", "secp256k1"));
+        assert_eq!(URL_SAFE_NO_PAD.decode(&jwk.x).unwrap(), GENERATOR_COMPRESSED[1..]);
+        assert_eq!(hex::encode(URL_SAFE_NO_PAD.decode(jwk.y.unwrap()).unwrap()), GENERATOR_Y);
+
+        // 前缀 03 取另一个 y
+        let mut odd = GENERATOR_COMPRESSED;
+        odd[0] = 0x03;
+        let y = URL_SAFE_NO_PAD.decode(secp256k1_jwk(&odd).unwrap().y.unwrap()).unwrap();
+        assert_ne!(hex::encode(y), GENERATOR_Y);
+        assert!(secp256k1_jwk(&[0x02; 10]).is_err());
+    }
+
+    #[test]
+    fn did_document_json_uses_w3c_names() {
+        let document = DidDocument {
+            id: "did:decent_credit:alice".to_string(),
+            controller: None,
+            also_known_as: vec!["did:decent_credit:platform:abc".to_string()],
+            verification_method: vec![VerificationMethod {
+                id: "did:decent_credit:alice#key-1".to_string(),
+                method_type: "JsonWebKey2020".to_string(),
+                controller: "did:decent_credit:alice".to_string(),
+                public_key_jwk: ed25519_jwk(&[0; 32]),
+            }],
+            authentication: vec!["did:decent_credit:alice#key-1".to_string()],
+            assertion_method: Vec::new(),
+        };
+
+        let json: Value = serde_json::from_str(&did_document_json(&document)).unwrap();
+        assert_eq!(json["verificationMethod"][0]["type"], "JsonWebKey2020");
+        assert_eq!(json["verificationMethod"][0]["publicKeyJwk"]["x"], "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+        assert_eq!(json["alsoKnownAs"][0], "did:decent_credit:platform:abc");
+        assert!(json.get("controller").is_none());
+    }
+}
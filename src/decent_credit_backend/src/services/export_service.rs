@@ -0,0 +1,566 @@
+use candid::Principal;
+use ic_cdk::api::time;
+use serde_json::{json, Value};
+use sha2::{Sha256, Digest};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use log::{info, debug};
+use crate::models::billing::BillingLedgerEntry;
+use crate::models::export::*;
+use crate::models::record::*;
+use crate::services::billing_service::BILLING_SERVICE;
+use crate::services::record_service::RECORD_SERVICE;
+
+const DEFAULT_PAGE_SIZE: u32 = 1000;
+const MAX_PAGE_SIZE: u32 = 5000;
+// 单个分片不超过查询响应上限（2MB）
+const MAX_CHUNK_BYTES: usize = 1_900_000;
+const EXPORT_TTL_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+const RECORD_COLUMNS: [&str; 15] = [
+    "id", "record_type", "user_did", "event_date", "status", "timestamp",
+    "amount", "loan_id", "term_months", "interest_rate", "repayment_date",
+    "overdue_days", "period_amount", "reward_amount", "query_price",
+];
+const DEDUCTION_COLUMNS: [&str; 10] = [
+    "id", "record_id", "institution_name", "deduction_points", "reason",
+    "data_quality_issue", "created_at", "operator_id", "operator_name", "reversed_at",
+];
+const BILLING_COLUMNS: [&str; 11] = [
+    "id", "escrow_id", "step", "from", "to", "amount", "split_role",
+    "success", "block_height", "tx_hash", "timestamp",
+];
+
+// 行的排序键（时间戳、ID），分片游标记录上一分片最后一行的键
+type SortKey = (u64, String);
+// 一页已编码的行及游标之后的剩余行数
+type RowPage = (Vec<(SortKey, Value)>, usize);
+
+// 按序生成分片的进度：当前数据集、已导出的最后一行、下一个分片序号
+#[derive(Default)]
+struct ExportCursor {
+    dataset: usize,
+    after: Option<SortKey>,
+    next_index: u32,
+}
+
+// 导出任务只保存在内存中，过期或升级后需重新创建；分片在获取时按游标依次生成
+struct ExportJobState {
+    job: ExportJob,
+    dataset_order: Vec<ExportDataset>,
+    page_size: usize,
+    as_of: u64,                         // 创建时间，之后新增的数据不计入本次导出
+    cursor: ExportCursor,
+    manifest: Sha256,                   // 已生成分片摘要的增量校验和
+    last_chunk: Option<ExportChunk>,    // 最近生成的分片，便于重试获取
+}
+
+pub struct ExportService {
+    jobs: BTreeMap<String, ExportJobState>,
+    next_id: u64,
+}
+
+thread_local! {
+    pub static EXPORT_SERVICE: RefCell<ExportService> = RefCell::new(ExportService::new());
+}
+
+fn in_range(timestamp: u64, filter: &ExportFilter) -> bool {
+    filter.from.is_none_or(|from| timestamp >= from) && filter.to.is_none_or(|to| timestamp <= to)
+}
+
+fn record_row(record: &CreditRecord) -> Value {
+    let mut row = json!({
+        "id": record.id,
+        "record_type": format!("{:?}", record.record_type),
+        "user_did": record.user_did,
+        "event_date": record.event_date,
+        "status": format!("{:?}", record.status),
+        "timestamp": record.timestamp,
+        "reward_amount": record.reward_amount,
+        "query_price": record.query_price,
+    });
+    let content = match &record.content {
+        RecordContent::Loan(loan) => json!({
+            "amount": loan.amount,
+            "loan_id": loan.loan_id,
+            "term_months": loan.term_months,
+            "interest_rate": loan.interest_rate,
+        }),
+        RecordContent::Repayment(repayment) => json!({
+            "amount": repayment.amount,
+            "loan_id": repayment.loan_id,
+            "repayment_date": repayment.repayment_date,
+        }),
+        RecordContent::Overdue(overdue) => json!({
+            "amount": overdue.amount,
+            "overdue_days": overdue.overdueDays,
+            "period_amount": overdue.period_amount,
+        }),
+    };
+    if let (Some(row), Value::Object(content)) = (row.as_object_mut(), content) {
+        row.extend(content);
+    }
+    row
+}
+
+fn deduction_row(record: &CreditDeductionRecord) -> Value {
+    json!({
+        "id": record.id,
+        "record_id": record.record_id,
+        "institution_name": record.institution_name,
+        "deduction_points": record.deduction_points,
+        "reason": record.reason,
+        "data_quality_issue": record.data_quality_issue,
+        "created_at": record.created_at,
+        "operator_id": record.operator_id.to_text(),
+        "operator_name": record.operator_name,
+        "reversed_at": record.reversed_at,
+    })
+}
+
+fn billing_row(entry: &BillingLedgerEntry) -> Value {
+    json!({
+        "id": entry.id,
+        "escrow_id": entry.escrow_id,
+        "step": format!("{:?}", entry.step),
+        "from": entry.from.to_text(),
+        "to": entry.to.to_text(),
+        "amount": entry.amount,
+        "split_role": entry.split_role.as_ref().map(|r| format!("{:?}", r)),
+        "success": entry.success,
+        "block_height": entry.block_height,
+        "tx_hash": entry.tx_hash,
+        "timestamp": entry.timestamp,
+    })
+}
+
+// 排序后跳过游标之前的行，只编码前 limit 行；同时返回游标之后的剩余行数
+fn page_rows<T>(
+    mut items: Vec<(SortKey, T)>,
+    after: Option<&SortKey>,
+    limit: usize,
+    encode: impl Fn(&T) -> Value,
+) -> RowPage {
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    items.retain(|(key, _)| after.is_none_or(|after| key > after));
+    let remaining = items.len();
+    let rows = items.iter()
+        .take(limit)
+        .map(|(key, item)| (key.clone(), encode(item)))
+        .collect();
+    (rows, remaining)
+}
+
+// 读取某个数据集中符合条件的行，按时间排序保证分页稳定；as_of 之后新增的数据不计入
+fn collect_rows(
+    dataset: &ExportDataset,
+    institution_id: Principal,
+    filter: &ExportFilter,
+    as_of: u64,
+    after: Option<&SortKey>,
+    limit: usize,
+) -> RowPage {
+    let included = |timestamp: u64| in_range(timestamp, filter) && timestamp <= as_of;
+    match dataset {
+        ExportDataset::Records => {
+            let records = RECORD_SERVICE.with(|service| {
+                service.borrow().query_records(RecordQueryParams {
+                    institution_id: Some(institution_id),
+                    user_did: None,
+                    record_type: filter.record_type.clone(),
+                    start_date: String::new(),
+                    status: filter.status.clone(),
+                })
+            });
+            let items = records.into_iter()
+                .filter(|r| included(r.timestamp))
+                .map(|r| ((r.timestamp, r.id.clone()), r))
+                .collect();
+            page_rows(items, after, limit, record_row)
+        }
+        ExportDataset::DeductionRecords => {
+            let deductions = RECORD_SERVICE.with(|service| {
+                service.borrow().get_deduction_records(Some(institution_id))
+            });
+            let items = deductions.into_iter()
+                .filter(|d| included(d.created_at))
+                .map(|d| ((d.created_at, format!("{:020}", d.id)), d))
+                .collect();
+            page_rows(items, after, limit, deduction_row)
+        }
+        ExportDataset::BillingHistory => {
+            let ledger = BILLING_SERVICE.with(|service| {
+                service.borrow().get_ledger(Some(institution_id))
+            });
+            let items = ledger.into_iter()
+                .filter(|e| included(e.timestamp))
+                .map(|e| ((e.id, String::new()), e))
+                .collect();
+            page_rows(items, after, limit, billing_row)
+        }
+    }
+}
+
+fn columns(dataset: &ExportDataset) -> &'static [&'static str] {
+    match dataset {
+        ExportDataset::Records => &RECORD_COLUMNS,
+        ExportDataset::DeductionRecords => &DEDUCTION_COLUMNS,
+        ExportDataset::BillingHistory => &BILLING_COLUMNS,
+    }
+}
+
+fn csv_field(value: Option<&Value>) -> String {
+    let text = match value {
+        None | Some(Value::Null) => return String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn encode_row(format: &ExportFormat, dataset: &ExportDataset, row: &Value) -> String {
+    match format {
+        ExportFormat::Ndjson => row.to_string(),
+        ExportFormat::Csv => columns(dataset).iter()
+            .map(|column| csv_field(row.get(*column)))
+            .collect::<Vec<_>>()
+            .join(","),
+    }
+}
+
+// 按行数和字节上限编码一个分片（至少一行），CSV 分片都带表头；返回分片数据和用掉的行数
+fn encode_page(format: &ExportFormat, dataset: &ExportDataset, rows: &[(SortKey, Value)], page_size: usize) -> (Vec<u8>, usize) {
+    let mut page = match format {
+        ExportFormat::Csv => format!("{}\n", columns(dataset).join(",")),
+        ExportFormat::Ndjson => String::new(),
+    };
+
+    let mut used = 0;
+    for (_, row) in rows.iter().take(page_size) {
+        let line = format!("{}\n", encode_row(format, dataset, row));
+        if used > 0 && page.len() + line.len() > MAX_CHUNK_BYTES {
+            break;
+        }
+        page.push_str(&line);
+        used += 1;
+    }
+    (page.into_bytes(), used)
+}
+
+impl ExportJobState {
+    /// 从游标处生成下一个分片并推进游标；rows 按数据集读取游标之后的行及剩余行数。
+    /// 校验和为各分片 SHA-256 摘要依次拼接后的 SHA-256，生成最后一个分片时确定
+    fn build_next_chunk(
+        &mut self,
+        rows: impl Fn(&ExportDataset, Option<&SortKey>, usize) -> RowPage,
+    ) -> Option<ExportChunk> {
+        while let Some(dataset) = self.dataset_order.get(self.cursor.dataset).cloned() {
+            let (page, remaining) = rows(&dataset, self.cursor.after.as_ref(), self.page_size);
+            if page.is_empty() {
+                self.cursor.dataset += 1;
+                self.cursor.after = None;
+                continue;
+            }
+
+            let (data, used) = encode_page(&self.job.format, &dataset, &page, self.page_size);
+            self.cursor.after = Some(page[used - 1].0.clone());
+            if used == remaining {
+                self.cursor.dataset += 1;
+                self.cursor.after = None;
+            }
+            // 后续数据集都没有数据时本分片即为最后一个
+            let is_last = self.dataset_order[self.cursor.dataset.min(self.dataset_order.len())..].iter()
+                .enumerate()
+                .all(|(offset, next)| {
+                    let after = if offset == 0 { self.cursor.after.as_ref() } else { None };
+                    rows(next, after, 0).1 == 0
+                });
+
+            let index = self.cursor.next_index;
+            self.cursor.next_index += 1;
+            let digest = Sha256::digest(&data);
+            self.manifest.update(digest);
+            if let Some(summary) = self.job.datasets.iter_mut().find(|s| s.dataset == dataset) {
+                summary.first_chunk.get_or_insert(index);
+                summary.chunk_count += 1;
+            }
+            if is_last {
+                self.finish();
+            }
+            return Some(ExportChunk {
+                job_id: self.job.id.clone(),
+                index,
+                dataset,
+                data,
+                sha256: hex::encode(digest),
+                is_last,
+            });
+        }
+        self.finish();
+        None
+    }
+
+    fn finish(&mut self) {
+        if self.job.checksum.is_none() {
+            self.job.total_chunks = Some(self.cursor.next_index);
+            self.job.checksum = Some(hex::encode(self.manifest.clone().finalize()));
+            self.cursor.dataset = self.dataset_order.len();
+        }
+    }
+
+    /// 分片须按序获取；重复获取最近一个分片时直接返回
+    fn chunk_at(
+        &mut self,
+        index: u32,
+        rows: impl Fn(&ExportDataset, Option<&SortKey>, usize) -> RowPage,
+    ) -> Result<ExportChunk, String> {
+        if let Some(last) = self.last_chunk.as_ref().filter(|c| c.index == index) {
+            return Ok(last.clone());
+        }
+        if index != self.cursor.next_index || self.job.checksum.is_some() {
+            return Err(match self.job.total_chunks {
+                Some(total) => format!("分片不存在: {}（共 {} 个分片）", index, total),
+                None => format!("请按顺序获取分片，下一个分片为 {}", self.cursor.next_index),
+            });
+        }
+
+        let chunk = self.build_next_chunk(rows)
+            .ok_or_else(|| format!("分片不存在: {}", index))?;
+        self.last_chunk = Some(chunk.clone());
+        Ok(chunk)
+    }
+}
+
+impl Default for ExportService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExportService {
+    pub fn new() -> Self {
+        Self {
+            jobs: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// 创建导出任务：只统计行数，分片在获取时按数据集依次生成
+    pub fn create_job(&mut self, request: CreateExportJobRequest) -> Result<ExportJob, String> {
+        if request.datasets.is_empty() {
+            return Err("至少选择一个导出数据集".to_string());
+        }
+        if let (Some(from), Some(to)) = (request.filter.from, request.filter.to) {
+            if from > to {
+                return Err("开始时间不能晚于结束时间".to_string());
+            }
+        }
+        let page_size = request.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+
+        let now = time();
+        self.purge_expired(now);
+        self.next_id += 1;
+        let job_id = format!("EXP-{}-{}", now / 1_000_000_000, self.next_id);
+
+        // 去重并保留请求中的顺序
+        let mut seen = HashSet::new();
+        let dataset_order: Vec<ExportDataset> = request.datasets.iter()
+            .filter(|d| seen.insert((*d).clone()))
+            .cloned()
+            .collect();
+        let summaries: Vec<ExportDatasetSummary> = dataset_order.iter()
+            .map(|dataset| ExportDatasetSummary {
+                dataset: dataset.clone(),
+                rows: collect_rows(dataset, request.institution_id, &request.filter, now, None, 0).1 as u64,
+                first_chunk: None,
+                chunk_count: 0,
+            })
+            .collect();
+        let total_rows = summaries.iter().map(|s| s.rows).sum();
+
+        let job = ExportJob {
+            id: job_id.clone(),
+            institution_id: request.institution_id,
+            format: request.format,
+            filter: request.filter,
+            datasets: summaries,
+            total_rows,
+            total_chunks: None,
+            checksum: None,
+            created_at: now,
+            expires_at: now + EXPORT_TTL_NANOS,
+        };
+        info!("Export job {} created for {}: {} rows", job_id, job.institution_id.to_text(), total_rows);
+        self.jobs.insert(job_id, ExportJobState {
+            job: job.clone(),
+            dataset_order,
+            page_size,
+            as_of: now,
+            cursor: ExportCursor::default(),
+            manifest: Sha256::new(),
+            last_chunk: None,
+        });
+        Ok(job)
+    }
+
+    pub fn get_job(&self, job_id: &str) -> Option<ExportJob> {
+        self.jobs.get(job_id)
+            .filter(|s| s.job.expires_at > time())
+            .map(|s| s.job.clone())
+    }
+
+    pub fn get_chunk(&mut self, job_id: &str, index: u32) -> Result<ExportChunk, String> {
+        let now = time();
+        let state = self.jobs.get_mut(job_id)
+            .filter(|s| s.job.expires_at > now)
+            .ok_or_else(|| format!("导出任务不存在或已过期: {}", job_id))?;
+        debug!("Serving export chunk {} of {}", index, job_id);
+
+        let institution_id = state.job.institution_id;
+        let filter = state.job.filter.clone();
+        let as_of = state.as_of;
+        state.chunk_at(index, |dataset, after, limit| {
+            collect_rows(dataset, institution_id, &filter, as_of, after, limit)
+        })
+    }
+
+    pub fn list_jobs(&self, institution_id: Principal) -> Vec<ExportJob> {
+        let now = time();
+        self.jobs.values()
+            .filter(|s| s.job.institution_id == institution_id && s.job.expires_at > now)
+            .map(|s| s.job.clone())
+            .collect()
+    }
+
+    pub fn delete_job(&mut self, job_id: &str) -> Result<(), String> {
+        self.jobs.remove(job_id)
+            .map(|_| ())
+            .ok_or_else(|| format!("导出任务不存在: {}", job_id))
+    }
+
+    fn purge_expired(&mut self, now: u64) {
+        self.jobs.retain(|_, s| s.job.expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ledger_row(id: u64, tx_hash: &str) -> Value {
+        json!({ "id": id, "amount": 100, "success": true, "tx_hash": tx_hash, "block_height": null })
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field(None), "");
+        assert_eq!(csv_field(Some(&Value::Null)), "");
+        assert_eq!(csv_field(Some(&json!(42))), "42");
+        assert_eq!(csv_field(Some(&json!("plain"))), "plain");
+        assert_eq!(csv_field(Some(&json!("a,\"b\""))), "\"a,\"\"b\"\"\"");
+    }
+
+    fn keyed(rows: Vec<Value>) -> Vec<(SortKey, Value)> {
+        rows.into_iter()
+            .map(|row| ((row["id"].as_u64().unwrap_or(0), String::new()), row))
+            .collect()
+    }
+
+    // 内存中的数据集，模拟 collect_rows 的游标与行数语义
+    fn source(data: Vec<(ExportDataset, Vec<Value>)>) -> impl Fn(&ExportDataset, Option<&SortKey>, usize) -> RowPage {
+        move |dataset, after, limit| {
+            let rows = data.iter()
+                .find(|(d, _)| d == dataset)
+                .map(|(_, rows)| keyed(rows.clone()))
+                .unwrap_or_default();
+            page_rows(rows, after, limit, Value::clone)
+        }
+    }
+
+    fn job_state(datasets: &[ExportDataset], page_size: usize) -> ExportJobState {
+        ExportJobState {
+            job: ExportJob {
+                id: "EXP-1".to_string(),
+                institution_id: Principal::anonymous(),
+                format: ExportFormat::Ndjson,
+                filter: ExportFilter::default(),
+                datasets: datasets.iter()
+                    .map(|d| ExportDatasetSummary { dataset: d.clone(), rows: 0, first_chunk: None, chunk_count: 0 })
+                    .collect(),
+                total_rows: 0,
+                total_chunks: None,
+                checksum: None,
+                created_at: 0,
+                expires_at: 1,
+            },
+            dataset_order: datasets.to_vec(),
+            page_size,
+            as_of: 0,
+            cursor: ExportCursor::default(),
+            manifest: Sha256::new(),
+            last_chunk: None,
+        }
+    }
+
+    #[test]
+    fn pages_repeat_csv_header_and_respect_page_size() {
+        let rows = keyed((1..=5).map(|id| ledger_row(id, "0x1")).collect());
+        let header = format!("{}\n", BILLING_COLUMNS.join(","));
+
+        let (page, used) = encode_page(&ExportFormat::Csv, &ExportDataset::BillingHistory, &rows, 2);
+        assert_eq!(used, 2);
+        assert!(String::from_utf8(page).unwrap().starts_with(&header));
+        let (last, used) = encode_page(&ExportFormat::Csv, &ExportDataset::BillingHistory, &rows[4..], 2);
+        assert_eq!(used, 1);
+        assert_eq!(String::from_utf8(last).unwrap(), format!("{}5,,,,,100,,true,,0x1,\n", header));
+
+        let (ndjson, used) = encode_page(&ExportFormat::Ndjson, &ExportDataset::BillingHistory, &rows, 10);
+        assert_eq!(used, 5);
+        assert_eq!(String::from_utf8(ndjson).unwrap().lines().count(), 5);
+    }
+
+    #[test]
+    fn chunks_are_built_in_order_with_incremental_checksum() {
+        let datasets = [ExportDataset::BillingHistory, ExportDataset::DeductionRecords, ExportDataset::Records];
+        let rows = source(vec![
+            (ExportDataset::BillingHistory, (1..=3).map(|id| ledger_row(id, "0x1")).collect()),
+            (ExportDataset::Records, vec![json!({ "id": 7, "amount": 5000 })]),
+        ]);
+        let mut state = job_state(&datasets, 2);
+
+        // 必须按序获取，重复获取最近的分片直接返回
+        assert!(state.chunk_at(1, &rows).is_err());
+        let first = state.chunk_at(0, &rows).unwrap();
+        assert_eq!(state.chunk_at(0, &rows).unwrap().sha256, first.sha256);
+        let chunks = vec![first, state.chunk_at(1, &rows).unwrap(), state.chunk_at(2, &rows).unwrap()];
+        assert!(state.chunk_at(3, &rows).unwrap_err().contains("共 3 个分片"));
+
+        assert_eq!(chunks.iter().map(|c| c.is_last).collect::<Vec<_>>(), vec![false, false, true]);
+        assert_eq!(String::from_utf8(chunks[1].data.clone()).unwrap().lines().count(), 1);
+        assert_eq!(chunks[2].dataset, ExportDataset::Records);
+        assert_eq!(
+            state.job.datasets.iter().map(|s| (s.first_chunk, s.chunk_count)).collect::<Vec<_>>(),
+            vec![(Some(0), 2), (None, 0), (Some(2), 1)],
+        );
+
+        let mut manifest = Sha256::new();
+        for chunk in &chunks {
+            assert_eq!(chunk.sha256, hex::encode(Sha256::digest(&chunk.data)));
+            manifest.update(hex::decode(&chunk.sha256).unwrap());
+        }
+        assert_eq!(state.job.total_chunks, Some(3));
+        assert_eq!(state.job.checksum, Some(hex::encode(manifest.finalize())));
+    }
+
+    #[test]
+    fn empty_export_finishes_without_chunks() {
+        let mut state = job_state(&[ExportDataset::Records], 10);
+        assert!(state.chunk_at(0, source(Vec::new())).is_err());
+        assert_eq!(state.job.total_chunks, Some(0));
+        assert_eq!(state.job.checksum, Some(hex::encode(Sha256::new().finalize())));
+    }
+}
@@ -0,0 +1,319 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ic_certification::{labeled, pruned, AsHashTree, Hash, RbTree};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Sha256, Digest};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use log::{info, warn};
+use crate::models::http::*;
+
+pub const OPENAPI_PATH: &str = "/openapi.json";
+const ASSETS_LABEL: &[u8] = b"http_assets";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+// 经过认证的公开响应（v1 http_assets 认证），查询调用返回时附带 IC-Certificate 头
+#[derive(Default)]
+struct CertifiedAssets {
+    tree: RbTree<String, Hash>,
+    bodies: HashMap<String, Vec<u8>>,
+}
+
+thread_local! {
+    static CERTIFIED_ASSETS: RefCell<CertifiedAssets> = RefCell::new(CertifiedAssets::default());
+}
+
+/// 启动或升级后生成需要认证的公开响应
+pub fn init_certified_assets() {
+    certify_asset(OPENAPI_PATH, openapi_document().to_string().into_bytes());
+    info!("Certified HTTP assets initialized");
+}
+
+/// 登记（或更新）一条经过认证的公开响应，并刷新 canister 的认证数据
+pub fn certify_asset(path: &str, body: Vec<u8>) {
+    CERTIFIED_ASSETS.with(|assets| {
+        let mut assets = assets.borrow_mut();
+        assets.tree.insert(path.to_string(), Sha256::digest(&body).into());
+        assets.bodies.insert(path.to_string(), body);
+
+        let root = labeled(ASSETS_LABEL, pruned(assets.tree.root_hash()));
+        ic_cdk::api::set_certified_data(&root.digest());
+    });
+}
+
+/// 返回已认证的响应；仅查询调用中可以拿到证书
+pub fn certified_response(path: &str) -> Option<HttpResponse> {
+    CERTIFIED_ASSETS.with(|assets| {
+        let assets = assets.borrow();
+        let body = assets.bodies.get(path)?.clone();
+        let mut headers = vec![("Content-Type".to_string(), JSON_CONTENT_TYPE.to_string())];
+
+        if let Some(certificate) = ic_cdk::api::data_certificate() {
+            let tree = labeled(ASSETS_LABEL, assets.tree.witness(path.as_bytes()));
+            let mut serializer = serde_cbor::ser::Serializer::new(Vec::new());
+            let encoded = serializer.self_describe()
+                .and_then(|_| tree.serialize(&mut serializer))
+                .map(|_| serializer.into_inner());
+            match encoded {
+                Ok(cbor) => headers.push((
+                    "IC-Certificate".to_string(),
+                    format!("certificate=:{}:, tree=:{}:", STANDARD.encode(certificate), STANDARD.encode(cbor)),
+                )),
+                Err(e) => warn!("Failed to encode certificate tree for {}: {}", path, e),
+            }
+        }
+
+        Some(HttpResponse {
+            status_code: 200,
+            headers,
+            body,
+            upgrade: None,
+        })
+    })
+}
+
+pub fn json_response<T: Serialize>(status_code: u16, value: &T) -> HttpResponse {
+    match serde_json::to_vec(value) {
+        Ok(body) => HttpResponse {
+            status_code,
+            headers: vec![("Content-Type".to_string(), JSON_CONTENT_TYPE.to_string())],
+            body,
+            upgrade: None,
+        },
+        Err(e) => error_response(500, &format!("响应序列化失败: {}", e)),
+    }
+}
+
+pub fn error_response(status_code: u16, message: &str) -> HttpResponse {
+    json_response(status_code, &json!({ "error": message }))
+}
+
+/// 让 HTTP 网关改用更新调用重新发起请求，响应经过共识
+pub fn upgrade_response() -> HttpResponse {
+    HttpResponse {
+        status_code: 200,
+        headers: Vec::new(),
+        body: Vec::new(),
+        upgrade: Some(true),
+    }
+}
+
+pub fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request.headers.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// 从 Authorization: Bearer 或 X-API-Key 头中取出 API Key
+pub fn api_key(request: &HttpRequest) -> Option<&str> {
+    header(request, "X-API-Key")
+        .or_else(|| header(request, "Authorization").and_then(|v| v.strip_prefix("Bearer ")))
+        .map(str::trim)
+}
+
+/// 拆分请求路径（去掉查询串）并对每段做百分号解码
+pub fn path_segments(url: &str) -> Vec<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect()
+}
+
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// REST 接口的 OpenAPI 描述
+pub fn openapi_document() -> Value {
+    let secured = json!([{ "apiKey": [] }, { "bearer": [] }]);
+    let did_param = json!({
+        "name": "did", "in": "path", "required": true,
+        "description": "用户 DID，需百分号编码", "schema": { "type": "string" }
+    });
+    let error = json!({ "description": "错误", "content": { JSON_CONTENT_TYPE: { "schema": { "$ref": "#/components/schemas/Error" } } } });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Decent Credit REST API",
+            "version": "1.0.0",
+            "description": "面向无法使用 Candid 的机构系统的 JSON 接口。需认证的请求会升级为更新调用，响应经过共识；公开的 /openapi.json 带有 IC-Certificate 认证。"
+        },
+        "paths": {
+            "/v1/records": {
+                "post": {
+                    "summary": "提交信用记录",
+                    "security": secured,
+                    "requestBody": { "required": true, "content": { JSON_CONTENT_TYPE: { "schema": { "$ref": "#/components/schemas/RecordSubmission" } } } },
+                    "responses": {
+                        "201": { "description": "已提交", "content": { JSON_CONTENT_TYPE: { "schema": { "$ref": "#/components/schemas/RecordSubmissionResponse" } } } },
+                        "400": error, "401": error, "429": error
+                    }
+                }
+            },
+            "/v1/users/{did}/records": {
+                "get": {
+                    "summary": "按用户 DID 查询记录（按定价计费）",
+                    "security": secured,
+                    "parameters": [did_param],
+                    "responses": {
+                        "200": { "description": "记录列表", "content": { JSON_CONTENT_TYPE: { "schema": { "type": "array", "items": { "type": "object" } } } } },
+                        "400": error, "401": error, "429": error
+                    }
+                }
+            },
+            "/v1/users/{did}/assessment": {
+                "post": {
+                    "summary": "生成风险评估（占用查询配额）",
+                    "security": secured,
+                    "parameters": [did_param],
+                    "responses": {
+                        "200": { "description": "风险评估", "content": { JSON_CONTENT_TYPE: { "schema": { "$ref": "#/components/schemas/RiskAssessment" } } } },
+                        "400": error, "401": error, "429": error
+                    }
+                }
+            },
+            "/v1/balance": {
+                "get": {
+                    "summary": "查询机构 DCC 余额",
+                    "security": secured,
+                    "responses": {
+                        "200": { "description": "余额", "content": { JSON_CONTENT_TYPE: { "schema": { "$ref": "#/components/schemas/Balance" } } } },
+                        "400": error, "401": error
+                    }
+                }
+            },
+            "/v1/dids/{did}": {
+                "get": {
+                    "summary": "解析 DID 文档（公开）",
+                    "parameters": [did_param],
+                    "responses": {
+                        "200": { "description": "W3C DID 文档", "content": { JSON_CONTENT_TYPE: { "schema": { "type": "object" } } } },
+                        "404": error
+                    }
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-API-Key" },
+                "bearer": { "type": "http", "scheme": "bearer" }
+            },
+            "schemas": {
+                "Error": { "type": "object", "properties": { "error": { "type": "string" } } },
+                "RecordSubmission": {
+                    "type": "object",
+                    "required": ["record_type", "user_did", "event_date", "content"],
+                    "properties": {
+                        "record_type": { "type": "string", "enum": ["LoanRecord", "RepaymentRecord", "OverdueRecord"] },
+                        "user_did": { "type": "string" },
+                        "event_date": { "type": "string", "format": "date" },
+                        "content": {
+                            "type": "object",
+                            "description": "按记录类型三选一：{\"Loan\": {amount, loan_id, term_months, interest_rate}}、{\"Repayment\": {amount, loan_id, repayment_date}}、{\"Overdue\": {amount, overdueDays, period_amount}}"
+                        }
+                    }
+                },
+                "RecordSubmissionResponse": {
+                    "type": "object",
+                    "properties": {
+                        "record_id": { "type": "string" },
+                        "status": { "type": "string" },
+                        "timestamp": { "type": "integer", "format": "int64" },
+                        "reward_amount": { "type": "integer", "nullable": true }
+                    }
+                },
+                "RiskAssessment": {
+                    "type": "object",
+                    "properties": {
+                        "credit_score": { "type": "integer" },
+                        "risk_level": { "type": "string" },
+                        "assessment_details": { "type": "array", "items": { "type": "string" } },
+                        "suggestions": { "type": "array", "items": { "type": "string" } },
+                        "model_id": { "type": "string" },
+                        "model_version": { "type": "integer" },
+                        "top_adverse_factors": { "type": "array", "items": { "type": "string" } }
+                    }
+                },
+                "Balance": {
+                    "type": "object",
+                    "properties": {
+                        "dcc": { "type": "integer", "format": "int64" },
+                        "usdt_value": { "type": "number" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            url: "/v1/balance".to_string(),
+            headers: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn path_segments_are_decoded_without_query() {
+        assert_eq!(
+            path_segments("/v1/users/did%3Adecent_credit%3Aabc/records?limit=10#top"),
+            vec!["v1", "users", "did:decent_credit:abc", "records"]
+        );
+        assert_eq!(path_segments("//v1//balance/"), vec!["v1", "balance"]);
+        // 不完整的转义原样保留
+        assert_eq!(path_segments("/a%2/b%zz"), vec!["a%2", "b%zz"]);
+    }
+
+    #[test]
+    fn api_key_read_from_either_header() {
+        assert_eq!(api_key(&request(&[("x-api-key", " dck_k1_ab ")])), Some("dck_k1_ab"));
+        assert_eq!(api_key(&request(&[("Authorization", "Bearer dck_k2_cd")])), Some("dck_k2_cd"));
+        assert_eq!(api_key(&request(&[("Authorization", "Basic abc")])), None);
+        assert_eq!(api_key(&request(&[])), None);
+    }
+
+    #[test]
+    fn openapi_document_lists_rest_routes() {
+        let document = openapi_document();
+        let paths = document["paths"].as_object().unwrap();
+        for path in ["/v1/records", "/v1/users/{did}/records", "/v1/users/{did}/assessment", "/v1/balance", "/v1/dids/{did}"] {
+            assert!(paths.contains_key(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn error_responses_are_json() {
+        let response = error_response(429, "too many requests");
+        assert_eq!(response.status_code, 429);
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["error"], "too many requests");
+    }
+}
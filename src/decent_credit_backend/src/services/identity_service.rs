@@ -0,0 +1,326 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::time;
+use sha2::{Sha256, Digest};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use log::{info, warn};
+use crate::models::identity::*;
+use crate::services::crypto_service::with_crypto_service;
+
+const CANONICAL_DID_PREFIX: &str = "did:decent_credit:platform:";
+
+// 需要跨升级保存的身份关联状态；盐值一旦生成不可更换，否则已签发的平台 DID 全部失效
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct IdentityState {
+    pub salt: Option<Vec<u8>>,
+    pub requests: BTreeMap<String, DidLinkRequest>,
+    pub links: HashMap<String, String>,             // 机构 DID -> 平台 DID
+    pub controllers: HashMap<String, Principal>,    // 平台 DID -> 借款人主体
+    pub next_request_id: u64,
+}
+
+pub struct IdentityService {
+    state: IdentityState,
+}
+
+thread_local! {
+    pub static IDENTITY_SERVICE: RefCell<IdentityService> = RefCell::new(IdentityService::new());
+}
+
+/// 首次使用时通过管理 canister 获取随机盐值
+pub async fn ensure_identity_salt() -> Result<(), String> {
+    if IDENTITY_SERVICE.with(|service| service.borrow().state.salt.is_some()) {
+        return Ok(());
+    }
+
+    let (bytes,) = raw_rand().await
+        .map_err(|(code, msg)| format!("获取随机盐值失败: {:?} {}", code, msg))?;
+    IDENTITY_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        // 等待期间可能已有其他调用写入盐值
+        if service.state.salt.is_none() {
+            service.state.salt = Some(bytes);
+            info!("Identity salt initialized");
+        }
+    });
+    Ok(())
+}
+
+impl Default for IdentityService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdentityService {
+    pub fn new() -> Self {
+        Self {
+            state: IdentityState::default(),
+        }
+    }
+
+    /// 由身份哈希生成与机构无关的平台 DID
+    pub fn canonical_did(&self, identity_hash: &str) -> Result<String, String> {
+        let salt = self.state.salt.as_ref()
+            .ok_or_else(|| "身份盐值尚未初始化".to_string())?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(identity_hash.as_bytes());
+        Ok(format!("{}{}", CANONICAL_DID_PREFIX, hex::encode(hasher.finalize())))
+    }
+
+    /// 借款人本人认领平台身份，成为其唯一主体；已被他人认领时拒绝
+    pub fn claim_identity(&mut self, identity_hash: &str, caller: Principal) -> Result<String, String> {
+        let canonical_did = self.canonical_did(identity_hash)?;
+        match self.state.controllers.get(&canonical_did) {
+            Some(controller) if *controller == caller => {}
+            Some(_) => return Err("该身份已被其他主体认领".to_string()),
+            None => {
+                self.state.controllers.insert(canonical_did.clone(), caller);
+                info!("Identity {} claimed by {}", canonical_did, caller.to_text());
+            }
+        }
+        Ok(canonical_did)
+    }
+
+    /// 管理员处理认领争议：解除认领并撤销该身份下的全部关联，返回被解除关联的机构 DID
+    pub fn release_claim(&mut self, canonical_did: &str) -> Result<Vec<String>, String> {
+        self.state.controllers.remove(canonical_did)
+            .ok_or_else(|| "该身份尚未被认领".to_string())?;
+
+        let mut unlinked: Vec<String> = self.state.links.iter()
+            .filter(|(_, c)| c.as_str() == canonical_did)
+            .map(|(did, _)| did.clone())
+            .collect();
+        unlinked.sort();
+        for did in &unlinked {
+            self.state.links.remove(did);
+        }
+        let open: Vec<String> = self.state.requests.values()
+            .filter(|r| r.canonical_did == canonical_did)
+            .filter(|r| matches!(r.status, DidLinkStatus::PendingConsent | DidLinkStatus::Linked))
+            .map(|r| r.id.clone())
+            .collect();
+        for id in open {
+            self.decide(&id, DidLinkStatus::Revoked);
+        }
+        warn!("Identity claim on {} released, {} DIDs unlinked", canonical_did, unlinked.len());
+        Ok(unlinked)
+    }
+
+    /// 机构发起关联请求；机构需证明其 DID 确由该身份哈希生成
+    pub fn request_link(&mut self, input: DidLinkRequestInput) -> Result<DidLinkRequest, String> {
+        let expected = with_crypto_service(|service| {
+            service.generate_did(&input.identity_hash, &input.institution_id)
+        });
+        if expected != input.institution_did {
+            return Err("机构 DID 与身份哈希不匹配".to_string());
+        }
+        if self.state.links.contains_key(&input.institution_did) {
+            return Err("该 DID 已关联到平台 DID".to_string());
+        }
+        if self.state.requests.values().any(|r| {
+            r.institution_did == input.institution_did && r.status == DidLinkStatus::PendingConsent
+        }) {
+            return Err("该 DID 已有待确认的关联请求".to_string());
+        }
+
+        // 借款人须先由本人认领平台身份，关联请求只发给认领者
+        let canonical_did = self.canonical_did(&input.identity_hash)?;
+        let borrower = self.state.controllers.get(&canonical_did)
+            .copied()
+            .ok_or_else(|| "借款人尚未认领该身份，请先由借款人本人认领".to_string())?;
+
+        self.state.next_request_id += 1;
+        let request = DidLinkRequest {
+            id: format!("LINK-{}", self.state.next_request_id),
+            institution_id: input.institution_id,
+            institution_did: input.institution_did,
+            canonical_did,
+            borrower,
+            status: DidLinkStatus::PendingConsent,
+            created_at: time(),
+            decided_at: None,
+        };
+        self.state.requests.insert(request.id.clone(), request.clone());
+        info!("DID link request {} created by {}", request.id, request.institution_id.to_text());
+        Ok(request)
+    }
+
+    /// 借款人同意关联
+    pub fn approve_link(&mut self, request_id: &str, caller: Principal) -> Result<DidLinkRequest, String> {
+        let request = self.pending_request(request_id, caller)?;
+        if self.state.controllers.get(&request.canonical_did) != Some(&caller) {
+            return Err("借款人与该身份已认领的主体不一致".to_string());
+        }
+
+        self.state.links.insert(request.institution_did.clone(), request.canonical_did.clone());
+        let request = self.decide(request_id, DidLinkStatus::Linked);
+        info!("DID {} linked to {}", request.institution_did, request.canonical_did);
+        Ok(request)
+    }
+
+    /// 借款人拒绝关联
+    pub fn reject_link(&mut self, request_id: &str, caller: Principal) -> Result<DidLinkRequest, String> {
+        self.pending_request(request_id, caller)?;
+        Ok(self.decide(request_id, DidLinkStatus::Rejected))
+    }
+
+    /// 借款人撤回对某个机构 DID 的关联同意
+    pub fn revoke_link(&mut self, institution_did: &str, caller: Principal) -> Result<(), String> {
+        let canonical_did = self.state.links.get(institution_did)
+            .cloned()
+            .ok_or_else(|| "该 DID 未关联到平台 DID".to_string())?;
+        if self.state.controllers.get(&canonical_did) != Some(&caller) {
+            return Err("只有借款人本人可以撤回关联".to_string());
+        }
+
+        self.state.links.remove(institution_did);
+        let linked_request = self.state.requests.values()
+            .find(|r| r.institution_did == institution_did && r.status == DidLinkStatus::Linked)
+            .map(|r| r.id.clone());
+        if let Some(id) = linked_request {
+            self.decide(&id, DidLinkStatus::Revoked);
+        }
+        warn!("DID {} unlinked from {}", institution_did, canonical_did);
+        Ok(())
+    }
+
+    /// 与给定 DID 属于同一借款人的全部 DID（含自身）
+    pub fn linked_dids(&self, user_did: &str) -> Vec<String> {
+        let canonical_did = self.state.links.get(user_did)
+            .map(|c| c.as_str())
+            .unwrap_or(user_did);
+
+        let mut dids: Vec<String> = self.state.links.iter()
+            .filter(|(_, c)| c.as_str() == canonical_did)
+            .map(|(did, _)| did.clone())
+            .collect();
+        dids.push(canonical_did.to_string());
+        if !dids.iter().any(|d| d == user_did) {
+            dids.push(user_did.to_string());
+        }
+        dids.sort();
+        dids
+    }
+
+    pub fn get_linked_identity(&self, user_did: &str) -> Option<LinkedIdentity> {
+        let canonical_did = self.state.links.get(user_did)
+            .cloned()
+            .or_else(|| user_did.starts_with(CANONICAL_DID_PREFIX).then(|| user_did.to_string()))?;
+
+        let mut linked_dids: Vec<String> = self.state.links.iter()
+            .filter(|(_, c)| **c == canonical_did)
+            .map(|(did, _)| did.clone())
+            .collect();
+        linked_dids.sort();
+
+        Some(LinkedIdentity {
+            controller: self.state.controllers.get(&canonical_did).copied(),
+            canonical_did,
+            linked_dids,
+        })
+    }
+
+    /// 借款人收到的关联请求
+    pub fn requests_for_borrower(&self, borrower: Principal) -> Vec<DidLinkRequest> {
+        self.state.requests.values()
+            .filter(|r| r.borrower == borrower)
+            .cloned()
+            .collect()
+    }
+
+    /// 机构发起的关联请求
+    pub fn requests_for_institution(&self, institution_id: Principal) -> Vec<DidLinkRequest> {
+        self.state.requests.values()
+            .filter(|r| r.institution_id == institution_id)
+            .cloned()
+            .collect()
+    }
+
+    fn pending_request(&self, request_id: &str, caller: Principal) -> Result<DidLinkRequest, String> {
+        let request = self.state.requests.get(request_id)
+            .ok_or_else(|| format!("关联请求不存在: {}", request_id))?;
+        if request.borrower != caller {
+            return Err("只有被请求的借款人可以处理该请求".to_string());
+        }
+        if request.status != DidLinkStatus::PendingConsent {
+            return Err(format!("关联请求已处理: {:?}", request.status));
+        }
+        Ok(request.clone())
+    }
+
+    fn decide(&mut self, request_id: &str, status: DidLinkStatus) -> DidLinkRequest {
+        let request = self.state.requests.get_mut(request_id)
+            .expect("request checked by caller");
+        request.status = status;
+        request.decided_at = Some(time());
+        request.clone()
+    }
+
+    pub fn export_state(&self) -> IdentityState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: IdentityState) {
+        info!("Restored identity state with {} linked DIDs", state.links.len());
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> IdentityService {
+        let mut service = IdentityService::new();
+        service.state.salt = Some(vec![7; 32]);
+        service
+    }
+
+    #[test]
+    fn identity_can_only_be_claimed_once() {
+        let borrower = Principal::from_slice(&[1]);
+        let squatter = Principal::from_slice(&[2]);
+        let mut service = service();
+
+        let canonical = service.claim_identity("hash-alice", borrower).unwrap();
+        assert!(canonical.starts_with(CANONICAL_DID_PREFIX));
+        // 本人重复认领幂等，其他主体认领被拒绝
+        assert_eq!(service.claim_identity("hash-alice", borrower).unwrap(), canonical);
+        assert!(service.claim_identity("hash-alice", squatter).is_err());
+        assert_eq!(service.state.controllers.get(&canonical), Some(&borrower));
+    }
+
+    #[test]
+    fn claim_requires_salt() {
+        let mut service = IdentityService::new();
+        assert!(service.claim_identity("hash-alice", Principal::from_slice(&[1])).is_err());
+    }
+
+    #[test]
+    fn released_claim_unlinks_dids() {
+        let squatter = Principal::from_slice(&[2]);
+        let borrower = Principal::from_slice(&[1]);
+        let mut service = service();
+        let canonical = service.claim_identity("hash-alice", squatter).unwrap();
+        service.state.links.insert("did:decent_credit:a".to_string(), canonical.clone());
+        service.state.links.insert("did:decent_credit:b".to_string(), canonical.clone());
+        service.state.links.insert("did:decent_credit:other".to_string(), "did:other".to_string());
+
+        let identity = service.get_linked_identity("did:decent_credit:a").unwrap();
+        assert_eq!(identity.controller, Some(squatter));
+        assert_eq!(identity.linked_dids.len(), 2);
+
+        let unlinked = service.release_claim(&canonical).unwrap();
+        assert_eq!(unlinked, vec!["did:decent_credit:a".to_string(), "did:decent_credit:b".to_string()]);
+        assert!(service.get_linked_identity("did:decent_credit:a").is_none());
+        assert_eq!(service.state.links.len(), 1);
+
+        // 解除后真正的借款人可以重新认领
+        assert_eq!(service.claim_identity("hash-alice", borrower).unwrap(), canonical);
+        assert!(service.release_claim("did:unknown").is_err());
+    }
+}
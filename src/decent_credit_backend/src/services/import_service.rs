@@ -0,0 +1,760 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use log::{info, debug, warn};
+use crate::models::http::HttpRecordSubmission;
+use crate::models::import::*;
+use crate::models::record::*;
+use crate::services::record_service::RECORD_SERVICE;
+
+// 单个分片不超过入口消息上限（2MB）
+const MAX_CHUNK_BYTES: usize = 1_900_000;
+// 每个机构尚未处理的分片总字节数上限，分片只保存在堆内存中
+const MAX_PENDING_BYTES_PER_INSTITUTION: usize = 64_000_000;
+// 接收状态的任务超过该时间未上传新分片即视为放弃
+const RECEIVING_TIMEOUT_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// 每批处理的行数，控制单次消息的指令消耗
+const ROWS_PER_BATCH: usize = 500;
+// 每个任务最多保存的行错误数，超出部分只计数
+const MAX_STORED_ERRORS: usize = 10_000;
+const IMPORT_INTERVAL_SECS: u64 = 5;
+// 同一批次连续未能完成（如执行中陷入）的次数上限，超过后任务标记为失败
+const MAX_BATCH_ATTEMPTS: u32 = 3;
+
+// 可导入的字段，CSV 表头或 NDJSON 对象的键按列映射对应到这些字段
+const IMPORT_FIELDS: [&str; 10] = [
+    "record_type", "user_did", "event_date",
+    "amount", "loan_id", "term_months", "interest_rate",
+    "repayment_date", "overdue_days", "period_amount",
+];
+
+// 解析进度：当前分片、分片内偏移、跨分片的未完整行以及 CSV 表头
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct ImportCursor {
+    pub chunk: u32,
+    pub offset: u64,
+    pub line: u64,
+    pub carry: Vec<u8>,
+    pub header: Option<Vec<String>>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ImportJobState {
+    pub job: ImportJob,
+    pub column_mapping: Vec<ColumnMapping>,
+    pub chunks: BTreeMap<u32, Vec<u8>>,     // 已处理完的分片会被释放；不跨升级保存
+    pub cursor: ImportCursor,
+    pub errors: Vec<ImportRowError>,
+}
+
+// 需要跨升级保存的导入状态，升级后从游标处继续处理
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct ImportState {
+    pub jobs: BTreeMap<String, ImportJobState>,
+    pub next_id: u64,
+}
+
+pub struct ImportService {
+    state: ImportState,
+    batch_attempts: HashMap<String, u32>,   // 任务 -> 当前批次已尝试次数，不跨升级保存
+}
+
+impl Default for ImportService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static IMPORT_SERVICE: RefCell<ImportService> = RefCell::new(ImportService::new());
+}
+
+type ParsedRow = (u64, Result<RecordSubmissionRequest, String>);
+
+impl ImportJobState {
+    // 取出下一行（不含换行符）；所有分片读完后返回最后一个未以换行结尾的行
+    fn next_line(&mut self) -> Option<(u64, Vec<u8>)> {
+        loop {
+            let Some(chunk) = self.chunks.get(&self.cursor.chunk) else {
+                if self.cursor.chunk < self.job.chunks_received || self.cursor.carry.is_empty() {
+                    return None;
+                }
+                self.cursor.line += 1;
+                return Some((self.cursor.line, std::mem::take(&mut self.cursor.carry)));
+            };
+
+            let rest = &chunk[self.cursor.offset as usize..];
+            match rest.iter().position(|b| *b == b'\n') {
+                Some(pos) => {
+                    self.cursor.carry.extend_from_slice(&rest[..pos]);
+                    self.cursor.offset += pos as u64 + 1;
+                    self.cursor.line += 1;
+                    return Some((self.cursor.line, std::mem::take(&mut self.cursor.carry)));
+                }
+                None => {
+                    self.cursor.carry.extend_from_slice(rest);
+                    self.chunks.remove(&self.cursor.chunk);
+                    self.cursor.chunk += 1;
+                    self.cursor.offset = 0;
+                }
+            }
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.cursor.chunk >= self.job.chunks_received && self.cursor.carry.is_empty()
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self.job.status, ImportJobStatus::Completed | ImportJobStatus::Cancelled | ImportJobStatus::Failed)
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.chunks.values().map(Vec::len).sum()
+    }
+
+    fn fail(&mut self, reason: String, now: u64) {
+        warn!("Import job {} failed after {} rows: {}", self.job.id, self.job.rows_processed, reason);
+        self.job.status = ImportJobStatus::Failed;
+        self.job.failure_reason = Some(reason);
+        self.job.updated_at = now;
+        self.job.completed_at = Some(now);
+        self.chunks.clear();
+    }
+
+    // 升级后分片数据已丢失：接收中的任务从头重新上传，处理中的任务若仍有未处理的分片则标记失败
+    fn recover_after_upgrade(&mut self, now: u64) {
+        match self.job.status {
+            ImportJobStatus::Receiving if self.job.chunks_received > 0 && self.chunks.is_empty() => {
+                warn!("Import job {} lost {} uploaded chunks on upgrade", self.job.id, self.job.chunks_received);
+                self.job.chunks_received = 0;
+                self.job.bytes_received = 0;
+                self.cursor = ImportCursor::default();
+                self.job.updated_at = now;
+            }
+            ImportJobStatus::Processing
+                if self.cursor.chunk < self.job.chunks_received && !self.chunks.contains_key(&self.cursor.chunk) =>
+            {
+                let reason = format!("升级时未处理的分片已丢弃，请从第 {} 行之后重新导入", self.cursor.line);
+                self.fail(reason, now);
+            }
+            _ => {}
+        }
+    }
+
+    // 写入稳定内存的副本，不含分片数据
+    fn without_chunks(&self) -> ImportJobState {
+        ImportJobState {
+            job: self.job.clone(),
+            column_mapping: self.column_mapping.clone(),
+            chunks: BTreeMap::new(),
+            cursor: self.cursor.clone(),
+            errors: self.errors.clone(),
+        }
+    }
+
+    fn column_for<'a>(&'a self, field: &'a str) -> &'a str {
+        self.column_mapping.iter()
+            .find(|m| m.field == field)
+            .map(|m| m.column.as_str())
+            .unwrap_or(field)
+    }
+
+    // 解析一行；CSV 表头行返回 None
+    fn parse_line(&mut self, line: &[u8]) -> Option<Result<RecordSubmissionRequest, String>> {
+        let text = match std::str::from_utf8(line) {
+            Ok(text) => text.trim_start_matches('\u{feff}').trim_end_matches('\r'),
+            Err(_) => return Some(Err("该行不是 UTF-8 编码".to_string())),
+        };
+        if text.trim().is_empty() {
+            return None;
+        }
+
+        let values = match self.job.format {
+            ImportFormat::Csv => {
+                let fields = split_csv_line(text);
+                let Some(header) = &self.cursor.header else {
+                    self.cursor.header = Some(fields.into_iter().map(|f| f.trim().to_string()).collect());
+                    return None;
+                };
+                if fields.len() != header.len() {
+                    return Some(Err(format!("列数 {} 与表头列数 {} 不一致", fields.len(), header.len())));
+                }
+                header.iter().cloned().zip(fields).collect::<HashMap<String, String>>()
+            }
+            ImportFormat::Ndjson => match serde_json::from_str::<Value>(text) {
+                // 与 REST 接口相同的结构化格式
+                Ok(value) if value.get("content").is_some() => {
+                    return Some(serde_json::from_value::<HttpRecordSubmission>(value)
+                        .map(|s| RecordSubmissionRequest {
+                            institution_id: self.job.institution_id,
+                            record_type: s.record_type,
+                            user_did: s.user_did,
+                            event_date: s.event_date,
+                            content: s.content,
+                        })
+                        .map_err(|e| format!("JSON 结构无效: {}", e)));
+                }
+                Ok(Value::Object(object)) => object.into_iter()
+                    .map(|(key, value)| match value {
+                        Value::String(s) => (key, s),
+                        other => (key, other.to_string()),
+                    })
+                    .collect(),
+                Ok(_) => return Some(Err("每行必须是一个 JSON 对象".to_string())),
+                Err(e) => return Some(Err(format!("JSON 解析失败: {}", e))),
+            },
+        };
+
+        Some(self.to_request(&values))
+    }
+
+    // 按列映射把一行的值转换为记录提交请求
+    fn to_request(&self, values: &HashMap<String, String>) -> Result<RecordSubmissionRequest, String> {
+        let field = |name: &str| values.get(self.column_for(name))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty());
+        let required = |name: &str| field(name)
+            .map(str::to_string)
+            .ok_or_else(|| format!("缺少字段 {}", name));
+        let number = |name: &str| required(name)?
+            .parse::<u64>()
+            .map_err(|_| format!("字段 {} 不是有效的整数", name));
+
+        let record_type = match required("record_type")?.to_lowercase().as_str() {
+            "loan" | "loanrecord" => RecordType::LoanRecord,
+            "repayment" | "repaymentrecord" => RecordType::RepaymentRecord,
+            "overdue" | "overduerecord" => RecordType::OverdueRecord,
+            other => return Err(format!("未知的记录类型: {}", other)),
+        };
+
+        let content = match record_type {
+            RecordType::LoanRecord => RecordContent::Loan(LoanContent {
+                amount: number("amount")?,
+                loan_id: required("loan_id")?,
+                term_months: number("term_months")?,
+                interest_rate: required("interest_rate")?
+                    .parse::<f64>()
+                    .map_err(|_| "字段 interest_rate 不是有效的数字".to_string())?,
+            }),
+            RecordType::RepaymentRecord => RecordContent::Repayment(RepaymentContent {
+                amount: number("amount")?,
+                loan_id: required("loan_id")?,
+                repayment_date: field("repayment_date")
+                    .or(field("event_date"))
+                    .map(str::to_string)
+                    .ok_or_else(|| "缺少字段 repayment_date".to_string())?,
+            }),
+            RecordType::OverdueRecord => RecordContent::Overdue(OverdueContent {
+                amount: number("amount")?,
+                overdueDays: number("overdue_days")?,
+                period_amount: field("period_amount")
+                    .map(|_| number("period_amount"))
+                    .transpose()?
+                    .unwrap_or(0),
+            }),
+        };
+
+        Ok(RecordSubmissionRequest {
+            institution_id: self.job.institution_id,
+            record_type,
+            user_did: required("user_did")?,
+            event_date: required("event_date")?,
+            content,
+        })
+    }
+}
+
+// 按 RFC 4180 拆分一行 CSV（支持双引号转义，不支持字段内换行）
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            ('"', _) => in_quotes = !in_quotes,
+            (',', false) => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+    fields
+}
+
+impl ImportService {
+    pub fn new() -> Self {
+        Self {
+            state: ImportState::default(),
+            batch_attempts: HashMap::new(),
+        }
+    }
+
+    pub fn create_job(&mut self, request: CreateImportJobRequest) -> Result<ImportJob, String> {
+        if let Some(unknown) = request.column_mapping.iter().find(|m| !IMPORT_FIELDS.contains(&m.field.as_str())) {
+            return Err(format!("未知的导入字段: {}", unknown.field));
+        }
+
+        self.state.next_id += 1;
+        let now = time();
+        let job = ImportJob {
+            id: format!("IMP-{}-{}", now / 1_000_000_000, self.state.next_id),
+            institution_id: request.institution_id,
+            format: request.format,
+            status: ImportJobStatus::Receiving,
+            chunks_received: 0,
+            bytes_received: 0,
+            rows_processed: 0,
+            rows_imported: 0,
+            rows_failed: 0,
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            failure_reason: None,
+        };
+        self.state.jobs.insert(job.id.clone(), ImportJobState {
+            job: job.clone(),
+            column_mapping: request.column_mapping,
+            chunks: BTreeMap::new(),
+            cursor: ImportCursor::default(),
+            errors: Vec::new(),
+        });
+        info!("Import job {} created for {}", job.id, job.institution_id.to_text());
+        Ok(job)
+    }
+
+    /// 接收分片；分片须按序号依次上传，重复上传相同内容的分片视为成功
+    pub fn upload_chunk(&mut self, job_id: &str, chunk_index: u32, data: Vec<u8>) -> Result<ImportJob, String> {
+        self.upload_chunk_at(job_id, chunk_index, data, time())
+    }
+
+    fn upload_chunk_at(&mut self, job_id: &str, chunk_index: u32, data: Vec<u8>, now: u64) -> Result<ImportJob, String> {
+        let institution_id = self.state.jobs.get(job_id)
+            .map(|s| s.job.institution_id)
+            .ok_or_else(|| format!("导入任务不存在: {}", job_id))?;
+        let pending: usize = self.state.jobs.values()
+            .filter(|s| s.job.institution_id == institution_id)
+            .map(ImportJobState::pending_bytes)
+            .sum();
+
+        let state = self.job_state_mut(job_id)?;
+        if state.job.status != ImportJobStatus::Receiving {
+            return Err(format!("导入任务不在接收状态: {:?}", state.job.status));
+        }
+        if data.is_empty() || data.len() > MAX_CHUNK_BYTES {
+            return Err(format!("分片大小必须在 1 到 {} 字节之间", MAX_CHUNK_BYTES));
+        }
+
+        if chunk_index < state.job.chunks_received {
+            return if state.chunks.get(&chunk_index) == Some(&data) {
+                Ok(state.job.clone())
+            } else {
+                Err(format!("分片 {} 已上传且内容不同", chunk_index))
+            };
+        }
+        if chunk_index != state.job.chunks_received {
+            return Err(format!("应上传分片 {}", state.job.chunks_received));
+        }
+        if pending + data.len() > MAX_PENDING_BYTES_PER_INSTITUTION {
+            return Err(format!(
+                "待处理的导入数据超过 {} 字节，请等待已上传的任务处理完成",
+                MAX_PENDING_BYTES_PER_INSTITUTION
+            ));
+        }
+
+        state.job.bytes_received += data.len() as u64;
+        state.job.chunks_received += 1;
+        state.job.updated_at = now;
+        state.chunks.insert(chunk_index, data);
+        debug!("Import job {} received chunk {}", job_id, chunk_index);
+        Ok(state.job.clone())
+    }
+
+    /// 结束上传，开始按批次校验导入
+    pub fn finalize(&mut self, job_id: &str) -> Result<ImportJob, String> {
+        let state = self.job_state_mut(job_id)?;
+        if state.job.status != ImportJobStatus::Receiving {
+            return Err(format!("导入任务不在接收状态: {:?}", state.job.status));
+        }
+        if state.job.chunks_received == 0 {
+            return Err("尚未上传任何分片".to_string());
+        }
+
+        state.job.status = ImportJobStatus::Processing;
+        state.job.updated_at = time();
+        info!("Import job {} finalized with {} chunks", job_id, state.job.chunks_received);
+        Ok(state.job.clone())
+    }
+
+    pub fn cancel(&mut self, job_id: &str) -> Result<ImportJob, String> {
+        let state = self.job_state_mut(job_id)?;
+        if state.is_finished() {
+            return Err(format!("导入任务已结束: {:?}", state.job.status));
+        }
+
+        state.job.status = ImportJobStatus::Cancelled;
+        state.job.updated_at = time();
+        state.chunks.clear();
+        warn!("Import job {} cancelled after {} rows", job_id, state.job.rows_processed);
+        Ok(state.job.clone())
+    }
+
+    /// 从游标处解析至多 max_rows 行数据
+    fn next_rows(&mut self, job_id: &str, max_rows: usize) -> Result<Vec<ParsedRow>, String> {
+        let state = self.job_state_mut(job_id)?;
+        if state.job.status != ImportJobStatus::Processing {
+            return Err(format!("导入任务不在处理状态: {:?}", state.job.status));
+        }
+
+        let mut rows = Vec::new();
+        while rows.len() < max_rows {
+            let Some((line, bytes)) = state.next_line() else { break };
+            if let Some(parsed) = state.parse_line(&bytes) {
+                rows.push((line, parsed));
+            }
+        }
+        Ok(rows)
+    }
+
+    // 记录本批结果，数据读完后任务结束
+    fn record_results(&mut self, job_id: &str, results: Vec<(u64, Result<String, String>)>) -> Result<ImportJob, String> {
+        self.record_results_at(job_id, results, time())
+    }
+
+    fn record_results_at(&mut self, job_id: &str, results: Vec<(u64, Result<String, String>)>, now: u64) -> Result<ImportJob, String> {
+        self.batch_attempts.remove(job_id);
+        let state = self.job_state_mut(job_id)?;
+        for (line, result) in results {
+            state.job.rows_processed += 1;
+            match result {
+                Ok(_) => state.job.rows_imported += 1,
+                Err(message) => {
+                    state.job.rows_failed += 1;
+                    if state.errors.len() < MAX_STORED_ERRORS {
+                        state.errors.push(ImportRowError { line, message });
+                    }
+                }
+            }
+        }
+
+        state.job.updated_at = now;
+        if state.is_exhausted() {
+            state.job.status = ImportJobStatus::Completed;
+            state.job.completed_at = Some(now);
+            info!(
+                "Import job {} completed: {} imported, {} failed",
+                job_id, state.job.rows_imported, state.job.rows_failed
+            );
+        }
+        Ok(state.job.clone())
+    }
+
+    pub fn get_job(&self, job_id: &str) -> Option<ImportJob> {
+        self.state.jobs.get(job_id).map(|s| s.job.clone())
+    }
+
+    pub fn list_jobs(&self, institution_id: Principal) -> Vec<ImportJob> {
+        let mut jobs: Vec<ImportJob> = self.state.jobs.values()
+            .filter(|s| s.job.institution_id == institution_id)
+            .map(|s| s.job.clone())
+            .collect();
+        jobs.sort_by_key(|j| std::cmp::Reverse(j.created_at));
+        jobs
+    }
+
+    pub fn get_errors(&self, job_id: &str, offset: usize, limit: usize) -> Result<ImportErrorPage, String> {
+        let state = self.state.jobs.get(job_id)
+            .ok_or_else(|| format!("导入任务不存在: {}", job_id))?;
+        Ok(ImportErrorPage {
+            errors: state.errors.iter().skip(offset).take(limit).cloned().collect(),
+            total: state.job.rows_failed,
+            truncated: state.job.rows_failed > state.errors.len() as u64,
+        })
+    }
+
+    /// 超时未继续上传的任务标记为失败并释放分片
+    fn expire_stale_jobs(&mut self, now: u64) {
+        for state in self.state.jobs.values_mut() {
+            if state.job.status == ImportJobStatus::Receiving
+                && now.saturating_sub(state.job.updated_at) > RECEIVING_TIMEOUT_NANOS
+            {
+                state.fail("上传超时，任务已过期".to_string(), now);
+            }
+        }
+    }
+
+    /// 选出最久未推进的处理中任务并记一次批次尝试；批次完成时清零，
+    /// 连续多次未完成（执行中陷入会回滚批次但不会回滚此处的计数）则标记任务失败
+    fn next_batch_job(&mut self, now: u64) -> Option<String> {
+        let mut candidates: Vec<(u64, String)> = self.state.jobs.values()
+            .filter(|s| s.job.status == ImportJobStatus::Processing)
+            .map(|s| (s.job.updated_at, s.job.id.clone()))
+            .collect();
+        candidates.sort();
+
+        for (_, job_id) in candidates {
+            let attempts = self.batch_attempts.entry(job_id.clone()).or_insert(0);
+            if *attempts >= MAX_BATCH_ATTEMPTS {
+                self.batch_attempts.remove(&job_id);
+                if let Some(state) = self.state.jobs.get_mut(&job_id) {
+                    let reason = format!("第 {} 行之后的批次连续 {} 次处理失败", state.cursor.line, MAX_BATCH_ATTEMPTS);
+                    state.fail(reason, now);
+                }
+                continue;
+            }
+            *attempts += 1;
+            return Some(job_id);
+        }
+        None
+    }
+
+    fn job_state_mut(&mut self, job_id: &str) -> Result<&mut ImportJobState, String> {
+        self.state.jobs.get_mut(job_id)
+            .ok_or_else(|| format!("导入任务不存在: {}", job_id))
+    }
+
+    /// 分片数据不写入稳定内存，避免大量未处理分片导致升级失败
+    pub fn export_state(&self) -> ImportState {
+        ImportState {
+            jobs: self.state.jobs.iter()
+                .map(|(id, state)| (id.clone(), state.without_chunks()))
+                .collect(),
+            next_id: self.state.next_id,
+        }
+    }
+
+    pub fn restore_state(&mut self, state: ImportState) {
+        self.restore_state_at(state, time());
+    }
+
+    fn restore_state_at(&mut self, mut state: ImportState, now: u64) {
+        for job in state.jobs.values_mut() {
+            job.recover_after_upgrade(now);
+        }
+        info!("Restored {} import jobs", state.jobs.len());
+        self.state = state;
+    }
+}
+
+/// 处理一批数据行：每行经 import_record（含 validate_record_content 校验）导入，记录标记为历史数据
+pub fn process_import_batch(job_id: &str) -> Result<ImportJob, String> {
+    let rows = IMPORT_SERVICE.with(|service| {
+        service.borrow_mut().next_rows(job_id, ROWS_PER_BATCH)
+    })?;
+
+    let results = rows.into_iter()
+        .map(|(line, parsed)| {
+            let outcome = parsed.and_then(|request| {
+                RECORD_SERVICE.with(|service| service.borrow_mut().import_record(request))
+                    .map_err(|e| e.to_string())
+            });
+            (line, outcome)
+        })
+        .collect();
+
+    IMPORT_SERVICE.with(|service| {
+        service.borrow_mut().record_results(job_id, results)
+    })
+}
+
+// 定时处理：每次只处理一个任务的一批数据。批次在单独的定时器消息中执行，
+// 批次陷入时只回滚该消息，本消息记下的尝试次数保留
+fn process_pending_imports() {
+    let job_id = IMPORT_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        service.expire_stale_jobs(time());
+        service.next_batch_job(time())
+    });
+
+    if let Some(job_id) = job_id {
+        ic_cdk_timers::set_timer(Duration::ZERO, move || {
+            if let Err(e) = process_import_batch(&job_id) {
+                warn!("Import job {} batch failed: {}", job_id, e);
+            }
+        });
+    }
+}
+
+pub fn init_import_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(IMPORT_INTERVAL_SECS), process_pending_imports);
+    info!("Import processing timer started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 直接构造处于处理状态的任务，分片按序放入
+    fn processing_job(service: &mut ImportService, format: ImportFormat, column_mapping: Vec<ColumnMapping>, chunks: &[&str]) -> String {
+        let id = "IMP-test".to_string();
+        let job = ImportJob {
+            id: id.clone(),
+            institution_id: Principal::from_slice(&[1]),
+            format,
+            status: ImportJobStatus::Processing,
+            chunks_received: chunks.len() as u32,
+            bytes_received: chunks.iter().map(|c| c.len() as u64).sum(),
+            rows_processed: 0,
+            rows_imported: 0,
+            rows_failed: 0,
+            created_at: 0,
+            updated_at: 0,
+            completed_at: None,
+            failure_reason: None,
+        };
+        service.state.jobs.insert(id.clone(), ImportJobState {
+            job,
+            column_mapping,
+            chunks: chunks.iter().enumerate().map(|(i, c)| (i as u32, c.as_bytes().to_vec())).collect(),
+            cursor: ImportCursor::default(),
+            errors: Vec::new(),
+        });
+        id
+    }
+
+    #[test]
+    fn csv_fields_support_quotes_and_escaped_quotes() {
+        assert_eq!(split_csv_line("a,b,,c"), vec!["a", "b", "", "c"]);
+        assert_eq!(split_csv_line(r#""x, y","say ""hi""",z"#), vec!["x, y", r#"say "hi""#, "z"]);
+        assert_eq!(split_csv_line(""), vec![""]);
+    }
+
+    #[test]
+    fn csv_rows_span_chunks_and_follow_column_mapping() {
+        let mut service = ImportService::new();
+        let mapping = vec![ColumnMapping { field: "user_did".to_string(), column: "customer".to_string() }];
+        let id = processing_job(&mut service, ImportFormat::Csv, mapping, &[
+            "\u{feff}record_type,customer,event_date,amount,loan_id,term_months,interest_rate\r\nloan,did:alice,2024-10-01,50",
+            "00,L-1,12,4.5\nrepayment,did:bob,2024-10-02,100,L-2,,\nloan,did:carol\n",
+        ]);
+
+        let rows = service.next_rows(&id, 10).unwrap();
+        assert_eq!(rows.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        let first = rows[0].1.as_ref().unwrap();
+        assert_eq!(first.user_did, "did:alice");
+        assert_eq!(first.record_type, RecordType::LoanRecord);
+        match &first.content {
+            RecordContent::Loan(loan) => {
+                assert_eq!(loan.amount, 5000);
+                assert_eq!(loan.loan_id, "L-1");
+                assert_eq!(loan.term_months, 12);
+            }
+            _ => panic!("应解析为贷款记录"),
+        }
+
+        // 还款日期缺省时取事件日期
+        match &rows[1].1.as_ref().unwrap().content {
+            RecordContent::Repayment(repayment) => assert_eq!(repayment.repayment_date, "2024-10-02"),
+            _ => panic!("应解析为还款记录"),
+        }
+        assert!(rows[2].1.as_ref().unwrap_err().contains("列数"));
+        assert!(service.state.jobs[&id].is_exhausted());
+    }
+
+    #[test]
+    fn ndjson_rows_report_parse_errors_per_line() {
+        let mut service = ImportService::new();
+        let id = processing_job(&mut service, ImportFormat::Ndjson, Vec::new(), &[concat!(
+            r#"{"record_type":"overdue","user_did":"did:alice","event_date":"2024-10-03","amount":300,"overdue_days":"15"}"#, "\n",
+            "\n",
+            "[1,2]\n",
+            r#"{"record_type":"transfer","user_did":"did:bob","event_date":"2024-10-03"}"#, "\n",
+            r#"{"record_type":"loan","user_did":"did:bob","event_date":"2024-10-03","amount":"abc"}"#,
+        )]);
+
+        let rows = service.next_rows(&id, 2).unwrap();
+        assert_eq!(rows.len(), 2);
+        match &rows[0].1.as_ref().unwrap().content {
+            RecordContent::Overdue(overdue) => {
+                assert_eq!(overdue.overdueDays, 15);
+                assert_eq!(overdue.period_amount, 0);
+            }
+            _ => panic!("应解析为逾期记录"),
+        }
+        // 空行不计入结果，但保留行号
+        assert_eq!(rows[1].0, 3);
+        assert!(rows[1].1.as_ref().unwrap_err().contains("JSON 对象"));
+
+        let rows = service.next_rows(&id, 10).unwrap();
+        assert_eq!(rows.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![4, 5]);
+        assert!(rows[0].1.as_ref().unwrap_err().contains("未知的记录类型"));
+        assert!(rows[1].1.as_ref().unwrap_err().contains("amount"));
+    }
+
+    #[test]
+    fn pending_bytes_are_capped_per_institution() {
+        let mut service = ImportService::new();
+        let id = processing_job(&mut service, ImportFormat::Csv, Vec::new(), &[]);
+        let state = service.state.jobs.get_mut(&id).unwrap();
+        state.job.status = ImportJobStatus::Receiving;
+        state.chunks.insert(0, vec![b'a'; MAX_PENDING_BYTES_PER_INSTITUTION - 10]);
+        state.job.chunks_received = 1;
+
+        assert!(service.upload_chunk_at(&id, 1, vec![b'b'; 10], 1).is_ok());
+        assert!(service.upload_chunk_at(&id, 2, vec![b'c'; 1], 1).unwrap_err().contains("待处理"));
+    }
+
+    #[test]
+    fn stale_receiving_jobs_expire() {
+        let mut service = ImportService::new();
+        let id = processing_job(&mut service, ImportFormat::Csv, Vec::new(), &["a\n"]);
+        service.state.jobs.get_mut(&id).unwrap().job.status = ImportJobStatus::Receiving;
+
+        service.expire_stale_jobs(RECEIVING_TIMEOUT_NANOS);
+        assert_eq!(service.state.jobs[&id].job.status, ImportJobStatus::Receiving);
+        service.expire_stale_jobs(RECEIVING_TIMEOUT_NANOS + 1);
+        let state = &service.state.jobs[&id];
+        assert_eq!(state.job.status, ImportJobStatus::Failed);
+        assert!(state.chunks.is_empty());
+        assert!(service.cancel(&id).is_err());
+    }
+
+    #[test]
+    fn chunks_are_not_persisted_across_upgrades() {
+        let mut service = ImportService::new();
+        let id = processing_job(&mut service, ImportFormat::Ndjson, Vec::new(), &["{}\n", "{}\n"]);
+        service.next_rows(&id, 1).unwrap();
+
+        let exported = service.export_state();
+        assert!(exported.jobs[&id].chunks.is_empty());
+
+        let mut restored = ImportService::new();
+        restored.restore_state_at(exported, 1);
+        let job = &restored.state.jobs[&id].job;
+        assert_eq!(job.status, ImportJobStatus::Failed);
+        assert!(job.failure_reason.as_ref().unwrap().contains("第 1 行"));
+    }
+
+    #[test]
+    fn batches_rotate_across_jobs_and_fail_after_repeated_attempts() {
+        let mut service = ImportService::new();
+        let first = processing_job(&mut service, ImportFormat::Ndjson, Vec::new(), &["{}\n"]);
+        let second = "IMP-second".to_string();
+        let mut state = service.state.jobs[&first].clone();
+        state.job.id = second.clone();
+        state.job.updated_at = 5;
+        service.state.jobs.insert(second.clone(), state);
+
+        // 最久未推进的任务优先，完成一批后轮到其他任务
+        assert_eq!(service.next_batch_job(10).as_deref(), Some(first.as_str()));
+        service.record_results_at(&first, Vec::new(), 20).unwrap();
+        assert!(!service.batch_attempts.contains_key(&first));
+        assert_eq!(service.next_batch_job(30).as_deref(), Some(second.as_str()));
+
+        // 批次一直未完成，超过尝试次数后标记失败并转向其他任务
+        for _ in 1..MAX_BATCH_ATTEMPTS {
+            assert_eq!(service.next_batch_job(30).as_deref(), Some(second.as_str()));
+        }
+        assert_eq!(service.next_batch_job(30).as_deref(), Some(first.as_str()));
+        assert_eq!(service.state.jobs[&second].job.status, ImportJobStatus::Failed);
+    }
+}
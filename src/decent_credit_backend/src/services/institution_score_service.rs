@@ -0,0 +1,422 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use log::{info, debug, warn};
+use crate::models::dashboard::ScoreTrend;
+use crate::models::institution_score::*;
+use crate::models::record::CreditDeductionRecord;
+use crate::services::admin_institution_service::ADMIN_SERVICE;
+use crate::services::record_service::RECORD_SERVICE;
+use crate::utils::time::format_day;
+
+// 每个机构保留的分数变更条数
+const MAX_HISTORY_PER_INSTITUTION: usize = 1_000;
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+const NANOS_PER_MONTH: u64 = 30 * NANOS_PER_DAY;
+const RECOVERY_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+// 需要跨升级保存的机构分数历史、申诉和恢复策略
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct InstitutionScoreState {
+    pub history: HashMap<Principal, Vec<InstitutionScoreChange>>,
+    pub appeals: BTreeMap<String, DeductionAppeal>,
+    pub next_appeal_id: u64,
+    pub policy: RecoveryPolicy,
+}
+
+pub struct InstitutionScoreService {
+    state: InstitutionScoreState,
+}
+
+impl Default for InstitutionScoreService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static INSTITUTION_SCORE_SERVICE: RefCell<InstitutionScoreService> = RefCell::new(InstitutionScoreService::new());
+}
+
+impl InstitutionScoreService {
+    pub fn new() -> Self {
+        Self {
+            state: InstitutionScoreState::default(),
+        }
+    }
+
+    pub fn export_state(&self) -> InstitutionScoreState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: InstitutionScoreState) {
+        info!(
+            "Restored institution score state: {} institutions, {} appeals",
+            state.history.len(),
+            state.appeals.len()
+        );
+        self.state = state;
+    }
+
+    // === 分数历史 ===
+
+    fn record_change(&mut self, change: InstitutionScoreChange) {
+        let history = self.state.history.entry(change.institution_id).or_default();
+        history.push(change);
+        if history.len() > MAX_HISTORY_PER_INSTITUTION {
+            let overflow = history.len() - MAX_HISTORY_PER_INSTITUTION;
+            history.drain(..overflow);
+        }
+    }
+
+    pub fn get_history(&self, institution_id: Principal, since: Option<u64>) -> Vec<InstitutionScoreChange> {
+        self.state.history.get(&institution_id)
+            .into_iter()
+            .flatten()
+            .filter(|change| since.is_none_or(|since| change.timestamp >= since))
+            .cloned()
+            .collect()
+    }
+
+    /// 最近若干天每天结束时的分数，供机构看板趋势图使用
+    pub fn get_trend(&self, institution_id: Principal, days: u64) -> Vec<ScoreTrend> {
+        let since = time().saturating_sub(days.saturating_mul(NANOS_PER_DAY));
+        let mut daily: BTreeMap<u64, u64> = BTreeMap::new();
+        for change in self.state.history.get(&institution_id).into_iter().flatten() {
+            if change.timestamp >= since {
+                daily.insert(change.timestamp / NANOS_PER_DAY, change.new_score);
+            }
+        }
+
+        daily.into_iter()
+            .map(|(day, score)| ScoreTrend {
+                date: format_day(day),
+                score: score as f64,
+            })
+            .collect()
+    }
+
+    // 最近一次仍然有效的扣分（已撤销的扣分不算）
+    fn last_incident(&self, institution_id: Principal) -> Option<u64> {
+        let reversed: HashSet<&str> = self.state.appeals.values()
+            .filter(|a| a.status == AppealStatus::Approved)
+            .map(|a| a.deduction_id.as_str())
+            .collect();
+        self.state.history.get(&institution_id)?
+            .iter()
+            .filter(|c| c.new_score < c.previous_score)
+            .filter(|c| matches!(c.reason, ScoreChangeReason::Deduction | ScoreChangeReason::ManualAdjustment))
+            .filter(|c| c.reference.as_deref().is_none_or(|r| !reversed.contains(r)))
+            .map(|c| c.timestamp)
+            .max()
+    }
+
+    fn last_recovery(&self, institution_id: Principal) -> Option<u64> {
+        self.state.history.get(&institution_id)?
+            .iter()
+            .filter(|c| c.reason == ScoreChangeReason::TimeBasedRecovery)
+            .map(|c| c.timestamp)
+            .max()
+    }
+
+    // === 扣分申诉 ===
+
+    pub fn submit_appeal(&mut self, deduction: &CreditDeductionRecord, reason: String) -> Result<DeductionAppeal, String> {
+        self.submit_appeal_at(deduction, reason, time())
+    }
+
+    fn submit_appeal_at(&mut self, deduction: &CreditDeductionRecord, reason: String, now: u64) -> Result<DeductionAppeal, String> {
+        if reason.trim().is_empty() {
+            return Err("申诉理由不能为空".to_string());
+        }
+        if deduction.reversed_at.is_some() {
+            return Err("该扣分已被撤销".to_string());
+        }
+        let appealed = self.state.appeals.values()
+            .any(|a| a.deduction_id == deduction.id && a.status != AppealStatus::Rejected);
+        if appealed {
+            return Err("该扣分已有处理中或已通过的申诉".to_string());
+        }
+
+        self.state.next_appeal_id += 1;
+        let appeal = DeductionAppeal {
+            id: format!("AP-{}", self.state.next_appeal_id),
+            deduction_id: deduction.id.clone(),
+            institution_id: deduction.institution_id,
+            deduction_points: deduction.deduction_points,
+            reason,
+            status: AppealStatus::Pending,
+            submitted_at: now,
+            reviewed_at: None,
+            reviewed_by: None,
+            review_note: None,
+            restored_points: 0,
+        };
+        info!("Appeal {} submitted for deduction {}", appeal.id, appeal.deduction_id);
+        self.state.appeals.insert(appeal.id.clone(), appeal.clone());
+        Ok(appeal)
+    }
+
+    pub fn get_appeal(&self, appeal_id: &str) -> Option<DeductionAppeal> {
+        self.state.appeals.get(appeal_id).cloned()
+    }
+
+    pub fn list_appeals(&self, institution_id: Option<Principal>, status: Option<AppealStatus>) -> Vec<DeductionAppeal> {
+        self.state.appeals.values()
+            .filter(|a| institution_id.is_none_or(|id| a.institution_id == id))
+            .filter(|a| status.as_ref().is_none_or(|s| a.status == *s))
+            .cloned()
+            .collect()
+    }
+
+    fn close_appeal(&mut self, request: &ReviewAppealRequest, operator: Principal, restored_points: u64, now: u64) -> Option<DeductionAppeal> {
+        let appeal = self.state.appeals.get_mut(&request.appeal_id)?;
+        appeal.status = if request.approve { AppealStatus::Approved } else { AppealStatus::Rejected };
+        appeal.reviewed_at = Some(now);
+        appeal.reviewed_by = Some(operator);
+        appeal.review_note = request.note.clone();
+        appeal.restored_points = restored_points;
+        Some(appeal.clone())
+    }
+
+    // === 恢复策略 ===
+
+    pub fn get_policy(&self) -> RecoveryPolicy {
+        self.state.policy.clone()
+    }
+
+    pub fn update_policy(&mut self, policy: RecoveryPolicy) -> Result<(), String> {
+        if policy.incident_free_months == 0 {
+            return Err("无违规月数必须大于0".to_string());
+        }
+        if policy.max_score > 100 {
+            return Err("恢复上限不能超过100".to_string());
+        }
+        info!("Recovery policy updated: {:?}", policy);
+        self.state.policy = policy;
+        Ok(())
+    }
+}
+
+/// 修改机构信用分并记录变更原因，返回修改前的分数
+pub fn change_score(
+    institution_id: Principal,
+    new_score: u64,
+    reason: ScoreChangeReason,
+    reference: Option<String>
+) -> Result<u64, String> {
+    let previous = ADMIN_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        let previous = service.get_institution(institution_id)
+            .map(|inst| inst.credit_score.score)
+            .ok_or_else(|| "机构不存在".to_string())?;
+        service.update_credit_score(institution_id, new_score)?;
+        Ok::<u64, String>(previous)
+    })?;
+
+    if previous != new_score {
+        debug!("Institution {} score {} -> {} ({:?})", institution_id.to_text(), previous, new_score, reason);
+        INSTITUTION_SCORE_SERVICE.with(|service| {
+            service.borrow_mut().record_change(InstitutionScoreChange {
+                institution_id,
+                previous_score: previous,
+                new_score,
+                reason,
+                reference,
+                timestamp: time(),
+            })
+        });
+    }
+    Ok(previous)
+}
+
+/// 处理申诉：通过时撤销扣分记录并恢复相应分数
+pub fn review_appeal(request: ReviewAppealRequest, operator: Principal) -> Result<DeductionAppeal, String> {
+    let appeal = INSTITUTION_SCORE_SERVICE.with(|service| service.borrow().get_appeal(&request.appeal_id))
+        .ok_or_else(|| format!("申诉不存在: {}", request.appeal_id))?;
+    if appeal.status != AppealStatus::Pending {
+        return Err(format!("申诉已处理: {:?}", appeal.status));
+    }
+
+    let mut restored = 0;
+    if request.approve {
+        let deduction = RECORD_SERVICE.with(|service| {
+            service.borrow_mut().reverse_deduction(&appeal.deduction_id)
+        })?;
+        let current = ADMIN_SERVICE.with(|service| {
+            service.borrow().get_institution(appeal.institution_id).map(|inst| inst.credit_score.score)
+        }).ok_or_else(|| "机构不存在".to_string())?;
+        restored = (deduction.deduction_points as u64).min(100 - current.min(100));
+        change_score(appeal.institution_id, current + restored, ScoreChangeReason::AppealReversal, Some(appeal.id.clone()))?;
+    }
+
+    let appeal = INSTITUTION_SCORE_SERVICE.with(|service| {
+        service.borrow_mut().close_appeal(&request, operator, restored, time())
+    }).ok_or_else(|| format!("申诉不存在: {}", request.appeal_id))?;
+    info!("Appeal {} {:?} by {}, restored {} points", appeal.id, appeal.status, operator.to_text(), restored);
+    Ok(appeal)
+}
+
+/// 对连续若干个月无扣分的机构恢复分数
+pub fn apply_time_based_recovery() -> Vec<InstitutionScoreChange> {
+    let now = time();
+    let policy = INSTITUTION_SCORE_SERVICE.with(|service| service.borrow().get_policy());
+    if !policy.enabled {
+        return Vec::new();
+    }
+    let period = policy.incident_free_months as u64 * NANOS_PER_MONTH;
+
+    let institutions = ADMIN_SERVICE.with(|service| service.borrow().get_all_institutions());
+    let mut changes = Vec::new();
+    for institution in institutions {
+        let score = institution.credit_score.score;
+        if score >= policy.max_score {
+            continue;
+        }
+        // 从最近一次扣分或自动恢复开始计算无违规时长
+        let anchor = INSTITUTION_SCORE_SERVICE.with(|service| {
+            let service = service.borrow();
+            [service.last_incident(institution.id), service.last_recovery(institution.id)]
+                .into_iter()
+                .flatten()
+                .max()
+        }).unwrap_or(institution.join_time);
+        if now.saturating_sub(anchor) < period {
+            continue;
+        }
+
+        let new_score = (score + policy.recovery_points).min(policy.max_score);
+        match change_score(institution.id, new_score, ScoreChangeReason::TimeBasedRecovery, None) {
+            Ok(previous) => {
+                info!("Time-based recovery for {}: {} -> {}", institution.id.to_text(), previous, new_score);
+                changes.push(InstitutionScoreChange {
+                    institution_id: institution.id,
+                    previous_score: previous,
+                    new_score,
+                    reason: ScoreChangeReason::TimeBasedRecovery,
+                    reference: None,
+                    timestamp: now,
+                });
+            }
+            Err(e) => warn!("Time-based recovery failed for {}: {}", institution.id.to_text(), e),
+        }
+    }
+    changes
+}
+
+pub fn init_score_recovery_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(RECOVERY_INTERVAL_SECS), || {
+        let changes = apply_time_based_recovery();
+        if !changes.is_empty() {
+            info!("Time-based recovery restored scores for {} institutions", changes.len());
+        }
+    });
+    info!("Score recovery timer started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = NANOS_PER_DAY;
+
+    fn deduction(id: &str, institution_id: Principal) -> CreditDeductionRecord {
+        CreditDeductionRecord {
+            id: id.to_string(),
+            record_id: format!("REC-{}", id),
+            institution_id,
+            institution_name: "bank".to_string(),
+            deduction_points: 10,
+            reason: "数据错误".to_string(),
+            data_quality_issue: String::new(),
+            created_at: 0,
+            operator_id: Principal::anonymous(),
+            operator_name: "admin".to_string(),
+            reversed_at: None,
+        }
+    }
+
+    fn change(institution_id: Principal, previous_score: u64, new_score: u64, reason: ScoreChangeReason, reference: Option<&str>, timestamp: u64) -> InstitutionScoreChange {
+        InstitutionScoreChange {
+            institution_id,
+            previous_score,
+            new_score,
+            reason,
+            reference: reference.map(str::to_string),
+            timestamp,
+        }
+    }
+
+    fn review(appeal_id: &str, approve: bool) -> ReviewAppealRequest {
+        ReviewAppealRequest { appeal_id: appeal_id.to_string(), approve, note: Some("已核实".to_string()) }
+    }
+
+    #[test]
+    fn one_open_or_approved_appeal_per_deduction() {
+        let bank = Principal::from_slice(&[1]);
+        let admin = Principal::from_slice(&[9]);
+        let mut service = InstitutionScoreService::new();
+        let first = deduction("D-1", bank);
+
+        assert!(service.submit_appeal_at(&first, "  ".to_string(), DAY).is_err());
+        let appeal = service.submit_appeal_at(&first, "数据已更正".to_string(), DAY).unwrap();
+        assert_eq!((appeal.id.as_str(), appeal.status.clone(), appeal.submitted_at), ("AP-1", AppealStatus::Pending, DAY));
+        assert!(service.submit_appeal_at(&first, "再次申诉".to_string(), DAY).is_err());
+
+        // 被驳回后可以重新申诉
+        let closed = service.close_appeal(&review("AP-1", false), admin, 0, 2 * DAY).unwrap();
+        assert_eq!(closed.status, AppealStatus::Rejected);
+        assert_eq!((closed.reviewed_at, closed.reviewed_by), (Some(2 * DAY), Some(admin)));
+        let retry = service.submit_appeal_at(&first, "补充材料".to_string(), 3 * DAY).unwrap();
+        service.close_appeal(&review(&retry.id, true), admin, 10, 4 * DAY).unwrap();
+        assert!(service.submit_appeal_at(&first, "再次申诉".to_string(), 5 * DAY).is_err());
+        assert!(service.close_appeal(&review("AP-99", true), admin, 0, 5 * DAY).is_none());
+
+        let mut reversed = deduction("D-2", bank);
+        reversed.reversed_at = Some(DAY);
+        assert!(service.submit_appeal_at(&reversed, "理由".to_string(), DAY).is_err());
+
+        assert_eq!(service.list_appeals(Some(bank), Some(AppealStatus::Approved)).len(), 1);
+        assert_eq!(service.list_appeals(None, None).len(), 2);
+        assert!(service.list_appeals(Some(admin), None).is_empty());
+    }
+
+    #[test]
+    fn recovery_anchors_ignore_reversed_deductions() {
+        let bank = Principal::from_slice(&[1]);
+        let mut service = InstitutionScoreService::new();
+        for c in [
+            change(bank, 100, 90, ScoreChangeReason::Deduction, Some("D-1"), DAY),
+            change(bank, 90, 80, ScoreChangeReason::Deduction, Some("D-2"), 5 * DAY),
+            change(bank, 80, 90, ScoreChangeReason::AppealReversal, Some("AP-1"), 6 * DAY),
+            change(bank, 90, 95, ScoreChangeReason::TimeBasedRecovery, None, 7 * DAY),
+            change(bank, 95, 97, ScoreChangeReason::QualityRecovery, None, 8 * DAY),
+        ] {
+            service.record_change(c);
+        }
+        assert_eq!(service.last_incident(bank), Some(5 * DAY));
+        assert_eq!(service.last_recovery(bank), Some(7 * DAY));
+
+        // D-2 申诉通过后，最近一次有效扣分回到 D-1
+        service.submit_appeal_at(&deduction("D-2", bank), "数据已更正".to_string(), 6 * DAY).unwrap();
+        service.close_appeal(&review("AP-1", true), Principal::anonymous(), 10, 6 * DAY).unwrap();
+        assert_eq!(service.last_incident(bank), Some(DAY));
+        assert_eq!(service.last_incident(Principal::from_slice(&[2])), None);
+
+        let since: Vec<u64> = service.get_history(bank, Some(6 * DAY)).iter().map(|c| c.new_score).collect();
+        assert_eq!(since, vec![90, 95, 97]);
+    }
+
+    #[test]
+    fn policy_updates_are_validated() {
+        let mut service = InstitutionScoreService::new();
+        assert!(service.update_policy(RecoveryPolicy { incident_free_months: 0, ..RecoveryPolicy::default() }).is_err());
+        assert!(service.update_policy(RecoveryPolicy { max_score: 101, ..RecoveryPolicy::default() }).is_err());
+        assert_eq!(service.get_policy().incident_free_months, 3);
+
+        service.update_policy(RecoveryPolicy { incident_free_months: 6, recovery_points: 2, ..RecoveryPolicy::default() }).unwrap();
+        assert_eq!((service.get_policy().incident_free_months, service.get_policy().recovery_points), (6, 2));
+    }
+}
@@ -0,0 +1,355 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use sha2::{Sha256, Digest};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use log::{info, warn};
+use crate::models::integrity::*;
+use crate::models::record::*;
+
+// 统计窗口内每次确认的数据问题在数据质量分中扣除的分数，累计扣分上限
+const PENALTY_PER_OFFENSE: u64 = 5;
+const MAX_PENALTY: u64 = 50;
+
+// 需要跨升级保存的审核队列；机构违规次数由问题成立的案件按处理时间统计
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct IntegrityState {
+    pub cases: BTreeMap<String, ReviewCase>,
+    pub next_case_id: u64,
+}
+
+pub struct IntegrityService {
+    state: IntegrityState,
+}
+
+thread_local! {
+    pub static INTEGRITY_SERVICE: RefCell<IntegrityService> = RefCell::new(IntegrityService::new());
+}
+
+/// 记录指纹：记录类型 + 用户DID + 贷款编号 + 金额 + 事件日期
+pub fn record_fingerprint(record_type: &RecordType, user_did: &str, event_date: &str, content: &RecordContent) -> String {
+    let (loan_id, amount) = match content {
+        RecordContent::Loan(loan) => (loan.loan_id.as_str(), loan.amount),
+        RecordContent::Repayment(repayment) => (repayment.loan_id.as_str(), repayment.amount),
+        RecordContent::Overdue(overdue) => ("", overdue.amount),
+    };
+    let day = event_date.trim().split(['T', ' ']).next().unwrap_or_default();
+    let material = format!("{:?}|{}|{}|{}|{}", record_type, user_did.trim(), loan_id.trim(), amount, day);
+    hex::encode(Sha256::digest(material.as_bytes()))
+}
+
+/// 按用户已有的有效记录检查新记录是否与贷款状态冲突
+pub fn detect_conflict<'a>(existing: impl Iterator<Item = &'a CreditRecord>, content: &RecordContent) -> Option<DetectedIssue> {
+    // 贷款编号 -> (贷款记录, 已还款记录)
+    let mut loans: HashMap<&str, (Vec<&CreditRecord>, Vec<&CreditRecord>)> = HashMap::new();
+    for record in existing.filter(|r| r.status != RecordStatus::Rejected) {
+        match &record.content {
+            RecordContent::Loan(loan) => loans.entry(loan.loan_id.as_str()).or_default().0.push(record),
+            RecordContent::Repayment(repayment) => loans.entry(repayment.loan_id.as_str()).or_default().1.push(record),
+            RecordContent::Overdue(_) => {}
+        }
+    }
+    let amount_of = |record: &CreditRecord| match &record.content {
+        RecordContent::Loan(loan) => loan.amount,
+        RecordContent::Repayment(repayment) => repayment.amount,
+        RecordContent::Overdue(overdue) => overdue.amount,
+    };
+    let outstanding = |(loan_records, repayments): &(Vec<&CreditRecord>, Vec<&CreditRecord>)| {
+        let principal = loan_records.iter().map(|r| amount_of(r)).max().unwrap_or(0);
+        let repaid: u64 = repayments.iter().map(|r| amount_of(r)).sum();
+        principal.saturating_sub(repaid)
+    };
+    let related = |records: Vec<&CreditRecord>| -> (Vec<String>, Vec<Principal>) {
+        let ids = records.iter().map(|r| r.id.clone()).collect();
+        let mut institutions: Vec<Principal> = records.iter().map(|r| r.institution_id).collect();
+        institutions.sort();
+        institutions.dedup();
+        (ids, institutions)
+    };
+
+    match content {
+        RecordContent::Repayment(repayment) => {
+            let entry = loans.get(repayment.loan_id.as_str()).filter(|(l, _)| !l.is_empty())?;
+            let remaining = outstanding(entry);
+            if repayment.amount <= remaining {
+                return None;
+            }
+            let (related_record_ids, related_institutions) = related(entry.0.iter().chain(&entry.1).copied().collect());
+            Some(DetectedIssue {
+                issue: IntegrityIssueType::RepaymentExceedsOutstanding,
+                related_record_ids,
+                related_institutions,
+                details: format!("还款金额 {} 超过贷款 {} 的未还余额 {}", repayment.amount, repayment.loan_id, remaining),
+            })
+        }
+        RecordContent::Overdue(_) => {
+            let with_loans: Vec<_> = loans.values().filter(|(l, _)| !l.is_empty()).collect();
+            if with_loans.is_empty() || with_loans.iter().any(|entry| outstanding(entry) > 0) {
+                return None;
+            }
+            let (related_record_ids, related_institutions) = related(
+                with_loans.iter().flat_map(|(l, r)| l.iter().chain(r)).copied().collect()
+            );
+            Some(DetectedIssue {
+                issue: IntegrityIssueType::OverdueOnSettledLoan,
+                related_record_ids,
+                related_institutions,
+                details: format!("用户的 {} 笔贷款均已结清，不应存在逾期", with_loans.len()),
+            })
+        }
+        RecordContent::Loan(_) => None,
+    }
+}
+
+impl Default for IntegrityService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IntegrityService {
+    pub fn new() -> Self {
+        Self {
+            state: IntegrityState::default(),
+        }
+    }
+
+    pub fn export_state(&self) -> IntegrityState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: IntegrityState) {
+        info!(
+            "Restored integrity state: {} review cases, next case id {}",
+            state.cases.len(),
+            state.next_case_id
+        );
+        self.state = state;
+    }
+
+    /// 为已提交的记录开启审核
+    pub fn open_case(&mut self, record: &CreditRecord, fingerprint: String, issue: DetectedIssue) -> String {
+        self.state.next_case_id += 1;
+        let case_id = format!("RC-{}", self.state.next_case_id);
+        warn!("Record {} flagged as {:?}: {}", record.id, issue.issue, issue.details);
+
+        self.state.cases.insert(case_id.clone(), ReviewCase {
+            id: case_id.clone(),
+            issue: issue.issue,
+            record_id: record.id.clone(),
+            institution_id: record.institution_id,
+            user_did: record.user_did.clone(),
+            fingerprint,
+            related_record_ids: issue.related_record_ids,
+            related_institutions: issue.related_institutions,
+            details: issue.details,
+            status: ReviewStatus::Open,
+            created_at: time(),
+            resolved_at: None,
+            resolved_by: None,
+            resolution_note: None,
+        });
+        case_id
+    }
+
+    pub fn get_case(&self, case_id: &str) -> Option<ReviewCase> {
+        self.state.cases.get(case_id).cloned()
+    }
+
+    pub fn list_cases(&self, status: Option<ReviewStatus>) -> Vec<ReviewCase> {
+        self.state.cases.values()
+            .filter(|c| status.as_ref().is_none_or(|s| c.status == *s))
+            .cloned()
+            .collect()
+    }
+
+    /// 记录是否仍有未处理的审核案件
+    pub fn has_open_cases(&self, record_id: &str) -> bool {
+        self.state.cases.values()
+            .any(|c| c.record_id == record_id && c.status == ReviewStatus::Open)
+    }
+
+    pub fn cases_for_institution(&self, institution_id: Principal) -> Vec<ReviewCase> {
+        self.state.cases.values()
+            .filter(|c| c.institution_id == institution_id)
+            .cloned()
+            .collect()
+    }
+
+    /// 处理审核：问题成立的案件按处理时间计入机构违规次数
+    pub fn resolve(&mut self, request: ResolveReviewRequest, operator: Principal) -> Result<ReviewCase, String> {
+        let case = self.state.cases.get_mut(&request.case_id)
+            .ok_or_else(|| format!("审核案件不存在: {}", request.case_id))?;
+        if case.status != ReviewStatus::Open {
+            return Err(format!("审核案件已处理: {:?}", case.status));
+        }
+
+        case.status = if request.uphold { ReviewStatus::Upheld } else { ReviewStatus::Dismissed };
+        case.resolved_at = Some(time());
+        case.resolved_by = Some(operator);
+        case.resolution_note = request.note;
+        let case = case.clone();
+
+        info!("Review case {} resolved as {:?} by {}", case.id, case.status, operator.to_text());
+        Ok(case)
+    }
+
+    /// 在 [since, now] 内处理且问题成立的案件数
+    pub fn offense_count(&self, institution_id: Principal, since: u64, now: u64) -> u64 {
+        self.state.cases.values()
+            .filter(|c| c.institution_id == institution_id && c.status == ReviewStatus::Upheld)
+            .filter(|c| c.resolved_at.is_some_and(|at| at >= since && at <= now))
+            .count() as u64
+    }
+
+    /// 数据质量分扣分：只统计窗口内的违规，窗口外的违规不再扣分
+    pub fn quality_penalty(&self, institution_id: Principal, since: u64, now: u64) -> u64 {
+        (self.offense_count(institution_id, since, now) * PENALTY_PER_OFFENSE).min(MAX_PENALTY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, institution: u8, content: RecordContent) -> CreditRecord {
+        let record_type = match content {
+            RecordContent::Loan(_) => RecordType::LoanRecord,
+            RecordContent::Repayment(_) => RecordType::RepaymentRecord,
+            RecordContent::Overdue(_) => RecordType::OverdueRecord,
+        };
+        CreditRecord {
+            id: id.to_string(),
+            institution_id: Principal::from_slice(&[institution]),
+            institution_name: String::new(),
+            institution_full_name: String::new(),
+            record_type,
+            user_did: "did:alice".to_string(),
+            event_date: "2024-10-15".to_string(),
+            content,
+            encrypted_content: Vec::new(),
+            proof: Vec::new(),
+            canister_id: String::new(),
+            timestamp: 0,
+            status: RecordStatus::Pending,
+            reward_amount: None,
+            query_price: 0,
+            rejection_reason: None,
+            resubmitted_from: None,
+            resubmitted_as: None,
+            imported: None,
+        }
+    }
+
+    fn loan(loan_id: &str, amount: u64) -> RecordContent {
+        RecordContent::Loan(LoanContent { amount, loan_id: loan_id.to_string(), term_months: 12, interest_rate: 5.0 })
+    }
+
+    fn repayment(loan_id: &str, amount: u64) -> RecordContent {
+        RecordContent::Repayment(RepaymentContent { amount, loan_id: loan_id.to_string(), repayment_date: "2024-11-15".to_string() })
+    }
+
+    fn overdue(amount: u64) -> RecordContent {
+        RecordContent::Overdue(OverdueContent { amount, overdueDays: 30, period_amount: amount })
+    }
+
+    #[test]
+    fn fingerprint_ignores_time_of_day_and_whitespace() {
+        let content = loan("L1", 1_000);
+        let base = record_fingerprint(&RecordType::LoanRecord, "did:alice", "2024-10-15", &content);
+        assert_eq!(base, record_fingerprint(&RecordType::LoanRecord, " did:alice ", "2024-10-15T08:00:00Z", &content));
+        assert_ne!(base, record_fingerprint(&RecordType::LoanRecord, "did:alice", "2024-10-16", &content));
+        assert_ne!(base, record_fingerprint(&RecordType::LoanRecord, "did:alice", "2024-10-15", &loan("L1", 1_001)));
+        assert_ne!(base, record_fingerprint(&RecordType::RepaymentRecord, "did:alice", "2024-10-15", &repayment("L1", 1_000)));
+    }
+
+    #[test]
+    fn repayment_beyond_outstanding_is_flagged() {
+        let existing = [
+            record("R1", 1, loan("L1", 1_000)),
+            record("R2", 2, repayment("L1", 600)),
+        ];
+
+        assert!(detect_conflict(existing.iter(), &repayment("L1", 400)).is_none());
+        let issue = detect_conflict(existing.iter(), &repayment("L1", 500)).unwrap();
+        assert_eq!(issue.issue, IntegrityIssueType::RepaymentExceedsOutstanding);
+        assert_eq!(issue.related_record_ids, vec!["R1".to_string(), "R2".to_string()]);
+        assert_eq!(issue.related_institutions.len(), 2);
+        // 没有对应贷款记录的还款不判断
+        assert!(detect_conflict(existing.iter(), &repayment("L2", 5_000)).is_none());
+    }
+
+    #[test]
+    fn overdue_on_settled_loans_is_flagged() {
+        let mut existing = [
+            record("R1", 1, loan("L1", 1_000)),
+            record("R2", 1, repayment("L1", 1_000)),
+        ];
+        let issue = detect_conflict(existing.iter(), &overdue(100)).unwrap();
+        assert_eq!(issue.issue, IntegrityIssueType::OverdueOnSettledLoan);
+
+        // 已驳回的还款不计入，贷款仍有余额
+        existing[1].status = RecordStatus::Rejected;
+        assert!(detect_conflict(existing.iter(), &overdue(100)).is_none());
+        assert!(detect_conflict(std::iter::empty(), &overdue(100)).is_none());
+    }
+
+    fn review_case(id: &str, record_id: &str, institution: u8, status: ReviewStatus, resolved_at: Option<u64>) -> ReviewCase {
+        ReviewCase {
+            id: id.to_string(),
+            issue: IntegrityIssueType::Duplicate,
+            record_id: record_id.to_string(),
+            institution_id: Principal::from_slice(&[institution]),
+            user_did: "did:alice".to_string(),
+            fingerprint: String::new(),
+            related_record_ids: Vec::new(),
+            related_institutions: Vec::new(),
+            details: String::new(),
+            status,
+            created_at: 0,
+            resolved_at,
+            resolved_by: None,
+            resolution_note: None,
+        }
+    }
+
+    #[test]
+    fn open_cases_hold_record_until_all_resolved() {
+        let mut service = IntegrityService::new();
+        for (id, status) in [("RC-1", ReviewStatus::Dismissed), ("RC-2", ReviewStatus::Open)] {
+            service.state.cases.insert(id.to_string(), review_case(id, "R1", 1, status, None));
+        }
+
+        assert!(service.has_open_cases("R1"));
+        service.state.cases.get_mut("RC-2").unwrap().status = ReviewStatus::Upheld;
+        assert!(!service.has_open_cases("R1"));
+        assert!(!service.has_open_cases("R2"));
+    }
+
+    #[test]
+    fn quality_penalty_counts_upheld_cases_within_window() {
+        let mut service = IntegrityService::new();
+        let bank = Principal::from_slice(&[1]);
+        let cases = [
+            review_case("RC-1", "R1", 1, ReviewStatus::Upheld, Some(100)),
+            review_case("RC-2", "R2", 1, ReviewStatus::Upheld, Some(200)),
+            review_case("RC-3", "R3", 1, ReviewStatus::Dismissed, Some(200)),
+            review_case("RC-4", "R4", 1, ReviewStatus::Open, None),
+            review_case("RC-5", "R5", 2, ReviewStatus::Upheld, Some(200)),
+        ];
+        for case in cases {
+            service.state.cases.insert(case.id.clone(), case);
+        }
+
+        assert_eq!(service.quality_penalty(bank, 0, 300), 2 * PENALTY_PER_OFFENSE);
+        // 窗口外的违规不再扣分
+        assert_eq!(service.quality_penalty(bank, 150, 300), PENALTY_PER_OFFENSE);
+        assert_eq!(service.quality_penalty(bank, 250, 300), 0);
+
+        for i in 0..20 {
+            let id = format!("RC-X{}", i);
+            service.state.cases.insert(id.clone(), review_case(&id, "R6", 1, ReviewStatus::Upheld, Some(250)));
+        }
+        assert_eq!(service.quality_penalty(bank, 250, 300), MAX_PENALTY);
+    }
+}
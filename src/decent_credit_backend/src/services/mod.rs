@@ -7,3 +7,40 @@ pub mod storage_service;
 pub mod credit_service;
 pub mod reports_storage;
 pub mod token_service;
+pub mod billing_service;
+
+pub mod reconciliation_service;
+
+pub mod pricing_service;
+
+pub mod quota_service;
+
+pub mod rate_limit_service;
+
+pub mod stable_state;
+
+pub mod scoring_registry;
+
+pub mod score_history_service;
+
+pub mod backtesting;
+
+pub mod identity_service;
+
+pub mod did_service;
+
+pub mod api_key_service;
+
+pub mod http_gateway;
+
+pub mod import_service;
+
+pub mod export_service;
+
+pub mod validation_service;
+
+pub mod integrity_service;
+
+pub mod data_quality_service;
+
+pub mod institution_score_service;
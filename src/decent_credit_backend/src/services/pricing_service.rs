@@ -0,0 +1,454 @@
+use candid::{CandidType, Deserialize, Principal};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use ic_cdk::api::time;
+use log::{info, debug};
+use crate::models::pricing::*;
+use crate::models::record::{CreditRecord, RecordType};
+use crate::utils::time::month_key;
+
+// 需要跨升级保存的定价与分成状态
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct PricingState {
+    pub split_config: RevenueSplitConfig,
+    pub borrower_accounts: HashMap<String, Principal>,  // user_did -> 借款人收款账户
+    pub schedules: HashMap<Principal, PricingSchedule>,  // 机构定价表
+    pub institution_groups: HashMap<Principal, String>,  // 机构所属集团，同组查询免费
+    pub monthly_volume: HashMap<(Principal, Principal), MonthlyVolume>,  // (查询方, 数据所有方) -> 当月查询次数
+}
+
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct MonthlyVolume {
+    pub month: u32,
+    pub count: u64,
+}
+
+pub struct PricingService {
+    state: PricingState,
+}
+
+thread_local! {
+    pub static PRICING_SERVICE: RefCell<PricingService> = RefCell::new(PricingService::new());
+}
+
+impl Default for PricingService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PricingService {
+    pub fn new() -> Self {
+        Self {
+            state: PricingState::default(),
+        }
+    }
+
+    pub fn export_state(&self) -> PricingState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: PricingState) {
+        info!("Restored pricing state: {} borrower accounts", state.borrower_accounts.len());
+        self.state = state;
+    }
+
+    // === 分成配置 ===
+
+    pub fn get_split_config(&self) -> RevenueSplitConfig {
+        self.state.split_config.clone()
+    }
+
+    pub fn update_split_config(&mut self, config: RevenueSplitConfig) -> Result<(), String> {
+        if config.min_owner_share > config.max_owner_share {
+            return Err("分成比例下限不能大于上限".to_string());
+        }
+        if config.default_owner_share < config.min_owner_share
+            || config.default_owner_share > config.max_owner_share {
+            return Err("默认分成比例必须在上下限之间".to_string());
+        }
+        if config.max_owner_share as u16 + config.borrower_share as u16 > 100 {
+            return Err("机构分成上限与借款人分成之和不能超过100".to_string());
+        }
+        info!("Revenue split config updated: {:?}", config);
+        self.state.split_config = config;
+        Ok(())
+    }
+
+    /// 校验机构请求的 reward_share_ratio 是否在管理员设定的范围内
+    pub fn validate_owner_share(&self, ratio: u8) -> Result<(), String> {
+        let config = &self.state.split_config;
+        if ratio < config.min_owner_share || ratio > config.max_owner_share {
+            return Err(format!(
+                "奖励分成比例必须在{}-{}之间",
+                config.min_owner_share, config.max_owner_share
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn default_owner_share(&self) -> u8 {
+        self.state.split_config.default_owner_share
+    }
+
+    // 配置调整后，已有机构的比例按新范围截断
+    fn effective_owner_share(&self, ratio: u8) -> u8 {
+        let config = &self.state.split_config;
+        ratio.clamp(config.min_owner_share, config.max_owner_share)
+    }
+
+    pub fn owner_amount(&self, amount: u64, ratio: u8) -> u64 {
+        share_of(amount, self.effective_owner_share(ratio))
+    }
+
+    // === 借款人收款账户 ===
+
+    pub fn set_borrower_account(&mut self, user_did: String, account: Option<Principal>) {
+        match account {
+            Some(account) => {
+                info!("Linked payout account {} for borrower {}", account.to_text(), user_did);
+                self.state.borrower_accounts.insert(user_did, account);
+            }
+            None => {
+                info!("Unlinked payout account for borrower {}", user_did);
+                self.state.borrower_accounts.remove(&user_did);
+            }
+        }
+    }
+
+    pub fn get_borrower_account(&self, user_did: &str) -> Option<Principal> {
+        self.state.borrower_accounts.get(user_did).copied()
+    }
+
+    /// 将一笔查询费拆分为数据所有方、借款人与平台金库三部分；
+    /// 取整余数及未支付的借款人份额都计入平台金库
+    pub fn split_fee(&self, amount: u64, owner: Principal, owner_ratio: u8, user_did: &str) -> Vec<RevenueSplitLine> {
+        let config = &self.state.split_config;
+        let owner_share = self.effective_owner_share(owner_ratio);
+        let owner_amount = share_of(amount, owner_share);
+
+        let borrower = self.get_borrower_account(user_did)
+            .filter(|_| config.borrower_share > 0);
+        let borrower_amount = borrower.map_or(0, |_| share_of(amount, config.borrower_share));
+
+        let treasury_amount = amount - owner_amount - borrower_amount;
+        let treasury_share = 100 - owner_share - borrower.map_or(0, |_| config.borrower_share);
+        let treasury = config.treasury.unwrap_or_else(ic_cdk::id);
+
+        let mut lines = vec![line(SplitRole::DataOwner, owner, owner_share, owner_amount)];
+        if let Some(account) = borrower {
+            lines.push(line(SplitRole::Borrower, account, config.borrower_share, borrower_amount));
+        }
+        lines.push(line(SplitRole::PlatformTreasury, treasury, treasury_share, treasury_amount));
+
+        debug!("Split fee {} for {}: {:?}", amount, user_did, lines);
+        lines.into_iter().filter(|l| l.amount > 0).collect()
+    }
+
+    // === 定价表 ===
+
+    pub fn set_schedule(&mut self, institution_id: Principal, mut schedule: PricingSchedule) -> Result<(), String> {
+        if schedule.volume_tiers.iter().any(|tier| tier.discount_percent > 100) {
+            return Err("阶梯折扣比例必须在0-100之间".to_string());
+        }
+        schedule.volume_tiers.sort_by_key(|tier| tier.min_monthly_queries);
+        if schedule.volume_tiers.windows(2).any(|w| w[0].min_monthly_queries == w[1].min_monthly_queries) {
+            return Err("阶梯查询次数不能重复".to_string());
+        }
+        info!("Pricing schedule updated for {}: {:?}", institution_id.to_text(), schedule);
+        self.state.schedules.insert(institution_id, schedule);
+        Ok(())
+    }
+
+    pub fn get_schedule(&self, institution_id: Principal, query_price: u64) -> PricingSchedule {
+        self.state.schedules.get(&institution_id)
+            .cloned()
+            .unwrap_or_else(|| PricingSchedule::flat(query_price))
+    }
+
+    pub fn set_institution_group(&mut self, institution_id: Principal, group: Option<String>) {
+        match group {
+            Some(group) => {
+                info!("Institution {} joined group {}", institution_id.to_text(), group);
+                self.state.institution_groups.insert(institution_id, group);
+            }
+            None => {
+                self.state.institution_groups.remove(&institution_id);
+            }
+        }
+    }
+
+    pub fn get_institution_group(&self, institution_id: Principal) -> Option<String> {
+        self.state.institution_groups.get(&institution_id).cloned()
+    }
+
+    fn same_group(&self, a: Principal, b: Principal) -> bool {
+        match (self.state.institution_groups.get(&a), self.state.institution_groups.get(&b)) {
+            (Some(x), Some(y)) => x == y,
+            _ => false,
+        }
+    }
+
+    /// 查询方本月对某数据所有方的查询次数（跨月自动归零）
+    pub fn monthly_queries(&self, payer: Principal, owner: Principal) -> u64 {
+        self.monthly_queries_at(payer, owner, time())
+    }
+
+    fn monthly_queries_at(&self, payer: Principal, owner: Principal, now: u64) -> u64 {
+        let current = month_key(now);
+        self.state.monthly_volume.get(&(payer, owner))
+            .filter(|volume| volume.month == current)
+            .map_or(0, |volume| volume.count)
+    }
+
+    pub fn record_query_volume(&mut self, payer: Principal, owner: Principal) {
+        self.record_query_volume_at(payer, owner, time())
+    }
+
+    fn record_query_volume_at(&mut self, payer: Principal, owner: Principal, now: u64) {
+        let current = month_key(now);
+        let volume = self.state.monthly_volume.entry((payer, owner)).or_default();
+        if volume.month != current {
+            volume.month = current;
+            volume.count = 0;
+        }
+        volume.count = volume.count.saturating_add(1);
+    }
+
+    /// 单条记录报价：按记录类型取基础价格，同组免费，再按当月查询量适用阶梯折扣
+    pub fn quote_record(&self, payer: Principal, owner_query_price: u64, record: &CreditRecord) -> QuoteItem {
+        self.quote_record_at(payer, owner_query_price, record, time())
+    }
+
+    fn quote_record_at(&self, payer: Principal, owner_query_price: u64, record: &CreditRecord, now: u64) -> QuoteItem {
+        let schedule = self.get_schedule(record.institution_id, owner_query_price);
+        let base_price = match record.record_type {
+            RecordType::LoanRecord => schedule.loan_price,
+            RecordType::RepaymentRecord => schedule.repayment_price,
+            RecordType::OverdueRecord => schedule.overdue_price,
+        };
+
+        let mut item = QuoteItem {
+            record_id: record.id.clone(),
+            record_type: record.record_type.clone(),
+            base_price,
+            discount_percent: 0,
+            price: base_price,
+            free_reason: None,
+        };
+
+        if self.same_group(payer, record.institution_id) {
+            item.discount_percent = 100;
+            item.price = 0;
+            item.free_reason = Some("同组机构查询免费".to_string());
+            return item;
+        }
+
+        let volume = self.monthly_queries_at(payer, record.institution_id, now);
+        if let Some(tier) = schedule.volume_tiers.iter().rev().find(|tier| volume >= tier.min_monthly_queries) {
+            item.discount_percent = tier.discount_percent;
+            item.price = base_price - share_of(base_price, tier.discount_percent);
+        }
+        item
+    }
+}
+
+fn share_of(amount: u64, share: u8) -> u64 {
+    (amount as u128 * share as u128 / 100) as u64
+}
+
+fn line(role: SplitRole, recipient: Principal, share: u8, amount: u64) -> RevenueSplitLine {
+    RevenueSplitLine {
+        role,
+        recipient,
+        share,
+        amount,
+        settled: false,
+        block_height: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::record::*;
+
+    const TREASURY: [u8; 1] = [9];
+    const OCTOBER: u64 = 1_728_950_400 * 1_000_000_000;     // 2024-10-15
+    const NOVEMBER: u64 = 1_730_419_200 * 1_000_000_000;    // 2024-11-01
+
+    fn service(borrower_share: u8) -> PricingService {
+        let mut service = PricingService::new();
+        service.update_split_config(RevenueSplitConfig {
+            treasury: Some(Principal::from_slice(&TREASURY)),
+            borrower_share,
+            ..RevenueSplitConfig::default()
+        }).unwrap();
+        service
+    }
+
+    fn amounts(lines: &[RevenueSplitLine]) -> Vec<(SplitRole, u8, u64)> {
+        lines.iter().map(|l| (l.role.clone(), l.share, l.amount)).collect()
+    }
+
+    #[test]
+    fn split_sends_rounding_remainder_to_treasury() {
+        let owner = Principal::from_slice(&[1]);
+        let service = service(0);
+
+        let lines = service.split_fee(99, owner, 90, "did:alice");
+        assert_eq!(amounts(&lines), vec![
+            (SplitRole::DataOwner, 90, 89),
+            (SplitRole::PlatformTreasury, 10, 10),
+        ]);
+        assert_eq!(lines[1].recipient, Principal::from_slice(&TREASURY));
+        // 超出范围的比例按当前上下限截断
+        assert_eq!(service.owner_amount(100, 99), 95);
+        assert_eq!(service.owner_amount(100, 10), 50);
+    }
+
+    #[test]
+    fn borrower_share_paid_only_to_linked_account() {
+        let owner = Principal::from_slice(&[1]);
+        let borrower = Principal::from_slice(&[2]);
+        let mut service = service(5);
+
+        assert_eq!(amounts(&service.split_fee(100, owner, 90, "did:alice")), vec![
+            (SplitRole::DataOwner, 90, 90),
+            (SplitRole::PlatformTreasury, 10, 10),
+        ]);
+
+        service.set_borrower_account("did:alice".to_string(), Some(borrower));
+        assert_eq!(amounts(&service.split_fee(100, owner, 90, "did:alice")), vec![
+            (SplitRole::DataOwner, 90, 90),
+            (SplitRole::Borrower, 5, 5),
+            (SplitRole::PlatformTreasury, 5, 5),
+        ]);
+        // 金额为零的分项不生成
+        assert_eq!(amounts(&service.split_fee(1, owner, 90, "did:alice")), vec![
+            (SplitRole::PlatformTreasury, 5, 1),
+        ]);
+    }
+
+    fn record(id: &str, institution_id: Principal, record_type: RecordType) -> CreditRecord {
+        let content = match record_type {
+            RecordType::LoanRecord => RecordContent::Loan(LoanContent {
+                amount: 1_000, loan_id: "L1".to_string(), term_months: 12, interest_rate: 5.0,
+            }),
+            RecordType::RepaymentRecord => RecordContent::Repayment(RepaymentContent {
+                amount: 1_000, loan_id: "L1".to_string(), repayment_date: "2024-10-15".to_string(),
+            }),
+            RecordType::OverdueRecord => RecordContent::Overdue(OverdueContent {
+                amount: 1_000, overdueDays: 30, period_amount: 1_000,
+            }),
+        };
+        CreditRecord {
+            id: id.to_string(),
+            institution_id,
+            institution_name: String::new(),
+            institution_full_name: String::new(),
+            record_type,
+            user_did: "did:alice".to_string(),
+            event_date: "2024-10-15".to_string(),
+            content,
+            encrypted_content: Vec::new(),
+            proof: Vec::new(),
+            canister_id: String::new(),
+            timestamp: 0,
+            status: RecordStatus::Confirmed,
+            reward_amount: None,
+            query_price: 0,
+            rejection_reason: None,
+            resubmitted_from: None,
+            resubmitted_as: None,
+            imported: None,
+        }
+    }
+
+    fn schedule() -> PricingSchedule {
+        PricingSchedule {
+            loan_price: 100,
+            repayment_price: 40,
+            overdue_price: 200,
+            volume_tiers: vec![
+                VolumeTier { min_monthly_queries: 10, discount_percent: 50 },
+                VolumeTier { min_monthly_queries: 3, discount_percent: 10 },
+            ],
+        }
+    }
+
+    #[test]
+    fn quotes_use_record_type_price_and_volume_tier() {
+        let payer = Principal::from_slice(&[1]);
+        let owner = Principal::from_slice(&[2]);
+        let mut service = PricingService::new();
+        service.set_schedule(owner, schedule()).unwrap();
+        let loan = record("R1", owner, RecordType::LoanRecord);
+
+        let quote = service.quote_record_at(payer, 1, &loan, OCTOBER);
+        assert_eq!((quote.base_price, quote.discount_percent, quote.price), (100, 0, 100));
+        assert_eq!(service.quote_record_at(payer, 1, &record("R2", owner, RecordType::OverdueRecord), OCTOBER).price, 200);
+
+        let prices: Vec<u64> = (0..10).map(|_| {
+            service.record_query_volume_at(payer, owner, OCTOBER);
+            service.quote_record_at(payer, 1, &loan, OCTOBER).price
+        }).collect();
+        assert_eq!(prices, vec![100, 100, 90, 90, 90, 90, 90, 90, 90, 50]);
+
+        // 跨月后查询次数归零
+        assert_eq!(service.monthly_queries_at(payer, owner, NOVEMBER), 0);
+        assert_eq!(service.quote_record_at(payer, 1, &loan, NOVEMBER).price, 100);
+        service.record_query_volume_at(payer, owner, NOVEMBER);
+        assert_eq!(service.monthly_queries_at(payer, owner, NOVEMBER), 1);
+    }
+
+    #[test]
+    fn same_group_queries_are_free_and_unscheduled_owners_are_flat() {
+        let payer = Principal::from_slice(&[1]);
+        let sibling = Principal::from_slice(&[2]);
+        let other = Principal::from_slice(&[3]);
+        let mut service = PricingService::new();
+        service.set_institution_group(payer, Some("group-a".to_string()));
+        service.set_institution_group(sibling, Some("group-a".to_string()));
+
+        let free = service.quote_record_at(payer, 30, &record("R1", sibling, RecordType::LoanRecord), OCTOBER);
+        assert_eq!((free.base_price, free.discount_percent, free.price), (30, 100, 0));
+        assert!(free.free_reason.is_some());
+
+        let flat = service.quote_record_at(payer, 30, &record("R2", other, RecordType::RepaymentRecord), OCTOBER);
+        assert_eq!((flat.price, flat.free_reason), (30, None));
+    }
+
+    #[test]
+    fn schedules_reject_invalid_tiers() {
+        let owner = Principal::from_slice(&[2]);
+        let mut service = PricingService::new();
+        let mut duplicate = schedule();
+        duplicate.volume_tiers.push(VolumeTier { min_monthly_queries: 3, discount_percent: 20 });
+        assert!(service.set_schedule(owner, duplicate).is_err());
+
+        let mut over = schedule();
+        over.volume_tiers[0].discount_percent = 101;
+        assert!(service.set_schedule(owner, over).is_err());
+
+        // 保存时按查询次数排序
+        service.set_schedule(owner, schedule()).unwrap();
+        let tiers: Vec<u64> = service.get_schedule(owner, 1).volume_tiers.iter().map(|t| t.min_monthly_queries).collect();
+        assert_eq!(tiers, vec![3, 10]);
+    }
+
+    #[test]
+    fn split_config_bounds_are_validated() {
+        let mut service = PricingService::new();
+        let invalid = [
+            RevenueSplitConfig { min_owner_share: 80, max_owner_share: 70, ..RevenueSplitConfig::default() },
+            RevenueSplitConfig { default_owner_share: 40, ..RevenueSplitConfig::default() },
+            RevenueSplitConfig { borrower_share: 10, ..RevenueSplitConfig::default() },
+        ];
+        for config in invalid {
+            assert!(service.update_split_config(config).is_err());
+        }
+        assert!(service.validate_owner_share(96).is_err());
+        assert!(service.validate_owner_share(50).is_ok());
+    }
+}
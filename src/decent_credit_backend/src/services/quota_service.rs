@@ -0,0 +1,493 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use log::{info, debug, warn, error};
+use crate::models::quota::*;
+use crate::models::record::DCCTransactionRequest;
+use crate::services::admin_institution_service::ADMIN_SERVICE;
+use crate::services::pricing_service::PRICING_SERVICE;
+use crate::services::token_service::*;
+use crate::utils::error::Error;
+use crate::utils::time::month_key;
+
+const DEFAULT_PLAN_ID: &str = "basic";
+const RENEWAL_INTERVAL_SECS: u64 = 60 * 60;
+
+// 需要跨升级保存的配额状态
+#[derive(CandidType, Deserialize, Clone)]
+pub struct QuotaState {
+    pub plans: HashMap<String, SubscriptionPlan>,
+    pub subscriptions: HashMap<Principal, InstitutionSubscription>,
+}
+
+impl Default for QuotaState {
+    fn default() -> Self {
+        let plans = [
+            plan(DEFAULT_PLAN_ID, "基础版", 20_000, 1_000, 0),
+            plan("professional", "专业版", 100_000, 5_000, 10_000),
+            plan("enterprise", "企业版", 1_000_000, 10_000, 50_000),
+        ];
+        Self {
+            plans: plans.into_iter().map(|p| (p.id.clone(), p)).collect(),
+            subscriptions: HashMap::new(),
+        }
+    }
+}
+
+fn plan(id: &str, name: &str, monthly_query_allowance: u64, batch_size_limit: u64, price_dcc: u64) -> SubscriptionPlan {
+    SubscriptionPlan {
+        id: id.to_string(),
+        name: name.to_string(),
+        monthly_query_allowance,
+        batch_size_limit,
+        price_dcc,
+    }
+}
+
+pub struct QuotaService {
+    state: QuotaState,
+    in_flight: HashSet<Principal>,     // 正在扣费变更或续费的机构，不跨升级保存
+}
+
+impl Default for QuotaService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static QUOTA_SERVICE: RefCell<QuotaService> = RefCell::new(QuotaService::new());
+}
+
+impl QuotaService {
+    pub fn new() -> Self {
+        Self {
+            state: QuotaState::default(),
+            in_flight: HashSet::new(),
+        }
+    }
+
+    pub fn export_state(&self) -> QuotaState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, mut state: QuotaState) {
+        // 旧版本在变更套餐时一次性付费，视为已支付变更当月
+        for subscription in state.subscriptions.values_mut() {
+            if subscription.paid_period.is_none() && subscription.plan_id != DEFAULT_PLAN_ID {
+                subscription.paid_period = Some(month_key(subscription.subscribed_at));
+            }
+        }
+        info!(
+            "Restored quota state: {} plans, {} subscriptions",
+            state.plans.len(),
+            state.subscriptions.len()
+        );
+        self.state = state;
+    }
+
+    // === 套餐管理 ===
+
+    pub fn get_plans(&self) -> Vec<SubscriptionPlan> {
+        let mut plans: Vec<SubscriptionPlan> = self.state.plans.values().cloned().collect();
+        plans.sort_by_key(|p| p.price_dcc);
+        plans
+    }
+
+    pub fn get_plan(&self, plan_id: &str) -> Option<SubscriptionPlan> {
+        self.state.plans.get(plan_id).cloned()
+    }
+
+    pub fn upsert_plan(&mut self, plan: SubscriptionPlan) -> Result<(), String> {
+        if plan.id.trim().is_empty() {
+            return Err("套餐ID不能为空".to_string());
+        }
+        if plan.batch_size_limit == 0 {
+            return Err("批量提交上限必须大于0".to_string());
+        }
+        info!("Subscription plan upserted: {:?}", plan);
+        self.state.plans.insert(plan.id.clone(), plan);
+        Ok(())
+    }
+
+    // 取机构订阅，跨周期时重置已用次数
+    fn subscription_mut(&mut self, institution_id: Principal, now: u64) -> &mut InstitutionSubscription {
+        let period = month_key(now);
+        let subscription = self.state.subscriptions.entry(institution_id).or_insert_with(|| InstitutionSubscription {
+            institution_id,
+            plan_id: DEFAULT_PLAN_ID.to_string(),
+            period,
+            queries_used: 0,
+            subscribed_at: now,
+            paid_period: None,
+        });
+        if subscription.period != period {
+            debug!("Quota period reset for {}: {} -> {}", institution_id.to_text(), subscription.period, period);
+            subscription.period = period;
+            subscription.queries_used = 0;
+        }
+        subscription
+    }
+
+    fn plan_of(&self, plan_id: &str) -> SubscriptionPlan {
+        self.state.plans.get(plan_id)
+            .or_else(|| self.state.plans.get(DEFAULT_PLAN_ID))
+            .cloned()
+            .unwrap_or_else(|| plan(DEFAULT_PLAN_ID, "基础版", 20_000, 1_000, 0))
+    }
+
+    // 付费套餐只在已付费的周期内生效，未续费时按基础套餐计算
+    fn effective_plan(&self, subscription: &InstitutionSubscription, period: u32) -> SubscriptionPlan {
+        let plan = self.plan_of(&subscription.plan_id);
+        if plan.price_dcc > 0 && subscription.paid_period != Some(period) {
+            return self.plan_of(DEFAULT_PLAN_ID);
+        }
+        plan
+    }
+
+    pub fn get_usage(&self, institution_id: Principal) -> QuotaUsage {
+        self.usage_at(institution_id, time())
+    }
+
+    fn usage_at(&self, institution_id: Principal, now: u64) -> QuotaUsage {
+        let period = month_key(now);
+        let (plan, queries_used) = match self.state.subscriptions.get(&institution_id) {
+            Some(sub) if sub.period == period => (self.effective_plan(sub, period), sub.queries_used),
+            Some(sub) => (self.effective_plan(sub, period), 0),
+            None => (self.plan_of(DEFAULT_PLAN_ID), 0),
+        };
+
+        QuotaUsage {
+            institution_id,
+            queries_remaining: plan.monthly_query_allowance.saturating_sub(queries_used),
+            plan,
+            period,
+            queries_used,
+        }
+    }
+
+    // === 配额校验 ===
+
+    /// 消耗一次查询配额；超出套餐额度时返回 RateLimitExceeded
+    pub fn consume_query(&mut self, institution_id: Principal) -> Result<(), Error> {
+        self.consume_query_at(institution_id, time())
+    }
+
+    fn consume_query_at(&mut self, institution_id: Principal, now: u64) -> Result<(), Error> {
+        let subscription = self.subscription_mut(institution_id, now).clone();
+        let used = subscription.queries_used;
+        let allowance = self.effective_plan(&subscription, subscription.period).monthly_query_allowance;
+
+        if used >= allowance {
+            warn!("Query quota exceeded for {}: {}/{}", institution_id.to_text(), used, allowance);
+            return Err(Error::RateLimitExceeded);
+        }
+        self.subscription_mut(institution_id, now).queries_used = used + 1;
+        Ok(())
+    }
+
+    /// 查询失败时归还已消耗的配额
+    pub fn release_query(&mut self, institution_id: Principal) {
+        let subscription = self.subscription_mut(institution_id, time());
+        subscription.queries_used = subscription.queries_used.saturating_sub(1);
+    }
+
+    pub fn check_batch_size(&self, institution_id: Principal, size: usize) -> Result<(), Error> {
+        let limit = self.get_usage(institution_id).plan.batch_size_limit;
+        if size as u64 > limit {
+            warn!("Batch size {} exceeds plan limit {} for {}", size, limit, institution_id.to_text());
+            return Err(Error::RateLimitExceeded);
+        }
+        Ok(())
+    }
+
+    // === 套餐变更与续费 ===
+
+    /// 同一机构同时只允许一次扣费操作
+    fn begin(&mut self, institution_id: Principal) -> bool {
+        self.in_flight.insert(institution_id)
+    }
+
+    fn finish(&mut self, institution_id: Principal) {
+        self.in_flight.remove(&institution_id);
+    }
+
+    /// 校验套餐变更；已是当前套餐且本周期已付费（或免费）时拒绝，避免重复扣费
+    fn check_change(&self, institution_id: Principal, plan_id: &str, now: u64) -> Result<SubscriptionPlan, String> {
+        let plan = self.get_plan(plan_id)
+            .ok_or_else(|| format!("套餐不存在: {}", plan_id))?;
+        let current = self.state.subscriptions.get(&institution_id);
+        let current_plan_id = current.map_or(DEFAULT_PLAN_ID, |sub| sub.plan_id.as_str());
+        let paid = plan.price_dcc == 0 || current.is_some_and(|sub| sub.paid_period == Some(month_key(now)));
+        if current_plan_id == plan_id && paid {
+            return Err(format!("已是当前套餐: {}", plan_id));
+        }
+        Ok(plan)
+    }
+
+    /// 变更套餐立即生效，本周期已用次数保留；付款期间套餐被删除或改价时拒绝
+    fn apply_plan(&mut self, institution_id: Principal, paid: &SubscriptionPlan, now: u64) -> Result<(), String> {
+        match self.state.plans.get(&paid.id) {
+            Some(plan) if plan.price_dcc == paid.price_dcc => {}
+            Some(_) => return Err(format!("套餐 {} 价格已变更", paid.id)),
+            None => return Err(format!("套餐不存在: {}", paid.id)),
+        }
+        let period = month_key(now);
+        let subscription = self.subscription_mut(institution_id, now);
+        subscription.plan_id = paid.id.clone();
+        subscription.subscribed_at = now;
+        subscription.paid_period = (paid.price_dcc > 0).then_some(period);
+        info!("Institution {} switched to plan {} for period {}", institution_id.to_text(), paid.id, period);
+        Ok(())
+    }
+
+    /// 本周期尚未付费的付费套餐
+    fn due_renewals(&self, now: u64) -> Vec<(Principal, SubscriptionPlan)> {
+        let period = month_key(now);
+        self.state.subscriptions.values()
+            .filter(|sub| sub.paid_period != Some(period) && !self.in_flight.contains(&sub.institution_id))
+            .filter_map(|sub| {
+                let plan = self.state.plans.get(&sub.plan_id);
+                match plan {
+                    Some(plan) if plan.price_dcc == 0 => None,
+                    _ => Some((sub.institution_id, plan.cloned().unwrap_or_else(|| self.plan_of(DEFAULT_PLAN_ID)))),
+                }
+            })
+            .collect()
+    }
+
+    // 续费失败或套餐已下架时降为基础套餐
+    fn downgrade(&mut self, institution_id: Principal, now: u64) {
+        let subscription = self.subscription_mut(institution_id, now);
+        warn!("Institution {} downgraded from plan {} to {}", institution_id.to_text(), subscription.plan_id, DEFAULT_PLAN_ID);
+        subscription.plan_id = DEFAULT_PLAN_ID.to_string();
+        subscription.paid_period = None;
+    }
+}
+
+fn plan_treasury() -> Principal {
+    PRICING_SERVICE.with(|service| service.borrow().get_split_config().treasury)
+        .unwrap_or_else(ic_cdk::id)
+}
+
+// 从机构账户向金库支付一个周期的套餐费用并记账
+async fn charge_plan(institution_id: Principal, plan: &SubscriptionPlan, treasury: Principal) -> Result<(), String> {
+    let token_canister_id = TOKEN_SERVICE.with(|service| service.borrow().token_canister_id);
+    let transfer_result = TokenService::operator_transfer_static(
+        token_canister_id,
+        institution_id,
+        treasury,
+        plan.price_dcc,
+        format!("Subscription plan {} for {}", plan.id, institution_id.to_text()),
+    ).await?;
+
+    ADMIN_SERVICE.with(|service| {
+        service.borrow_mut().record_dcc_transaction(
+            DCCTransactionRequest {
+                dcc_amount: plan.price_dcc,
+                usdt_amount: 0.0,
+                tx_hash: String::new(),
+                remarks: format!("订阅套餐: {} ({})", plan.name, month_key(time())),
+                created_at: time(),
+            },
+            &transfer_result,
+        )
+    });
+    TOKEN_SERVICE.with(|service| {
+        service.borrow_mut().update_institution_stats(institution_id, 0, plan.price_dcc)
+    });
+    Ok(())
+}
+
+// 套餐未能生效时退还已支付的费用
+async fn refund_plan(institution_id: Principal, plan: &SubscriptionPlan, treasury: Principal) {
+    let token_canister_id = TOKEN_SERVICE.with(|service| service.borrow().token_canister_id);
+    let result = TokenService::operator_transfer_static(
+        token_canister_id,
+        treasury,
+        institution_id,
+        plan.price_dcc,
+        format!("Refund subscription plan {} for {}", plan.id, institution_id.to_text()),
+    ).await;
+    match result {
+        Ok(transfer_result) => {
+            ADMIN_SERVICE.with(|service| {
+                service.borrow_mut().record_dcc_transaction(
+                    DCCTransactionRequest {
+                        dcc_amount: plan.price_dcc,
+                        usdt_amount: 0.0,
+                        tx_hash: String::new(),
+                        remarks: format!("退还套餐费用: {}", plan.name),
+                        created_at: time(),
+                    },
+                    &transfer_result,
+                )
+            });
+            TOKEN_SERVICE.with(|service| {
+                service.borrow_mut().update_institution_stats(institution_id, plan.price_dcc, 0)
+            });
+        }
+        Err(e) => error!("Refund of plan {} for {} failed: {}", plan.id, institution_id.to_text(), e),
+    }
+}
+
+/// 支付本周期套餐费用（转入平台金库）后变更套餐；变更失败时退款
+pub async fn change_plan(institution_id: Principal, plan_id: String) -> Result<QuotaUsage, String> {
+    if !QUOTA_SERVICE.with(|service| service.borrow_mut().begin(institution_id)) {
+        return Err("套餐变更或续费正在处理中".to_string());
+    }
+    let result = change_plan_locked(institution_id, &plan_id).await;
+    QUOTA_SERVICE.with(|service| service.borrow_mut().finish(institution_id));
+    result
+}
+
+async fn change_plan_locked(institution_id: Principal, plan_id: &str) -> Result<QuotaUsage, String> {
+    let plan = QUOTA_SERVICE.with(|service| service.borrow().check_change(institution_id, plan_id, time()))?;
+    let treasury = plan_treasury();
+    if plan.price_dcc > 0 {
+        charge_plan(institution_id, &plan, treasury).await?;
+    }
+
+    let applied = QUOTA_SERVICE.with(|service| service.borrow_mut().apply_plan(institution_id, &plan, time()));
+    if let Err(e) = applied {
+        warn!("Plan change for {} failed after payment: {}", institution_id.to_text(), e);
+        if plan.price_dcc > 0 {
+            refund_plan(institution_id, &plan, treasury).await;
+        }
+        return Err(e);
+    }
+    Ok(QUOTA_SERVICE.with(|service| service.borrow().get_usage(institution_id)))
+}
+
+/// 为本周期尚未付费的付费套餐续费，扣费失败则降为基础套餐
+pub async fn renew_subscriptions() {
+    let due = QUOTA_SERVICE.with(|service| service.borrow().due_renewals(time()));
+    for (institution_id, plan) in due {
+        if !QUOTA_SERVICE.with(|service| service.borrow_mut().begin(institution_id)) {
+            continue;
+        }
+        let renewed = if plan.price_dcc == 0 {
+            Err(format!("套餐 {} 已下架", plan.id))
+        } else {
+            let treasury = plan_treasury();
+            match charge_plan(institution_id, &plan, treasury).await {
+                Ok(()) => {
+                    let applied = QUOTA_SERVICE.with(|service| service.borrow_mut().apply_plan(institution_id, &plan, time()));
+                    if applied.is_err() {
+                        refund_plan(institution_id, &plan, treasury).await;
+                    }
+                    applied
+                }
+                Err(e) => Err(e),
+            }
+        };
+        QUOTA_SERVICE.with(|service| {
+            let mut service = service.borrow_mut();
+            if let Err(e) = renewed {
+                warn!("Renewal of plan {} for {} failed: {}", plan.id, institution_id.to_text(), e);
+                service.downgrade(institution_id, time());
+            }
+            service.finish(institution_id);
+        });
+    }
+}
+
+pub fn init_quota_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(RENEWAL_INTERVAL_SECS), || {
+        ic_cdk::spawn(renew_subscriptions());
+    });
+    info!("Subscription renewal timer started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-10-15 与 2024-11-01（UTC）
+    const OCTOBER: u64 = 1_728_950_400 * 1_000_000_000;
+    const NOVEMBER: u64 = 1_730_419_200 * 1_000_000_000;
+
+    fn service_with_allowance(allowance: u64) -> QuotaService {
+        let mut service = QuotaService::new();
+        service.upsert_plan(plan(DEFAULT_PLAN_ID, "基础版", allowance, 10, 0)).unwrap();
+        service
+    }
+
+    #[test]
+    fn rejects_queries_beyond_allowance() {
+        let institution = Principal::from_slice(&[1]);
+        let mut service = service_with_allowance(2);
+
+        assert!(service.consume_query_at(institution, OCTOBER).is_ok());
+        assert!(service.consume_query_at(institution, OCTOBER).is_ok());
+        assert!(matches!(service.consume_query_at(institution, OCTOBER), Err(Error::RateLimitExceeded)));
+
+        let usage = service.usage_at(institution, OCTOBER);
+        assert_eq!(usage.period, 202410);
+        assert_eq!(usage.queries_used, 2);
+        assert_eq!(usage.queries_remaining, 0);
+    }
+
+    #[test]
+    fn new_period_resets_usage() {
+        let institution = Principal::from_slice(&[1]);
+        let mut service = service_with_allowance(1);
+
+        assert!(service.consume_query_at(institution, OCTOBER).is_ok());
+        assert!(service.consume_query_at(institution, OCTOBER).is_err());
+        assert_eq!(service.usage_at(institution, NOVEMBER).queries_used, 0);
+        assert!(service.consume_query_at(institution, NOVEMBER).is_ok());
+        assert_eq!(service.usage_at(institution, NOVEMBER).period, 202411);
+    }
+
+    #[test]
+    fn unknown_plan_falls_back_to_default() {
+        let mut service = service_with_allowance(5);
+        service.state.plans.remove("professional");
+        assert_eq!(service.plan_of("professional").monthly_query_allowance, 5);
+    }
+
+    #[test]
+    fn unpaid_period_falls_back_to_default_plan() {
+        let institution = Principal::from_slice(&[1]);
+        let mut service = service_with_allowance(1);
+        let professional = service.get_plan("professional").unwrap();
+        service.apply_plan(institution, &professional, OCTOBER).unwrap();
+
+        assert_eq!(service.usage_at(institution, OCTOBER).plan.id, "professional");
+        // 11 月未续费，按基础套餐计算额度，且需要续费
+        assert_eq!(service.usage_at(institution, NOVEMBER).plan.id, DEFAULT_PLAN_ID);
+        assert!(service.consume_query_at(institution, NOVEMBER).is_ok());
+        assert!(service.consume_query_at(institution, NOVEMBER).is_err());
+        let due = service.due_renewals(NOVEMBER);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].1.id, "professional");
+        assert!(service.due_renewals(OCTOBER).is_empty());
+    }
+
+    #[test]
+    fn rejects_switching_to_paid_current_plan() {
+        let institution = Principal::from_slice(&[1]);
+        let mut service = service_with_allowance(1);
+        assert!(service.check_change(institution, DEFAULT_PLAN_ID, OCTOBER).is_err());
+
+        let professional = service.check_change(institution, "professional", OCTOBER).unwrap();
+        service.apply_plan(institution, &professional, OCTOBER).unwrap();
+        assert!(service.check_change(institution, "professional", OCTOBER).is_err());
+        // 下个周期未付费时允许重新付费开通
+        assert!(service.check_change(institution, "professional", NOVEMBER).is_ok());
+    }
+
+    #[test]
+    fn apply_plan_revalidates_after_payment() {
+        let institution = Principal::from_slice(&[1]);
+        let mut service = service_with_allowance(1);
+        let professional = service.check_change(institution, "professional", OCTOBER).unwrap();
+        service.state.plans.remove("professional");
+
+        assert!(service.apply_plan(institution, &professional, OCTOBER).is_err());
+        assert_eq!(service.usage_at(institution, OCTOBER).plan.id, DEFAULT_PLAN_ID);
+    }
+}
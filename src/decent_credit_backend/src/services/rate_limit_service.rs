@@ -0,0 +1,320 @@
+use candid::Principal;
+use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use log::{info, warn};
+use crate::models::rate_limit::*;
+use crate::utils::auth::is_institution_caller;
+use crate::utils::error::Error;
+
+// 桶数量超过该值时清理已回满的桶
+const MAX_BUCKETS: usize = 10_000;
+const NANOS_PER_MINUTE: f64 = 60_000_000_000.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum BucketScope {
+    Principal,
+    Institution,
+    Anonymous,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: u64,
+}
+
+pub struct RateLimitService {
+    config: RateLimitConfig,
+    buckets: HashMap<(BucketScope, RateLimitCategory, Principal), Bucket>,
+}
+
+impl Default for RateLimitService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static RATE_LIMIT_SERVICE: RefCell<RateLimitService> = RefCell::new(RateLimitService::new());
+}
+
+impl RateLimitService {
+    pub fn new() -> Self {
+        Self {
+            config: RateLimitConfig::default(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn restore_config(&mut self, config: RateLimitConfig) {
+        info!("Restored rate limit config");
+        self.config = config;
+        self.buckets.clear();
+    }
+
+    pub fn get_config(&self) -> RateLimitConfig {
+        self.config.clone()
+    }
+
+    pub fn update_config(&mut self, config: RateLimitConfig) -> Result<(), String> {
+        let categories = [
+            RateLimitCategory::Submission,
+            RateLimitCategory::Query,
+            RateLimitCategory::Assessment,
+            RateLimitCategory::General,
+        ];
+        for category in categories {
+            let limits = config.limits_for(category);
+            if limits.per_principal.capacity == 0 || limits.per_institution.capacity == 0 {
+                return Err(format!("{:?} 限流容量必须大于0", category));
+            }
+            // 补充速率为0时令牌耗尽后永不恢复
+            if limits.per_principal.refill_per_minute == 0 || limits.per_institution.refill_per_minute == 0 {
+                return Err(format!("{:?} 限流补充速率必须大于0", category));
+            }
+        }
+        let anonymous = config.anonymous_limit();
+        if anonymous.capacity == 0 || anonymous.refill_per_minute == 0 {
+            return Err("匿名调用限流容量和补充速率必须大于0".to_string());
+        }
+        info!("Rate limit config updated: {:?}", config);
+        self.config = config;
+        // 新配置下从满桶重新开始
+        self.buckets.clear();
+        Ok(())
+    }
+
+    fn take(&mut self, scope: BucketScope, category: RateLimitCategory, key: Principal, limit: &BucketLimit, now: u64) -> bool {
+        if self.buckets.len() > MAX_BUCKETS {
+            self.prune(now);
+        }
+
+        let bucket = self.buckets.entry((scope, category, key)).or_insert(Bucket {
+            tokens: limit.capacity as f64,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_sub(bucket.last_refill) as f64;
+        let refill = elapsed * limit.refill_per_minute as f64 / NANOS_PER_MINUTE;
+        bucket.tokens = (bucket.tokens + refill).min(limit.capacity as f64);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // 清理长时间未使用、按配置早已回满的桶
+    fn prune(&mut self, now: u64) {
+        let config = &self.config;
+        let anonymous = config.anonymous_limit();
+        self.buckets.retain(|(scope, category, _), bucket| {
+            let limits = config.limits_for(*category);
+            let limit = match scope {
+                BucketScope::Principal => &limits.per_principal,
+                BucketScope::Institution => &limits.per_institution,
+                BucketScope::Anonymous => &anonymous,
+            };
+            let elapsed = now.saturating_sub(bucket.last_refill) as f64;
+            bucket.tokens + elapsed * limit.refill_per_minute as f64 / NANOS_PER_MINUTE < limit.capacity as f64
+        });
+    }
+
+    /// 按调用者限流
+    pub fn acquire_principal(&mut self, category: RateLimitCategory, caller: Principal) -> Result<(), Error> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let limit = self.config.limits_for(category).per_principal.clone();
+        if self.take(BucketScope::Principal, category, caller, &limit, time()) {
+            Ok(())
+        } else {
+            warn!("Rate limit exceeded for caller {} on {:?}", caller.to_text(), category);
+            Err(Error::RateLimitExceeded)
+        }
+    }
+
+    /// 按机构限流，同一机构的多个调用者共享额度
+    pub fn acquire_institution(&mut self, category: RateLimitCategory, institution_id: Principal) -> Result<(), Error> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let limit = self.config.limits_for(category).per_institution.clone();
+        if self.take(BucketScope::Institution, category, institution_id, &limit, time()) {
+            Ok(())
+        } else {
+            warn!("Rate limit exceeded for institution {} on {:?}", institution_id.to_text(), category);
+            Err(Error::RateLimitExceeded)
+        }
+    }
+
+    /// 所有匿名调用共享一个桶，在 API Key 认证之前限制网关的总请求量
+    pub fn acquire_anonymous(&mut self) -> Result<(), Error> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let limit = self.config.anonymous_limit();
+        if self.take(BucketScope::Anonymous, RateLimitCategory::General, Principal::anonymous(), &limit, time()) {
+            Ok(())
+        } else {
+            warn!("Rate limit exceeded for anonymous callers");
+            Err(Error::RateLimitExceeded)
+        }
+    }
+
+    fn is_exempt(&self, caller: Principal) -> bool {
+        self.config.exempt_controllers && ic_cdk::api::is_controller(&caller)
+    }
+}
+
+fn guard(category: RateLimitCategory) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    RATE_LIMIT_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        if service.is_exempt(caller) {
+            return Ok(());
+        }
+        service.acquire_principal(category, caller).map_err(|e| e.to_string())
+    })
+}
+
+/// 机构维度的限流，在接口内拿到机构ID后调用；
+/// 只有调用者确实可以代表该机构时才计入机构额度，避免他人冒用机构ID耗尽其额度（此时仅受调用者 guard 限制）
+pub fn check_institution(category: RateLimitCategory, institution_id: Principal) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if !is_institution_caller(caller, institution_id) {
+        return Ok(());
+    }
+    RATE_LIMIT_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        if service.is_exempt(caller) {
+            return Ok(());
+        }
+        service.acquire_institution(category, institution_id).map_err(|e| e.to_string())
+    })
+}
+
+/// 已通过 API Key 认证的机构限流（HTTP 网关的调用者为匿名主体）
+pub fn check_authenticated_institution(category: RateLimitCategory, institution_id: Principal) -> Result<(), String> {
+    RATE_LIMIT_SERVICE.with(|service| {
+        service.borrow_mut().acquire_institution(category, institution_id).map_err(|e| e.to_string())
+    })
+}
+
+// 更新接口的 guard，按调用者限流；超限的调用在执行前即被拒绝
+
+pub fn submission_guard() -> Result<(), String> {
+    guard(RateLimitCategory::Submission)
+}
+
+pub fn query_guard() -> Result<(), String> {
+    guard(RateLimitCategory::Query)
+}
+
+pub fn assessment_guard() -> Result<(), String> {
+    guard(RateLimitCategory::Assessment)
+}
+
+pub fn general_guard() -> Result<(), String> {
+    guard(RateLimitCategory::General)
+}
+
+/// HTTP 网关以匿名主体调用，匿名调用先计入共享的匿名桶，认证后再由各路由按 API Key 所属机构限流
+pub fn http_guard() -> Result<(), String> {
+    if ic_cdk::caller() == Principal::anonymous() {
+        return RATE_LIMIT_SERVICE.with(|service| {
+            service.borrow_mut().acquire_anonymous().map_err(|e| e.to_string())
+        });
+    }
+    guard(RateLimitCategory::General)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60_000_000_000;
+
+    fn limit(capacity: u64, refill_per_minute: u64) -> BucketLimit {
+        BucketLimit { capacity, refill_per_minute }
+    }
+
+    #[test]
+    fn bucket_allows_burst_up_to_capacity() {
+        let mut service = RateLimitService::new();
+        let key = Principal::from_slice(&[1]);
+        let limit = limit(3, 3);
+
+        for _ in 0..3 {
+            assert!(service.take(BucketScope::Principal, RateLimitCategory::Query, key, &limit, 0));
+        }
+        assert!(!service.take(BucketScope::Principal, RateLimitCategory::Query, key, &limit, 0));
+    }
+
+    #[test]
+    fn bucket_refills_over_time_without_exceeding_capacity() {
+        let mut service = RateLimitService::new();
+        let key = Principal::from_slice(&[1]);
+        let limit = limit(2, 60);
+
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, 0));
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, 0));
+        assert!(!service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, 0));
+
+        // 每分钟 60 个令牌，即每秒 1 个
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, MINUTE / 60));
+        assert!(!service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, MINUTE / 60));
+
+        // 空闲很久后最多回满到容量
+        let later = 10 * MINUTE;
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, later));
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, later));
+        assert!(!service.take(BucketScope::Principal, RateLimitCategory::General, key, &limit, later));
+    }
+
+    #[test]
+    fn buckets_are_separate_per_scope_category_and_key() {
+        let mut service = RateLimitService::new();
+        let alice = Principal::from_slice(&[1]);
+        let bob = Principal::from_slice(&[2]);
+        let limit = limit(1, 1);
+
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::Query, alice, &limit, 0));
+        assert!(!service.take(BucketScope::Principal, RateLimitCategory::Query, alice, &limit, 0));
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::Query, bob, &limit, 0));
+        assert!(service.take(BucketScope::Institution, RateLimitCategory::Query, alice, &limit, 0));
+        assert!(service.take(BucketScope::Principal, RateLimitCategory::Submission, alice, &limit, 0));
+    }
+
+    #[test]
+    fn prune_drops_only_refilled_buckets() {
+        let mut service = RateLimitService::new();
+        let capacity = service.config.general.per_principal.capacity;
+        let limit = service.config.general.per_principal.clone();
+        let busy = Principal::from_slice(&[1]);
+        let idle = Principal::from_slice(&[2]);
+
+        for _ in 0..capacity {
+            service.take(BucketScope::Principal, RateLimitCategory::General, busy, &limit, 10 * MINUTE);
+        }
+        service.take(BucketScope::Principal, RateLimitCategory::General, idle, &limit, 0);
+
+        service.prune(10 * MINUTE);
+        assert!(service.buckets.contains_key(&(BucketScope::Principal, RateLimitCategory::General, busy)));
+        assert!(!service.buckets.contains_key(&(BucketScope::Principal, RateLimitCategory::General, idle)));
+    }
+
+    #[test]
+    fn update_config_rejects_zero_refill() {
+        let mut service = RateLimitService::new();
+        let mut config = RateLimitConfig::default();
+        config.query.per_institution.refill_per_minute = 0;
+        assert!(service.update_config(config).is_err());
+        let config = RateLimitConfig { anonymous: Some(limit(10, 0)), ..RateLimitConfig::default() };
+        assert!(service.update_config(config).is_err());
+        assert!(service.update_config(RateLimitConfig::default()).is_ok());
+    }
+}
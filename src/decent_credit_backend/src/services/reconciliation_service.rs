@@ -0,0 +1,248 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use ic_cdk_timers::TimerId;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use log::{info, warn, error};
+use crate::models::reconciliation::*;
+use crate::services::admin_institution_service::ADMIN_SERVICE;
+use crate::services::token_service::*;
+
+// 保留的历史对账报告数量
+const MAX_REPORTS: usize = 30;
+const MIN_INTERVAL_SECS: u64 = 60;
+
+// 需要跨升级保存的对账状态
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct ReconciliationState {
+    pub config: ReconciliationConfig,
+    pub reports: Vec<ReconciliationReport>,
+    pub next_run_id: u64,
+    pub flagged: BTreeMap<Principal, FlaggedBalance>,
+}
+
+pub struct ReconciliationService {
+    state: ReconciliationState,
+    timer: Option<TimerId>,
+    running: bool,
+}
+
+impl Default for ReconciliationService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static RECONCILIATION_SERVICE: RefCell<ReconciliationService> = RefCell::new(ReconciliationService::new());
+}
+
+impl ReconciliationService {
+    pub fn new() -> Self {
+        Self {
+            state: ReconciliationState::default(),
+            timer: None,
+            running: false,
+        }
+    }
+
+    pub fn export_state(&self) -> ReconciliationState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: ReconciliationState) {
+        info!("Restored reconciliation state: {} reports", state.reports.len());
+        self.state = state;
+        self.running = false;
+    }
+
+    pub fn get_config(&self) -> ReconciliationConfig {
+        self.state.config.clone()
+    }
+
+    pub fn update_config(&mut self, config: ReconciliationConfig) -> Result<(), String> {
+        if config.interval_secs < MIN_INTERVAL_SECS {
+            return Err(format!("对账间隔不能小于 {} 秒", MIN_INTERVAL_SECS));
+        }
+        info!("Reconciliation config updated: {:?}", config);
+        self.state.config = config;
+        Ok(())
+    }
+
+    pub fn latest_report(&self) -> Option<ReconciliationReport> {
+        self.state.reports.last().cloned()
+    }
+
+    pub fn get_reports(&self, limit: usize) -> Vec<ReconciliationReport> {
+        self.state.reports.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// 标记本地余额不足以扣减的机构，差额累计到下一次对账
+    pub fn flag_shortfall(&mut self, institution_id: Principal, shortfall: u64, now: u64) {
+        let flag = self.state.flagged.entry(institution_id).or_insert(FlaggedBalance {
+            institution_id,
+            shortfall: 0,
+            flagged_at: now,
+        });
+        flag.shortfall = flag.shortfall.saturating_add(shortfall);
+        warn!("Institution {} flagged for reconciliation, shortfall {}", institution_id.to_text(), flag.shortfall);
+    }
+
+    pub fn get_flagged(&self) -> Vec<FlaggedBalance> {
+        self.state.flagged.values().cloned().collect()
+    }
+
+    fn clear_flag(&mut self, institution_id: &Principal) {
+        self.state.flagged.remove(institution_id);
+    }
+
+    fn push_report(&mut self, report: ReconciliationReport) {
+        self.state.reports.push(report);
+        if self.state.reports.len() > MAX_REPORTS {
+            let overflow = self.state.reports.len() - MAX_REPORTS;
+            self.state.reports.drain(..overflow);
+        }
+    }
+}
+
+fn difference(chain_balance: u64, local_balance: u64) -> i64 {
+    let diff = chain_balance as i128 - local_balance as i128;
+    diff.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// 逐个机构比较本地余额与链上 balance_of，按配置决定是否自动修正
+pub async fn run_reconciliation() -> Result<ReconciliationReport, String> {
+    let (run_id, auto_correct) = RECONCILIATION_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        if service.running {
+            return Err("对账任务正在执行中".to_string());
+        }
+        service.running = true;
+        service.state.next_run_id += 1;
+        Ok((service.state.next_run_id, service.state.config.auto_correct))
+    })?;
+
+    let started_at = time();
+    let token_canister_id = TOKEN_SERVICE.with(|service| service.borrow().token_canister_id);
+    let snapshot = ADMIN_SERVICE.with(|service| service.borrow().local_balances());
+
+    let mut discrepancies = Vec::new();
+    let mut errors = Vec::new();
+
+    for (id, name, _) in &snapshot {
+        let chain_balance = match TokenService::query_balance_static(token_canister_id, *id).await {
+            Ok(balance) => balance,
+            Err(e) => {
+                warn!("Reconciliation skipped {}: {}", id.to_text(), e);
+                errors.push(ReconciliationError { institution_id: *id, message: e });
+                continue;
+            }
+        };
+
+        // 已与链上余额核对，之前标记的差额随本次结果处理
+        RECONCILIATION_SERVICE.with(|service| service.borrow_mut().clear_flag(id));
+
+        // 等待链上查询期间本地余额可能已变化，以当前值为准
+        let local_balance = match ADMIN_SERVICE.with(|service| {
+            service.borrow().get_institution(*id).map(|inst| inst.balance)
+        }) {
+            Some(balance) => balance,
+            None => continue,
+        };
+        if local_balance == chain_balance {
+            continue;
+        }
+
+        let corrected = auto_correct && ADMIN_SERVICE.with(|service| {
+            service.borrow_mut().correct_local_balance(*id, chain_balance).is_ok()
+        });
+        warn!(
+            "Balance discrepancy for {}: local={}, chain={}, corrected={}",
+            id.to_text(), local_balance, chain_balance, corrected
+        );
+        discrepancies.push(BalanceDiscrepancy {
+            institution_id: *id,
+            institution_name: name.clone(),
+            local_balance,
+            chain_balance,
+            difference: difference(chain_balance, local_balance),
+            corrected,
+            detected_at: time(),
+        });
+    }
+
+    let report = ReconciliationReport {
+        run_id,
+        started_at,
+        finished_at: time(),
+        institutions_checked: snapshot.len() as u64,
+        discrepancies,
+        errors,
+        auto_corrected: auto_correct,
+    };
+    info!(
+        "Reconciliation run {} finished: {} checked, {} discrepancies, {} errors",
+        run_id, report.institutions_checked, report.discrepancies.len(), report.errors.len()
+    );
+
+    RECONCILIATION_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        service.push_report(report.clone());
+        service.running = false;
+    });
+    Ok(report)
+}
+
+/// 按当前配置（重新）启动定时对账
+pub fn init_reconciliation_timer() {
+    RECONCILIATION_SERVICE.with(|service| {
+        let mut service = service.borrow_mut();
+        if let Some(timer) = service.timer.take() {
+            ic_cdk_timers::clear_timer(timer);
+        }
+
+        let config = service.state.config.clone();
+        if !config.enabled {
+            info!("Reconciliation timer disabled");
+            return;
+        }
+
+        service.timer = Some(ic_cdk_timers::set_timer_interval(Duration::from_secs(config.interval_secs), || {
+            ic_cdk::spawn(async {
+                if let Err(e) = run_reconciliation().await {
+                    error!("Scheduled reconciliation failed: {}", e);
+                }
+            });
+        }));
+        info!("Reconciliation timer started, interval {}s", config.interval_secs);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difference_is_chain_minus_local() {
+        assert_eq!(difference(150, 100), 50);
+        assert_eq!(difference(100, 150), -50);
+        assert_eq!(difference(u64::MAX, 0), i64::MAX);
+    }
+
+    #[test]
+    fn shortfalls_accumulate_until_cleared() {
+        let institution = Principal::from_slice(&[1]);
+        let mut service = ReconciliationService::new();
+        service.flag_shortfall(institution, 30, 10);
+        service.flag_shortfall(institution, 20, 20);
+
+        let flagged = service.get_flagged();
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].shortfall, 50);
+        assert_eq!(flagged[0].flagged_at, 10);
+
+        service.clear_flag(&institution);
+        assert!(service.get_flagged().is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use candid::{CandidType, Principal};
+use candid::{CandidType, Deserialize, Principal};
 use ic_cdk::api::{call, time};
 use std::collections::HashMap;
 use std::cell::RefCell;
@@ -10,10 +10,16 @@ use crate::services::zk_proof_service::ZKProofService;
 use crate::services::admin_institution_service::ADMIN_SERVICE;  // 移到顶部
 use crate::utils::error::Error;
 use crate::services::dashboard_service::DASHBOARD_SERVICE;
-use crate::services::token_service::*;
 use crate::services::credit_service::*;
 
 use crate::models::record::*;
+use crate::models::billing::QueryCharge;
+use crate::models::integrity::{DetectedIssue, IntegrityIssueType};
+use crate::models::institution_score::ScoreChangeReason;
+use crate::services::pricing_service::PRICING_SERVICE;
+use crate::services::validation_service::VALIDATION_SERVICE;
+use crate::services::integrity_service::{self as integrity, INTEGRITY_SERVICE};
+use crate::services::institution_score_service;
 
 thread_local! {
     pub static RECORD_SERVICE: RefCell<RecordService> = RefCell::new(
@@ -33,8 +39,19 @@ pub struct RecordService {
     crypto_service:CryptoService,
     credit_records: Vec<CreditRecord>,
     deduction_records: Vec<CreditDeductionRecord>,
+    next_deduction_id: u64,
     institution_records: HashMap<Principal, Vec<CreditRecord>>,
     upload_history: Vec<UploadRecord>,
+    fingerprints: HashMap<String, Vec<String>>,  // 记录指纹 -> 记录ID，用于重复检测
+}
+
+pub const DUPLICATE_RECORD_CODE: &str = "duplicate_record";
+
+// 需要跨升级保存的扣分记录，申诉和分数历史按扣分ID引用它们
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct DeductionState {
+    pub records: Vec<CreditDeductionRecord>,
+    pub next_deduction_id: u64,
 }
 
 impl RecordService {
@@ -49,8 +66,10 @@ impl RecordService {
             zk_service: ZKProofService::new(),
             credit_records: Vec::new(),
             deduction_records: Vec::new(),
+            next_deduction_id: 0,
             institution_records: HashMap::new(),
             upload_history: Vec::new(),
+            fingerprints: HashMap::new(),
         }
     }
     pub fn get_record_statistics(
@@ -76,13 +95,29 @@ impl RecordService {
 
     
     pub fn submit_record(&mut self, request: RecordSubmissionRequest) -> Result<String, Error> {
+        self.submit_record_from(request, None, false)
+    }
+
+    /// 批量导入的记录，标记为历史数据，不计入迟报统计
+    pub fn import_record(&mut self, request: RecordSubmissionRequest) -> Result<String, Error> {
+        self.submit_record_from(request, None, true)
+    }
+
+    // resubmitted_from 为修正重提时对应的原失败记录，imported 标记批量导入的记录
+    fn submit_record_from(
+        &mut self,
+        request: RecordSubmissionRequest,
+        resubmitted_from: Option<String>,
+        imported: bool
+    ) -> Result<String, Error> {
         // 校验内容
         self.validate_record_content(
             &request.record_type,
             &request.content,
             request.institution_id,
             request.user_did.clone(),
-            request.event_date.clone()
+            request.event_date.clone(),
+            resubmitted_from.clone()
         )?;
      // 获取机构信息并提取机构名称
         let institution = ADMIN_SERVICE.with(|service| {
@@ -106,6 +141,28 @@ impl RecordService {
         }).map_err(|e| Error::EncryptionFailed(format!("Failed to encrypt: {:?}", e)))?;
 
         let proof = self.zk_service.generate_proof(&content_bytes);
+
+        // 其他机构的重复记录和与贷款状态的冲突在提交后进入审核队列
+        let fingerprint = integrity::record_fingerprint(
+            &request.record_type, &request.user_did, &request.event_date, &request.content
+        );
+        let mut issues = Vec::new();
+        let duplicates = self.find_duplicates(&fingerprint);
+        if !duplicates.is_empty() {
+            let mut related_institutions: Vec<Principal> = duplicates.iter().map(|r| r.institution_id).collect();
+            related_institutions.sort();
+            related_institutions.dedup();
+            issues.push(DetectedIssue {
+                issue: IntegrityIssueType::Duplicate,
+                related_record_ids: duplicates.iter().map(|r| r.id.clone()).collect(),
+                related_institutions,
+                details: format!("与 {} 条其他机构提交的记录指纹相同", duplicates.len()),
+            });
+        }
+        let user_records = self.records.values().filter(|r| r.user_did == request.user_did);
+        issues.extend(integrity::detect_conflict(user_records, &request.content));
+        // 被标记的记录在审核结论前不参与评分
+        let flagged = !issues.is_empty();
         
             // 在使用 encrypted_content 之前先克隆一份
         let encrypted_content_for_storage = encrypted_content.clone();
@@ -125,16 +182,31 @@ impl RecordService {
                 timestamp: time(),
                 status: RecordStatus::Pending,
                 reward_amount: None, // 初始时没有奖励,
-                query_price:institution.query_price.clone()
+                query_price: institution.query_price,
+                rejection_reason: None,
+                resubmitted_from,
+                resubmitted_as: None,
+                imported: Some(imported)
             };
         
             // 存储记录
             self.records.insert(record_id.clone(), record.clone());
-
-            CREDIT_SERVICE.with(|service| {
-                let mut credit_service = service.borrow_mut();
-                credit_service.records.insert(record_id.clone(), record.clone());
+            self.fingerprints.entry(fingerprint.clone()).or_default().push(record_id.clone());
+            INTEGRITY_SERVICE.with(|service| {
+                let mut service = service.borrow_mut();
+                for issue in issues {
+                    service.open_case(&record, fingerprint.clone(), issue);
+                }
             });
+
+            if flagged {
+                info!("Record {} held out of scoring pending review", record_id);
+            } else {
+                CREDIT_SERVICE.with(|service| {
+                    let mut credit_service = service.borrow_mut();
+                    credit_service.records.insert(record_id.clone(), record.clone());
+                });
+            }
             // 存储到服务中
             let storage_id = with_storage_service(|service| {
                 service.store_data(encrypted_content_for_storage)  
@@ -225,6 +297,12 @@ impl RecordService {
         } else {
             // 更新为拒绝状态
             record.status = RecordStatus::Rejected;
+            record.rejection_reason = Some(RejectionReason {
+                code: "proof_verification_failed".to_string(),
+                field: None,
+                message: "Proof verification failed".to_string(),
+                rejected_at: time()
+            });
             
             // 记录拒绝原因
             ic_cdk::println!(
@@ -310,78 +388,80 @@ impl RecordService {
         None
     }
     
-    pub async fn process_token_operation(op: TokenOperation) -> Result<(), String> {
-        info!("Starting token operation processing for user: {}", op.user_did);
-        
-        let TokenOperation {
-            from_id,
-            to_id,
-            user_did,
-            query_price,
-            record: _record,  // 添加下划线避免警告
-        } = op;
-    
-        // 1. 获取 token canister id
-        let token_canister_id = TOKEN_SERVICE.with(|service| {
-            service.borrow().token_canister_id
-        });
-    
-        // 2. 检查余额 - 修改异步调用方式
-        let balance = TOKEN_SERVICE.with(|service| {
-            let token_service = service.borrow();
-            TokenService::query_balance_static(token_canister_id, op.from_id)
-        }).await?;
+    /// 查询前报价：按数据所有方汇总需支付的查询费，不解密任何数据
+    pub fn quote_user_records(
+        &self,
+        institution_id: Principal,
+        user_did: &str
+    ) -> Result<Vec<QueryCharge>, String> {
+        let mut charges: HashMap<Principal, QueryCharge> = HashMap::new();
 
-  
-        
-    
-        if balance < query_price {
-            info!("余额不足，跳过转账: 当前余额 {}, 需要 {}", balance, query_price);
+        for record in self.records.values().filter(|r| r.user_did == user_did) {
+            if record.institution_id == institution_id {
+                continue;
+            }
+            let target_institution = ADMIN_SERVICE.with(|service| {
+                service.borrow().get_institution(record.institution_id)
+                    .ok_or_else(|| "机构不存在".to_string())
+            })?;
+
+            if !target_institution.data_service_enabled {
+                return Err(format!(
+                    "机构 {} 未开启数据服务",
+                    target_institution.name
+                ));
+            }
+
+            let item = PRICING_SERVICE.with(|service| {
+                service.borrow().quote_record(institution_id, target_institution.query_price, record)
+            });
+            let charge = charges.entry(record.institution_id).or_insert_with(|| QueryCharge {
+                payee: record.institution_id,
+                amount: 0,
+                record_ids: Vec::new(),
+                items: Vec::new(),
+            });
+            charge.amount = charge.amount.saturating_add(item.price);
+            charge.record_ids.push(record.id.clone());
+            charge.items.push(item);
         }
-    
-        // 3. 准备费用转账参数
-        let fee_transfer_args = TOKEN_SERVICE.with(|service| {
-            let mut service = service.borrow_mut();
-            service.prepare_query_fee(
-                from_id, 
-                to_id, 
-                user_did.clone(), 
-                query_price
-            ).map_err(|e| format!("准备转账参数失败: {:?}", e))
-        })?;
-    
-        // 4. 执行费用转账
-        info!("Executing fee transfer...");
-        TokenService::execute_transfer(token_canister_id, fee_transfer_args)
-            .await
-            .map_err(|e| format!("费用转账失败: {:?}", e))?;
-    
-        // 5. 准备奖励转账参数
-        let reward_transfer_args = TOKEN_SERVICE.with(|service| {
-            let mut service = service.borrow_mut();
-            service.prepare_query_reward(
-                to_id,
-                user_did.clone()
-            ).map_err(|e| format!("准备奖励参数失败: {:?}", e))
+
+        Ok(charges.into_values().collect())
+    }
+
+    /// 单条记录报价，返回记录所属的用户DID和应付费用（查询自己的记录无需付费）
+    pub fn quote_record(
+        &self,
+        record_id: &str,
+        institution_id: Principal
+    ) -> Result<(String, Option<QueryCharge>), String> {
+        let record = self.records.get(record_id)
+            .ok_or_else(|| format!("Record with ID {} not found or access denied.", record_id))?;
+
+        if record.institution_id == institution_id {
+            return Ok((record.user_did.clone(), None));
+        }
+
+        let target_institution = ADMIN_SERVICE.with(|service| {
+            service.borrow().get_institution(record.institution_id)
+                .ok_or_else(|| "机构不存在".to_string())
         })?;
-    
-        // 6. 如果有奖励，执行奖励转账
-        if let Some(reward_args) = reward_transfer_args {
-            info!("Executing rewards transfer...");
-            TokenService::execute_transfer(token_canister_id, reward_args)
-                .await
-                .map_err(|e| format!("奖励转账失败: {:?}", e))?;
-        } else {
-            info!("No rewards transfer needed");
+
+        if !target_institution.data_service_enabled {
+            return Err(format!("机构 {} 未开启数据服务", target_institution.name));
         }
-    
-        info!("Token operation completed successfully");
-        Ok(())
+
+        let item = PRICING_SERVICE.with(|service| {
+            service.borrow().quote_record(institution_id, target_institution.query_price, record)
+        });
+        Ok((record.user_did.clone(), Some(QueryCharge {
+            payee: record.institution_id,
+            amount: item.price,
+            record_ids: vec![record.id.clone()],
+            items: vec![item],
+        })))
     }
-        
-    
-    
-    
+
     pub fn get_record_userId(
             &mut self, 
             institution_id: Principal,
@@ -427,15 +507,20 @@ impl RecordService {
                                     service.increment_inbound_queries(record.institution_id);
                                 });
         
+                                // 按定价表计算该记录的实际价格
+                                let price = PRICING_SERVICE.with(|service| {
+                                    service.borrow().quote_record(institution_id, target_institution.query_price, &record).price
+                                });
+
                                 // 收集代币操作信息
                                 token_operations.push(TokenOperation {
                                     from_id: institution_id,
                                     to_id: record.institution_id,
                                     user_did: user_did.clone(),
-                                    query_price: target_institution.query_price,
+                                    query_price: price,
                                     record: record.clone(),
                                 });
-                                record.query_price  = target_institution.query_price;
+                                record.query_price  = price;
                                 result.push(record);
                             }else{
                                 record.query_price  =0;
@@ -478,8 +563,11 @@ impl RecordService {
         let mut records = Vec::new();
         
         // 2. 从本地缓存获取记录
+        // 已修正重提的失败记录不再列出
         let local_records: Vec<&CreditRecord> = self.records.values()
-            .filter(|r| r.institution_id == institution_id)
+            .filter(|r| r.institution_id == institution_id
+                && r.status == RecordStatus::Rejected
+                && r.resubmitted_as.is_none())
             .collect();
             info!("Fetching records for local_records: {}", local_records.len());
 
@@ -518,38 +606,50 @@ impl RecordService {
         content: &RecordContent,
         institution_id: Principal,
         user_did: String,
-        event_date: String
+        event_date: String,
+        resubmitted_from: Option<String>
     ) -> Result<(), Error> {
-        // 1. 首先进行基础验证
-        let validation_result = match (record_type, content) {
-            (RecordType::LoanRecord, RecordContent::Loan(loan)) => {
-                if loan.amount == 0 || loan.loan_id.is_empty() || loan.term_months == 0 {
-                    Err("Invalid loan data: missing required fields")
-                } else if loan.interest_rate <= 0.0 || loan.interest_rate > 100.0 {
-                    Err("Invalid loan data: interest rate out of range")
-                } else {
-                    Ok(())
-                }
-            },
-            (RecordType::RepaymentRecord, RecordContent::Repayment(repayment)) => {
-                if repayment.amount == 0 || repayment.loan_id.is_empty() {
-                    Err("Invalid repayment data: missing required fields")
-                } else {
-                    Ok(())
-                }
-            },
-            (RecordType::OverdueRecord, RecordContent::Overdue(overdue)) => {
-                if overdue.amount == 0 || overdue.overdueDays == 0 {
-                    Err("Invalid overdue data: missing required fields")
-                } else {
-                    Ok(())
-                }
-            },
-            _ => Err("Record type mismatch")
+        // 1. 类型与内容须一致，其余检查由所属辖区的校验规则完成
+        let type_matches = matches!(
+            (record_type, content),
+            (RecordType::LoanRecord, RecordContent::Loan(_))
+                | (RecordType::RepaymentRecord, RecordContent::Repayment(_))
+                | (RecordType::OverdueRecord, RecordContent::Overdue(_))
+        );
+        let validation_result = if type_matches {
+            let violations = VALIDATION_SERVICE.with(|service| {
+                service.borrow().evaluate(institution_id, record_type, content, &user_did, &event_date)
+            });
+            match violations.first() {
+                None => Ok(()),
+                Some(first) => Err((
+                    first.rule_id.clone(),
+                    Some(first.field.as_str().to_string()),
+                    violations.iter()
+                        .map(|v| format!("[{}] {}", v.rule_id, v.message))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                )),
+            }
+        } else {
+            Err(("record_type_mismatch".to_string(), Some("content".to_string()), "Record type mismatch".to_string()))
         };
+
+        // 同一机构重复提交相同指纹的记录直接拒绝
+        let validation_result = validation_result.and_then(|_| {
+            let fingerprint = integrity::record_fingerprint(record_type, &user_did, &event_date, content);
+            match self.find_duplicates(&fingerprint).iter().find(|r| r.institution_id == institution_id) {
+                Some(existing) => Err((
+                    DUPLICATE_RECORD_CODE.to_string(),
+                    None,
+                    format!("[{}] 与已提交的记录 {} 重复", DUPLICATE_RECORD_CODE, existing.id)
+                )),
+                None => Ok(()),
+            }
+        });
     
         // 2. 如果验证失败，创建失败记录并存储
-        if let Err(error_msg) = validation_result {
+        if let Err((code, field, error_msg)) = validation_result {
             // 获取机构信息
             let institution = ADMIN_SERVICE.with(|service| {
                 let service = service.borrow();
@@ -585,7 +685,16 @@ impl RecordService {
                 timestamp: time(),
                 status: RecordStatus::Rejected,
                 reward_amount: None,
-                query_price: 0
+                query_price: 0,
+                rejection_reason: Some(RejectionReason {
+                    code,
+                    field,
+                    message: error_msg.clone(),
+                    rejected_at: time()
+                }),
+                resubmitted_from,
+                resubmitted_as: None,
+                imported: None
             };
     
             // 保存记录到本地和链上
@@ -599,19 +708,116 @@ impl RecordService {
                 service.store_on_chain(record_id, storage_id, proof)
             }).map_err(|_| Error::StorageFailed)?;
     
-            // 记录API调用
-            ADMIN_SERVICE.with(|service| {
-                let mut service = service.borrow_mut();
-                service.institution_record_data_upload(institution_id, 1);
-            });
+            // 失败记录不计入机构的数据上传量；重复提交只计入数据质量的重复率，不计入违规次数
     
             // 返回验证错误
-            return Err(Error::ValidationError(error_msg.to_string()));
+            return Err(Error::ValidationError(error_msg));
         }
     
         // 3. 验证通过
         Ok(())
     }
+
+    /// 修正失败记录的内容后重新提交，新记录与原失败记录互相关联
+    pub fn resubmit_failed_record(
+        &mut self,
+        record_id: &str,
+        corrected_content: RecordContent
+    ) -> Result<String, Error> {
+        let original = self.records.get(record_id)
+            .ok_or(Error::RecordNotFound)?;
+        if original.status != RecordStatus::Rejected {
+            return Err(Error::InvalidStatus);
+        }
+        if let Some(new_id) = &original.resubmitted_as {
+            return Err(Error::InvalidData(format!("记录已修正重提为 {}", new_id)));
+        }
+
+        let request = RecordSubmissionRequest {
+            institution_id: original.institution_id,
+            record_type: original.record_type.clone(),
+            user_did: original.user_did.clone(),
+            event_date: original.event_date.clone(),
+            content: corrected_content
+        };
+        let new_id = match self.submit_record_from(request, Some(record_id.to_string()), false) {
+            Ok(new_id) => new_id,
+            Err(e) => {
+                // 再次校验失败时同样生成了新的失败记录，关联到原记录，之后应修正新的失败记录
+                let failed_id = self.records.values()
+                    .filter(|r| r.resubmitted_from.as_deref() == Some(record_id) && r.status == RecordStatus::Rejected)
+                    .max_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.id.cmp(&b.id)))
+                    .map(|r| r.id.clone());
+                let Some(failed_id) = failed_id else {
+                    return Err(e);
+                };
+                if let Some(original) = self.records.get_mut(record_id) {
+                    original.resubmitted_as = Some(failed_id.clone());
+                }
+                warn!("Resubmission of {} failed again as {}", record_id, failed_id);
+                return Err(match e {
+                    Error::ValidationError(message) => Error::ValidationError(
+                        format!("{}（已生成新的失败记录 {}，请修正该记录后重提）", message, failed_id)
+                    ),
+                    other => other,
+                });
+            }
+        };
+
+        if let Some(original) = self.records.get_mut(record_id) {
+            original.resubmitted_as = Some(new_id.clone());
+        }
+        info!("Failed record {} resubmitted as {}", record_id, new_id);
+        Ok(new_id)
+    }
+
+    // 指纹相同且未被拒绝的已有记录
+    fn find_duplicates(&self, fingerprint: &str) -> Vec<&CreditRecord> {
+        self.fingerprints.get(fingerprint)
+            .map(|ids| ids.iter()
+                .filter_map(|id| self.records.get(id))
+                .filter(|r| r.status != RecordStatus::Rejected)
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// 审核确认问题后驳回记录，并从评分数据中移除
+    pub fn reject_record(&mut self, record_id: &str, reason: RejectionReason) -> Result<(), Error> {
+        let record = self.records.get_mut(record_id)
+            .ok_or(Error::RecordNotFound)?;
+        record.status = RecordStatus::Rejected;
+        record.rejection_reason = Some(reason);
+
+        CREDIT_SERVICE.with(|service| {
+            service.borrow_mut().records.remove(record_id);
+        });
+        warn!("Record {} rejected after review", record_id);
+        Ok(())
+    }
+
+    /// 审核认定问题不成立后，把暂扣的记录纳入评分数据；已驳回的记录不纳入
+    pub fn admit_record(&mut self, record_id: &str) -> Result<bool, Error> {
+        let record = self.records.get(record_id)
+            .ok_or(Error::RecordNotFound)?;
+        if record.status == RecordStatus::Rejected {
+            return Ok(false);
+        }
+
+        CREDIT_SERVICE.with(|service| {
+            service.borrow_mut().records.insert(record_id.to_string(), record.clone());
+        });
+        info!("Record {} admitted to scoring after review", record_id);
+        Ok(true)
+    }
+
+    pub fn get_record_institution(&self, record_id: &str) -> Option<Principal> {
+        self.records.get(record_id).map(|r| r.institution_id)
+    }
+
+    /// 机构是否提交过该用户的记录
+    pub fn has_user_records(&self, institution_id: Principal, user_did: &str) -> bool {
+        self.records.values().any(|r| r.institution_id == institution_id && r.user_did == user_did)
+    }
     // store_encrypted_data 方法的实现
     pub async fn store_encrypted_data(&self, data: Vec<u8>) -> Result<String, Error> {
         let result: Result<(String,), _> = call(
@@ -732,28 +938,30 @@ impl RecordService {
         let current_score = institution.credit_score.score;
         debug!("Current credit score: {}", current_score);
     
-        // 2. 检查扣分后是否会小于0
-        if current_score < request.deduction_points as u64 {
-            error!(
-                "Deduction points ({}) exceed current credit score ({})",
+        // 2. 扣分超过当前分数时扣至0分
+        let applied_points = (request.deduction_points as u64).min(current_score) as u32;
+        if applied_points < request.deduction_points {
+            warn!(
+                "Deduction points ({}) exceed current credit score ({}), deducting {}",
                 request.deduction_points,
-                current_score
+                current_score,
+                applied_points
             );
-            return Err(format!(
-                "Deduction points ({}) exceed current credit score ({})",
-                request.deduction_points,
-                current_score
-            ));
         }
-    
-        // 3. 更新机构分数
-        let new_score = current_score - request.deduction_points as u64;
+
+        // 3. 更新机构分数并记录分数变更
+        // 扣分ID单调递增并随扣分记录一起持久化，带前缀以免与旧版本按序号生成的ID重复
+        self.next_deduction_id += 1;
+        let deduction_id = format!("DED-{}", self.next_deduction_id);
+        let new_score = current_score - applied_points as u64;
         info!("Updating credit score from {} to {}", current_score, new_score);
         
-        match ADMIN_SERVICE.with(|service| {
-            let mut service = service.borrow_mut();
-            service.update_credit_score(request.institution_id, new_score)
-        }) {
+        match institution_score_service::change_score(
+            request.institution_id,
+            new_score,
+            ScoreChangeReason::Deduction,
+            Some(deduction_id.clone())
+        ) {
             Ok(_) => {
                 info!("Successfully updated credit score");
             },
@@ -764,21 +972,22 @@ impl RecordService {
         }
     
         // 4. 创建扣分记录
-        debug!("Creating deduction record with ID: {}", self.deduction_records.len() + 1);
+        debug!("Creating deduction record with ID: {}", deduction_id);
         let record = CreditDeductionRecord {
-            id: format!("{}", self.deduction_records.len() + 1),
+            id: deduction_id,
             record_id: format!("CR{}{:03}",
                 time() / 1_000_000_000,
-                self.deduction_records.len() + 1
+                self.next_deduction_id
             ),
             institution_id: request.institution_id,
             institution_name: institution.name.clone(),
-            deduction_points: request.deduction_points,
+            deduction_points: applied_points,
             reason: request.reason.clone(),
             data_quality_issue: request.data_quality_issue.clone(),
             created_at: time(),
             operator_id: operator,
-            operator_name: "Administrator".to_string(),
+            operator_name: if operator == ic_cdk::id() { "System" } else { "Administrator" }.to_string(),
+            reversed_at: None,
         };
     
         // 5. 保存记录
@@ -789,36 +998,38 @@ impl RecordService {
         
         Ok(record)
     }
-    // 修改调用奖励的地方
-pub fn reward_query_token(
-    &mut self,
-    query_institution_id: Principal,  // 查询方机构
-    record: CreditRecord,
-    user_did: String
-) -> Result<bool, String> {
-    // 获取目标机构的查询价格
-    let target_institution = ADMIN_SERVICE.with(|service| {
-        service.borrow().get_institution(record.institution_id)
-    }).ok_or_else(|| "机构不存在".to_string())?;
-
-    let token_op = TokenOperation {
-        from_id: query_institution_id,  // 查询方为支付方
-        to_id: record.institution_id,    // 被查询方为接收方
-        user_did: user_did.clone(),
-        query_price: target_institution.query_price,  // 使用正确的查询价格
-        record: record.clone(),
-    };
-
-    // 异步处理代币操作
-    ic_cdk::spawn(async move {
-        if let Err(e) = Self::process_token_operation(token_op).await {
-            error!("代币操作处理失败: {}", e);
+
+    pub fn export_deductions(&self) -> DeductionState {
+        DeductionState {
+            records: self.deduction_records.clone(),
+            next_deduction_id: self.next_deduction_id,
         }
-    });
+    }
 
-    Ok(true)
-}
+    pub fn restore_deductions(&mut self, state: DeductionState) {
+        info!("Restored {} deduction records", state.records.len());
+        self.deduction_records = state.records;
+        self.next_deduction_id = state.next_deduction_id;
+    }
 
+    pub fn get_deduction_record(&self, deduction_id: &str) -> Option<CreditDeductionRecord> {
+        self.deduction_records.iter()
+            .find(|record| record.id == deduction_id)
+            .cloned()
+    }
+
+    /// 申诉通过后撤销扣分记录，分数由调用方恢复
+    pub fn reverse_deduction(&mut self, deduction_id: &str) -> Result<CreditDeductionRecord, String> {
+        let record = self.deduction_records.iter_mut()
+            .find(|record| record.id == deduction_id)
+            .ok_or_else(|| format!("扣分记录不存在: {}", deduction_id))?;
+        if record.reversed_at.is_some() {
+            return Err("该扣分已被撤销".to_string());
+        }
+        record.reversed_at = Some(time());
+        info!("Deduction {} of {} reversed", deduction_id, record.institution_id.to_text());
+        Ok(record.clone())
+    }
     pub fn get_deduction_records(&self, institution_id: Option<Principal>) -> Vec<CreditDeductionRecord> {
         match institution_id {
             Some(id) => self.deduction_records
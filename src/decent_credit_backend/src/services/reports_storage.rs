@@ -1,18 +1,21 @@
 use candid::{CandidType, Deserialize, Principal};
 use ic_cdk::api::time;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::borrow::Borrow;
-use log::{info, warn, error};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use log::{info, debug};
 use crate::models::credit::*;
-use crate::services::storage_service::*;
-use crate::services::crypto_service::*;
 
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+// 单页最多返回的报告数
+const MAX_PAGE_SIZE: u64 = 100;
+
+// 报告只保存一份，按 report_id 索引，并维护机构到报告的索引
 #[derive(CandidType, Deserialize, Default, Clone)]
 pub struct ReportsStorage {
-   reports: HashMap<Principal, Vec<RiskAssessmentReport>>,
-   stored_data: HashMap<String, Vec<u8>>,
-   chain_data: HashMap<String, (String, Vec<u8>)>,
+   reports: BTreeMap<String, RiskAssessmentReport>,
+   institution_index: HashMap<Principal, Vec<String>>,
+   next_id: u64,
    version: u32,  // 添加版本控制
 }
 
@@ -21,144 +24,116 @@ thread_local! {
 }
 
 impl ReportsStorage {
-   pub fn save_state(&self) -> Result<(), String> {
-       match ic_cdk::storage::stable_save((&*self,)) {
-           Ok(_) => {
-               info!("Successfully saved ReportsStorage state");
-               Ok(())
-           },
-           Err(e) => {
-               error!("Failed to save ReportsStorage state: {:?}", e);
-               Err(format!("Failed to save state: {:?}", e))
-           }
-       }
+   pub fn export_state(&self) -> ReportsStorage {
+       self.clone()
    }
 
-   // 辅助函数：计算数据哈希
-   fn calculate_hash(data: &[u8]) -> Vec<u8> {
-       use sha2::{Sha256, Digest};
-       let mut hasher = Sha256::new();
-       hasher.update(data);
-       hasher.finalize().to_vec()
-   }
-
-   // 辅助函数：验证数据完整性
-   fn verify_data_integrity(&self, original: &[u8], stored: &[u8]) -> bool {
-       let original_hash = Self::calculate_hash(original);
-       let stored_hash = Self::calculate_hash(stored);
-       original_hash == stored_hash
-   }
-
-   // 专门用于存储报告的方法
-   fn store_report_data(&mut self, data: Vec<u8>) -> Result<String, String> {
-       let id = format!("report-storage-{}", time());
-       self.stored_data.insert(id.clone(), data);
-       info!("Stored report data with ID: {}", id);
-       Ok(id)
-   }
-
-   // 专门用于链上存储报告的方法
-   fn store_report_on_chain(&mut self, report_id: String, storage_id: String, data: Vec<u8>) -> Result<(), String> {
-       let key = format!("REPORT-{}", report_id);
-       self.chain_data.insert(key.clone(), (storage_id.clone(), data));
-       info!("Stored report chain data for report: {}, storage: {}", report_id, storage_id);
-       Ok(())
-   }
-
-   fn get_all_chain_data(&self) -> Vec<(String, String, Vec<u8>)> {
-       self.chain_data
-           .iter()
-           .map(|(record_id, (storage_id, data))| {
-               (record_id.clone(), storage_id.clone(), data.clone())
-           })
-           .collect()
-   }
-
-   pub fn store_report(&mut self, institution_id: Principal, report: RiskAssessmentReport) -> Result<String, String> {
-       info!("Starting to store report for institution: {}", institution_id.to_text());
-       
-       // 序列化报告数据
-       let report_bytes = candid::encode_one(&report)
-           .map_err(|e| format!("Failed to serialize report: {}", e))?;
-       
-       // 本地存储
-       let reports = self.reports.entry(institution_id).or_insert_with(Vec::new);
-       reports.push(report.clone());
-       
-       // 存储序列化数据
-       let storage_id = self.store_report_data(report_bytes.clone())?;
-       
-       // 存储到链上
-       self.store_report_on_chain(
-           report.report_id.clone(), 
-           storage_id.clone(),
-           report_bytes
-       )?;
-       
-       Ok(storage_id)
-   }
-   
-   pub fn query_reports(&self, institution_id: Principal) -> Vec<RiskAssessmentReport> {
-    info!("Starting query reports for institution: {}", institution_id.to_text());
-    
-    let mut all_reports = Vec::new();
-    
-    // 1. 从本地存储获取
-    if let Some(local_reports) = self.reports.get(&institution_id) {
-        all_reports.extend(local_reports.clone());
-        info!("Found {} reports in local storage", local_reports.len());
-    }
-    
-    // 2. 从存储服务和链上获取
-    for (record_key, storage_id, data) in self.get_all_chain_data() {
-        info!("Processing record: {}, storage_id: {}", record_key, storage_id);
-        
-        // 尝试从 chain_data 解析
-        match candid::decode_one::<RiskAssessmentReport>(&data) {
-            Ok(report) => {
-                if report.institution_id == institution_id 
-                   && !all_reports.iter().any(|r| r.report_id == report.report_id) {
-                    info!("Found report from chain data: {}", report.report_id);
-                    all_reports.push(report);
-                }
-            },
-            Err(_) => {
-                // 如果直接解析失败，尝试从 stored_data 获取
-                if let Some(stored_data) = self.stored_data.get(&storage_id) {
-                    if let Ok(report) = candid::decode_one::<RiskAssessmentReport>(stored_data) {
-                        if report.institution_id == institution_id 
-                           && !all_reports.iter().any(|r| r.report_id == report.report_id) {
-                            info!("Found report from stored data: {}", report.report_id);
-                            all_reports.push(report);
-                        }
-                    } else {
-                        info!("Failed to decode report from stored data for {}", storage_id);
-                    }
-                }
-            }
-        }
-    }
-
-    info!(
-        "Retrieved total {} reports for institution {}", 
-        all_reports.len(),
-        institution_id.to_text()
-    );
-    
-    all_reports
-}
+   pub fn restore_state(&mut self, state: ReportsStorage) {
+       *self = state;
+       self.print_storage_status();
+   }
 
-   pub fn get_latest_report(&self, institution_id: Principal) -> Option<RiskAssessmentReport> {
-       self.reports
+   fn generate_report_id(&mut self) -> String {
+       self.next_id += 1;
+       format!("RPT-{}-{}", time() / 1_000_000_000, self.next_id)
+   }
+
+   /// 为一次评估生成报告并保存，返回报告
+   pub fn create_report(&mut self, institution_id: Principal, user_did: &str, assessment: RiskAssessment) -> RiskAssessmentReport {
+       let report = RiskAssessmentReport {
+           report_id: self.generate_report_id(),
+           user_did: user_did.to_string(),
+           institution_id,
+           assessment,
+           created_at: time(),
+       };
+       self.store_report(report.clone());
+       report
+   }
+
+   pub fn store_report(&mut self, report: RiskAssessmentReport) -> String {
+       let report_id = report.report_id.clone();
+       self.institution_index
+           .entry(report.institution_id)
+           .or_default()
+           .push(report_id.clone());
+       self.reports.insert(report_id.clone(), report);
+
+       debug!("Stored assessment report {}", report_id);
+       report_id
+   }
+
+   pub fn get_report(&self, report_id: &str) -> Option<RiskAssessmentReport> {
+       self.reports.get(report_id).cloned()
+   }
+
+   fn institution_reports(&self, institution_id: Principal) -> impl Iterator<Item = &RiskAssessmentReport> {
+       self.institution_index
            .get(&institution_id)
-           .and_then(|reports| reports.last())
+           .into_iter()
+           .flatten()
+           .filter_map(|id| self.reports.get(id))
+   }
+
+   /// 查询机构的报告，days 为空时返回全部，按创建时间倒序
+   pub fn query_reports(&self, institution_id: Principal, days: Option<u64>) -> Vec<RiskAssessmentReport> {
+       self.query_reports_at(institution_id, days, time())
+   }
+
+   fn query_reports_at(&self, institution_id: Principal, days: Option<u64>, now: u64) -> Vec<RiskAssessmentReport> {
+       let since = days.map(|d| now.saturating_sub(d.saturating_mul(NANOS_PER_DAY)));
+       let mut reports: Vec<RiskAssessmentReport> = self.institution_reports(institution_id)
+           .filter(|r| since.is_none_or(|s| r.created_at >= s))
+           .cloned()
+           .collect();
+       reports.sort_by_key(|r| Reverse(r.created_at));
+
+       info!("Retrieved {} reports for institution {}", reports.len(), institution_id.to_text());
+       reports
+   }
+
+   /// 分页查询，支持按用户、时间范围过滤和排序
+   pub fn query_reports_page(&self, query: &ReportQuery) -> AssessmentPageResponse {
+       self.query_reports_page_at(query, time())
+   }
+
+   fn query_reports_page_at(&self, query: &ReportQuery, now: u64) -> AssessmentPageResponse {
+       let since = query.days.map(|d| now.saturating_sub(d.saturating_mul(NANOS_PER_DAY)));
+       let mut reports: Vec<&RiskAssessmentReport> = self.institution_reports(query.institution_id)
+           .filter(|r| since.is_none_or(|s| r.created_at >= s))
+           .filter(|r| query.user_did.as_ref().is_none_or(|did| &r.user_did == did))
+           .collect();
+
+       match query.sort.clone().unwrap_or_default() {
+           ReportSort::CreatedAtDesc => reports.sort_by_key(|r| Reverse(r.created_at)),
+           ReportSort::CreatedAtAsc => reports.sort_by_key(|r| r.created_at),
+           ReportSort::ScoreDesc => reports.sort_by_key(|r| Reverse(r.assessment.credit_score)),
+           ReportSort::ScoreAsc => reports.sort_by_key(|r| r.assessment.credit_score),
+       }
+
+       let limit = query.limit.clamp(1, MAX_PAGE_SIZE);
+       AssessmentPageResponse {
+           total: reports.len() as u64,
+           offset: query.offset,
+           limit,
+           data: reports.into_iter()
+               .skip(query.offset as usize)
+               .take(limit as usize)
+               .cloned()
+               .collect(),
+       }
+   }
+
+   pub fn get_latest_report(&self, institution_id: Principal) -> Option<RiskAssessmentReport> {
+       self.institution_reports(institution_id)
+           .max_by_key(|r| r.created_at)
            .cloned()
    }
 
    fn print_storage_status(&self) {
        info!("=== Storage Status ===");
-       info!("Total institutions: {}", self.reports.len());
-       for (institution_id, reports) in &self.reports {
+       info!("Total institutions: {}", self.institution_index.len());
+       for (institution_id, reports) in &self.institution_index {
            info!(
                "Institution {}: {} reports", 
                institution_id.to_text(), 
@@ -184,4 +159,93 @@ where
    REPORTS_STORAGE.with(|storage| {
        f(&mut storage.borrow_mut())
    })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   const NOW: u64 = 10 * NANOS_PER_DAY;
+
+   fn report(id: u64, institution_id: Principal, user_did: &str, credit_score: u32, created_at: u64) -> RiskAssessmentReport {
+       RiskAssessmentReport {
+           report_id: format!("RPT-{}", id),
+           user_did: user_did.to_string(),
+           institution_id,
+           assessment: RiskAssessment {
+               credit_score,
+               risk_level: "Low Risk".to_string(),
+               assessment_details: Vec::new(),
+               suggestions: Vec::new(),
+               model_id: "default".to_string(),
+               model_version: 1,
+               contributions: Vec::new(),
+               top_adverse_factors: Vec::new(),
+           },
+           created_at,
+       }
+   }
+
+   fn storage(bank: Principal) -> ReportsStorage {
+       let mut storage = ReportsStorage::default();
+       storage.store_report(report(1, bank, "did:alice", 70, NOW - 5 * NANOS_PER_DAY));
+       storage.store_report(report(2, bank, "did:bob", 90, NOW - NANOS_PER_DAY));
+       storage.store_report(report(3, bank, "did:alice", 60, NOW - 2 * NANOS_PER_DAY));
+       storage.store_report(report(4, Principal::from_slice(&[2]), "did:alice", 80, NOW));
+       storage
+   }
+
+   fn ids(reports: &[RiskAssessmentReport]) -> Vec<&str> {
+       reports.iter().map(|r| r.report_id.as_str()).collect()
+   }
+
+   fn query(institution_id: Principal) -> ReportQuery {
+       ReportQuery {
+           institution_id,
+           user_did: None,
+           days: None,
+           sort: None,
+           offset: 0,
+           limit: 10,
+       }
+   }
+
+   #[test]
+   fn reports_are_scoped_to_institution_and_window() {
+       let bank = Principal::from_slice(&[1]);
+       let storage = storage(bank);
+
+       assert_eq!(ids(&storage.query_reports_at(bank, None, NOW)), vec!["RPT-2", "RPT-3", "RPT-1"]);
+       assert_eq!(ids(&storage.query_reports_at(bank, Some(3), NOW)), vec!["RPT-2", "RPT-3"]);
+       assert_eq!(storage.get_latest_report(bank).unwrap().report_id, "RPT-2");
+   }
+
+   #[test]
+   fn pages_are_filtered_sorted_and_sliced() {
+       let bank = Principal::from_slice(&[1]);
+       let storage = storage(bank);
+
+       let page = storage.query_reports_page_at(&ReportQuery { sort: Some(ReportSort::ScoreAsc), ..query(bank) }, NOW);
+       assert_eq!(ids(&page.data), vec!["RPT-3", "RPT-1", "RPT-2"]);
+
+       let page = storage.query_reports_page_at(&ReportQuery {
+           sort: Some(ReportSort::ScoreDesc),
+           offset: 1,
+           limit: 1,
+           ..query(bank)
+       }, NOW);
+       assert_eq!((page.total, ids(&page.data)), (3, vec!["RPT-1"]));
+
+       let page = storage.query_reports_page_at(&ReportQuery {
+           user_did: Some("did:alice".to_string()),
+           sort: Some(ReportSort::CreatedAtAsc),
+           ..query(bank)
+       }, NOW);
+       assert_eq!(ids(&page.data), vec!["RPT-1", "RPT-3"]);
+
+       // limit 限制在 1..=100 之间
+       let page = storage.query_reports_page_at(&ReportQuery { limit: 0, ..query(bank) }, NOW);
+       assert_eq!((page.limit, ids(&page.data)), (1, vec!["RPT-2"]));
+       assert_eq!(storage.query_reports_page_at(&ReportQuery { limit: 1_000, ..query(bank) }, NOW).limit, MAX_PAGE_SIZE);
+   }
+}
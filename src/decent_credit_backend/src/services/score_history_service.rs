@@ -0,0 +1,264 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use log::{info, warn};
+use crate::models::credit::*;
+use crate::models::dashboard::ScoreTrend;
+use crate::utils::time::format_day;
+
+// 每个用户保留的历史评分条数
+const MAX_HISTORY_PER_USER: usize = 500;
+const MAX_ALERTS: usize = 10_000;
+const DEFAULT_DROP_THRESHOLD: u32 = 20;
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+// 需要跨升级保存的评分历史
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ScoreHistoryState {
+    pub history: HashMap<String, Vec<ScoreHistoryEntry>>,
+    pub alerts: Vec<ScoreAlert>,
+    pub next_alert_id: u64,
+    pub drop_threshold: u32,
+}
+
+impl Default for ScoreHistoryState {
+    fn default() -> Self {
+        Self {
+            history: HashMap::new(),
+            alerts: Vec::new(),
+            next_alert_id: 0,
+            drop_threshold: DEFAULT_DROP_THRESHOLD,
+        }
+    }
+}
+
+pub struct ScoreHistoryService {
+    state: ScoreHistoryState,
+}
+
+impl Default for ScoreHistoryService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    pub static SCORE_HISTORY_SERVICE: RefCell<ScoreHistoryService> = RefCell::new(ScoreHistoryService::new());
+}
+
+impl ScoreHistoryService {
+    pub fn new() -> Self {
+        Self {
+            state: ScoreHistoryState::default(),
+        }
+    }
+
+    pub fn export_state(&self) -> ScoreHistoryState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: ScoreHistoryState) {
+        info!(
+            "Restored score history: {} users, {} alerts",
+            state.history.len(),
+            state.alerts.len()
+        );
+        self.state = state;
+    }
+
+    pub fn get_drop_threshold(&self) -> u32 {
+        self.state.drop_threshold
+    }
+
+    pub fn set_drop_threshold(&mut self, points: u32) -> Result<(), String> {
+        if points == 0 {
+            return Err("提醒阈值必须大于0".to_string());
+        }
+        self.state.drop_threshold = points;
+        info!("Score drop alert threshold set to {}", points);
+        Ok(())
+    }
+
+    /// 记录一次评估结果；相比上一次下降超过阈值时生成提醒
+    pub fn record(&mut self, user_did: &str, institution_id: Principal, assessment: &RiskAssessment) -> Option<ScoreAlert> {
+        let now = time();
+        let history = self.state.history.entry(user_did.to_string()).or_default();
+        let previous = history.last().map(|entry| entry.credit_score);
+
+        history.push(ScoreHistoryEntry {
+            timestamp: now,
+            credit_score: assessment.credit_score,
+            risk_level: assessment.risk_level.clone(),
+            model_id: assessment.model_id.clone(),
+            model_version: assessment.model_version,
+            institution_id,
+        });
+        if history.len() > MAX_HISTORY_PER_USER {
+            let overflow = history.len() - MAX_HISTORY_PER_USER;
+            history.drain(..overflow);
+        }
+
+        let previous = previous?;
+        let drop_points = previous.saturating_sub(assessment.credit_score);
+        if drop_points <= self.state.drop_threshold {
+            return None;
+        }
+
+        self.state.next_alert_id += 1;
+        let alert = ScoreAlert {
+            id: self.state.next_alert_id,
+            user_did: user_did.to_string(),
+            previous_score: previous,
+            current_score: assessment.credit_score,
+            drop_points,
+            institution_id,
+            model_version: assessment.model_version,
+            created_at: now,
+            acknowledged: false,
+        };
+        warn!("Score of {} dropped by {} points ({} -> {})", user_did, drop_points, previous, assessment.credit_score);
+
+        self.state.alerts.push(alert.clone());
+        if self.state.alerts.len() > MAX_ALERTS {
+            let overflow = self.state.alerts.len() - MAX_ALERTS;
+            self.state.alerts.drain(..overflow);
+        }
+        Some(alert)
+    }
+
+    pub fn get_history(&self, user_did: &str, range: &ScoreHistoryRange) -> Vec<ScoreHistoryEntry> {
+        let Some(history) = self.state.history.get(user_did) else {
+            return Vec::new();
+        };
+
+        let filtered: Vec<ScoreHistoryEntry> = history.iter()
+            .filter(|entry| range.start_time.is_none_or(|start| entry.timestamp >= start))
+            .filter(|entry| range.end_time.is_none_or(|end| entry.timestamp <= end))
+            .cloned()
+            .collect();
+
+        match range.limit {
+            Some(limit) if (limit as usize) < filtered.len() => filtered[filtered.len() - limit as usize..].to_vec(),
+            _ => filtered,
+        }
+    }
+
+    /// 按天汇总最近若干天的平均分，供看板趋势图使用
+    pub fn get_trend(&self, user_did: &str, days: u64) -> Vec<ScoreTrend> {
+        let since = time().saturating_sub(days.saturating_mul(NANOS_PER_DAY));
+        let mut daily: BTreeMap<u64, (f64, u32)> = BTreeMap::new();
+
+        for entry in self.state.history.get(user_did).into_iter().flatten() {
+            if entry.timestamp < since {
+                continue;
+            }
+            let day = daily.entry(entry.timestamp / NANOS_PER_DAY).or_insert((0.0, 0));
+            day.0 += entry.credit_score as f64;
+            day.1 += 1;
+        }
+
+        daily.into_iter()
+            .map(|(day, (total, count))| ScoreTrend {
+                date: format_day(day),
+                score: total / count as f64,
+            })
+            .collect()
+    }
+
+    pub fn get_alerts(&self, user_did: Option<String>, institution_id: Option<Principal>, unacknowledged_only: bool) -> Vec<ScoreAlert> {
+        self.state.alerts.iter()
+            .rev()
+            .filter(|alert| user_did.as_ref().is_none_or(|did| &alert.user_did == did))
+            .filter(|alert| institution_id.is_none_or(|id| self.institution_follows(id, &alert.user_did)))
+            .filter(|alert| !unacknowledged_only || !alert.acknowledged)
+            .cloned()
+            .collect()
+    }
+
+    /// 机构评估过该用户，即视为关注其评分变化
+    pub fn institution_follows(&self, institution_id: Principal, user_did: &str) -> bool {
+        self.state.history.get(user_did)
+            .is_some_and(|history| history.iter().any(|entry| entry.institution_id == institution_id))
+    }
+
+    pub fn get_alert(&self, alert_id: u64) -> Option<ScoreAlert> {
+        self.state.alerts.iter().find(|alert| alert.id == alert_id).cloned()
+    }
+
+    pub fn acknowledge_alert(&mut self, alert_id: u64) -> Result<(), String> {
+        let alert = self.state.alerts.iter_mut()
+            .find(|alert| alert.id == alert_id)
+            .ok_or_else(|| format!("提醒不存在: {}", alert_id))?;
+        alert.acknowledged = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, credit_score: u32, institution_id: Principal) -> ScoreHistoryEntry {
+        ScoreHistoryEntry {
+            timestamp,
+            credit_score,
+            risk_level: "Low".to_string(),
+            model_id: "default".to_string(),
+            model_version: 1,
+            institution_id,
+        }
+    }
+
+    fn alert(id: u64, user_did: &str, institution_id: Principal) -> ScoreAlert {
+        ScoreAlert {
+            id,
+            user_did: user_did.to_string(),
+            previous_score: 90,
+            current_score: 60,
+            drop_points: 30,
+            institution_id,
+            model_version: 1,
+            created_at: id,
+            acknowledged: false,
+        }
+    }
+
+    #[test]
+    fn history_filters_by_range_and_keeps_latest_entries() {
+        let bank = Principal::from_slice(&[1]);
+        let mut service = ScoreHistoryService::new();
+        service.state.history.insert(
+            "did:alice".to_string(),
+            (1..=5).map(|t| entry(t, 60 + t as u32, bank)).collect(),
+        );
+
+        let range = ScoreHistoryRange { start_time: Some(2), end_time: Some(4), limit: None };
+        let scores: Vec<u32> = service.get_history("did:alice", &range).iter().map(|e| e.credit_score).collect();
+        assert_eq!(scores, vec![62, 63, 64]);
+
+        let latest = ScoreHistoryRange { limit: Some(2), ..ScoreHistoryRange::default() };
+        let scores: Vec<u32> = service.get_history("did:alice", &latest).iter().map(|e| e.credit_score).collect();
+        assert_eq!(scores, vec![64, 65]);
+        assert!(service.get_history("did:bob", &latest).is_empty());
+    }
+
+    #[test]
+    fn alerts_visible_to_institutions_that_assessed_the_user() {
+        let bank = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        let mut service = ScoreHistoryService::new();
+        service.state.history.insert("did:alice".to_string(), vec![entry(1, 90, bank)]);
+        service.state.alerts = vec![alert(1, "did:alice", bank), alert(2, "did:bob", other)];
+
+        assert!(service.institution_follows(bank, "did:alice"));
+        assert!(!service.institution_follows(other, "did:alice"));
+        let ids: Vec<u64> = service.get_alerts(None, Some(bank), false).iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![1]);
+
+        service.acknowledge_alert(1).unwrap();
+        assert!(service.get_alerts(None, Some(bank), true).is_empty());
+        assert!(service.get_alert(1).unwrap().acknowledged);
+        assert!(service.acknowledge_alert(99).is_err());
+    }
+}
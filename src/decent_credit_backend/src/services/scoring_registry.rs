@@ -0,0 +1,205 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use std::collections::HashMap;
+use log::info;
+use crate::models::scoring::*;
+
+pub const DEFAULT_MODEL_ID: &str = "default";
+
+// 评分模型注册表：同一模型的每次上传生成新版本，旧版本保留以便复现历史评分
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ScoringRegistry {
+    models: HashMap<String, Vec<ScoringModel>>,
+    active: ModelVersion,
+}
+
+impl Default for ScoringRegistry {
+    fn default() -> Self {
+        let default_model = ScoringModel {
+            model_id: DEFAULT_MODEL_ID.to_string(),
+            version: 1,
+            name: "Baseline".to_string(),
+            description: "Original rule-based credit scoring formula".to_string(),
+            params: ScoringParams::default(),
+            created_by: Principal::anonymous(),
+            created_at: 0,
+        };
+        Self {
+            models: HashMap::from([(DEFAULT_MODEL_ID.to_string(), vec![default_model])]),
+            active: ModelVersion {
+                model_id: DEFAULT_MODEL_ID.to_string(),
+                version: 1,
+            },
+        }
+    }
+}
+
+impl ScoringRegistry {
+    pub fn active_model(&self) -> ScoringModel {
+        self.get(&self.active.model_id, Some(self.active.version))
+            .expect("active scoring model must exist")
+    }
+
+    pub fn active_version(&self) -> ModelVersion {
+        self.active.clone()
+    }
+
+    /// 获取指定版本，未指定版本时返回最新版本
+    pub fn get(&self, model_id: &str, version: Option<u32>) -> Option<ScoringModel> {
+        let versions = self.models.get(model_id)?;
+        match version {
+            Some(v) => versions.iter().find(|m| m.version == v).cloned(),
+            None => versions.last().cloned(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<ScoringModel> {
+        let mut models: Vec<ScoringModel> = self.models.values().flatten().cloned().collect();
+        models.sort_by(|a, b| a.model_id.cmp(&b.model_id).then(a.version.cmp(&b.version)));
+        models
+    }
+
+    pub fn register(&mut self, upload: ScoringModelUpload, created_by: Principal) -> Result<ModelVersion, String> {
+        if upload.model_id.trim().is_empty() {
+            return Err("模型ID不能为空".to_string());
+        }
+        validate_params(&upload.params)?;
+
+        let versions = self.models.entry(upload.model_id.clone()).or_default();
+        let version = versions.last().map_or(1, |m| m.version + 1);
+        versions.push(ScoringModel {
+            model_id: upload.model_id.clone(),
+            version,
+            name: upload.name,
+            description: upload.description,
+            params: upload.params,
+            created_by,
+            created_at: time(),
+        });
+
+        let registered = ModelVersion { model_id: upload.model_id, version };
+        info!("Registered scoring model {} v{}", registered.model_id, registered.version);
+        if upload.activate {
+            self.active = registered.clone();
+            info!("Activated scoring model {} v{}", registered.model_id, registered.version);
+        }
+        Ok(registered)
+    }
+
+    pub fn activate(&mut self, model_id: &str, version: u32) -> Result<(), String> {
+        if self.get(model_id, Some(version)).is_none() {
+            return Err(format!("评分模型不存在: {} v{}", model_id, version));
+        }
+        self.active = ModelVersion { model_id: model_id.to_string(), version };
+        info!("Activated scoring model {} v{}", model_id, version);
+        Ok(())
+    }
+}
+
+fn validate_params(params: &ScoringParams) -> Result<(), String> {
+    let values = [
+        ("base_score", params.base_score),
+        ("new_user_loan_activity", params.new_user_loan_activity),
+        ("loan_frequency_weight", params.loan_frequency_weight),
+        ("loan_activity_cap", params.loan_activity_cap),
+        ("new_user_repayment", params.new_user_repayment),
+        ("repayment_ratio_weight", params.repayment_ratio_weight),
+        ("overdue_ratio_penalty", params.overdue_ratio_penalty),
+        ("stability_weight", params.stability_weight),
+        ("repayment_strength_weight", params.repayment_strength_weight),
+        ("recent_activity_weight", params.recent_activity_weight),
+        ("consistency_weight", params.consistency_weight),
+        ("non_increasing_trend_bonus", params.non_increasing_trend_bonus),
+        ("overdue_days_unit", params.overdue_days_unit),
+        ("overdue_days_weight", params.overdue_days_weight),
+        ("overdue_days_cap", params.overdue_days_cap),
+        ("overdue_frequency_weight", params.overdue_frequency_weight),
+        ("overdue_frequency_cap", params.overdue_frequency_cap),
+        ("overdue_amount_unit", params.overdue_amount_unit),
+        ("overdue_amount_cap", params.overdue_amount_cap),
+        ("min_score", params.min_score),
+        ("max_score", params.max_score),
+    ];
+    // NaN 与无穷会让所有比较失效，负权重会把加分项变成扣分项
+    if let Some((name, value)) = values.iter().find(|(_, v)| !v.is_finite() || *v < 0.0) {
+        return Err(format!("参数 {} 必须是非负有限数: {}", name, value));
+    }
+
+    if params.min_score > params.max_score {
+        return Err("最低分不能高于最高分".to_string());
+    }
+    if params.high_risk_max >= params.medium_risk_max {
+        return Err("高风险阈值必须低于中风险阈值".to_string());
+    }
+    if (params.high_risk_max as f64) < params.min_score || (params.medium_risk_max as f64) > params.max_score {
+        return Err("风险等级阈值必须在最低分与最高分之间".to_string());
+    }
+    if params.overdue_days_unit <= 0.0 || params.overdue_amount_unit <= 0.0 {
+        return Err("逾期折算单位必须大于0".to_string());
+    }
+
+    if let Some(features) = &params.time_features {
+        if !features.recency_half_life_months.is_finite() || features.recency_half_life_months <= 0.0 {
+            return Err("近期权重半衰期必须大于0".to_string());
+        }
+        if features.lookback_windows_months.contains(&0) {
+            return Err("统计窗口月数必须大于0".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_params_are_valid() {
+        assert!(validate_params(&ScoringParams::default()).is_ok());
+    }
+
+    #[test]
+    fn rejects_non_finite_and_negative_values() {
+        let nan = ScoringParams { stability_weight: f64::NAN, ..ScoringParams::default() };
+        assert!(validate_params(&nan).is_err());
+
+        let infinite = ScoringParams { max_score: f64::INFINITY, ..ScoringParams::default() };
+        assert!(validate_params(&infinite).is_err());
+
+        let negative = ScoringParams { overdue_days_weight: -1.0, ..ScoringParams::default() };
+        assert!(validate_params(&negative).is_err());
+    }
+
+    #[test]
+    fn rejects_unordered_thresholds() {
+        let inverted = ScoringParams { high_risk_max: 80, medium_risk_max: 70, ..ScoringParams::default() };
+        assert!(validate_params(&inverted).is_err());
+
+        let above_max = ScoringParams { medium_risk_max: 200, ..ScoringParams::default() };
+        assert!(validate_params(&above_max).is_err());
+
+        let below_min = ScoringParams { min_score: 60.0, ..ScoringParams::default() };
+        assert!(validate_params(&below_min).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_time_features() {
+        let no_decay = ScoringParams {
+            time_features: Some(TimeFeatureParams {
+                recency_half_life_months: 0.0,
+                ..TimeFeatureParams::default()
+            }),
+            ..ScoringParams::default()
+        };
+        assert!(validate_params(&no_decay).is_err());
+
+        let empty_window = ScoringParams {
+            time_features: Some(TimeFeatureParams {
+                lookback_windows_months: vec![0, 6],
+                ..TimeFeatureParams::default()
+            }),
+            ..ScoringParams::default()
+        };
+        assert!(validate_params(&empty_window).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use candid::{CandidType, Deserialize};
+use log::info;
+use crate::models::rate_limit::RateLimitConfig;
+use crate::services::billing_service::{BillingState, BILLING_SERVICE};
+use crate::services::reconciliation_service::{ReconciliationState, RECONCILIATION_SERVICE};
+use crate::services::pricing_service::{PricingState, PRICING_SERVICE};
+use crate::services::quota_service::{QuotaState, QUOTA_SERVICE};
+use crate::services::rate_limit_service::RATE_LIMIT_SERVICE;
+use crate::services::credit_service::CREDIT_SERVICE;
+use crate::services::scoring_registry::ScoringRegistry;
+use crate::services::score_history_service::{ScoreHistoryState, SCORE_HISTORY_SERVICE};
+use crate::services::reports_storage::{ReportsStorage, REPORTS_STORAGE};
+use crate::services::identity_service::{IdentityState, IDENTITY_SERVICE};
+use crate::services::did_service::{DidState, DID_SERVICE};
+use crate::services::api_key_service::{ApiKeyState, API_KEY_SERVICE};
+use crate::services::import_service::{ImportState, IMPORT_SERVICE};
+use crate::services::validation_service::{ValidationState, VALIDATION_SERVICE};
+use crate::services::integrity_service::{IntegrityState, INTEGRITY_SERVICE};
+use crate::services::data_quality_service::{DataQualityState, DATA_QUALITY_SERVICE};
+use crate::services::institution_score_service::{InstitutionScoreState, INSTITUTION_SCORE_SERVICE};
+use crate::services::record_service::{DeductionState, RECORD_SERVICE};
+
+// 升级时写入稳定内存的各服务状态；字段均为可选，新增服务不影响旧数据的恢复
+#[derive(CandidType, Deserialize, Default)]
+pub struct StableState {
+    pub billing: Option<BillingState>,
+    pub reconciliation: Option<ReconciliationState>,
+    pub pricing: Option<PricingState>,
+    pub quota: Option<QuotaState>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub scoring: Option<ScoringRegistry>,
+    pub score_history: Option<ScoreHistoryState>,
+    pub reports: Option<ReportsStorage>,
+    pub identity: Option<IdentityState>,
+    pub did: Option<DidState>,
+    pub api_keys: Option<ApiKeyState>,
+    pub imports: Option<ImportState>,
+    pub validation: Option<ValidationState>,
+    pub integrity: Option<IntegrityState>,
+    pub data_quality: Option<DataQualityState>,
+    pub institution_scores: Option<InstitutionScoreState>,
+    pub deductions: Option<DeductionState>,
+}
+
+impl StableState {
+    pub fn collect() -> Self {
+        Self {
+            billing: Some(BILLING_SERVICE.with(|service| service.borrow().export_state())),
+            reconciliation: Some(RECONCILIATION_SERVICE.with(|service| service.borrow().export_state())),
+            pricing: Some(PRICING_SERVICE.with(|service| service.borrow().export_state())),
+            quota: Some(QUOTA_SERVICE.with(|service| service.borrow().export_state())),
+            rate_limit: Some(RATE_LIMIT_SERVICE.with(|service| service.borrow().get_config())),
+            scoring: Some(CREDIT_SERVICE.with(|service| service.borrow().export_scoring_registry())),
+            score_history: Some(SCORE_HISTORY_SERVICE.with(|service| service.borrow().export_state())),
+            reports: Some(REPORTS_STORAGE.with(|storage| storage.borrow().export_state())),
+            identity: Some(IDENTITY_SERVICE.with(|service| service.borrow().export_state())),
+            did: Some(DID_SERVICE.with(|service| service.borrow().export_state())),
+            api_keys: Some(API_KEY_SERVICE.with(|service| service.borrow().export_state())),
+            imports: Some(IMPORT_SERVICE.with(|service| service.borrow().export_state())),
+            validation: Some(VALIDATION_SERVICE.with(|service| service.borrow().export_state())),
+            integrity: Some(INTEGRITY_SERVICE.with(|service| service.borrow().export_state())),
+            data_quality: Some(DATA_QUALITY_SERVICE.with(|service| service.borrow().export_state())),
+            institution_scores: Some(INSTITUTION_SCORE_SERVICE.with(|service| service.borrow().export_state())),
+            deductions: Some(RECORD_SERVICE.with(|service| service.borrow().export_deductions())),
+        }
+    }
+
+    pub fn restore(self) {
+        if let Some(state) = self.billing {
+            BILLING_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.reconciliation {
+            RECONCILIATION_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.pricing {
+            PRICING_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.quota {
+            QUOTA_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(config) = self.rate_limit {
+            RATE_LIMIT_SERVICE.with(|service| service.borrow_mut().restore_config(config));
+        }
+        if let Some(registry) = self.scoring {
+            CREDIT_SERVICE.with(|service| service.borrow_mut().restore_scoring_registry(registry));
+        }
+        if let Some(state) = self.score_history {
+            SCORE_HISTORY_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.reports {
+            REPORTS_STORAGE.with(|storage| storage.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.identity {
+            IDENTITY_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.did {
+            DID_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.api_keys {
+            API_KEY_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.imports {
+            IMPORT_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.validation {
+            VALIDATION_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.integrity {
+            INTEGRITY_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.data_quality {
+            DATA_QUALITY_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.institution_scores {
+            INSTITUTION_SCORE_SERVICE.with(|service| service.borrow_mut().restore_state(state));
+        }
+        if let Some(state) = self.deductions {
+            RECORD_SERVICE.with(|service| service.borrow_mut().restore_deductions(state));
+        }
+        info!("Stable state restored");
+    }
+}
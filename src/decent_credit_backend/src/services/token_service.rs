@@ -8,6 +8,8 @@ use crate::utils::error::Error;
 use log::{info, warn, error};
 use crate::services::admin_institution_service::ADMIN_SERVICE;
 use crate::services::dashboard_service::DASHBOARD_SERVICE;
+use crate::services::pricing_service::PRICING_SERVICE;
+use crate::services::reconciliation_service::RECONCILIATION_SERVICE;
 use crate::models::record::{CreditRecord, DCCTransactionRequest};
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -34,12 +36,49 @@ pub struct TokenTransferArgs {
     pub created_at_time: Option<u64>,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct TransferResult {
     pub block_height: u64,
     pub tx_hash: String,
 }
 
+#[derive(CandidType, Deserialize, Clone)]
+pub struct BurnArgs {
+    pub from: Principal,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct OperatorTransferArgs {
+    pub from: Principal,
+    pub to: Principal,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub created_at_time: Option<u64>,
+}
+
+// 与 token canister 中的账户交易视图保持一致
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum LedgerOperation {
+    Mint,
+    Burn,
+    Transfer,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AccountTransaction {
+    pub block_height: u64,
+    pub operation: LedgerOperation,
+    pub from: Option<Principal>,
+    pub to: Option<Principal>,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub timestamp: u64,
+    pub tx_hash: String,
+}
+
 pub struct TokenService {
     state: TokenState,
     pub token_canister_id: Principal,
@@ -149,17 +188,21 @@ impl TokenService {
             let token_canister_id = self.token_canister_id;
             
             // 执行链上转账
-            let result: Result<(TransferResult,), _> = ic_cdk::call(
+            let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
                 token_canister_id,
                 "transfer",
                 (transfer_args,)
             ).await;
     
             match result {
-                Ok(_) => {
+                Ok((Ok(_),)) => {
                     info!("DCC transfer completed successfully");
                     Ok(())
                 }
+                Ok((Err(msg),)) => {
+                    error!("Token canister rejected transfer: {}", msg);
+                    Err(Error::NetworkError(format!("Transfer failed: {}", msg)))
+                }
                 Err((code, msg)) => {
                     error!("Transfer failed: {:?} - {}", code, msg);
                     Err(Error::NetworkError(format!("Transfer failed: {}", msg)))
@@ -236,6 +279,7 @@ impl TokenService {
             
             // 使用静态方法执行转账
             Self::execute_transfer(self.token_canister_id, transfer_args).await
+                .map(|_| ())
         }
     
         /// 简单的奖励发放方法
@@ -252,17 +296,21 @@ impl TokenService {
             };
     
             // 直接调用 transfer，由 token canister 作为发送方
-            let result: Result<(TransferResult,), _> = ic_cdk::call(
+            let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
                 self.token_canister_id,
                 "transfer",
                 (transfer_args,)
             ).await;
     
             match result {
-                Ok(_) => {
+                Ok((Ok(_),)) => {
                     info!("Reward sent successfully: {} tokens to {}", amount, to_id);
                     Ok(())
                 }
+                Ok((Err(msg),)) => {
+                    error!("Token canister rejected transfer: {}", msg);
+                    Err(format!("发送奖励失败: {}", msg))
+                }
                 Err((code, msg)) => {
                     error!("Failed to send rewards: {:?} - {}", code, msg);
                     Err(format!("发送奖励失败: {}", msg))
@@ -291,17 +339,21 @@ impl TokenService {
         };
 
         // 执行扣除操作
-        let result: Result<(TransferResult,), _> = ic_cdk::call(
+        let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
             self.token_canister_id,
             "transfer",
             (transfer_args,)
         ).await;
 
         match result {
-            Ok(_) => {
+            Ok((Ok(_),)) => {
                 info!("Tokens deducted successfully: {} tokens from {}", amount, from_id);
                 Ok(())
             }
+            Ok((Err(msg),)) => {
+                error!("Token canister rejected transfer: {}", msg);
+                Err(format!("扣除代币失败: {}", msg))
+            }
             Err((code, msg)) => {
                 error!("Failed to deduct tokens: {:?} - {}", code, msg);
                 Err(format!("扣除代币失败: {}", msg))
@@ -314,7 +366,7 @@ impl TokenService {
         to_id: Principal,
         amount: u64,
         memo: String
-    ) -> Result<(), String> {
+    ) -> Result<TransferResult, String> {
         info!("Static sending reward to account: {}, amount: {}", to_id, amount);
         
         // 添加调试日志
@@ -358,57 +410,63 @@ impl TokenService {
     
         info!("Executing transfer of {} tokens to {} with from_subaccount", amount, to_id);
         
-        let result: Result<(TransferResult,), _> = ic_cdk::call(
+        let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
             token_canister_id,
             "transfer",
             (transfer_args,)
         ).await;
     
         match result {
-            Ok(_) => {
+            Ok((Ok(transfer_result),)) => {
                 info!("Reward sent successfully: {} tokens to {}", amount, to_id);
                 // 验证转账结果
                 let new_balance = Self::query_balance_static(token_canister_id, to_id).await?;
                 info!("Recipient new balance: {}", new_balance);
-                Ok(())
+                Ok(transfer_result)
             }
-            Err((code, msg)) => {
-                error!("Failed to send reward: {:?} - {}", code, msg);
+            Ok((Err(msg),)) => {
+                error!("Token canister rejected transfer: {}", msg);
                 if msg.contains("Insufficient balance") {
                     error!("Balance check failed - canister: {}, amount: {}", canister_balance, amount);
                 }
                 Err(format!("发送奖励失败: {}", msg))
             }
+            Err((code, msg)) => {
+                error!("Failed to send reward: {:?} - {}", code, msg);
+                Err(format!("发送奖励失败: {}", msg))
+            }
         }
     }
-    // 修改 deduct_tokens_static，简化余额检查
+    // 扣除通过 token canister 的 burn 完成，本 canister 需被设为铸币账户或管理员
     pub async fn deduct_tokens_static(
         token_canister_id: Principal,
         from_id: Principal,
         amount: u64,
         memo: String
-    ) -> Result<(), String> {
+    ) -> Result<TransferResult, String> {
         info!("Static deducting tokens from account: {}, amount: {}", from_id, amount);
 
-        let transfer_args = TokenTransferArgs {
-            to: from_id,  // 这里是 from_id，因为是扣除操作
+        let burn_args = BurnArgs {
+            from: from_id,
             amount,
             memo: memo.into_bytes(),
-            from_subaccount: None,
-            to_subaccount: None,
             created_at_time: Some(time()),
         };
 
-        let result: Result<(TransferResult,), _> = ic_cdk::call(
+        let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
             token_canister_id,
-            "transfer",
-            (transfer_args,)
+            "burn",
+            (burn_args,)
         ).await;
 
         match result {
-            Ok(_) => {
+            Ok((Ok(transfer_result),)) => {
                 info!("Tokens deducted successfully: {} tokens from {}", amount, from_id);
-                Ok(())
+                Ok(transfer_result)
+            }
+            Ok((Err(msg),)) => {
+                error!("Token canister rejected burn: {}", msg);
+                Err(format!("扣除代币失败: {}", msg))
             }
             Err((code, msg)) => {
                 error!("Failed to deduct tokens: {:?} - {}", code, msg);
@@ -416,6 +474,45 @@ impl TokenService {
             }
         }
     }
+    /// 由本 canister 作为操作方在两个账户之间划转，用于查询计费托管
+    pub async fn operator_transfer_static(
+        token_canister_id: Principal,
+        from: Principal,
+        to: Principal,
+        amount: u64,
+        memo: String
+    ) -> Result<TransferResult, String> {
+        info!("Operator transfer: from={}, to={}, amount={}", from, to, amount);
+
+        let args = OperatorTransferArgs {
+            from,
+            to,
+            amount,
+            memo: memo.into_bytes(),
+            created_at_time: Some(time()),
+        };
+
+        let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
+            token_canister_id,
+            "operator_transfer",
+            (args,)
+        ).await;
+
+        match result {
+            Ok((Ok(transfer_result),)) => {
+                info!("Operator transfer completed at block {}", transfer_result.block_height);
+                Ok(transfer_result)
+            }
+            Ok((Err(msg),)) => {
+                warn!("Token canister rejected operator transfer: {}", msg);
+                Err(msg)
+            }
+            Err((code, msg)) => {
+                error!("Operator transfer call failed: {:?} - {}", code, msg);
+                Err(format!("划转调用失败: {}", msg))
+            }
+        }
+    }
     pub fn prepare_query_reward(
         &mut self,
         institution_id: Principal,
@@ -481,17 +578,21 @@ impl TokenService {
             
         let token_canister_id = self.token_canister_id;
         
-        let result: Result<(TransferResult,), _> = ic_cdk::call(
+        let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
             token_canister_id,
             "transfer",
             (transfer_args,)
         ).await;
 
         match result {
-            Ok(_) => {
+            Ok((Ok(_),)) => {
                 info!("DCC transfer completed successfully");
                 Ok(())
             }
+            Ok((Err(msg),)) => {
+                error!("Token canister rejected transfer: {}", msg);
+                Err(Error::NetworkError(format!("Transfer failed: {}", msg)))
+            }
             Err((code, msg)) => {
                 error!("Transfer failed: {:?} - {}", code, msg);
                 Err(Error::NetworkError(format!("Transfer failed: {}", msg)))
@@ -537,20 +638,24 @@ impl TokenService {
  pub async fn execute_transfer(
     token_canister_id: Principal,
     transfer_args: TokenTransferArgs,
-) -> Result<(), Error> {
+) -> Result<TransferResult, Error> {
     info!("Executing transfer: to={}, amount={}", 
         transfer_args.to, transfer_args.amount);
         
-    let result: Result<(TransferResult,), _> = ic_cdk::call(
+    let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
         token_canister_id,
         "transfer",
         (transfer_args,)
     ).await;
 
     match result {
-        Ok(_) => {
-            info!("Transfer completed successfully");
-            Ok(())
+        Ok((Ok(transfer_result),)) => {
+            info!("Transfer completed successfully, block: {}", transfer_result.block_height);
+            Ok(transfer_result)
+        }
+        Ok((Err(msg),)) => {
+            error!("Token canister rejected transfer: {}", msg);
+            Err(Error::NetworkError(format!("Transfer failed: {}", msg)))
         }
         Err((code, msg)) => {
             error!("Transfer failed: {:?} - {}", code, msg);
@@ -567,12 +672,12 @@ impl TokenService {
         return Err(Error::InvalidData("Invalid reward ratio".to_string()));
     }
     
-    // 计算剩余比例 (100 - ratio) 
-    let remaining_ratio = 100 - ratio;
-    // 使用剩余比例计算金额
-    let amount = (base_amount * remaining_ratio as u64) / 100;
+    // 数据所有方按自身分成比例获得收益（受管理员设定的上下限约束）
+    let amount = PRICING_SERVICE.with(|service| {
+        service.borrow().owner_amount(base_amount, ratio as u8)
+    });
     
-    info!("Calculated reward amount with remaining ratio {}: {}", remaining_ratio, amount);
+    info!("Calculated reward amount with owner share {}: {}", ratio, amount);
     Ok(amount)
 }
 
@@ -623,20 +728,32 @@ impl TokenService {
         Ok(tx_request)
     }
 
-    fn update_institution_stats(&mut self, institution_id: Principal, rewards: u64, consumption: u64) {
+    pub fn update_institution_stats(&mut self, institution_id: Principal, rewards: u64, consumption: u64) {
         info!("Updating stats for {}: rewards={}, consumption={}", 
             institution_id, rewards, consumption);
 
 
-        ADMIN_SERVICE.with(|service| {
+        let shortfall = ADMIN_SERVICE.with(|service| {
                 let mut service = service.borrow_mut();
+                // 本地计数失败不影响链上结果，由对账任务修正
                 if rewards > 0 {
-                    service.record_token_reward(institution_id, rewards);
+                    if let Err(e) = service.record_token_reward(institution_id, rewards) {
+                        warn!("Failed to record rewards for {}: {}", institution_id, e);
+                    }
                 }
-                if consumption > 0 {
-                    service.record_token_consumption(institution_id, consumption);
+                if consumption == 0 {
+                    return 0;
                 }
+                service.record_token_consumption(institution_id, consumption).unwrap_or_else(|e| {
+                    warn!("Failed to record consumption for {}: {}", institution_id, e);
+                    0
+                })
+            });
+        if shortfall > 0 {
+            RECONCILIATION_SERVICE.with(|service| {
+                service.borrow_mut().flag_shortfall(institution_id, shortfall, time())
             });
+        }
         DASHBOARD_SERVICE.with(|service| {
             service.borrow_mut().update_admin_token_stats(rewards, consumption);
         });
@@ -662,17 +779,21 @@ impl TokenService {
     ) -> Result<(), Error> {
         info!("Initiating DCC transfer: to={}, amount={}", args.to, args.amount);
 
-        let result: Result<(TransferResult,), _> = ic_cdk::call(
+        let result: Result<(Result<TransferResult, String>,), _> = ic_cdk::call(
             self.token_canister_id,
             "transfer",
             (args,)
         ).await;
 
         match result {
-            Ok(_) => {
+            Ok((Ok(_),)) => {
                 info!("DCC transfer completed successfully");
                 Ok(())
             }
+            Ok((Err(msg),)) => {
+                error!("Token canister rejected transfer: {}", msg);
+                Err(Error::NetworkError(format!("Transfer failed: {}", msg)))
+            }
             Err((code, msg)) => {
                 error!("Transfer failed: {:?} - {}", code, msg);
                 Err(Error::NetworkError(format!("Transfer failed: {}", msg)))
@@ -700,6 +821,45 @@ impl TokenService {
             }
         }
     }
+
+    /// 从链上交易索引分页拉取账户的全部交易（按区块高度倒序）
+    pub async fn get_account_transactions_static(
+        token_canister_id: Principal,
+        account: Principal,
+    ) -> Result<Vec<AccountTransaction>, String> {
+        const PAGE_SIZE: u64 = 500;
+        info!("Fetching ledger transactions for account: {}", account);
+
+        let mut transactions: Vec<AccountTransaction> = Vec::new();
+        let mut start: Option<u64> = None;
+
+        loop {
+            let result: Result<(Vec<AccountTransaction>,), _> = ic_cdk::call(
+                token_canister_id,
+                "get_account_transactions",
+                (account, start, PAGE_SIZE)
+            ).await;
+
+            let page = match result {
+                Ok((page,)) => page,
+                Err((code, msg)) => {
+                    error!("Failed to fetch account transactions: {:?} - {}", code, msg);
+                    return Err(format!("查询链上交易失败: {}", msg));
+                }
+            };
+
+            let page_len = page.len() as u64;
+            start = page.last().map(|tx| tx.block_height);
+            transactions.extend(page);
+
+            if page_len < PAGE_SIZE || start.is_none() {
+                break;
+            }
+        }
+
+        info!("Retrieved {} ledger transactions for {}", transactions.len(), account);
+        Ok(transactions)
+    }
 }
 
 pub async fn init_token_service() {
@@ -0,0 +1,399 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use log::{info, debug};
+use crate::models::record::{RecordContent, RecordType};
+use crate::models::validation::*;
+use crate::utils::time::parse_event_date;
+
+pub const DEFAULT_JURISDICTION: &str = "default";
+
+// 需要跨升级保存的规则集（含历史版本）和机构所属辖区
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ValidationState {
+    pub rule_sets: HashMap<String, Vec<ValidationRuleSet>>,
+    pub institution_jurisdictions: HashMap<Principal, String>,
+}
+
+impl Default for ValidationState {
+    fn default() -> Self {
+        let default_set = ValidationRuleSet {
+            jurisdiction: DEFAULT_JURISDICTION.to_string(),
+            version: 1,
+            rules: default_rules(),
+            note: Some("内置基础校验".to_string()),
+            updated_by: Principal::anonymous(),
+            updated_at: 0,
+        };
+        Self {
+            rule_sets: HashMap::from([(DEFAULT_JURISDICTION.to_string(), vec![default_set])]),
+            institution_jurisdictions: HashMap::new(),
+        }
+    }
+}
+
+fn rule(id: &str, record_type: RecordType, field: RecordField, condition: RuleCondition, message: &str) -> ValidationRule {
+    ValidationRule {
+        id: id.to_string(),
+        record_type,
+        field,
+        condition,
+        message: message.to_string(),
+        enabled: true,
+    }
+}
+
+// 与原有硬编码校验等价的基础规则
+fn default_rules() -> Vec<ValidationRule> {
+    use RecordField::*;
+    use RecordType::*;
+    use RuleCondition::*;
+
+    vec![
+        rule("loan.amount.required", LoanRecord, Amount, Required, "Invalid loan data: missing required fields"),
+        rule("loan.loan_id.required", LoanRecord, LoanId, Required, "Invalid loan data: missing required fields"),
+        rule("loan.term_months.required", LoanRecord, TermMonths, Required, "Invalid loan data: missing required fields"),
+        rule("loan.interest_rate.positive", LoanRecord, InterestRate, GreaterThan(0.0), "Invalid loan data: interest rate out of range"),
+        rule("loan.interest_rate.max", LoanRecord, InterestRate, AtMost(100.0), "Invalid loan data: interest rate out of range"),
+        rule("repayment.amount.required", RepaymentRecord, Amount, Required, "Invalid repayment data: missing required fields"),
+        rule("repayment.loan_id.required", RepaymentRecord, LoanId, Required, "Invalid repayment data: missing required fields"),
+        rule("overdue.amount.required", OverdueRecord, Amount, Required, "Invalid overdue data: missing required fields"),
+        rule("overdue.overdue_days.required", OverdueRecord, OverdueDays, Required, "Invalid overdue data: missing required fields"),
+    ]
+}
+
+enum FieldValue<'a> {
+    Number(f64),
+    Text(&'a str),
+}
+
+fn field_applies(record_type: &RecordType, field: &RecordField) -> bool {
+    match field {
+        RecordField::Amount | RecordField::EventDate | RecordField::UserDid => true,
+        RecordField::LoanId => matches!(record_type, RecordType::LoanRecord | RecordType::RepaymentRecord),
+        RecordField::TermMonths | RecordField::InterestRate => *record_type == RecordType::LoanRecord,
+        RecordField::RepaymentDate => *record_type == RecordType::RepaymentRecord,
+        RecordField::OverdueDays | RecordField::PeriodAmount => *record_type == RecordType::OverdueRecord,
+    }
+}
+
+fn is_text_field(field: &RecordField) -> bool {
+    matches!(field, RecordField::LoanId | RecordField::RepaymentDate | RecordField::EventDate | RecordField::UserDid)
+}
+
+fn field_value<'a>(field: &RecordField, content: &'a RecordContent, user_did: &'a str, event_date: &'a str) -> Option<FieldValue<'a>> {
+    let value = match (field, content) {
+        (RecordField::EventDate, _) => FieldValue::Text(event_date),
+        (RecordField::UserDid, _) => FieldValue::Text(user_did),
+        (RecordField::Amount, RecordContent::Loan(loan)) => FieldValue::Number(loan.amount as f64),
+        (RecordField::Amount, RecordContent::Repayment(repayment)) => FieldValue::Number(repayment.amount as f64),
+        (RecordField::Amount, RecordContent::Overdue(overdue)) => FieldValue::Number(overdue.amount as f64),
+        (RecordField::LoanId, RecordContent::Loan(loan)) => FieldValue::Text(&loan.loan_id),
+        (RecordField::LoanId, RecordContent::Repayment(repayment)) => FieldValue::Text(&repayment.loan_id),
+        (RecordField::TermMonths, RecordContent::Loan(loan)) => FieldValue::Number(loan.term_months as f64),
+        (RecordField::InterestRate, RecordContent::Loan(loan)) => FieldValue::Number(loan.interest_rate),
+        (RecordField::RepaymentDate, RecordContent::Repayment(repayment)) => FieldValue::Text(&repayment.repayment_date),
+        (RecordField::OverdueDays, RecordContent::Overdue(overdue)) => FieldValue::Number(overdue.overdueDays as f64),
+        (RecordField::PeriodAmount, RecordContent::Overdue(overdue)) => FieldValue::Number(overdue.period_amount as f64),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn charset_matches(charset: &CharacterSet, c: char) -> bool {
+    match charset {
+        CharacterSet::Digits => c.is_ascii_digit(),
+        CharacterSet::Alphanumeric => c.is_ascii_alphanumeric(),
+        CharacterSet::AlphanumericDash => c.is_ascii_alphanumeric() || c == '-' || c == '_',
+    }
+}
+
+fn condition_holds(condition: &RuleCondition, value: &FieldValue) -> bool {
+    match (condition, value) {
+        (RuleCondition::Required, FieldValue::Number(n)) => *n != 0.0,
+        (RuleCondition::Required, FieldValue::Text(s)) => !s.trim().is_empty(),
+        (RuleCondition::GreaterThan(limit), FieldValue::Number(n)) => n > limit,
+        (RuleCondition::AtLeast(limit), FieldValue::Number(n)) => n >= limit,
+        (RuleCondition::AtMost(limit), FieldValue::Number(n)) => n <= limit,
+        (RuleCondition::Format { prefix, min_length, max_length, charset }, FieldValue::Text(s)) => {
+            let length = s.chars().count() as u32;
+            prefix.as_ref().is_none_or(|p| s.starts_with(p.as_str()))
+                && min_length.is_none_or(|min| length >= min)
+                && max_length.is_none_or(|max| length <= max)
+                && charset.as_ref().is_none_or(|cs| s.chars().all(|c| charset_matches(cs, c)))
+        }
+        (RuleCondition::ValidDate, FieldValue::Text(s)) => parse_event_date(s).is_some(),
+        // 条件与字段类型不符的规则在保存时已被拒绝
+        _ => true,
+    }
+}
+
+fn check_rule(rule: &ValidationRule) -> Result<(), String> {
+    if rule.id.trim().is_empty() {
+        return Err("规则ID不能为空".to_string());
+    }
+    if !field_applies(&rule.record_type, &rule.field) {
+        return Err(format!("规则 {} 的字段 {:?} 不适用于 {:?}", rule.id, rule.field, rule.record_type));
+    }
+    let text = is_text_field(&rule.field);
+    let valid = match &rule.condition {
+        RuleCondition::Required => true,
+        RuleCondition::GreaterThan(_) | RuleCondition::AtLeast(_) | RuleCondition::AtMost(_) => !text,
+        RuleCondition::Format { min_length, max_length, .. } => {
+            if let (Some(min), Some(max)) = (min_length, max_length) {
+                if min > max {
+                    return Err(format!("规则 {} 的最小长度大于最大长度", rule.id));
+                }
+            }
+            text
+        }
+        RuleCondition::ValidDate => matches!(rule.field, RecordField::EventDate | RecordField::RepaymentDate),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("规则 {} 的条件 {:?} 不适用于字段 {:?}", rule.id, rule.condition, rule.field))
+    }
+}
+
+pub struct ValidationService {
+    state: ValidationState,
+}
+
+thread_local! {
+    pub static VALIDATION_SERVICE: RefCell<ValidationService> = RefCell::new(ValidationService::new());
+}
+
+impl Default for ValidationService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValidationService {
+    pub fn new() -> Self {
+        Self {
+            state: ValidationState::default(),
+        }
+    }
+
+    pub fn export_state(&self) -> ValidationState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: ValidationState) {
+        info!(
+            "Restored validation state: {} jurisdictions, {} institution mappings",
+            state.rule_sets.len(),
+            state.institution_jurisdictions.len()
+        );
+        self.state = state;
+    }
+
+    // === 规则集管理 ===
+
+    /// 取辖区当前生效的规则集，指定版本时返回历史版本
+    pub fn get_rule_set(&self, jurisdiction: &str, version: Option<u32>) -> Option<ValidationRuleSet> {
+        let versions = self.state.rule_sets.get(jurisdiction)?;
+        match version {
+            Some(version) => versions.iter().find(|s| s.version == version).cloned(),
+            None => versions.last().cloned(),
+        }
+    }
+
+    pub fn list_versions(&self, jurisdiction: &str) -> Vec<ValidationRuleSet> {
+        self.state.rule_sets.get(jurisdiction).cloned().unwrap_or_default()
+    }
+
+    pub fn list_jurisdictions(&self) -> Vec<String> {
+        let mut jurisdictions: Vec<String> = self.state.rule_sets.keys().cloned().collect();
+        jurisdictions.sort();
+        jurisdictions
+    }
+
+    /// 保存规则集为新版本
+    pub fn update_rule_set(&mut self, request: UpdateRuleSetRequest, operator: Principal) -> Result<ValidationRuleSet, String> {
+        let jurisdiction = request.jurisdiction.trim().to_string();
+        if jurisdiction.is_empty() {
+            return Err("辖区不能为空".to_string());
+        }
+        let mut ids = HashSet::new();
+        for rule in &request.rules {
+            check_rule(rule)?;
+            if !ids.insert(rule.id.as_str()) {
+                return Err(format!("规则ID重复: {}", rule.id));
+            }
+        }
+
+        let versions = self.state.rule_sets.entry(jurisdiction.clone()).or_default();
+        let rule_set = ValidationRuleSet {
+            jurisdiction,
+            version: versions.last().map_or(1, |s| s.version + 1),
+            rules: request.rules,
+            note: request.note,
+            updated_by: operator,
+            updated_at: time(),
+        };
+        info!(
+            "Validation rules for {} updated to v{} ({} rules) by {}",
+            rule_set.jurisdiction, rule_set.version, rule_set.rules.len(), operator.to_text()
+        );
+        versions.push(rule_set.clone());
+        Ok(rule_set)
+    }
+
+    pub fn set_institution_jurisdiction(&mut self, institution_id: Principal, jurisdiction: String) -> Result<(), String> {
+        if !self.state.rule_sets.contains_key(&jurisdiction) {
+            return Err(format!("辖区不存在: {}", jurisdiction));
+        }
+        info!("Institution {} assigned to jurisdiction {}", institution_id.to_text(), jurisdiction);
+        if jurisdiction == DEFAULT_JURISDICTION {
+            self.state.institution_jurisdictions.remove(&institution_id);
+        } else {
+            self.state.institution_jurisdictions.insert(institution_id, jurisdiction);
+        }
+        Ok(())
+    }
+
+    pub fn institution_jurisdiction(&self, institution_id: Principal) -> String {
+        self.state.institution_jurisdictions.get(&institution_id)
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_JURISDICTION.to_string())
+    }
+
+    // === 规则执行 ===
+
+    /// 按基础规则和机构所属辖区的规则校验记录，辖区规则与基础规则同ID时覆盖基础规则
+    pub fn evaluate(
+        &self,
+        institution_id: Principal,
+        record_type: &RecordType,
+        content: &RecordContent,
+        user_did: &str,
+        event_date: &str
+    ) -> Vec<RuleViolation> {
+        let jurisdiction = self.institution_jurisdiction(institution_id);
+        let mut layers = vec![DEFAULT_JURISDICTION];
+        if jurisdiction != DEFAULT_JURISDICTION {
+            layers.push(jurisdiction.as_str());
+        }
+
+        let mut active: Vec<(&ValidationRuleSet, &ValidationRule)> = Vec::new();
+        for set in layers.into_iter().filter_map(|j| self.get_active(j)) {
+            for rule in &set.rules {
+                active.retain(|(_, r)| r.id != rule.id);
+                active.push((set, rule));
+            }
+        }
+
+        let violations: Vec<RuleViolation> = active.into_iter()
+            .filter(|(_, rule)| rule.enabled && rule.record_type == *record_type)
+            .filter_map(|(set, rule)| {
+                let value = field_value(&rule.field, content, user_did, event_date)?;
+                (!condition_holds(&rule.condition, &value)).then(|| RuleViolation {
+                    rule_id: rule.id.clone(),
+                    jurisdiction: set.jurisdiction.clone(),
+                    version: set.version,
+                    field: rule.field.clone(),
+                    message: rule.message.clone(),
+                })
+            })
+            .collect();
+        if !violations.is_empty() {
+            debug!("Record failed {} validation rules under {}", violations.len(), jurisdiction);
+        }
+        violations
+    }
+
+    fn get_active(&self, jurisdiction: &str) -> Option<&ValidationRuleSet> {
+        self.state.rule_sets.get(jurisdiction).and_then(|versions| versions.last())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::record::LoanContent;
+
+    fn loan(loan_id: &str, interest_rate: f64) -> RecordContent {
+        RecordContent::Loan(LoanContent {
+            amount: 10_000,
+            loan_id: loan_id.to_string(),
+            term_months: 12,
+            interest_rate,
+        })
+    }
+
+    fn rule_set(jurisdiction: &str, rules: Vec<ValidationRule>) -> ValidationRuleSet {
+        ValidationRuleSet {
+            jurisdiction: jurisdiction.to_string(),
+            version: 1,
+            rules,
+            note: None,
+            updated_by: Principal::anonymous(),
+            updated_at: 0,
+        }
+    }
+
+    fn violated(service: &ValidationService, institution_id: Principal, content: &RecordContent) -> Vec<String> {
+        service.evaluate(institution_id, &RecordType::LoanRecord, content, "did:alice", "2024-10-15")
+            .into_iter()
+            .map(|v| v.rule_id)
+            .collect()
+    }
+
+    #[test]
+    fn default_rules_check_interest_rate_range() {
+        let service = ValidationService::new();
+        let bank = Principal::from_slice(&[1]);
+
+        assert!(violated(&service, bank, &loan("L1", 5.0)).is_empty());
+        assert_eq!(violated(&service, bank, &loan("L1", 0.0)), vec!["loan.interest_rate.positive"]);
+        assert_eq!(violated(&service, bank, &loan("", 120.0)), vec!["loan.loan_id.required", "loan.interest_rate.max"]);
+
+        let violation = &service.evaluate(bank, &RecordType::LoanRecord, &loan("L1", 120.0), "did:alice", "").remove(0);
+        assert_eq!(violation.field.as_str(), "interest_rate");
+    }
+
+    #[test]
+    fn jurisdiction_rules_override_base_rules_with_same_id() {
+        let mut service = ValidationService::new();
+        let bank = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        service.state.rule_sets.insert("cn".to_string(), vec![rule_set("cn", vec![
+            rule("loan.interest_rate.max", RecordType::LoanRecord, RecordField::InterestRate, RuleCondition::AtMost(24.0), "rate cap"),
+            rule("loan.loan_id.format", RecordType::LoanRecord, RecordField::LoanId, RuleCondition::Format {
+                prefix: Some("CN".to_string()),
+                min_length: Some(4),
+                max_length: None,
+                charset: Some(CharacterSet::AlphanumericDash),
+            }, "loan id format"),
+        ])]);
+        service.set_institution_jurisdiction(bank, "cn".to_string()).unwrap();
+
+        assert_eq!(violated(&service, bank, &loan("CN-001", 30.0)), vec!["loan.interest_rate.max"]);
+        assert_eq!(violated(&service, bank, &loan("L1", 10.0)), vec!["loan.loan_id.format"]);
+        assert!(violated(&service, bank, &loan("CN-001", 24.0)).is_empty());
+        // 其他机构仍只受基础规则约束
+        assert!(violated(&service, other, &loan("L1", 30.0)).is_empty());
+        assert!(service.set_institution_jurisdiction(bank, "unknown".to_string()).is_err());
+    }
+
+    #[test]
+    fn rules_must_fit_their_field() {
+        let numeric_on_text = rule("x", RecordType::LoanRecord, RecordField::LoanId, RuleCondition::AtLeast(1.0), "");
+        let wrong_record_type = rule("x", RecordType::OverdueRecord, RecordField::InterestRate, RuleCondition::Required, "");
+        let date_on_amount = rule("x", RecordType::LoanRecord, RecordField::Amount, RuleCondition::ValidDate, "");
+        let bad_lengths = rule("x", RecordType::LoanRecord, RecordField::LoanId, RuleCondition::Format {
+            prefix: None,
+            min_length: Some(5),
+            max_length: Some(3),
+            charset: None,
+        }, "");
+        for invalid in [numeric_on_text, wrong_record_type, date_on_amount, bad_lengths] {
+            assert!(check_rule(&invalid).is_err());
+        }
+        assert!(check_rule(&rule("x", RecordType::RepaymentRecord, RecordField::RepaymentDate, RuleCondition::ValidDate, "")).is_ok());
+    }
+}
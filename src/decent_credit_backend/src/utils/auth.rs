@@ -0,0 +1,53 @@
+use candid::Principal;
+use log::warn;
+use crate::services::admin_institution_service::ADMIN_SERVICE;
+
+/// 管理类接口仅允许 canister 控制者调用
+pub fn ensure_controller() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if ic_cdk::api::is_controller(&caller) {
+        Ok(())
+    } else {
+        warn!("Unauthorized admin call from {}", caller.to_text());
+        Err("仅管理员可执行此操作".to_string())
+    }
+}
+
+/// 调用者是否为机构本身、其注册者或控制者
+pub fn is_institution_caller(caller: Principal, institution_id: Principal) -> bool {
+    if caller == institution_id || ic_cdk::api::is_controller(&caller) {
+        return true;
+    }
+
+    ADMIN_SERVICE.with(|service| {
+        service.borrow().get_caller_institutions(caller)
+            .iter()
+            .any(|inst| inst.id == institution_id)
+    })
+}
+
+/// 调用者本身（已注册为机构时）及其注册的所有机构
+pub fn caller_institution_ids(caller: Principal) -> Vec<Principal> {
+    ADMIN_SERVICE.with(|service| {
+        let service = service.borrow();
+        let mut ids: Vec<Principal> = service.get_caller_institutions(caller)
+            .iter()
+            .map(|inst| inst.id)
+            .collect();
+        if service.get_institution(caller).is_some() && !ids.contains(&caller) {
+            ids.push(caller);
+        }
+        ids
+    })
+}
+
+/// 涉及机构资金的操作仅允许机构本身、其注册者或控制者调用
+pub fn ensure_institution_caller(institution_id: Principal) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if is_institution_caller(caller, institution_id) {
+        Ok(())
+    } else {
+        warn!("Caller {} is not allowed to act for {}", caller.to_text(), institution_id.to_text());
+        Err("无权操作该机构".to_string())
+    }
+}
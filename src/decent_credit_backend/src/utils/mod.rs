@@ -1,2 +1,4 @@
 pub mod logger;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod auth;
+pub mod time;
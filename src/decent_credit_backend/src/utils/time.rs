@@ -0,0 +1,83 @@
+use chrono::{DateTime, Datelike, NaiveDate, Timelike};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// 将纳秒时间戳转换为自然月标识，如 202410
+pub fn month_key(timestamp_nanos: u64) -> u32 {
+    DateTime::from_timestamp((timestamp_nanos / NANOS_PER_SEC) as i64, 0)
+        .map(|dt| dt.year() as u32 * 100 + dt.month())
+        .unwrap_or(0)
+}
+
+/// 将自 1970-01-01 起的天数格式化为 YYYY-MM-DD
+pub fn format_day(day: u64) -> String {
+    DateTime::from_timestamp((day * 24 * 60 * 60) as i64, 0)
+        .map(|dt| format!("{:04}-{:02}-{:02}", dt.year(), dt.month(), dt.day()))
+        .unwrap_or_default()
+}
+
+/// 解析事件日期（YYYY-MM-DD，允许带时间后缀），返回当日零点的纳秒时间戳
+pub fn parse_event_date(date: &str) -> Option<u64> {
+    let day = date.trim().split(['T', ' ']).next()?;
+    let mut parts = day.split('-');
+    let year = parts.next()?.parse::<i32>().ok()?;
+    let month = parts.next()?.parse::<u32>().ok()?;
+    let day = parts.next()?.parse::<u32>().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let seconds = NaiveDate::from_ymd_opt(year, month, day)?
+        .and_hms_opt(0, 0, 0)?
+        .and_utc()
+        .timestamp();
+    u64::try_from(seconds).ok().map(|s| s * NANOS_PER_SEC)
+}
+
+/// 将纳秒时间戳格式化为 ISO 8601 UTC 时间，如 2024-10-01T08:00:00Z
+pub fn iso8601(timestamp_nanos: u64) -> String {
+    DateTime::from_timestamp((timestamp_nanos / NANOS_PER_SEC) as i64, 0)
+        .map(|dt| format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            dt.year(), dt.month(), dt.day(), dt.hour(), dt.minute(), dt.second()
+        ))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn month_key_uses_utc_calendar_month() {
+        assert_eq!(month_key(1_728_950_400 * NANOS_PER_SEC), 202410);
+        // 2024-10-31T23:59:59Z 与 2024-11-01T00:00:00Z
+        assert_eq!(month_key(1_730_419_199 * NANOS_PER_SEC), 202410);
+        assert_eq!(month_key(1_730_419_200 * NANOS_PER_SEC), 202411);
+        assert_eq!(month_key(0), 197001);
+    }
+
+    #[test]
+    fn format_day_counts_days_since_epoch() {
+        assert_eq!(format_day(0), "1970-01-01");
+        assert_eq!(format_day(1_728_950_400 / 86_400), "2024-10-15");
+        assert_eq!(format_day(19_782), "2024-02-29");
+    }
+
+    #[test]
+    fn event_dates_parse_to_utc_midnight() {
+        let midnight = Some(1_728_950_400 * NANOS_PER_SEC);
+        assert_eq!(parse_event_date("2024-10-15"), midnight);
+        assert_eq!(parse_event_date(" 2024-10-15T08:30:00Z"), midnight);
+        assert_eq!(parse_event_date("2024-10-15 08:30"), midnight);
+        for invalid in ["", "2024-10", "2024-02-30", "2024-10-15-01", "15/10/2024", "1969-12-31"] {
+            assert_eq!(parse_event_date(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn iso8601_formats_utc_seconds() {
+        assert_eq!(iso8601(1_728_982_861 * NANOS_PER_SEC + 999), "2024-10-15T09:01:01Z");
+        assert_eq!(iso8601(0), "1970-01-01T00:00:00Z");
+    }
+}
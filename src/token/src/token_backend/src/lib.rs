@@ -1,23 +1,43 @@
-use candid::{CandidType, Deserialize, Principal};
+use candid::{CandidType, Deserialize, Nat, Principal};
 use ic_cdk::api::{caller, time};
 use ic_cdk_macros::*;
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
 use std::collections::HashMap;
 
-#[derive(Default)]
+// 单次 icrc3_get_blocks 最多返回的区块数
+const MAX_BLOCKS_PER_REQUEST: u64 = 2000;
+const DEFAULT_INITIAL_SUPPLY: u64 = 1_000_000_000_000;
+
+// 升级时整体写入稳定内存
+#[derive(CandidType, Deserialize, Default)]
 struct TokenData {
     balances: HashMap<Principal, u64>,
     total_supply: u64,
-    last_block_height: u64,
     admin: Option<Principal>,
+    // 铸币账户：只有它（或管理员）可以铸造和销毁
+    minting_account: Option<Principal>,
+    // 供应量上限，None 表示不设上限
+    max_supply: Option<u64>,
+    total_minted: u64,
+    total_burned: u64,
+    // 只追加的区块日志，区块高度即下标
+    blocks: Vec<Block>,
+    // 账户 -> 涉及该账户的区块高度列表
+    account_index: HashMap<Principal, Vec<u64>>,
 }
 
 thread_local! {
     static TOKEN: RefCell<TokenData> = RefCell::new(TokenData {
         balances: HashMap::new(),
-        total_supply: 1_000_000_000_000,
-        last_block_height: 0,
+        total_supply: 0,
         admin: None,
+        minting_account: None,
+        max_supply: None,
+        total_minted: 0,
+        total_burned: 0,
+        blocks: Vec::new(),
+        account_index: HashMap::new(),
     });
 }
 
@@ -37,23 +57,398 @@ pub struct TransferResult {
     pub tx_hash: String,
 }
 
+#[derive(CandidType, Deserialize)]
+pub struct InitArgs {
+    pub admin: Option<Principal>,
+    pub minting_account: Option<Principal>,
+    pub initial_supply: Option<u64>,
+    pub max_supply: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct MintArgs {
+    pub to: Principal,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct BurnArgs {
+    pub from: Principal,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub created_at_time: Option<u64>,
+}
+
+/// 由授权操作方代为划转，用于查询计费的托管（预留、结算、退款）
+#[derive(CandidType, Deserialize)]
+pub struct OperatorTransferArgs {
+    pub from: Principal,
+    pub to: Principal,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SupplyInfo {
+    pub total_supply: u64,
+    pub max_supply: Option<u64>,
+    pub total_minted: u64,
+    pub total_burned: u64,
+    pub treasury_balance: u64,
+    pub minting_account: Option<Principal>,
+}
+
+// === 区块日志相关结构 ===
+
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Operation {
+    Mint,
+    Burn,
+    Transfer,
+}
+
+impl Operation {
+    // ICRC-3 中 ICRC-1 区块的 btype
+    fn btype(&self) -> &'static str {
+        match self {
+            Operation::Mint => "1mint",
+            Operation::Burn => "1burn",
+            Operation::Transfer => "1xfer",
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Transaction {
+    pub operation: Operation,
+    pub from: Option<Principal>,
+    pub to: Option<Principal>,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Block {
+    pub id: u64,
+    pub parent_hash: Option<Vec<u8>>,
+    pub timestamp: u64,
+    pub transaction: Transaction,
+    pub hash: Vec<u8>,
+}
+
+/// ICRC-3 通用值类型
+#[derive(CandidType, Deserialize, Clone)]
+pub enum Icrc3Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Int(candid::Int),
+    Array(Vec<Icrc3Value>),
+    Map(Vec<(String, Icrc3Value)>),
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct GetBlocksArgs {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Icrc3Value,
+}
+
+candid::define_function!(pub GetBlocksCallback : (Vec<GetBlocksArgs>) -> (GetBlocksResult) query);
+
+#[derive(CandidType, Deserialize)]
+pub struct ArchivedBlocks {
+    pub args: Vec<GetBlocksArgs>,
+    pub callback: GetBlocksCallback,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+    pub archived_blocks: Vec<ArchivedBlocks>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SupportedBlockType {
+    pub block_type: String,
+    pub url: String,
+}
+
+/// 账户维度的交易视图，供后端对账使用
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AccountTransaction {
+    pub block_height: u64,
+    pub operation: Operation,
+    pub from: Option<Principal>,
+    pub to: Option<Principal>,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    pub timestamp: u64,
+    pub tx_hash: String,
+}
+
+impl Icrc3Value {
+    /// ICRC-3 规定的与表示无关的哈希
+    fn hash(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        match self {
+            Icrc3Value::Blob(bytes) => hasher.update(bytes),
+            Icrc3Value::Text(text) => hasher.update(text.as_bytes()),
+            Icrc3Value::Nat(n) => {
+                let mut buf = Vec::new();
+                n.encode(&mut buf).expect("leb128 encoding cannot fail");
+                hasher.update(&buf);
+            }
+            Icrc3Value::Int(i) => {
+                let mut buf = Vec::new();
+                i.encode(&mut buf).expect("sleb128 encoding cannot fail");
+                hasher.update(&buf);
+            }
+            Icrc3Value::Array(items) => {
+                for item in items {
+                    hasher.update(item.hash());
+                }
+            }
+            Icrc3Value::Map(entries) => {
+                let mut pairs: Vec<Vec<u8>> = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let mut pair = Sha256::digest(key.as_bytes()).to_vec();
+                        pair.extend(value.hash());
+                        pair
+                    })
+                    .collect();
+                pairs.sort();
+                for pair in pairs {
+                    hasher.update(pair);
+                }
+            }
+        }
+        hasher.finalize().to_vec()
+    }
+}
+
+fn account_value(owner: &Principal) -> Icrc3Value {
+    Icrc3Value::Array(vec![Icrc3Value::Blob(owner.as_slice().to_vec())])
+}
+
+impl Block {
+    fn to_value(&self) -> Icrc3Value {
+        let tx = &self.transaction;
+        let mut tx_fields = vec![("amt".to_string(), Icrc3Value::Nat(Nat::from(tx.amount)))];
+        if let Some(from) = &tx.from {
+            tx_fields.push(("from".to_string(), account_value(from)));
+        }
+        if let Some(to) = &tx.to {
+            tx_fields.push(("to".to_string(), account_value(to)));
+        }
+        if !tx.memo.is_empty() {
+            tx_fields.push(("memo".to_string(), Icrc3Value::Blob(tx.memo.clone())));
+        }
+        if let Some(created_at_time) = tx.created_at_time {
+            tx_fields.push(("ts".to_string(), Icrc3Value::Nat(Nat::from(created_at_time))));
+        }
+
+        let mut fields = vec![
+            ("btype".to_string(), Icrc3Value::Text(tx.operation.btype().to_string())),
+            ("ts".to_string(), Icrc3Value::Nat(Nat::from(self.timestamp))),
+            ("tx".to_string(), Icrc3Value::Map(tx_fields)),
+        ];
+        if let Some(parent_hash) = &self.parent_hash {
+            fields.push(("phash".to_string(), Icrc3Value::Blob(parent_hash.clone())));
+        }
+        Icrc3Value::Map(fields)
+    }
+
+    fn to_account_transaction(&self) -> AccountTransaction {
+        AccountTransaction {
+            block_height: self.id,
+            operation: self.transaction.operation,
+            from: self.transaction.from,
+            to: self.transaction.to,
+            amount: self.transaction.amount,
+            memo: self.transaction.memo.clone(),
+            timestamp: self.timestamp,
+            tx_hash: hex::encode(&self.hash),
+        }
+    }
+}
+
+impl TokenData {
+    /// 追加一个区块并维护哈希链和账户索引，返回新区块
+    fn append_block(&mut self, transaction: Transaction) -> &Block {
+        let id = self.blocks.len() as u64;
+        let parent_hash = self.blocks.last().map(|b| b.hash.clone());
+        let mut block = Block {
+            id,
+            parent_hash,
+            timestamp: time(),
+            transaction,
+            hash: Vec::new(),
+        };
+        block.hash = block.to_value().hash();
+
+        let mut accounts = vec![block.transaction.from, block.transaction.to];
+        accounts.dedup();
+        for account in accounts.into_iter().flatten() {
+            self.account_index.entry(account).or_default().push(id);
+        }
+
+        self.blocks.push(block);
+        self.blocks.last().expect("block was just appended")
+    }
+
+    fn is_admin(&self, principal: &Principal) -> bool {
+        self.admin.as_ref() == Some(principal) || ic_cdk::api::is_controller(principal)
+    }
+
+    fn can_mint(&self, principal: &Principal) -> bool {
+        self.minting_account.as_ref() == Some(principal) || self.is_admin(principal)
+    }
+
+    /// 供应量不变式：余额之和 == 总供应量 == 铸造总量 - 销毁总量，且不超过上限
+    fn check_supply_invariants(&self) -> Result<(), String> {
+        let balance_sum: u128 = self.balances.values().map(|b| *b as u128).sum();
+        if balance_sum != self.total_supply as u128 {
+            return Err(format!(
+                "Supply invariant violated: balances sum {} != total supply {}",
+                balance_sum, self.total_supply
+            ));
+        }
+        if self.total_minted.checked_sub(self.total_burned) != Some(self.total_supply) {
+            return Err(format!(
+                "Supply invariant violated: minted {} - burned {} != total supply {}",
+                self.total_minted, self.total_burned, self.total_supply
+            ));
+        }
+        if let Some(max_supply) = self.max_supply {
+            if self.total_supply > max_supply {
+                return Err(format!(
+                    "Supply invariant violated: total supply {} exceeds max supply {}",
+                    self.total_supply, max_supply
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // 每次状态变更后调用；失败时 trap 以回滚本次调用的全部修改
+    fn assert_supply_invariants(&self) {
+        if let Err(e) = self.check_supply_invariants() {
+            ic_cdk::trap(&e);
+        }
+    }
+
+    fn mint_to(&mut self, args: MintArgs) -> Result<&Block, String> {
+        if args.amount == 0 {
+            return Err("Amount must be greater than 0".to_string());
+        }
+        let new_supply = self.total_supply.checked_add(args.amount)
+            .ok_or_else(|| "Total supply overflow".to_string())?;
+        if let Some(max_supply) = self.max_supply {
+            if new_supply > max_supply {
+                return Err(format!("Mint would exceed max supply {}", max_supply));
+            }
+        }
+
+        let balance = self.balances.entry(args.to).or_insert(0);
+        *balance = balance.checked_add(args.amount)
+            .ok_or_else(|| "Balance overflow".to_string())?;
+        self.total_supply = new_supply;
+        self.total_minted += args.amount;
+
+        Ok(self.append_block(Transaction {
+            operation: Operation::Mint,
+            from: None,
+            to: Some(args.to),
+            amount: args.amount,
+            memo: args.memo,
+            created_at_time: args.created_at_time,
+        }))
+    }
+}
+
+fn nat_to_u64(n: &Nat) -> u64 {
+    u64::try_from(&n.0).unwrap_or(u64::MAX)
+}
+
 #[init]
-fn init() {
+fn init(args: Option<InitArgs>) {
+    initialize(args);
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let data = TOKEN.with(|token| std::mem::take(&mut *token.borrow_mut()));
+    if let Err(e) = ic_cdk::storage::stable_save((data,)) {
+        ic_cdk::trap(&format!("Failed to save token state before upgrade: {:?}", e));
+    }
+}
+
+/// 恢复余额、区块日志、账户索引和供应量设置；没有可恢复的状态时（从旧版本升级）按安装参数重新初始化
+#[post_upgrade]
+fn post_upgrade(args: Option<InitArgs>) {
+    match ic_cdk::storage::stable_restore::<(TokenData,)>() {
+        Ok((data,)) => TOKEN.with(|token| {
+            let mut token = token.borrow_mut();
+            *token = data;
+            token.assert_supply_invariants();
+        }),
+        Err(e) => {
+            ic_cdk::println!("No token state restored after upgrade: {}", e);
+            initialize(args);
+        }
+    }
+}
+
+fn initialize(args: Option<InitArgs>) {
+    let args = args.unwrap_or(InitArgs {
+        admin: None,
+        minting_account: None,
+        initial_supply: None,
+        max_supply: None,
+    });
+
     TOKEN.with(|token| {
         let mut data = token.borrow_mut();
         let canister_id = ic_cdk::id();
-        let total_supply = data.total_supply;  // 先获取值
-        data.balances.insert(canister_id, total_supply);
-        data.admin = Some(canister_id);
+        data.admin = Some(args.admin.unwrap_or(canister_id));
+        data.minting_account = args.minting_account;
+        data.max_supply = args.max_supply;
+
+        // 创世区块：初始供应量铸造到 canister 自身（国库）
+        let initial_supply = args.initial_supply.unwrap_or(DEFAULT_INITIAL_SUPPLY);
+        if initial_supply > 0 {
+            if let Err(e) = data.mint_to(MintArgs {
+                to: canister_id,
+                amount: initial_supply,
+                memo: b"genesis".to_vec(),
+                created_at_time: None,
+            }) {
+                ic_cdk::trap(&e);
+            }
+        }
+        data.assert_supply_invariants();
     });
 }
 
+/// 只有当前管理员或 canister 控制者可以更换管理员
 #[update]
 fn set_admin(new_admin: Principal) -> Result<(), String> {
     let caller = caller();
     TOKEN.with(|token| {
         let mut data = token.borrow_mut();
-        if data.admin.is_none() || caller == ic_cdk::id() {
+        if data.is_admin(&caller) || caller == ic_cdk::id() {
             data.admin = Some(new_admin);
             Ok(())
         } else {
@@ -63,50 +458,244 @@ fn set_admin(new_admin: Principal) -> Result<(), String> {
 }
 
 #[update]
-fn initialize_canister_balance() -> Result<(), String> {
-    let canister_id = ic_cdk::id();
-    
+fn set_minting_account(minting_account: Option<Principal>) -> Result<(), String> {
+    let caller = caller();
+    TOKEN.with(|token| {
+        let mut data = token.borrow_mut();
+        if !data.is_admin(&caller) {
+            return Err("Unauthorized".to_string());
+        }
+        data.minting_account = minting_account;
+        Ok(())
+    })
+}
+
+#[update]
+fn set_max_supply(max_supply: Option<u64>) -> Result<(), String> {
+    let caller = caller();
     TOKEN.with(|token| {
         let mut data = token.borrow_mut();
-        let total = data.total_supply;  // 先获取值
-        data.balances.insert(canister_id, total);
+        if !data.is_admin(&caller) {
+            return Err("Unauthorized".to_string());
+        }
+        if let Some(max) = max_supply {
+            if max < data.total_supply {
+                return Err(format!(
+                    "Max supply {} is below current total supply {}",
+                    max, data.total_supply
+                ));
+            }
+        }
+        data.max_supply = max_supply;
+        data.assert_supply_invariants();
         Ok(())
     })
 }
+
+#[update]
+fn mint(args: MintArgs) -> Result<TransferResult, String> {
+    let caller = caller();
+    TOKEN.with(|token| {
+        let mut data = token.borrow_mut();
+        if !data.can_mint(&caller) {
+            return Err("Unauthorized".to_string());
+        }
+
+        let (block_height, tx_hash) = {
+            let block = data.mint_to(args)?;
+            (block.id, hex::encode(&block.hash))
+        };
+        data.assert_supply_invariants();
+
+        Ok(TransferResult { block_height, tx_hash })
+    })
+}
+
+#[update]
+fn burn(args: BurnArgs) -> Result<TransferResult, String> {
+    let caller = caller();
+    TOKEN.with(|token| {
+        let mut data = token.borrow_mut();
+        if !data.can_mint(&caller) {
+            return Err("Unauthorized".to_string());
+        }
+        if args.amount == 0 {
+            return Err("Amount must be greater than 0".to_string());
+        }
+
+        let from_balance = *data.balances.get(&args.from).unwrap_or(&0);
+        if from_balance < args.amount {
+            return Err(format!(
+                "Insufficient balance: has {}, needs {}",
+                from_balance, args.amount
+            ));
+        }
+
+        data.balances.insert(args.from, from_balance - args.amount);
+        data.total_supply -= args.amount;
+        data.total_burned += args.amount;
+
+        let (block_height, tx_hash) = {
+            let block = data.append_block(Transaction {
+                operation: Operation::Burn,
+                from: Some(args.from),
+                to: None,
+                amount: args.amount,
+                memo: args.memo,
+                created_at_time: args.created_at_time,
+            });
+            (block.id, hex::encode(&block.hash))
+        };
+        data.assert_supply_invariants();
+
+        Ok(TransferResult { block_height, tx_hash })
+    })
+}
+/// 从国库（canister 自身）转出，只有铸币账户或管理员可以调用
 #[update]
-fn transfer(args: TokenTransferArgs) -> TransferResult {
+fn transfer(args: TokenTransferArgs) -> Result<TransferResult, String> {
+    let caller = caller();
     TOKEN.with(|token| {
         let mut data = token.borrow_mut();
-        
+        if !data.can_mint(&caller) {
+            return Err("Unauthorized".to_string());
+        }
+
         // 直接使用 canister_id 作为转账来源
         let from = ic_cdk::id();
-        
+
         // 检查余额
         let from_balance = *data.balances.get(&from).unwrap_or(&0);
         if from_balance < args.amount {
-            ic_cdk::trap("Insufficient balance");
+            return Err(format!(
+                "Insufficient balance: has {}, needs {}",
+                from_balance, args.amount
+            ));
         }
-        
+
         // 更新余额
         data.balances.insert(from, from_balance - args.amount);
-        let to_balance = *data.balances.get(&args.to).unwrap_or(&0);
-        data.balances.insert(args.to, to_balance + args.amount);
-        
-        // 更新区块高度
-        data.last_block_height += 1;
-        
-        TransferResult {
-            block_height: data.last_block_height,
-            tx_hash: format!(
-                "tx_{}_{}_{}",
-                time(),
-                from,
-                args.to
-            ),
+        *data.balances.entry(args.to).or_insert(0) += args.amount;
+
+        // 写入区块日志
+        let (block_height, tx_hash) = {
+            let block = data.append_block(Transaction {
+                operation: Operation::Transfer,
+                from: Some(from),
+                to: Some(args.to),
+                amount: args.amount,
+                memo: args.memo,
+                created_at_time: args.created_at_time,
+            });
+            (block.id, hex::encode(&block.hash))
+        };
+        data.assert_supply_invariants();
+
+        Ok(TransferResult { block_height, tx_hash })
+    })
+}
+
+#[update]
+fn operator_transfer(args: OperatorTransferArgs) -> Result<TransferResult, String> {
+    let caller = caller();
+    TOKEN.with(|token| {
+        let mut data = token.borrow_mut();
+        if !data.can_mint(&caller) {
+            return Err("Unauthorized".to_string());
+        }
+        if args.amount == 0 {
+            return Err("Amount must be greater than 0".to_string());
+        }
+        if args.from == args.to {
+            return Err("Sender and recipient must differ".to_string());
+        }
+
+        let from_balance = *data.balances.get(&args.from).unwrap_or(&0);
+        if from_balance < args.amount {
+            return Err(format!(
+                "Insufficient balance: has {}, needs {}",
+                from_balance, args.amount
+            ));
+        }
+        data.balances.insert(args.from, from_balance - args.amount);
+        *data.balances.entry(args.to).or_insert(0) += args.amount;
+
+        let (block_height, tx_hash) = {
+            let block = data.append_block(Transaction {
+                operation: Operation::Transfer,
+                from: Some(args.from),
+                to: Some(args.to),
+                amount: args.amount,
+                memo: args.memo,
+                created_at_time: args.created_at_time,
+            });
+            (block.id, hex::encode(&block.hash))
+        };
+        data.assert_supply_invariants();
+
+        Ok(TransferResult { block_height, tx_hash })
+    })
+}
+
+#[query]
+fn icrc3_get_blocks(args: Vec<GetBlocksArgs>) -> GetBlocksResult {
+    TOKEN.with(|token| {
+        let data = token.borrow();
+        let log_length = data.blocks.len() as u64;
+        let mut blocks = Vec::new();
+
+        for arg in args {
+            let start = nat_to_u64(&arg.start).min(log_length);
+            let length = nat_to_u64(&arg.length).min(MAX_BLOCKS_PER_REQUEST);
+            let end = start.saturating_add(length).min(log_length);
+
+            for block in &data.blocks[start as usize..end as usize] {
+                blocks.push(BlockWithId {
+                    id: Nat::from(block.id),
+                    block: block.to_value(),
+                });
+            }
+        }
+
+        GetBlocksResult {
+            log_length: Nat::from(log_length),
+            blocks,
+            archived_blocks: Vec::new(),
         }
     })
 }
 
+#[query]
+fn icrc3_supported_block_types() -> Vec<SupportedBlockType> {
+    let url = "https://github.com/dfinity/ICRC-1/blob/main/standards/ICRC-3/README.md";
+    ["1xfer", "1mint", "1burn"]
+        .iter()
+        .map(|block_type| SupportedBlockType {
+            block_type: block_type.to_string(),
+            url: url.to_string(),
+        })
+        .collect()
+}
+
+/// 按账户查询交易，按区块高度倒序返回；start 为上一页最后一个区块高度
+#[query]
+fn get_account_transactions(account: Principal, start: Option<u64>, max_results: u64) -> Vec<AccountTransaction> {
+    TOKEN.with(|token| {
+        let data = token.borrow();
+        let Some(ids) = data.account_index.get(&account) else {
+            return Vec::new();
+        };
+
+        ids.iter()
+            .rev()
+            .filter(|id| start.is_none_or(|s| **id < s))
+            .take(max_results.min(MAX_BLOCKS_PER_REQUEST) as usize)
+            .filter_map(|id| data.blocks.get(*id as usize))
+            .map(Block::to_account_transaction)
+            .collect()
+    })
+}
+
 #[query]
 fn get_admin() -> Principal {
     TOKEN.with(|token| {
@@ -143,4 +732,135 @@ fn total_supply() -> u64 {
     })
 }
 
-ic_cdk::export_candid!();
\ No newline at end of file
+#[query]
+fn get_supply_info() -> SupplyInfo {
+    TOKEN.with(|token| {
+        let data = token.borrow();
+        SupplyInfo {
+            total_supply: data.total_supply,
+            max_supply: data.max_supply,
+            total_minted: data.total_minted,
+            total_burned: data.total_burned,
+            treasury_balance: *data.balances.get(&ic_cdk::id()).unwrap_or(&0),
+            minting_account: data.minting_account,
+        }
+    })
+}
+
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_hex(value: &Icrc3Value) -> String {
+        hex::encode(value.hash())
+    }
+
+    fn sample_block(id: u64, parent_hash: Option<Vec<u8>>) -> Block {
+        let mut block = Block {
+            id,
+            parent_hash,
+            timestamp: 1_700_000_000_000_000_000,
+            transaction: Transaction {
+                operation: Operation::Transfer,
+                from: Some(Principal::from_slice(&[1])),
+                to: Some(Principal::from_slice(&[2])),
+                amount: 100,
+                memo: Vec::new(),
+                created_at_time: None,
+            },
+            hash: Vec::new(),
+        };
+        block.hash = block.to_value().hash();
+        block
+    }
+
+    // ICRC-3 规范中的示例值
+    #[test]
+    fn hashes_scalar_values_per_icrc3() {
+        assert_eq!(
+            hash_hex(&Icrc3Value::Nat(Nat::from(42u64))),
+            "684888c0ebb17f374298b65ee2807526c066094c701bcc7ebbe1c1095f494fc1"
+        );
+        assert_eq!(
+            hash_hex(&Icrc3Value::Int(candid::Int::from(-42))),
+            "de5a6f78116eca62d7fc5ce159d23ae6b889b365a1739ad2cf36f925a140d0cc"
+        );
+        assert_eq!(
+            hash_hex(&Icrc3Value::Text("Hello, World!".to_string())),
+            "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f"
+        );
+        assert_eq!(
+            hash_hex(&Icrc3Value::Blob(vec![1, 2, 3, 4])),
+            "9f64a747e1b97f131fabb6b447296c9b6f0201e79fb3c5356e6c77e89b6a806a"
+        );
+    }
+
+    #[test]
+    fn hashes_arrays_in_order() {
+        let array = Icrc3Value::Array(vec![
+            Icrc3Value::Nat(Nat::from(3u64)),
+            Icrc3Value::Text("foo".to_string()),
+            Icrc3Value::Blob(vec![5, 6]),
+        ]);
+        assert_eq!(
+            hash_hex(&array),
+            "514a04011caa503990d446b7dec5d79e19c221ae607fb08b2848c67734d468d6"
+        );
+    }
+
+    #[test]
+    fn map_hash_ignores_entry_order() {
+        let entries = vec![
+            ("a".to_string(), Icrc3Value::Nat(Nat::from(1u64))),
+            ("b".to_string(), Icrc3Value::Text("x".to_string())),
+        ];
+        let mut reversed = entries.clone();
+        reversed.reverse();
+
+        let expected = "950c4e07be08b4eb1af4c5ee3d6460785a822261d93d36532a5bfcaf7fcd482e";
+        assert_eq!(hash_hex(&Icrc3Value::Map(entries)), expected);
+        assert_eq!(hash_hex(&Icrc3Value::Map(reversed)), expected);
+    }
+
+    #[test]
+    fn block_hash_covers_parent_hash() {
+        let genesis = sample_block(0, None);
+        let child = sample_block(1, Some(genesis.hash.clone()));
+        let orphan = sample_block(1, Some(vec![0; 32]));
+
+        let Icrc3Value::Map(fields) = genesis.to_value() else {
+            panic!("block should encode as a map");
+        };
+        assert!(fields.iter().all(|(key, _)| key != "phash"));
+        assert_ne!(child.hash, orphan.hash);
+        assert_eq!(child.to_account_transaction().tx_hash, hex::encode(&child.hash));
+    }
+
+    #[test]
+    fn supply_invariants_detect_drift() {
+        let mut data = TokenData {
+            total_supply: 150,
+            total_minted: 200,
+            total_burned: 50,
+            max_supply: Some(1_000),
+            ..TokenData::default()
+        };
+        data.balances.insert(Principal::from_slice(&[1]), 100);
+        data.balances.insert(Principal::from_slice(&[2]), 50);
+        assert!(data.check_supply_invariants().is_ok());
+
+        // 余额被直接修改而未记账
+        data.balances.insert(Principal::from_slice(&[2]), 60);
+        assert!(data.check_supply_invariants().is_err());
+        data.balances.insert(Principal::from_slice(&[2]), 50);
+
+        data.total_burned = 40;
+        assert!(data.check_supply_invariants().is_err());
+        data.total_burned = 50;
+
+        data.max_supply = Some(100);
+        assert!(data.check_supply_invariants().is_err());
+    }
+}